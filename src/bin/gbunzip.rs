@@ -1,5 +1,5 @@
-use gbwt::{GBZ, Orientation};
-use gbwt::REF_SAMPLE;
+use gbwt::{GBZ, PathSense};
+use gbwt::gfa::{GfaWriter, PathMode};
 use gbwt::internal;
 
 use simple_sds::serialize::Serialize;
@@ -7,19 +7,16 @@ use simple_sds::serialize;
 
 use std::fs::OpenOptions;
 use std::io::{Write, BufWriter};
-use std::sync::Mutex;
 use std::time::Instant;
 use std::{env, io, process};
 
 use getopts::Options;
-use rayon::prelude::*;
 
 //-----------------------------------------------------------------------------
 
 fn main() -> Result<(), String> {
     let start = Instant::now();
     let config = Config::new().map_err(|x| x.to_string())?;
-    rayon::ThreadPoolBuilder::new().num_threads(config.threads).build_global().map_err(|e| e.to_string())?;
 
     let filename = config.filename.as_ref().unwrap();
     if config.verbose {
@@ -57,21 +54,12 @@ fn main() -> Result<(), String> {
 
 //-----------------------------------------------------------------------------
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-enum PathMode {
-    Default,
-    PanSN,
-    RefOnly,
-}
-
-impl PathMode {
-    fn new(mode: &str) -> Option<Self> {
-        match mode {
-            "default" => Some(PathMode::Default),
-            "pan-sn" => Some(PathMode::PanSN),
-            "ref-only" => Some(PathMode::RefOnly),
-            _ => None,
-        }
+fn parse_path_mode(mode: &str) -> Option<PathMode> {
+    match mode {
+        "default" => Some(PathMode::Default),
+        "pan-sn" => Some(PathMode::PanSN),
+        "ref-only" => Some(PathMode::RefOnly),
+        _ => None,
     }
 }
 
@@ -140,7 +128,7 @@ impl Config {
             config.output = Some(s);
         }
         if let Some(s) = matches.opt_str("p") {
-            match PathMode::new(&s) {
+            match parse_path_mode(&s) {
                 Some(mode) => config.path_mode = mode,
                 None => return Err(format!("--paths: invalid path mode {}", s)),
             }
@@ -187,351 +175,37 @@ fn write_gfa(gbz: &GBZ, config: &Config) -> io::Result<()> {
     Ok(())
 }
 
-fn write_gfa_impl<T: Write + Send>(gbz: &GBZ, output: T, config: &Config) -> io::Result<()> {
-    let mut buffer = BufWriter::with_capacity(config.buffer_size, output);
-    buffer.write_all(b"H\tVN:Z:1.1\n")?;
-    write_segments(gbz, &mut buffer, config)?;
-    write_links(gbz, &mut buffer, config)?;
-
-    match config.path_mode {
-        PathMode::Default => {
-            write_paths(gbz, &mut buffer, config)?;
-            write_walks(gbz, &mut buffer, config)?;
-        },
-        PathMode::PanSN => {
-            write_pan_sn(gbz, &mut buffer, config)?;
-        },
-        PathMode::RefOnly => {
-            write_paths(gbz, &mut buffer, config)?;
-        },
-    }
-
-    buffer.flush()?;
-    Ok(())
-}
-
-//-----------------------------------------------------------------------------
-
-fn write_segments<T: Write>(gbz: &GBZ, output: &mut T, config: &Config) -> io::Result<()> {
+fn write_gfa_impl<T: Write>(gbz: &GBZ, output: T, config: &Config) -> io::Result<()> {
     let start = Instant::now();
-    let mut segments = 0;
-    if config.verbose {
-        eprintln!("Writing segments");
-    }
-
-    match gbz.segment_iter() {
-        Some(iter) => {
-            for segment in iter {
-                write_segment(segment.name, segment.sequence, output)?;
-                segments += 1;
-            }
-        },
-        None => {
-            for node_id in gbz.node_iter() {
-                write_segment(node_id.to_string().as_bytes(), gbz.sequence(node_id).unwrap(), output)?;
-                segments += 1;
-            }
-        },
-    }
-
     if config.verbose {
-        eprintln!("Wrote {} segments in {:.3} seconds", segments, start.elapsed().as_secs_f64());
+        eprintln!("Writing GFA");
     }
-    Ok(())
-}
-
-fn write_segment<T: Write>(name: &[u8], sequence: &[u8], output: &mut T) -> io::Result<()> {
-    output.write_all(b"S\t")?;
-    output.write_all(name)?;
-    output.write_all(b"\t")?;
-    output.write_all(sequence)?;
-    output.write_all(b"\n")
-}
 
-//-----------------------------------------------------------------------------
-
-fn write_links<T: Write>(gbz: &GBZ, output: &mut T, config: &Config) -> io::Result<()> {
-    let start = Instant::now();
-    let mut links = 0;
-    if config.verbose {
-        eprintln!("Writing links");
-    }
-
-    match gbz.segment_iter() {
-        Some(iter) => {
-            for segment in iter {
-                for (successor, orientation) in gbz.segment_successors(&segment, Orientation::Forward).unwrap() {
-                    // A link from forward orientation is canonical if it is a self-loop or the successor
-                    // has a greater id.
-                    if successor.id >= segment.id {
-                        write_link((segment.name, Orientation::Forward), (successor.name, orientation), output)?;
-                        links += 1;
-                    }
-                }
-                for (successor, orientation) in gbz.segment_successors(&segment, Orientation::Reverse).unwrap() {
-                    // A link from reverse orientation is canonical if it is a self-loop that to forward
-                    // orientation or the successor has a greater id.
-                    if successor.id > segment.id || (successor.id == segment.id && orientation == Orientation::Forward) {
-                        write_link((segment.name, Orientation::Reverse), (successor.name, orientation), output)?;
-                        links += 1;
-                    }
-                }
+    let metadata = gbz.metadata().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "GFA decompression requires GBWT metadata"))?;
+    match config.path_mode {
+        PathMode::Default | PathMode::RefOnly => {
+            if metadata.paths_with_sense(PathSense::Generic).is_empty() {
+                eprintln!("No named paths in the graph");
             }
         },
-        None => {
-            for node_id in gbz.node_iter() {
-                for (successor, orientation) in gbz.successors(node_id, Orientation::Forward).unwrap() {
-                    if successor >= node_id {
-                        write_link((node_id.to_string().as_bytes(), Orientation::Forward), (successor.to_string().as_bytes(), orientation), output)?;
-                        links += 1;
-                    }
-                }
-                for (successor, orientation) in gbz.successors(node_id, Orientation::Reverse).unwrap() {
-                    if successor > node_id || (successor == node_id && orientation == Orientation::Forward) {
-                        write_link((node_id.to_string().as_bytes(), Orientation::Reverse), (successor.to_string().as_bytes(), orientation), output)?;
-                        links += 1;
-                    }
-                }
+        PathMode::PanSN => {
+            if metadata.path_iter().any(|path_name| path_name.fragment() > 0) {
+                eprintln!("Warning: Fragment field is in use; there may be duplicate path names");
             }
         },
     }
 
-    if config.verbose {
-        eprintln!("Wrote {} links in {:.3} seconds", links, start.elapsed().as_secs_f64());
-    }
-    Ok(())
-}
-
-fn write_link<T: Write>(from: (&[u8], Orientation), to: (&[u8], Orientation), output: &mut T) -> io::Result<()> {
-    output.write_all(b"L\t")?;
-    output.write_all(from.0)?;
-    match from.1 {
-        Orientation::Forward => output.write_all(b"\t+\t")?,
-        Orientation::Reverse => output.write_all(b"\t-\t")?,
-    }
-    output.write_all(to.0)?;
-    match to.1 {
-        Orientation::Forward => output.write_all(b"\t+\t*\n"),
-        Orientation::Reverse => output.write_all(b"\t-\t*\n"),
-    }
-}
-
-//-----------------------------------------------------------------------------
-
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-enum LineType {
-    PLine,
-    PanSN,
-    WLine,
-}
-
-fn write_paths<T: Write + Send>(gbz: &GBZ, output: &mut T, config: &Config) -> io::Result<()> {
-    let start = Instant::now();
-    let metadata = gbz.metadata().unwrap();
-    let ref_sample = metadata.sample_id(REF_SAMPLE);
-    if ref_sample.is_none() {
-        eprintln!("No named paths in the graph");
-        return Ok(());
-    }
-    let ref_sample = ref_sample.unwrap();
-    if config.verbose {
-        eprintln!("Writing paths");
-    }
-
-    // Determine the identifiers of named paths and write them as P-lines.
-    let mut paths: Vec<usize> = Vec::new();
-    for (path_id, path_name) in metadata.path_iter().enumerate() {
-        if path_name.sample() == ref_sample {
-            paths.push(path_id);
-        }
-    }
-    write_lines(gbz, &paths, output, config, LineType::PLine)?;
-
-    if config.verbose {
-        eprintln!("Wrote {} paths in {:.3} seconds", paths.len(), start.elapsed().as_secs_f64());
-    }
-    Ok(())
-}
-
-fn write_pan_sn<T: Write + Send>(gbz: &GBZ, output: &mut T, config: &Config) -> io::Result<()> {
-    let start = Instant::now();
-    let metadata = gbz.metadata().unwrap();
-    if config.verbose {
-        eprintln!("Writing PanSN paths");
-    }
-
-    // Write all paths as P-lines with PanSN names.
-    let mut paths: Vec<usize> = Vec::new();
-    let mut warned = false;
-    for (path_id, path_name) in metadata.path_iter().enumerate() {
-        paths.push(path_id);
-        if !warned && path_name.fragment() > 0 {
-            eprintln!("Warning: Fragment field is in use; there may be duplicate path names");
-            warned = true;
-        }
-    }
-    write_lines(gbz, &paths, output, config, LineType::PanSN)?;
-
-    if config.verbose {
-        eprintln!("Wrote {} PanSN paths in {:.3} seconds", paths.len(), start.elapsed().as_secs_f64());
-    }
-    Ok(())
-}
-
-fn write_walks<T: Write + Send>(gbz: &GBZ, output: &mut T, config: &Config) -> io::Result<()> {
-    let start = Instant::now();
-    let metadata = gbz.metadata().unwrap();
-    let ref_sample = metadata.sample_id(REF_SAMPLE).unwrap_or(metadata.samples());
-    if config.verbose {
-        eprintln!("Writing walks");
-    }
-
-    // Determine the identifiers of haplotype paths and write them as W-lines.
-    let mut paths: Vec<usize> = Vec::new();
-    for (path_id, path_name) in metadata.path_iter().enumerate() {
-        if path_name.sample() != ref_sample {
-            paths.push(path_id);
-        }
-    }
-    write_lines(gbz, &paths, output, config, LineType::WLine)?;
+    let mut writer = GfaWriter::new(gbz);
+    writer.set_path_mode(config.path_mode);
+    writer.set_threads(config.threads);
+    let mut buffer = BufWriter::with_capacity(config.buffer_size, output);
+    writer.write(&mut buffer)?;
+    buffer.flush()?;
 
     if config.verbose {
-        eprintln!("Wrote {} walks in {:.3} seconds", paths.len(), start.elapsed().as_secs_f64());
+        eprintln!("Wrote GFA in {:.3} seconds", start.elapsed().as_secs_f64());
     }
     Ok(())
 }
 
 //-----------------------------------------------------------------------------
-
-fn write_lines<T: Write + Send>(gbz: &GBZ, paths: &Vec<usize>, output: &mut T, _config: &Config, line_type: LineType) -> io::Result<()> {
-    let mutex = Mutex::new(output);
-    paths.par_iter().try_for_each(|path_id| {
-        let line = match line_type {
-            LineType::PLine => path_to_p_line(gbz, *path_id),
-            LineType::PanSN => path_to_pan_sn(gbz, *path_id),
-            LineType::WLine => path_to_w_line(gbz, *path_id),
-        };
-        if let Ok(mut out) = mutex.lock() {
-            out.write_all(&line)?;
-        }
-        Ok(())
-    })
-}
-
-//-----------------------------------------------------------------------------
-
-fn write_p_line(gbz: &GBZ, path_id: usize, name: &str) -> Vec<u8> {
-    let mut buffer: Vec<u8> = Vec::new();
-
-    buffer.push(b'P');
-    buffer.push(b'\t');
-    buffer.extend_from_slice(name.as_bytes());
-    buffer.push(b'\t');
-
-    let mut len = 0;
-    match gbz.segment_path(path_id, Orientation::Forward) {
-        Some(iter) => {
-            for (segment, orientation) in iter {
-                if len > 0 {
-                    buffer.push(b',');
-                }
-                buffer.extend_from_slice(segment.name);
-                match orientation {
-                    Orientation::Forward => buffer.push(b'+'),
-                    Orientation::Reverse => buffer.push(b'-'),
-                }
-                len += 1;
-            }
-        },
-        None => {
-            for (node_id, orientation) in gbz.path(path_id, Orientation::Forward).unwrap() {
-                if len > 0 {
-                    buffer.push(b',');
-                }
-                buffer.extend_from_slice(node_id.to_string().as_bytes());
-                match orientation {
-                    Orientation::Forward => buffer.push(b'+'),
-                    Orientation::Reverse => buffer.push(b'-'),
-                }
-                len += 1;
-            }
-        },
-    }
-
-    buffer.extend_from_slice(b"\t*\n");
-    buffer
-}
-
-fn path_to_p_line(gbz: &GBZ, path_id: usize) -> Vec<u8> {
-    let metadata = gbz.metadata().unwrap();
-    let path_name = metadata.path(path_id).unwrap();
-    let contig_name = metadata.contig(path_name.contig()).unwrap();
-    write_p_line(gbz, path_id, &contig_name)
-}
-
-fn path_to_pan_sn(gbz: &GBZ, path_id: usize) -> Vec<u8> {
-    let metadata = gbz.metadata().unwrap();
-    let path_name = metadata.pan_sn_path(path_id).unwrap();
-    write_p_line(gbz, path_id, &path_name)
-}
-
-//-----------------------------------------------------------------------------
-
-fn path_to_w_line(gbz: &GBZ, path_id: usize) -> Vec<u8> {
-    let mut buffer: Vec<u8> = Vec::new();
-
-    let metadata = gbz.metadata().unwrap();
-    let path_name = metadata.path(path_id).unwrap();
-    buffer.push(b'W');
-    buffer.push(b'\t');
-    buffer.extend_from_slice(metadata.sample_name(path_name.sample()).as_bytes());
-    buffer.push(b'\t');
-    buffer.extend_from_slice(path_name.phase().to_string().as_bytes());
-    buffer.push(b'\t');
-    buffer.extend_from_slice(metadata.contig_name(path_name.contig()).as_bytes());
-    buffer.push(b'\t');
-    buffer.extend_from_slice(path_name.fragment().to_string().as_bytes());
-    buffer.push(b'\t');
-
-    match gbz.segment_path(path_id, Orientation::Forward) {
-        Some(iter) => {
-            let mut path: Vec<(&[u8], Orientation)> = Vec::new();
-            let mut len: usize = 0;
-            for (segment, orientation) in iter {
-                path.push((segment.name, orientation));
-                len += segment.sequence.len();
-            }
-            buffer.extend_from_slice((path_name.fragment() + len).to_string().as_bytes());
-            buffer.push(b'\t');
-            for (name, orientation) in path.iter() {
-                match orientation {
-                    Orientation::Forward => buffer.push(b'>'),
-                    Orientation::Reverse => buffer.push(b'<'),
-                }
-                buffer.extend_from_slice(name);
-            }
-        },
-        None => {
-            let mut path: Vec<(usize, Orientation)> = Vec::new();
-            let mut len: usize = 0;
-            for (node_id, orientation) in gbz.path(path_id, Orientation::Forward).unwrap() {
-                path.push((node_id, orientation));
-                len += gbz.sequence_len(node_id).unwrap_or(0);
-            }
-            buffer.extend_from_slice((path_name.fragment() + len).to_string().as_bytes());
-            buffer.push(b'\t');
-            for (node_id, orientation) in path.iter() {
-                match orientation {
-                    Orientation::Forward => buffer.push(b'>'),
-                    Orientation::Reverse => buffer.push(b'<'),
-                }
-                buffer.extend_from_slice(node_id.to_string().as_bytes());
-            }
-        },
-    }
-
-    buffer.push(b'\n');
-    buffer
-}
-
-//-----------------------------------------------------------------------------
@@ -0,0 +1,112 @@
+use gbwt::{GBWT, GBZ};
+use gbwt::gbwt::ValidationReport;
+use gbwt::internal;
+
+use simple_sds::serialize::Serialize;
+use simple_sds::serialize;
+
+use std::time::Instant;
+use std::{env, process};
+
+use getopts::Options;
+
+//-----------------------------------------------------------------------------
+
+fn main() -> Result<(), String> {
+    let start = Instant::now();
+    let config = Config::new().map_err(|x| x.to_string())?;
+
+    let filename = config.filename.as_ref().unwrap();
+    let report = if config.gbwt {
+        if config.verbose {
+            eprintln!("Loading GBWT index {}", filename);
+        }
+        let index: GBWT = serialize::load_from(filename).map_err(|x| x.to_string())?;
+        if config.verbose {
+            let (size, units) = internal::readable_size(index.size_in_bytes());
+            eprintln!("GBWT size: {:.3} {}", size, units);
+            eprintln!("");
+        }
+        index.validate()
+    } else {
+        if config.verbose {
+            eprintln!("Loading GBZ graph {}", filename);
+        }
+        let gbz: GBZ = serialize::load_from(filename).map_err(|x| x.to_string())?;
+        if config.verbose {
+            let (size, units) = internal::readable_size(gbz.size_in_bytes());
+            eprintln!("GBZ size: {:.3} {}", size, units);
+            eprintln!("");
+        }
+        gbz.validate()
+    };
+    print!("{}", report);
+
+    if config.verbose {
+        eprintln!("");
+        eprintln!("Checked the file in {:.3} seconds", start.elapsed().as_secs_f64());
+        internal::report_memory_usage();
+        eprintln!("");
+    }
+    check_result(&report)
+}
+
+//-----------------------------------------------------------------------------
+
+fn check_result(report: &ValidationReport) -> Result<(), String> {
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(format!("Found {} errors", report.errors()))
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+struct Config {
+    filename: Option<String>,
+    gbwt: bool,
+    verbose: bool,
+}
+
+impl Config {
+    pub fn new() -> Result<Config, String> {
+        let args: Vec<String> = env::args().collect();
+        let program = args[0].clone();
+
+        let mut opts = Options::new();
+        opts.optflag("g", "gbwt", "the input is a GBWT index instead of a GBZ graph");
+        opts.optflag("h", "help", "print this help");
+        opts.optflag("v", "verbose", "print progress information");
+        let matches = opts.parse(&args[1..]).map_err(|x| x.to_string())?;
+
+        let mut config = Config {
+            filename: None,
+            gbwt: false,
+            verbose: false,
+        };
+        if matches.opt_present("g") {
+            config.gbwt = true;
+        }
+        if matches.opt_present("h") {
+            let header = format!("Usage: {} [options] graph.gbz", program);
+            eprint!("{}", opts.usage(&header));
+            process::exit(0);
+        }
+        if matches.opt_present("v") {
+            config.verbose = true;
+        }
+
+        if !matches.free.is_empty() {
+            config.filename = Some(matches.free[0].clone());
+        } else {
+            let header = format!("Usage: {} [options] graph.gbz", program);
+            eprint!("{}", opts.usage(&header));
+            process::exit(1);
+        }
+
+        Ok(config)
+    }
+}
+
+//-----------------------------------------------------------------------------
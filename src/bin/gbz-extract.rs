@@ -208,12 +208,7 @@ fn select_paths(gbz: &GBZ, metadata: &Metadata, config: &Config) -> Result<Vec<u
         return Err("Cannot select a contig without contig names".to_string());
     }
     let contig_id = metadata.contig_id(contig).ok_or(format!("The graph does not contain contig {}", contig))?;
-    let mut initial_paths: Vec<usize> = Vec::new();
-    for (path_id, path_name) in metadata.path_iter().enumerate() {
-        if path_name.contig() == contig_id {
-            initial_paths.push(path_id);
-        }
-    }
+    let initial_paths: Vec<usize> = metadata.paths_for_contig(contig_id).collect();
     if initial_paths.is_empty() {
         return Err(format!("The graph does not contain any paths for contig {}", contig));
     }
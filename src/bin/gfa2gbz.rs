@@ -0,0 +1,116 @@
+use gbwt::GBZ;
+use gbwt::{gfa, internal};
+
+use simple_sds::serialize::Serialize;
+use simple_sds::serialize;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Instant;
+use std::{env, process};
+
+use getopts::Options;
+
+//-----------------------------------------------------------------------------
+
+fn main() -> Result<(), String> {
+    let start = Instant::now();
+    let config = Config::new().map_err(|x| x.to_string())?;
+
+    let filename = config.filename.as_ref().unwrap();
+    if config.verbose {
+        eprintln!("Parsing GFA file {}", filename);
+    }
+    let file = File::open(filename).map_err(|x| x.to_string())?;
+    let gbz: GBZ = gfa::read_gfa(BufReader::new(file), config.max_node_len).map_err(|x| x.to_string())?;
+    if config.verbose {
+        eprintln!("Built a graph with {} nodes and {} paths", gbz.nodes(), gbz.paths());
+        let (size, units) = internal::readable_size(gbz.size_in_bytes());
+        eprintln!("GBZ size: {:.3} {}", size, units);
+        eprintln!("");
+    }
+
+    let output = config.output.as_ref().unwrap();
+    if config.verbose {
+        eprintln!("Writing GBZ graph {}", output);
+    }
+    serialize::serialize_to(&gbz, output).map_err(|x| x.to_string())?;
+
+    if config.verbose {
+        eprintln!("GBZ built in {:.3} seconds", start.elapsed().as_secs_f64());
+        internal::report_memory_usage();
+        eprintln!("");
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------------
+
+struct Config {
+    filename: Option<String>,
+    output: Option<String>,
+    max_node_len: usize,
+    verbose: bool,
+}
+
+impl Config {
+    pub fn new() -> Result<Config, String> {
+        let args: Vec<String> = env::args().collect();
+        let program = args[0].clone();
+
+        let mut opts = Options::new();
+        opts.optflag("h", "help", "print this help");
+        opts.optopt("m", "max-node", &format!("chop segments into nodes of at most INT bp (default {})", gfa::MAX_NODE_LENGTH), "INT");
+        opts.optopt("o", "output", "write the GBZ to FILE (default: input with .gbz extension)", "FILE");
+        opts.optflag("v", "verbose", "print progress information");
+        let matches = opts.parse(&args[1..]).map_err(|x| x.to_string())?;
+
+        let mut config = Config {
+            filename: None,
+            output: None,
+            max_node_len: gfa::MAX_NODE_LENGTH,
+            verbose: false,
+        };
+        if matches.opt_present("h") {
+            let header = format!("Usage: {} [options] graph.gfa", program);
+            eprint!("{}", opts.usage(&header));
+            process::exit(0);
+        }
+        if let Some(s) = matches.opt_str("m") {
+            match s.parse::<usize>() {
+                Ok(n) => {
+                    if n == 0 {
+                        return Err("--max-node: maximum node length must be > 0".to_string());
+                    }
+                    config.max_node_len = n;
+                },
+                Err(f) => {
+                    return Err(format!("--max-node: {}", f.to_string()));
+                },
+            }
+        }
+        if let Some(s) = matches.opt_str("o") {
+            config.output = Some(s);
+        }
+        if matches.opt_present("v") {
+            config.verbose = true;
+        }
+
+        if !matches.free.is_empty() {
+            let filename = matches.free[0].clone();
+            if config.output.is_none() {
+                let base = filename.strip_suffix(".gfa").unwrap_or(&filename);
+                config.output = Some(format!("{}.gbz", base));
+            }
+            config.filename = Some(filename);
+        } else {
+            let header = format!("Usage: {} [options] graph.gfa", program);
+            eprint!("{}", opts.usage(&header));
+            process::exit(1);
+        }
+
+        Ok(config)
+    }
+}
+
+//-----------------------------------------------------------------------------
@@ -39,7 +39,7 @@
 //! assert_eq!(ids, vec![0, 1, 2, 3, 4, 5, 6, 7]);
 //! ```
 
-use crate::support::{ByteCodeIter, Run, RLE, RLEIter};
+use crate::support::{ByteCodeIter, Run, RLE, RLEIter, SharedMap};
 use crate::ENDMARKER;
 use crate::support;
 
@@ -48,11 +48,13 @@ use simple_sds::ops::{BitVec, Select};
 use simple_sds::serialize::Serialize;
 
 use std::cmp::Ordering;
+use std::cmp;
 use std::convert::TryFrom;
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, Read};
 use std::iter::FusedIterator;
-use std::ops::Range;
-use std::io;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+use std::{fmt, io, mem};
 
 #[cfg(test)]
 mod tests;
@@ -93,10 +95,13 @@ impl From<(usize, usize)> for Pos {
 /// Record identifiers are characters in the effective alphabet `0..self.len()`, but they are not necessarily the same as the node identifiers.
 /// There may be empty records that do not correspond to any node in the graph.
 /// See module-level documentation for an example.
+///
+/// The byte vector can be stored in memory or borrowed from a shared read-only memory mapping.
+/// See [`BWT::load_mapped`].
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BWT {
     index: SparseVector,
-    data: Vec<u8>,
+    data: RecordData,
 }
 
 impl BWT {
@@ -142,6 +147,29 @@ impl BWT {
         Some((&bytes[..offset], &bytes[offset..]))
     }
 
+    /// Decodes the `i`th record without assuming that the encoding is valid.
+    ///
+    /// Returns `Ok(None)` if the record is empty, or [`crate::Error::InvalidData`] if the record does not exist or cannot be decoded.
+    /// The successors must be in sorted order, and the runs must refer to existing successors and end at the end of the record.
+    /// This is intended for validating untrusted data; use [`BWT::record`] for queries.
+    pub fn checked_record(&self, i: usize) -> Result<Option<DecompressedRecord>, crate::Error> {
+        let invalid = |msg: String| crate::Error::InvalidData(format!("BWT: Record {}: {}", i, msg));
+        if i >= self.len() {
+            return Err(invalid(String::from("Record does not exist")));
+        }
+        let mut iter = self.index.select_iter(i);
+        let start = iter.next().map(|(_, value)| value).ok_or_else(|| invalid(String::from("Record does not exist")))?;
+        let limit = if i + 1 < self.len() {
+            iter.next().map(|(_, value)| value).ok_or_else(|| invalid(String::from("Next record does not exist")))?
+        } else {
+            self.data.len()
+        };
+        if start > limit || limit > self.data.len() {
+            return Err(invalid(format!("Invalid byte range {}..{}", start, limit)));
+        }
+        check_record(i, &self.data[start..limit])
+    }
+
     /// Returns an iterator over the records in the BWT.
     ///
     /// Note that the iterator skips empty records.
@@ -160,6 +188,43 @@ impl BWT {
             next: 0,
         }
     }
+
+    /// Returns `true` if the records are borrowed from a memory mapping.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.data, RecordData::Mapped(_, _))
+    }
+}
+
+/// Memory mapping.
+impl BWT {
+    /// Loads the BWT from a reader over the bytes of a memory mapped file.
+    ///
+    /// The index is loaded into memory as usual, but the records are borrowed from the mapping.
+    /// The reader must be positioned at the start of a serialized BWT, and it will be positioned after the BWT.
+    pub fn load_mapped(reader: &mut io::Cursor<&[u8]>, map: &Arc<SharedMap>) -> io::Result<Self> {
+        let index = SparseVector::load(reader)?;
+
+        // A byte vector is serialized as its length followed by the bytes padded to a multiple of 8 bytes.
+        let mut buf = [0u8; mem::size_of::<u64>()];
+        reader.read_exact(&mut buf)?;
+        let len = u64::from_ne_bytes(buf) as usize;
+        let start = reader.position() as usize;
+        let padded = match len.checked_next_multiple_of(mem::size_of::<u64>()) {
+            Some(padded) if padded <= map.len().saturating_sub(start) => padded,
+            _ => return Err(Error::new(ErrorKind::UnexpectedEof, "BWT: Record data extends past the end of the file")),
+        };
+        reader.set_position((start + padded) as u64);
+
+        if index.len() != len {
+            return Err(Error::new(ErrorKind::InvalidData, "BWT: Index / data length mismatch"));
+        }
+        check_offsets(&index, len)?;
+        Ok(BWT {
+            index,
+            data: RecordData::Mapped(Arc::clone(map), start..start + len),
+        })
+    }
 }
 
 impl Serialize for BWT {
@@ -179,8 +244,10 @@ impl Serialize for BWT {
         if index.len() != data.len() {
             return Err(Error::new(ErrorKind::InvalidData, "BWT: Index / data length mismatch"));
         }
+        check_offsets(&index, data.len())?;
         Ok(BWT {
-            index, data,
+            index,
+            data: RecordData::Owned(data),
         })
     }
 
@@ -189,6 +256,143 @@ impl Serialize for BWT {
     }
 }
 
+// Decodes record `i` from the given bytes without assuming that the encoding is valid.
+// See [`BWT::checked_record`] for the checks.
+pub(crate) fn check_record(i: usize, bytes: &[u8]) -> Result<Option<DecompressedRecord>, crate::Error> {
+    let invalid = |msg: String| crate::Error::InvalidData(format!("BWT: Record {}: {}", i, msg));
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    // Edges.
+    let mut iter = ByteCodeIter::new(bytes);
+    let sigma = iter.next().ok_or_else(|| invalid(String::from("Truncated outdegree")))?;
+    if sigma == 0 {
+        return Ok(None);
+    }
+    let mut edges: Vec<Pos> = Vec::new();
+    let mut prev: usize = 0;
+    for rank in 0..sigma {
+        let delta = iter.next().ok_or_else(|| invalid(String::from("Truncated edge list")))?;
+        if rank > 0 && delta == 0 {
+            return Err(invalid(format!("Successor of rank {} is not in sorted order", rank)));
+        }
+        let node = prev.checked_add(delta).ok_or_else(|| invalid(format!("Successor of rank {} is too large", rank)))?;
+        prev = node;
+        let offset = iter.next().ok_or_else(|| invalid(String::from("Truncated edge list")))?;
+        edges.push(Pos::new(node, offset));
+    }
+
+    // Runs.
+    let bwt = &bytes[iter.offset()..];
+    let mut runs: Vec<Run> = Vec::new();
+    let mut starts: Vec<usize> = Vec::new();
+    let mut targets: Vec<Pos> = Vec::new();
+    let mut successor_runs: Vec<Vec<usize>> = vec![Vec::new(); sigma];
+    let mut next = edges.clone();
+    let mut offset: usize = 0;
+    let mut rle = RLEIter::with_sigma(bwt, sigma);
+    while rle.offset() < bwt.len() {
+        let run = rle.next().ok_or_else(|| invalid(format!("Truncated run at byte {}", rle.offset())))?;
+        if run.value >= sigma {
+            return Err(invalid(format!("Run refers to successor of rank {} with outdegree {}", run.value, sigma)));
+        }
+        successor_runs[run.value].push(runs.len());
+        runs.push(run);
+        starts.push(offset);
+        targets.push(next[run.value]);
+        next[run.value].offset = next[run.value].offset.checked_add(run.len).ok_or_else(|| invalid(String::from("Successor offset overflow")))?;
+        offset = offset.checked_add(run.len).ok_or_else(|| invalid(String::from("Record length overflow")))?;
+    }
+    if runs.is_empty() {
+        return Err(invalid(String::from("Non-empty record without runs")));
+    }
+
+    Ok(Some(DecompressedRecord {
+        id: i,
+        edges, runs, starts, targets, successor_runs,
+    }))
+}
+
+// Returns an error if the record offsets in the index are not sorted or not within the record data.
+pub(crate) fn check_offsets(index: &SparseVector, len: usize) -> io::Result<()> {
+    let mut prev = 0;
+    for (_, offset) in index.one_iter() {
+        if offset < prev || offset >= len {
+            return Err(Error::new(ErrorKind::InvalidData, "BWT: Invalid record offsets"));
+        }
+        prev = offset;
+    }
+    Ok(())
+}
+
+//-----------------------------------------------------------------------------
+
+// Concatenated node records, either in memory or in a shared memory mapping.
+#[derive(Clone)]
+enum RecordData {
+    Owned(Vec<u8>),
+    Mapped(Arc<SharedMap>, Range<usize>),
+}
+
+impl Deref for RecordData {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            RecordData::Owned(data) => data,
+            RecordData::Mapped(map, range) => &map.bytes()[range.clone()],
+        }
+    }
+}
+
+impl PartialEq for RecordData {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for RecordData {}
+
+impl fmt::Debug for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordData::Owned(data) => f.debug_tuple("Owned").field(&data.len()).finish(),
+            RecordData::Mapped(_, range) => f.debug_tuple("Mapped").field(range).finish(),
+        }
+    }
+}
+
+// Serialized in the same format as `Vec<u8>`, as the data is loaded as one.
+impl Serialize for RecordData {
+    fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        match self {
+            RecordData::Owned(data) => data.serialize_header(writer),
+            RecordData::Mapped(_, range) => writer.write_all(&(range.len() as u64).to_ne_bytes()),
+        }
+    }
+
+    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        match self {
+            RecordData::Owned(data) => data.serialize_body(writer),
+            RecordData::Mapped(_, _) => {
+                writer.write_all(self)?;
+                let padding = self.len().div_ceil(mem::size_of::<u64>()) * mem::size_of::<u64>() - self.len();
+                writer.write_all(&[0u8; mem::size_of::<u64>()][..padding])
+            },
+        }
+    }
+
+    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
+        let data = Vec::<u8>::load(reader)?;
+        Ok(RecordData::Owned(data))
+    }
+
+    fn size_in_elements(&self) -> usize {
+        1 + self.len().div_ceil(mem::size_of::<u64>())
+    }
+}
+
 impl From<BWTBuilder> for BWT {
     fn from(source: BWTBuilder) -> Self {
         let mut builder = SparseBuilder::new(source.encoder.len(), source.offsets.len()).unwrap();
@@ -197,7 +401,7 @@ impl From<BWTBuilder> for BWT {
         }
         BWT {
             index: SparseVector::try_from(builder).unwrap(),
-            data: Vec::<u8>::from(source.encoder),
+            data: RecordData::Owned(Vec::<u8>::from(source.encoder)),
         }
     }
 }
@@ -206,7 +410,7 @@ impl From<BWTBuilder> for BWT {
 
 /// A structure for building the BWT by appending node records.
 ///
-/// This is mostly inteded for testing at the moment, as no BWT construction algorithms have been implemented.
+/// The records must be given in order. See [`crate::gbwt::GBWTBuilder`] for building the BWT from paths.
 /// See module-level documentation for an example.
 #[derive(Clone, Debug, Default)]
 pub struct BWTBuilder {
@@ -328,6 +532,10 @@ impl<'a> FusedIterator for IdIter<'a> {}
 
 /// A partially decompressed node record.
 ///
+/// Only the edge list is decoded when the record is created.
+/// The runs are decoded in each query without assuming that they are valid, and the record ends at the first run that cannot be decoded or refers to a nonexistent successor.
+/// Hence the queries do not panic on malformed records, but they may return wrong answers.
+/// Use [`BWT::checked_record`] for validating the records.
 /// See module-level documentation for an example.
 #[derive(Clone, Debug)]
 pub struct Record<'a> {
@@ -338,6 +546,8 @@ pub struct Record<'a> {
 
 impl<'a> Record<'a> {
     /// Returns a record corresponding to the byte slice, or `None` if the record would be empty.
+    ///
+    /// Also returns `None` if the edge list cannot be decoded.
     pub fn new(id: usize, bytes: &'a [u8]) -> Option<Self> {
         if bytes.is_empty() {
             return None;
@@ -355,23 +565,20 @@ impl<'a> Record<'a> {
     /// Returns the list of edges and the slice offset after the adjacency
     /// list, or `None` if the list is empty.
     ///
-    /// # Panics
-    ///
-    /// May panic if slice does not encode an adjacency list or if the slice
-    /// ends early.
+    /// Also returns `None` if the slice does not encode an adjacency list or if the slice ends early.
     pub fn decompress_edges(bytes: &[u8]) -> Option<(Vec<Pos>, usize)> {
         let mut iter = ByteCodeIter::new(bytes);
-        let sigma = iter.next().unwrap();
+        let sigma = iter.next()?;
         if sigma == 0 {
             return None;
         }
 
         let mut edges: Vec<Pos> = Vec::new();
-        let mut prev = 0;
+        let mut prev: usize = 0;
         for _ in 0..sigma {
-            let node = iter.next().unwrap() + prev;
+            let node = iter.next()?.checked_add(prev)?;
             prev = node;
-            let offset = iter.next().unwrap();
+            let offset = iter.next()?;
             edges.push(Pos::new(node, offset));
         }
 
@@ -382,19 +589,26 @@ impl<'a> Record<'a> {
     // after the edge list.
     fn skip_edges(bytes: &[u8]) -> Option<usize> {
         let mut iter = ByteCodeIter::new(bytes);
-        let sigma = iter.next().unwrap();
+        let sigma = iter.next()?;
         if sigma == 0 {
             return None;
         }
 
         for _ in 0..sigma {
-            let _ = iter.next().unwrap();
-            let _ = iter.next().unwrap();
+            let _ = iter.next()?;
+            let _ = iter.next()?;
         }
 
         Some(iter.offset())
     }
 
+    // Returns an iterator over the runs.
+    // The iterator stops at the first run that refers to a nonexistent successor.
+    fn runs(&self) -> impl Iterator<Item = Run> + 'a {
+        let sigma = self.outdegree();
+        RLEIter::with_sigma(self.bwt, sigma).take_while(move |run| run.value < sigma)
+    }
+
     /// Returns the identifier of the record.
     pub fn id(&self) -> usize {
         self.id
@@ -429,11 +643,11 @@ impl<'a> Record<'a> {
     /// Returns the length of the offset range.
     ///
     /// This is somewhat slow, as it requires iterating over the run-lenght encoded BWT slice.
-    /// Note that the length is always non-zero.
+    /// Note that the length is always non-zero in a valid record.
     pub fn len(&self) -> usize {
-        let mut result = 0;
-        for run in RLEIter::with_sigma(self.bwt, self.edges.len()) {
-            result += run.len;
+        let mut result: usize = 0;
+        for run in self.runs() {
+            result = result.saturating_add(run.len);
         }
         result
     }
@@ -449,10 +663,10 @@ impl<'a> Record<'a> {
     pub fn decompress(&self) -> Vec<Pos> {
         let mut edges = self.edges.clone();
         let mut result: Vec<Pos> = Vec::new();
-        for run in RLEIter::with_sigma(self.bwt, self.edges.len()) {
+        for run in self.runs() {
             for _ in 0..run.len {
                 result.push(edges[run.value]);
-                edges[run.value].offset += 1;
+                edges[run.value].offset = edges[run.value].offset.saturating_add(1);
             }
         }
         result
@@ -463,17 +677,17 @@ impl<'a> Record<'a> {
     /// Returns [`None`] if the sequence ends or offset `i` does not exist.
     pub fn lf(&self, i: usize) -> Option<Pos> {
         let mut edges = self.edges.clone();
-        let mut offset = 0;
-        for run in RLEIter::with_sigma(self.bwt, self.edges.len()) {
-            if offset + run.len > i {
+        let mut offset: usize = 0;
+        for run in self.runs() {
+            if offset.saturating_add(run.len) > i {
                 if self.successor(run.value) == ENDMARKER {
                     return None;
                 } else {
-                    edges[run.value].offset += i - offset;
+                    edges[run.value].offset = edges[run.value].offset.checked_add(i - offset)?;
                     return Some(edges[run.value]);
                 }
             }
-            edges[run.value].offset += run.len;
+            edges[run.value].offset = edges[run.value].offset.saturating_add(run.len);
             offset += run.len;
         }
         None
@@ -489,8 +703,8 @@ impl<'a> Record<'a> {
         for rank in 0..self.edges.len() {
             edges.push(Pos::new(self.successor(rank), 0));
         }
-        for run in RLEIter::with_sigma(self.bwt, self.edges.len()) {
-            edges[run.value].offset += run.len;
+        for run in self.runs() {
+            edges[run.value].offset = edges[run.value].offset.saturating_add(run.len);
         }
 
         // Flip the successor nodes to make them the predecessors of the other orientation of this node.
@@ -509,9 +723,9 @@ impl<'a> Record<'a> {
         }
 
         // Find the predecessor, if it exists.
-        let mut offset = 0;
+        let mut offset: usize = 0;
         for edge in edges {
-            offset += edge.offset;
+            offset = offset.saturating_add(edge.offset);
             if offset > i {
                 if edge.node == ENDMARKER {
                     return None;
@@ -552,15 +766,15 @@ impl<'a> Record<'a> {
         }
 
         // Find the occurrence of `pos.0` of rank `pos.1 - succ_rank`.
-        let mut offset = 0;
-        for run in RLEIter::with_sigma(self.bwt, self.outdegree()) {
-            offset += run.len;
+        let mut offset: usize = 0;
+        for run in self.runs() {
+            offset = offset.saturating_add(run.len);
             if run.value != outrank {
                 continue;
             }
-            succ_rank += run.len;
+            succ_rank = succ_rank.saturating_add(run.len);
             if succ_rank > pos.offset {
-                return Some(offset - (succ_rank - pos.offset));
+                return offset.checked_sub(succ_rank - pos.offset);
             }
         }
 
@@ -583,14 +797,14 @@ impl<'a> Record<'a> {
         let rank = self.edge_to(node)?;
 
         let mut result = self.offset(rank)..self.offset(rank);
-        let mut offset = 0;
-        for run in RLEIter::with_sigma(self.bwt, self.outdegree()) {
+        let mut offset: usize = 0;
+        for run in self.runs() {
+            let run_range = offset..offset.saturating_add(run.len);
             if run.value == rank {
-                let run_range = offset..offset + run.len;
-                result.start += support::intersect(&run_range, &(0..range.start)).len();
-                result.end += support::intersect(&run_range, &(0..range.end)).len();
+                result.start = result.start.saturating_add(support::intersect(&run_range, &(0..range.start)).len());
+                result.end = result.end.saturating_add(support::intersect(&run_range, &(0..range.end)).len());
             }
-            offset += run.len;
+            offset = run_range.end;
             if offset >= range.end {
                 break;
             }
@@ -599,6 +813,41 @@ impl<'a> Record<'a> {
         if result.is_empty() { None } else { Some(result) }
     }
 
+    /// Follows all sequences in the offset ranges and returns the successor positions as (query range, node, offset range) triples.
+    ///
+    /// The query ranges must be sorted and disjoint, and the first component of each triple is the index of the query range.
+    /// Each returned range corresponds to the intersection of a run with a query range, and the ranges are in the order of the runs.
+    /// Ranges in the same successor node are disjoint, but they are not merged.
+    /// Sequences ending in the query ranges are reported with [`ENDMARKER`] as the node.
+    pub fn follow_ranges(&self, ranges: &[Range<usize>]) -> Vec<(usize, usize, Range<usize>)> {
+        let mut result: Vec<(usize, usize, Range<usize>)> = Vec::new();
+        let mut edges = self.edges.clone();
+        let mut offset: usize = 0;
+        let mut first = 0;
+        for run in self.runs() {
+            if first >= ranges.len() {
+                break;
+            }
+            let run_range = offset..offset.saturating_add(run.len);
+            while first < ranges.len() && ranges[first].end <= run_range.start {
+                first += 1;
+            }
+            for (i, range) in ranges.iter().enumerate().skip(first) {
+                if range.start >= run_range.end {
+                    break;
+                }
+                let overlap = support::intersect(&run_range, range);
+                if !overlap.is_empty() {
+                    let start = edges[run.value].offset.saturating_add(overlap.start - offset);
+                    result.push((i, edges[run.value].node, start..start.saturating_add(overlap.len())));
+                }
+            }
+            edges[run.value].offset = edges[run.value].offset.saturating_add(run.len);
+            offset = run_range.end;
+        }
+        result
+    }
+
     /// Follows all sequences in the offset range to the given node.
     ///
     /// This query assumes that the GBWT index is bidirectional.
@@ -620,17 +869,17 @@ impl<'a> Record<'a> {
 
         let mut result = self.offset(rank)..self.offset(rank);
         let mut count = 0;
-        let mut offset = 0;
-        for run in RLEIter::with_sigma(self.bwt, self.outdegree()) {
-            let run_range = offset..offset + run.len;
+        let mut offset: usize = 0;
+        for run in self.runs() {
+            let run_range = offset..offset.saturating_add(run.len);
             if run.value == rank {
-                result.start += support::intersect(&run_range, &(0..range.start)).len();
-                result.end += support::intersect(&run_range, &(0..range.end)).len();
+                result.start = result.start.saturating_add(support::intersect(&run_range, &(0..range.start)).len());
+                result.end = result.end.saturating_add(support::intersect(&run_range, &(0..range.end)).len());
             }
             if support::flip_node(self.successor(run.value)) < reverse {
                 count += support::intersect(&run_range, &range).len();
             }
-            offset += run.len;
+            offset = run_range.end;
             if offset >= range.end {
                 break;
             }
@@ -641,3 +890,340 @@ impl<'a> Record<'a> {
 }
 
 //-----------------------------------------------------------------------------
+
+/// A fully decompressed node record.
+///
+/// The edges and the runs are stored as vectors.
+/// This uses more space than [`Record`] but avoids decompressing the record again in each query.
+/// The queries have the same semantics as in [`Record`].
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::Pos;
+/// use gbwt::bwt::{BWT, BWTBuilder, DecompressedRecord};
+/// use gbwt::support::Run;
+///
+/// let mut builder = BWTBuilder::new();
+/// builder.append(&[Pos::new(1, 0)], &[Run::new(0, 3)]);
+/// builder.append(&[Pos::new(2, 0), Pos::new(3, 0)], &[Run::new(0, 2), Run::new(1, 1)]);
+/// let bwt = BWT::from(builder);
+///
+/// let record = DecompressedRecord::from(&bwt.record(1).unwrap());
+/// assert_eq!(record.id(), 1);
+/// assert_eq!(record.outdegree(), 2);
+/// assert_eq!(record.len(), 3);
+/// assert_eq!(record.lf(2), Some(Pos::new(3, 0)));
+/// assert_eq!(record.follow(0..3, 2), Some(0..2));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecompressedRecord {
+    id: usize,
+    edges: Vec<Pos>,
+    runs: Vec<Run>,
+    // Starting offset of each run.
+    starts: Vec<usize>,
+    // Successor position for the first offset of each run.
+    targets: Vec<Pos>,
+    // Indexes of the runs with each successor rank.
+    successor_runs: Vec<Vec<usize>>,
+}
+
+impl DecompressedRecord {
+    /// Returns the identifier of the record.
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns the outdegree of the node.
+    #[inline]
+    pub fn outdegree(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Returns the successor node of rank `i`.
+    ///
+    /// # Panics
+    ///
+    /// May panic if `i >= self.outdegree()`.
+    #[inline]
+    pub fn successor(&self, i: usize) -> usize {
+        self.edges[i].node
+    }
+
+    /// Returns the BWT offset in the node of rank `i`.
+    ///
+    /// # Panics
+    ///
+    /// May panic if `i >= self.outdegree()`.
+    #[inline]
+    pub fn offset(&self, i: usize) -> usize {
+        self.edges[i].offset
+    }
+
+    /// Returns the length of the offset range.
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self.runs.last() {
+            Some(run) => self.starts[self.starts.len() - 1] + run.len,
+            None => 0,
+        }
+    }
+
+    /// Returns `true` if the record is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the runs as (rank of the successor node, length) pairs.
+    #[inline]
+    pub fn runs(&self) -> &[Run] {
+        &self.runs
+    }
+
+    /// Decompress the record as a vector of successor positions.
+    pub fn decompress(&self) -> Vec<Pos> {
+        let mut result: Vec<Pos> = Vec::with_capacity(self.len());
+        for (run, target) in self.runs.iter().zip(self.targets.iter()) {
+            for i in 0..run.len {
+                result.push(Pos::new(target.node, target.offset + i));
+            }
+        }
+        result
+    }
+
+    /// Follows the sequence at offset `i` and returns the successor position.
+    ///
+    /// Returns [`None`] if the sequence ends or offset `i` does not exist.
+    pub fn lf(&self, i: usize) -> Option<Pos> {
+        if i >= self.len() {
+            return None;
+        }
+        let run = self.starts.partition_point(|start| *start <= i) - 1;
+        let target = self.targets[run];
+        if target.node == ENDMARKER {
+            None
+        } else {
+            Some(Pos::new(target.node, target.offset + (i - self.starts[run])))
+        }
+    }
+
+    /// Returns the predecessor node for the sequence at offset `i` in the other orientation of this node.
+    ///
+    /// This query assumes that the GBWT index is bidirectional.
+    /// Returns [`None`] if the predecessor or the offset does not exist.
+    pub fn predecessor_at(&self, i: usize) -> Option<usize> {
+        // Determine the number of sequences going to each successor node.
+        let mut edges: Vec<Pos> = self.edges.iter().map(|edge| Pos::new(edge.node, 0)).collect();
+        for run in self.runs.iter() {
+            edges[run.value].offset += run.len;
+        }
+
+        // Flip the successor nodes to make them the predecessors of the other orientation of this node.
+        for edge in &mut edges {
+            if edge.node != ENDMARKER {
+                edge.node = support::flip_node(edge.node);
+            }
+        }
+
+        // Handle the special case where the predecessors are now in wrong order because they contain
+        // both orientations of the same node.
+        for rank in 1..edges.len() {
+            if support::node_id(edges[rank - 1].node) == support::node_id(edges[rank].node) {
+                edges.swap(rank - 1, rank);
+            }
+        }
+
+        // Find the predecessor, if it exists.
+        let mut offset = 0;
+        for edge in edges {
+            offset += edge.offset;
+            if offset > i {
+                if edge.node == ENDMARKER {
+                    return None;
+                }
+                return Some(edge.node);
+            }
+        }
+
+        None
+    }
+
+    // Returns the rank of the edge to the given node.
+    fn edge_to(&self, node: usize) -> Option<usize> {
+        self.edges.binary_search_by_key(&node, |edge| edge.node).ok()
+    }
+
+    /// Returns the offset for which [`Self::lf`] would return `pos`, or [`None`] if no such offset exists.
+    pub fn offset_to(&self, pos: Pos) -> Option<usize> {
+        if pos.node == ENDMARKER {
+            return None;
+        }
+        let rank = self.edge_to(pos.node)?;
+        for ((run, start), target) in self.runs.iter().zip(self.starts.iter()).zip(self.targets.iter()) {
+            if run.value == rank && target.offset <= pos.offset && pos.offset < target.offset + run.len {
+                return Some(start + (pos.offset - target.offset));
+            }
+        }
+        None
+    }
+
+    // Returns the rank of the successor of rank `rank` at offset `i`, or the total number of occurrences of that successor if `i >= self.len()`.
+    // The target of each run is the rank at the start of the run, so we only need the last run with that successor before offset `i`.
+    fn rank_at(&self, rank: usize, i: usize) -> usize {
+        let runs = &self.successor_runs[rank];
+        let count = runs.partition_point(|run| self.starts[*run] < i);
+        if count == 0 {
+            return self.offset(rank);
+        }
+        let run = runs[count - 1];
+        let run_end = self.starts[run].saturating_add(self.runs[run].len);
+        self.targets[run].offset.saturating_add(cmp::min(i, run_end) - self.starts[run])
+    }
+
+    /// Follows all sequences in the offset range to the given node.
+    ///
+    /// Returns a semiopen offset range in the destination node, or [`None`] if no such sequences exist.
+    /// See also [`DecompressedRecord::bd_follow`].
+    ///
+    /// # Arguments
+    ///
+    /// * `range`: Offset range in the record.
+    /// * `node`: Destination node.
+    pub fn follow(&self, range: Range<usize>, node: usize) -> Option<Range<usize>> {
+        if range.is_empty() || node == ENDMARKER {
+            return None;
+        }
+        let rank = self.edge_to(node)?;
+        let result = self.rank_at(rank, range.start)..self.rank_at(rank, range.end);
+        if result.is_empty() { None } else { Some(result) }
+    }
+
+    /// Follows all sequences in the offset range to the given node.
+    ///
+    /// This query assumes that the GBWT index is bidirectional.
+    /// Returns a semiopen offset range in the destination node, or [`None`] if no such sequences exist.
+    /// The second return value is the number of occurrences of nodes `v` in the query range such that [`support::flip_node`]`(v) < `[`support::flip_node`]`(node)`.
+    /// See also [`DecompressedRecord::follow`].
+    ///
+    /// # Arguments
+    ///
+    /// * `range`: Offset range in the record.
+    /// * `node`: Destination node.
+    pub fn bd_follow(&self, range: Range<usize>, node: usize) -> Option<(Range<usize>, usize)> {
+        let result = self.follow(range.clone(), node)?;
+        let reverse = support::flip_node(node);
+
+        let mut count = 0;
+        let first = self.starts.partition_point(|start| *start <= range.start) - 1;
+        for run in first..self.runs.len() {
+            if self.starts[run] >= range.end {
+                break;
+            }
+            if support::flip_node(self.successor(self.runs[run].value)) < reverse {
+                let run_range = self.starts[run]..self.starts[run] + self.runs[run].len;
+                count += support::intersect(&run_range, &range).len();
+            }
+        }
+
+        Some((result, count))
+    }
+}
+
+impl<'a> From<&Record<'a>> for DecompressedRecord {
+    fn from(record: &Record<'a>) -> Self {
+        let mut runs: Vec<Run> = Vec::new();
+        let mut starts: Vec<usize> = Vec::new();
+        let mut targets: Vec<Pos> = Vec::new();
+        let mut successor_runs: Vec<Vec<usize>> = vec![Vec::new(); record.edges.len()];
+        let mut edges = record.edges.clone();
+        let mut offset: usize = 0;
+        for run in record.runs() {
+            successor_runs[run.value].push(runs.len());
+            runs.push(run);
+            starts.push(offset);
+            targets.push(edges[run.value]);
+            edges[run.value].offset = edges[run.value].offset.saturating_add(run.len);
+            offset = offset.saturating_add(run.len);
+        }
+        DecompressedRecord {
+            id: record.id,
+            edges: record.edges.clone(),
+            runs, starts, targets, successor_runs,
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// The queries shared by `Record` and `DecompressedRecord`.
+// This allows implementing the navigation and search queries once for all sources of records.
+pub(crate) trait NodeRecord {
+    fn len(&self) -> usize;
+
+    fn lf(&self, i: usize) -> Option<Pos>;
+
+    fn predecessor_at(&self, i: usize) -> Option<usize>;
+
+    fn offset_to(&self, pos: Pos) -> Option<usize>;
+
+    fn follow(&self, range: Range<usize>, node: usize) -> Option<Range<usize>>;
+
+    fn bd_follow(&self, range: Range<usize>, node: usize) -> Option<(Range<usize>, usize)>;
+}
+
+impl<'a> NodeRecord for Record<'a> {
+    fn len(&self) -> usize {
+        Record::len(self)
+    }
+
+    fn lf(&self, i: usize) -> Option<Pos> {
+        Record::lf(self, i)
+    }
+
+    fn predecessor_at(&self, i: usize) -> Option<usize> {
+        Record::predecessor_at(self, i)
+    }
+
+    fn offset_to(&self, pos: Pos) -> Option<usize> {
+        Record::offset_to(self, pos)
+    }
+
+    fn follow(&self, range: Range<usize>, node: usize) -> Option<Range<usize>> {
+        Record::follow(self, range, node)
+    }
+
+    fn bd_follow(&self, range: Range<usize>, node: usize) -> Option<(Range<usize>, usize)> {
+        Record::bd_follow(self, range, node)
+    }
+}
+
+impl NodeRecord for DecompressedRecord {
+    fn len(&self) -> usize {
+        DecompressedRecord::len(self)
+    }
+
+    fn lf(&self, i: usize) -> Option<Pos> {
+        DecompressedRecord::lf(self, i)
+    }
+
+    fn predecessor_at(&self, i: usize) -> Option<usize> {
+        DecompressedRecord::predecessor_at(self, i)
+    }
+
+    fn offset_to(&self, pos: Pos) -> Option<usize> {
+        DecompressedRecord::offset_to(self, pos)
+    }
+
+    fn follow(&self, range: Range<usize>, node: usize) -> Option<Range<usize>> {
+        DecompressedRecord::follow(self, range, node)
+    }
+
+    fn bd_follow(&self, range: Range<usize>, node: usize) -> Option<(Range<usize>, usize)> {
+        DecompressedRecord::bd_follow(self, range, node)
+    }
+}
+
+//-----------------------------------------------------------------------------
@@ -111,6 +111,7 @@ fn check_records(bwt: &BWT, edges: &[Vec<Pos>]) {
         let record = bwt.record(i);
         let curr_edges = &edges[i];
         assert_eq!(record.is_none(), curr_edges.is_empty(), "Invalid record {} existence", i);
+        assert_eq!(bwt.checked_record(i).ok(), Some(record.as_ref().map(DecompressedRecord::from)), "Invalid checked record {}", i);
         if let Some(record) = record {
             assert_eq!(record.id(), i, "Invalid id for record {}", i);
             assert_eq!(record.outdegree(), curr_edges.len(), "Invalid outdegree in record {}", i);
@@ -236,6 +237,35 @@ fn check_follow(bwt: &BWT, invalid_node: usize) {
     }
 }
 
+// Check `follow_ranges()` using `decompress()` as the source of truth.
+fn check_follow_ranges(bwt: &BWT) {
+    for record in bwt.iter() {
+        let i = record.id();
+        let successors = record.decompress();
+        let len = successors.len();
+        // Single ranges, including empty and past-the-end ones, and pairs of ranges with a gap.
+        for start in 0..len + 1 {
+            for limit in start..len + 2 {
+                let mut queries = vec![vec![start..limit]];
+                if limit + 1 < len {
+                    queries.push(vec![start..limit, limit + 1..len]);
+                }
+                for ranges in queries {
+                    let truth: Vec<(usize, Pos)> = ranges.iter().enumerate()
+                        .flat_map(|(k, range)| range.clone().map(move |j| (k, j)))
+                        .filter(|(_, j)| *j < len)
+                        .map(|(k, j)| (k, successors[j]))
+                        .collect();
+                    let result: Vec<(usize, Pos)> = record.follow_ranges(&ranges).into_iter()
+                        .flat_map(|(k, node, range)| range.map(move |offset| (k, Pos::new(node, offset))))
+                        .collect();
+                    assert_eq!(result, truth, "Invalid follow_ranges({:?}) in record {}", ranges, i);
+                }
+            }
+        }
+    }
+}
+
 // Check negative cases for `offset_to()`.
 fn negative_offset_to(bwt: &BWT, invalid_node: usize) {
     for record in bwt.iter() {
@@ -282,6 +312,46 @@ fn check_predecessor_at(bwt: &BWT) {
     }
 }
 
+// Check that `DecompressedRecord` gives the same results as `Record`.
+fn check_decompressed(bwt: &BWT, invalid_node: usize) {
+    for record in bwt.iter() {
+        let i = record.id();
+        let decompressed = DecompressedRecord::from(&record);
+        assert_eq!(decompressed.id(), i, "Invalid id for decompressed record {}", i);
+        assert_eq!(decompressed.outdegree(), record.outdegree(), "Invalid outdegree for decompressed record {}", i);
+        for rank in 0..record.outdegree() {
+            assert_eq!(decompressed.successor(rank), record.successor(rank), "Invalid successor {} for decompressed record {}", rank, i);
+            assert_eq!(decompressed.offset(rank), record.offset(rank), "Invalid offset {} for decompressed record {}", rank, i);
+        }
+        let len = record.len();
+        assert_eq!(decompressed.len(), len, "Invalid length for decompressed record {}", i);
+        assert_eq!(decompressed.decompress(), record.decompress(), "Invalid decompression for decompressed record {}", i);
+
+        for offset in 0..len + 1 {
+            assert_eq!(decompressed.lf(offset), record.lf(offset), "Invalid lf({}) in decompressed record {}", offset, i);
+            assert_eq!(decompressed.predecessor_at(offset), record.predecessor_at(offset), "Invalid predecessor_at({}) in decompressed record {}", offset, i);
+        }
+        for rank in 0..record.outdegree() {
+            for offset in 0..len + 2 {
+                let pos = Pos::new(record.successor(rank), offset);
+                assert_eq!(decompressed.offset_to(pos), record.offset_to(pos), "Invalid offset_to(({}, {})) in decompressed record {}", pos.node, pos.offset, i);
+            }
+        }
+
+        let mut nodes: Vec<usize> = (0..record.outdegree()).map(|rank| record.successor(rank)).collect();
+        nodes.push(ENDMARKER);
+        nodes.push(invalid_node);
+        for start in 0..len + 1 {
+            for limit in start..len + 1 {
+                for node in nodes.iter() {
+                    assert_eq!(decompressed.follow(start..limit, *node), record.follow(start..limit, *node), "Invalid follow({}..{}, {}) in decompressed record {}", start, limit, node, i);
+                    assert_eq!(decompressed.bd_follow(start..limit, *node), record.bd_follow(start..limit, *node), "Invalid bd_follow({}..{}, {}) in decompressed record {}", start, limit, node, i);
+                }
+            }
+        }
+    }
+}
+
 //-----------------------------------------------------------------------------
 
 #[test]
@@ -294,7 +364,9 @@ fn empty_bwt() {
     check_iter(&bwt);
     check_lf(&bwt, &edges, &runs);
     check_follow(&bwt, invalid_node);
+    check_follow_ranges(&bwt);
     negative_offset_to(&bwt, invalid_node);
+    check_decompressed(&bwt, invalid_node);
     serialize::test(&bwt, "empty-bwt", None, true);
 }
 
@@ -306,7 +378,9 @@ fn non_empty_bwt() {
     check_iter(&bwt);
     check_lf(&bwt, &edges, &runs);
     check_follow(&bwt, invalid_node);
+    check_follow_ranges(&bwt);
     negative_offset_to(&bwt, invalid_node);
+    check_decompressed(&bwt, invalid_node);
     serialize::test(&bwt, "non-empty-bwt", None, true);
 }
 
@@ -323,7 +397,9 @@ fn empty_records() {
     check_iter(&bwt);
     check_lf(&bwt, &edges, &runs);
     check_follow(&bwt, invalid_node);
+    check_follow_ranges(&bwt);
     negative_offset_to(&bwt, invalid_node);
+    check_decompressed(&bwt, invalid_node);
     serialize::test(&bwt, "bwt-with-empty", None, true);
 }
 
@@ -335,9 +411,88 @@ fn bidirectional_bwt() {
     check_iter(&bwt);
     check_lf(&bwt, &edges, &runs);
     check_follow(&bwt, invalid_node);
+    check_follow_ranges(&bwt);
     negative_offset_to(&bwt, invalid_node);
     check_predecessor_at(&bwt);
+    check_decompressed(&bwt, invalid_node);
     serialize::test(&bwt, "bidirectional-bwt", None, true);
 }
 
 //-----------------------------------------------------------------------------
+
+// Creates a BWT from the given record encodings without checking them.
+fn raw_bwt(records: &[Vec<u8>]) -> BWT {
+    let mut offsets: Vec<usize> = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    for record in records {
+        offsets.push(data.len());
+        data.extend_from_slice(record);
+    }
+    let mut builder = SparseBuilder::new(data.len(), offsets.len()).unwrap();
+    for offset in offsets {
+        unsafe { builder.set_unchecked(offset); }
+    }
+    BWT {
+        index: SparseVector::try_from(builder).unwrap(),
+        data: RecordData::Owned(data),
+    }
+}
+
+#[test]
+fn checked_records() {
+    let records = vec![
+        vec![0], // Empty record.
+        vec![1, 1, 0, 2], // Edge (1, 0) and run (0, 3).
+        vec![2, 1, 0, 0, 0, 0], // Successors are not sorted.
+        vec![2, 1, 0], // Truncated edge list.
+        vec![1, 1, 0], // No runs.
+        vec![2, 1, 0, 1, 0, 254], // Truncated run.
+    ];
+    let bwt = raw_bwt(&records);
+
+    assert_eq!(bwt.checked_record(0).ok(), Some(None), "Invalid empty record");
+    let record = bwt.checked_record(1).unwrap().unwrap();
+    assert_eq!(record.outdegree(), 1, "Invalid outdegree in a valid record");
+    assert_eq!(record.successor(0), 1, "Invalid successor in a valid record");
+    assert_eq!(record.len(), 3, "Invalid length for a valid record");
+    for i in 2..records.len() {
+        assert!(matches!(bwt.checked_record(i), Err(crate::Error::InvalidData(_))), "Decoded invalid record {}", i);
+    }
+    assert!(bwt.checked_record(records.len()).is_err(), "Decoded a nonexistent record");
+}
+
+#[test]
+fn malformed_records() {
+    // Edge (1, 0) and two runs of length 256 + (2^63 - 1) that overflow the length of the record.
+    let mut overflow: Vec<u8> = vec![1, 1, 0];
+    for _ in 0..2 {
+        overflow.push(255);
+        overflow.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    // Edges (1, 0) to (255, 0) and run (300, 1) to a nonexistent successor.
+    let mut nonexistent: Vec<u8> = vec![0xFF, 0x01];
+    for _ in 0..255 {
+        nonexistent.extend_from_slice(&[1, 0]);
+    }
+    nonexistent.extend_from_slice(&[0xAC, 0x02, 0]);
+
+    // The queries must not panic, even though the answers are meaningless.
+    let bwt = raw_bwt(&[overflow, nonexistent]);
+    for i in 0..bwt.len() {
+        assert!(bwt.checked_record(i).is_err(), "Decoded malformed record {}", i);
+        let record = bwt.record(i).unwrap();
+        let len = record.len();
+        let _ = DecompressedRecord::from(&record);
+        for offset in [0, 255, 256, len / 2, len.saturating_sub(1), len, usize::MAX] {
+            let _ = record.lf(offset);
+            let _ = record.predecessor_at(offset);
+            let _ = record.offset_to(Pos::new(1, offset));
+            let _ = record.follow(offset..usize::MAX, 1);
+            let _ = record.bd_follow(0..offset, 1);
+        }
+        let _ = record.follow_ranges(&[0..1, 256..len, len..usize::MAX]);
+    }
+}
+
+//-----------------------------------------------------------------------------
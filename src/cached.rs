@@ -0,0 +1,517 @@
+//! A GBWT wrapper that caches decompressed records.
+//!
+//! Queries in [`GBWT`] decompress the relevant node records each time.
+//! [`CachedGBWT`] stores decompressed records for recently used nodes and for explicitly pinned nodes.
+//! The cache uses interior mutability and is not thread-safe.
+//! Each thread should create its own [`CachedGBWT`] over a shared [`GBWT`].
+//!
+//! # Examples
+//!
+//! ```
+//! use gbwt::{GBWT, Orientation};
+//! use gbwt::cached::CachedGBWT;
+//! use gbwt::support;
+//! use simple_sds::serialize;
+//!
+//! let filename = support::get_test_data("example.gbwt");
+//! let index: GBWT = serialize::load_from(&filename).unwrap();
+//! let cached = CachedGBWT::new(&index);
+//!
+//! // Extract path 3 in reverse orientation.
+//! let path: Vec<usize> = cached.sequence(support::encode_path(3, Orientation::Reverse)).unwrap().collect();
+//! assert_eq!(path, vec![35, 33, 29, 27, 23]);
+//! assert_eq!(cached.cached_records(), 5);
+//!
+//! // Subpath search.
+//! let state = cached.find(support::encode_node(12, Orientation::Forward)).unwrap();
+//! let state = cached.extend(&state, support::encode_node(14, Orientation::Forward)).unwrap();
+//! assert_eq!(state.len(), 2);
+//! ```
+
+use crate::{GBWT, Pos, SearchState, BidirectionalState};
+use crate::bwt::{BWT, DecompressedRecord};
+use crate::gbwt::RecordSource;
+use crate::support::LruCache;
+use crate::support;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::iter::FusedIterator;
+use std::ops::Range;
+use std::rc::Rc;
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// A wrapper over [`GBWT`] that caches decompressed node records.
+///
+/// The cache holds at most `capacity` recently used records.
+/// When the cache is full, the least recently used record is evicted.
+/// Pinned records are stored separately and never evicted.
+///
+/// The wrapper provides the same sequence navigation, subpath search, matching, and locate queries as [`GBWT`].
+/// Sequence navigation uses the sequence samples of the underlying index if they are present.
+/// See module-level documentation for an example.
+#[derive(Clone, Debug)]
+pub struct CachedGBWT<'a> {
+    parent: &'a GBWT,
+    pinned: HashMap<usize, Rc<DecompressedRecord>>,
+    cache: RefCell<LruCache<usize, Rc<DecompressedRecord>>>,
+}
+
+/// Construction and cache management.
+impl<'a> CachedGBWT<'a> {
+    /// Default number of records in the cache.
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// Returns a new cached GBWT over the given index with the default capacity.
+    pub fn new(parent: &'a GBWT) -> Self {
+        Self::with_capacity(parent, Self::DEFAULT_CAPACITY)
+    }
+
+    /// Returns a new cached GBWT over the given index with the given capacity.
+    ///
+    /// Capacity `0` disables the cache for records that have not been pinned.
+    pub fn with_capacity(parent: &'a GBWT, capacity: usize) -> Self {
+        CachedGBWT {
+            parent,
+            pinned: HashMap::new(),
+            cache: RefCell::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns the underlying index.
+    #[inline]
+    pub fn index(&self) -> &'a GBWT {
+        self.parent
+    }
+
+    /// Returns the maximum number of records in the cache, excluding pinned records.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.cache.borrow().capacity()
+    }
+
+    /// Returns the number of records currently in the cache, excluding pinned records.
+    pub fn cached_records(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    /// Returns the number of pinned records.
+    pub fn pinned_records(&self) -> usize {
+        self.pinned.len()
+    }
+
+    /// Pins the record for the given node, keeping it decompressed until it is unpinned.
+    ///
+    /// Returns `false` if there is no such node or the record is empty.
+    pub fn pin(&mut self, node: usize) -> bool {
+        if !self.has_node(node) {
+            return false;
+        }
+        let record_id = self.parent.node_to_record(node);
+        if self.pinned.contains_key(&record_id) {
+            return true;
+        }
+        let record = self.cache.get_mut().remove(&record_id);
+        match record.or_else(|| self.decompress(record_id)) {
+            Some(record) => {
+                self.pinned.insert(record_id, record);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Unpins the record for the given node.
+    ///
+    /// Returns `false` if the record was not pinned.
+    pub fn unpin(&mut self, node: usize) -> bool {
+        if !self.has_node(node) {
+            return false;
+        }
+        self.pinned.remove(&self.parent.node_to_record(node)).is_some()
+    }
+
+    /// Removes all records from the cache, excluding pinned records.
+    pub fn clear_cache(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    // Decompresses the given record from the parent, or returns `None` if the record is empty.
+    fn decompress(&self, record_id: usize) -> Option<Rc<DecompressedRecord>> {
+        let bwt: &BWT = self.parent.as_ref();
+        let record = bwt.record(record_id)?;
+        Some(Rc::new(DecompressedRecord::from(&record)))
+    }
+
+    /// Returns the decompressed record for the given node, or [`None`] if there is no such node or the record is empty.
+    ///
+    /// Records that are not pinned are cached.
+    pub fn record(&self, node: usize) -> Option<Rc<DecompressedRecord>> {
+        if !self.has_node(node) {
+            return None;
+        }
+        self.get(self.parent.node_to_record(node))
+    }
+
+    // Returns the decompressed record from the pinned records or the cache, or decompresses and caches it.
+    fn get(&self, record_id: usize) -> Option<Rc<DecompressedRecord>> {
+        if let Some(record) = self.pinned.get(&record_id) {
+            return Some(Rc::clone(record));
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        if let Some(record) = cache.get(&record_id) {
+            return Some(Rc::clone(record));
+        }
+
+        let record = self.decompress(record_id)?;
+        cache.insert(record_id, Rc::clone(&record));
+        Some(record)
+    }
+}
+
+/// Index statistics.
+impl<'a> CachedGBWT<'a> {
+    /// Returns the number of sequences in the index.
+    #[inline]
+    pub fn sequences(&self) -> usize {
+        self.parent.sequences()
+    }
+
+    /// Returns `true` if the index contains the given node.
+    #[inline]
+    pub fn has_node(&self, id: usize) -> bool {
+        self.parent.has_node(id)
+    }
+
+    /// Returns `true` if the index is bidirectional.
+    #[inline]
+    pub fn is_bidirectional(&self) -> bool {
+        self.parent.is_bidirectional()
+    }
+}
+
+impl<'a> RecordSource for CachedGBWT<'a> {
+    type Record<'b> = DecompressedRecord;
+
+    fn alphabet_size(&self) -> usize {
+        self.parent.alphabet_size()
+    }
+
+    fn alphabet_offset(&self) -> usize {
+        self.parent.alphabet_offset()
+    }
+
+    fn is_bidirectional(&self) -> bool {
+        self.parent.is_bidirectional()
+    }
+
+    fn with_record<T, F: for<'b> FnOnce(&Self::Record<'b>) -> Option<T>>(&self, node: usize, f: F) -> Option<T> {
+        let record = self.get(self.record_id(node)?)?;
+        f(record.as_ref())
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Sequence navigation.
+impl<'a> CachedGBWT<'a> {
+    /// Returns the first position in sequence `id`.
+    ///
+    /// The return value is [`None`] if no such sequence exists or the sequence is empty.
+    #[inline]
+    pub fn start(&self, id: usize) -> Option<Pos> {
+        self.parent.start(id)
+    }
+
+    /// Follows the sequence forward and returns the next position, or [`None`] if no such position exists.
+    pub fn forward(&self, pos: Pos) -> Option<Pos> {
+        RecordSource::forward(self, pos)
+    }
+
+    /// Follows the sequence backward and returns the previous position, or [`None`] if no such position exists.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not bidirectional.
+    pub fn backward(&self, pos: Pos) -> Option<Pos> {
+        RecordSource::backward(self, pos)
+    }
+
+    /// Returns an iterator over sequence `id`, or [`None`] if there is no such sequence.
+    pub fn sequence(&self, id: usize) -> Option<CachedSequenceIter<'_, 'a>> {
+        if id >= self.sequences() {
+            return None;
+        }
+        Some(CachedSequenceIter {
+            parent: self,
+            next: self.start(id),
+            remaining: self.parent.len(),
+        })
+    }
+
+    /// Returns an iterator over sequence `id` in reverse order, or [`None`] if there is no such sequence.
+    ///
+    /// See [`GBWT::sequence_rev`] for details.
+    pub fn sequence_rev(&self, id: usize) -> Option<CachedReverseSequenceIter<'_, 'a>> {
+        if id >= self.sequences() {
+            return None;
+        }
+        if self.is_bidirectional() {
+            return Some(CachedReverseSequenceIter {
+                reverse: self.sequence(support::flip_path(id)),
+                buffer: Vec::new(),
+            });
+        }
+        let buffer: Vec<usize> = self.sequence(id)?.collect();
+        Some(CachedReverseSequenceIter {
+            reverse: None,
+            buffer,
+        })
+    }
+
+    /// Returns the position at offset `offset` of sequence `id`, or [`None`] if there is no such position.
+    ///
+    /// See [`GBWT::position_at`] for details.
+    pub fn position_at(&self, id: usize, offset: usize) -> Option<Pos> {
+        let (mut pos, mut remaining) = match self.parent.sequence_samples() {
+            Some(samples) => samples.sample_before(id, offset)?,
+            None => (self.start(id)?, offset),
+        };
+        // No sequence is longer than the index, even if a malformed index contains a cycle.
+        if remaining >= self.parent.len() {
+            return None;
+        }
+        while remaining > 0 {
+            pos = self.forward(pos)?;
+            remaining -= 1;
+        }
+        Some(pos)
+    }
+
+    /// Returns an iterator over sequence `id` starting from offset `offset`, or [`None`] if there is no such sequence.
+    ///
+    /// See [`GBWT::sequence_from`] for details.
+    pub fn sequence_from(&self, id: usize, offset: usize) -> Option<CachedSequenceIter<'_, 'a>> {
+        if id >= self.sequences() {
+            return None;
+        }
+        Some(CachedSequenceIter {
+            parent: self,
+            next: self.position_at(id, offset),
+            remaining: self.parent.len(),
+        })
+    }
+
+    /// Returns the length of sequence `id`, or [`None`] if there is no such sequence.
+    ///
+    /// See [`GBWT::sequence_len`] for details.
+    pub fn sequence_len(&self, id: usize) -> Option<usize> {
+        if id >= self.sequences() {
+            return None;
+        }
+        match self.parent.sequence_samples() {
+            Some(samples) => Some(samples.sequence_len(id)),
+            None => Some(self.sequence(id)?.count()),
+        }
+    }
+
+    /// Extracts the given offset range of sequence `id` as a vector of nodes.
+    ///
+    /// See [`GBWT::extract`] for details.
+    pub fn extract(&self, id: usize, range: Range<usize>) -> Vec<usize> {
+        match self.sequence_from(id, range.start) {
+            Some(iter) => iter.take(range.len()).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Subpath search.
+impl<'a> CachedGBWT<'a> {
+    /// Returns a search state for all occurrences of the given node, or [`None`] if no such node exists.
+    pub fn find(&self, node: usize) -> Option<SearchState> {
+        RecordSource::find(self, node)
+    }
+
+    /// Extends the search by the given node forward and returns the new search state, or [`None`] if no such extensions exist.
+    ///
+    /// See [`GBWT::extend`] for details.
+    pub fn extend(&self, state: &SearchState, node: usize) -> Option<SearchState> {
+        RecordSource::extend(self, state, node)
+    }
+
+    /// Returns a bidirectional search state for all occurrences of the given node, or [`None`] if no such node exists.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn bd_find(&self, node: usize) -> Option<BidirectionalState> {
+        RecordSource::bd_find(self, node)
+    }
+
+    /// Extends the search by the given node forward and returns the new search state, or [`None`] if no such extensions exist.
+    ///
+    /// See [`GBWT::extend_forward`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn extend_forward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+        RecordSource::extend_forward(self, state, node)
+    }
+
+    /// Extends the search by the given node backward and returns the new search state, or [`None`] if no such extensions exist.
+    ///
+    /// See [`GBWT::extend_backward`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn extend_backward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+        RecordSource::extend_backward(self, state, node)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Matching queries.
+impl<'a> CachedGBWT<'a> {
+    /// Returns the longest suffix of the query that occurs in the index as a range of query offsets and a search state, or [`None`] if no such suffix exists.
+    ///
+    /// See [`GBWT::longest_match`] for details.
+    pub fn longest_match(&self, query: &[usize]) -> Option<(Range<usize>, SearchState)> {
+        RecordSource::longest_match(self, query)
+    }
+
+    /// Returns all maximal exact matches between the query and the index.
+    ///
+    /// See [`GBWT::maximal_matches`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn maximal_matches(&self, query: &[usize]) -> Vec<(Range<usize>, SearchState)> {
+        RecordSource::maximal_matches(self, query)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Locate queries.
+///
+/// These are the same as in [`GBWT`], as locating a position rarely visits the same record twice.
+impl<'a> CachedGBWT<'a> {
+    /// Returns the identifier of the sequence containing the given position, or [`None`] if there is no such position.
+    ///
+    /// This is the same as [`GBWT::locate_pos`], as locating a position rarely visits the same record twice.
+    #[inline]
+    pub fn locate_pos(&self, pos: Pos) -> Option<usize> {
+        self.parent.locate_pos(pos)
+    }
+
+    /// Returns the identifiers of the sequences containing the occurrences in the search state.
+    ///
+    /// This is the same as [`GBWT::locate`].
+    #[inline]
+    pub fn locate(&self, state: &SearchState) -> Vec<usize> {
+        self.parent.locate(state)
+    }
+
+    /// Returns the distinct sequences containing the occurrences in the search state as (sequence identifier, number of occurrences) pairs.
+    ///
+    /// This is the same as [`GBWT::distinct_sequences`].
+    #[inline]
+    pub fn distinct_sequences(&self, state: &SearchState) -> Vec<(usize, usize)> {
+        self.parent.distinct_sequences(state)
+    }
+
+    /// Returns the distinct paths containing the occurrences in the search state as (path identifier, number of occurrences) pairs.
+    ///
+    /// This is the same as [`GBWT::distinct_paths`].
+    #[inline]
+    pub fn distinct_paths(&self, state: &SearchState) -> Vec<(usize, usize)> {
+        self.parent.distinct_paths(state)
+    }
+
+    /// Returns the distinct samples containing the occurrences in the search state as (sample identifier, number of occurrences) pairs.
+    ///
+    /// This is the same as [`GBWT::distinct_samples`].
+    #[inline]
+    pub fn distinct_samples(&self, state: &SearchState) -> Option<Vec<(usize, usize)>> {
+        self.parent.distinct_samples(state)
+    }
+
+    /// Returns the distinct contigs containing the occurrences in the search state as (contig identifier, number of occurrences) pairs.
+    ///
+    /// This is the same as [`GBWT::distinct_contigs`].
+    #[inline]
+    pub fn distinct_contigs(&self, state: &SearchState) -> Option<Vec<(usize, usize)>> {
+        self.parent.distinct_contigs(state)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// An iterator over a sequence in [`CachedGBWT`].
+///
+/// The type of `Item` is [`usize`].
+/// See module-level documentation for an example.
+#[derive(Clone, Debug)]
+pub struct CachedSequenceIter<'b, 'a> {
+    parent: &'b CachedGBWT<'a>,
+    // The next position.
+    next: Option<Pos>,
+    // Upper bound for the number of remaining nodes, in case a malformed index contains a cycle.
+    remaining: usize,
+}
+
+impl<'b, 'a> Iterator for CachedSequenceIter<'b, 'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.next?;
+        if self.remaining == 0 {
+            self.next = None;
+            return None;
+        }
+        self.remaining -= 1;
+        self.next = self.parent.forward(pos);
+        Some(pos.node)
+    }
+}
+
+impl<'b, 'a> FusedIterator for CachedSequenceIter<'b, 'a> {}
+
+/// An iterator over a sequence in [`CachedGBWT`] in reverse order.
+///
+/// The type of `Item` is [`usize`].
+/// See [`crate::gbwt::ReverseSequenceIter`] for details.
+#[derive(Clone, Debug)]
+pub struct CachedReverseSequenceIter<'b, 'a> {
+    // The reverse sequence in a bidirectional index.
+    reverse: Option<CachedSequenceIter<'b, 'a>>,
+    // Buffered nodes in a unidirectional index.
+    buffer: Vec<usize>,
+}
+
+impl<'b, 'a> Iterator for CachedReverseSequenceIter<'b, 'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reverse.as_mut() {
+            Some(iter) => iter.next().map(support::flip_node),
+            None => self.buffer.pop(),
+        }
+    }
+}
+
+impl<'b, 'a> FusedIterator for CachedReverseSequenceIter<'b, 'a> {}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,189 @@
+use super::*;
+
+use crate::Orientation;
+use crate::gbwt::GBWTBuilder;
+use crate::support;
+use crate::test_support::{self, load_index, positions};
+
+use std::convert::TryFrom;
+
+//-----------------------------------------------------------------------------
+
+fn test_navigation(test_file: &'static str, capacity: usize) {
+    let index = load_index(test_file);
+    let cached = CachedGBWT::with_capacity(&index, capacity);
+
+    test_support::check_navigation(&index, &cached);
+    for i in 0..index.sequences() {
+        assert_eq!(cached.start(i), index.start(i), "Invalid start for sequence {}", i);
+        let truth: Vec<usize> = index.sequence(i).unwrap().collect();
+        let extracted: Vec<usize> = cached.sequence(i).unwrap().collect();
+        assert_eq!(extracted, truth, "Invalid sequence {}", i);
+    }
+    assert!(cached.sequence(index.sequences()).is_none(), "Got an iterator for a past-the-end sequence id");
+    assert!(cached.cached_records() <= capacity, "The cache exceeds its capacity");
+}
+
+#[test]
+fn navigation() {
+    test_navigation("example.gbwt", CachedGBWT::DEFAULT_CAPACITY);
+}
+
+#[test]
+fn navigation_with_empty() {
+    test_navigation("with-empty.gbwt", CachedGBWT::DEFAULT_CAPACITY);
+}
+
+#[test]
+fn navigation_first_node() {
+    // The first node of the alphabet is in the middle of the path.
+    let path = vec![
+        support::encode_node(2, Orientation::Forward),
+        support::encode_node(1, Orientation::Forward),
+        support::encode_node(3, Orientation::Reverse),
+    ];
+    let mut builder = GBWTBuilder::new(true);
+    builder.insert(&path).unwrap();
+    let index = GBWT::try_from(builder).unwrap();
+    let cached = CachedGBWT::new(&index);
+
+    let pos = index.forward(index.start(0).unwrap()).unwrap();
+    assert_eq!(pos.node, index.first_node(), "The path does not visit the first node in the middle");
+    assert_eq!(cached.backward(pos), index.backward(pos), "Invalid backward({:?})", pos);
+    assert_eq!(cached.backward(pos), index.start(0), "Could not follow the path backward from the first node");
+}
+
+#[test]
+fn navigation_small_cache() {
+    test_navigation("example.gbwt", 2);
+    test_navigation("example.gbwt", 0);
+}
+
+//-----------------------------------------------------------------------------
+
+fn test_random_access(index: &GBWT, cached: &CachedGBWT) {
+    for i in 0..index.sequences() {
+        let truth: Vec<usize> = index.sequence_rev(i).unwrap().collect();
+        let extracted: Vec<usize> = cached.sequence_rev(i).unwrap().collect();
+        assert_eq!(extracted, truth, "Invalid reverse sequence {}", i);
+
+        let len = index.sequence_len(i).unwrap();
+        assert_eq!(cached.sequence_len(i), Some(len), "Invalid length for sequence {}", i);
+        for offset in 0..len + 2 {
+            assert_eq!(cached.position_at(i, offset), index.position_at(i, offset), "Invalid position at offset {} of sequence {}", offset, i);
+            let truth: Vec<usize> = index.sequence_from(i, offset).unwrap().collect();
+            let extracted: Vec<usize> = cached.sequence_from(i, offset).unwrap().collect();
+            assert_eq!(extracted, truth, "Invalid sequence {} from offset {}", i, offset);
+            assert_eq!(cached.extract(i, offset..offset + 2), index.extract(i, offset..offset + 2), "Invalid extraction at offset {} of sequence {}", offset, i);
+        }
+    }
+    assert!(cached.sequence_rev(index.sequences()).is_none(), "Got a reverse iterator for a past-the-end sequence id");
+    assert!(cached.sequence_len(index.sequences()).is_none(), "Got a length for a past-the-end sequence id");
+    assert!(cached.extract(index.sequences(), 0..1).is_empty(), "Extracted a past-the-end sequence id");
+}
+
+#[test]
+fn random_access() {
+    for test_file in ["example.gbwt", "with-empty.gbwt"] {
+        let mut index = load_index(test_file);
+        test_random_access(&index, &CachedGBWT::new(&index));
+        index.build_sequence_samples(2);
+        test_random_access(&index, &CachedGBWT::new(&index));
+    }
+}
+
+#[test]
+fn random_access_unidirectional() {
+    let index = load_index("example.gbwt");
+    let mut builder = GBWTBuilder::new(false);
+    for i in (0..index.sequences()).step_by(2) {
+        let path: Vec<usize> = index.sequence(i).unwrap().collect();
+        builder.insert(&path).unwrap();
+    }
+    let index = GBWT::try_from(builder).unwrap();
+    test_random_access(&index, &CachedGBWT::new(&index));
+}
+
+//-----------------------------------------------------------------------------
+
+fn test_search(test_file: &'static str) {
+    let index = load_index(test_file);
+    let cached = CachedGBWT::new(&index);
+    test_support::check_search(&index, &cached);
+
+    for node in 0..index.alphabet_size() + 1 {
+        if let Some(state) = index.find(node) {
+            assert_eq!(cached.locate(&state), index.locate(&state), "Invalid locate for node {}", node);
+        }
+    }
+    for pos in positions(&index) {
+        assert_eq!(cached.locate_pos(pos), index.locate_pos(pos), "Invalid locate_pos({:?})", pos);
+    }
+    for node in 0..index.alphabet_size() + 1 {
+        if let Some(state) = index.find(node) {
+            assert_eq!(cached.distinct_sequences(&state), index.distinct_sequences(&state), "Invalid distinct sequences for node {}", node);
+            assert_eq!(cached.distinct_paths(&state), index.distinct_paths(&state), "Invalid distinct paths for node {}", node);
+            assert_eq!(cached.distinct_samples(&state), index.distinct_samples(&state), "Invalid distinct samples for node {}", node);
+            assert_eq!(cached.distinct_contigs(&state), index.distinct_contigs(&state), "Invalid distinct contigs for node {}", node);
+        }
+    }
+
+    // Queries over every subpath of the stored sequences.
+    for i in 0..index.sequences() {
+        let path: Vec<usize> = index.sequence(i).unwrap().collect();
+        for start in 0..path.len() {
+            let query = &path[start..];
+            assert_eq!(cached.longest_match(query), index.longest_match(query), "Invalid longest match for sequence {} from {}", i, start);
+            assert_eq!(cached.maximal_matches(query), index.maximal_matches(query), "Invalid maximal matches for sequence {} from {}", i, start);
+        }
+    }
+}
+
+#[test]
+fn search() {
+    test_search("example.gbwt");
+}
+
+#[test]
+fn search_with_empty() {
+    test_search("with-empty.gbwt");
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn cache_management() {
+    let index = load_index("example.gbwt");
+    let mut cached = CachedGBWT::with_capacity(&index, 2);
+    let first = support::encode_node(11, Orientation::Forward);
+    let second = support::encode_node(12, Orientation::Forward);
+    let third = support::encode_node(14, Orientation::Forward);
+
+    // Least recently used records are evicted.
+    assert!(cached.record(first).is_some(), "No record for node {}", first);
+    assert!(cached.record(second).is_some(), "No record for node {}", second);
+    assert!(cached.record(first).is_some(), "No record for node {}", first);
+    assert!(cached.record(third).is_some(), "No record for node {}", third);
+    assert_eq!(cached.cached_records(), 2, "Invalid number of cached records");
+    assert!(cached.cache.borrow().contains_key(&index.node_to_record(first)), "Recently used record was evicted");
+    assert!(!cached.cache.borrow().contains_key(&index.node_to_record(second)), "Least recently used record was not evicted");
+
+    // Pinned records are not in the cache.
+    assert!(cached.pin(first), "Could not pin node {}", first);
+    assert!(cached.pin(second), "Could not pin node {}", second);
+    assert!(!cached.pin(index.alphabet_size()), "Pinned an invalid node");
+    assert_eq!(cached.pinned_records(), 2, "Invalid number of pinned records");
+    assert_eq!(cached.cached_records(), 1, "Pinned record remained in the cache");
+    assert!(cached.record(first).is_some(), "No record for pinned node {}", first);
+    assert_eq!(cached.cached_records(), 1, "Pinned record was added to the cache");
+
+    assert!(cached.unpin(first), "Could not unpin node {}", first);
+    assert!(!cached.unpin(first), "Unpinned node {} twice", first);
+    assert_eq!(cached.pinned_records(), 1, "Invalid number of pinned records after unpinning");
+
+    cached.clear_cache();
+    assert_eq!(cached.cached_records(), 0, "The cache is not empty after clearing it");
+    assert_eq!(cached.pinned_records(), 1, "Clearing the cache removed pinned records");
+}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,557 @@
+//! Out-of-core queries in GBWT indexes that are too large for memory.
+//!
+//! [`DiskBWT`] keeps only the record index of the BWT in memory and reads the encoded node records from the file on demand.
+//! The records are read in fixed-size pages, and a least recently used cache holds recently used pages.
+//! Cache hits and misses are counted in [`CacheStatistics`].
+//! The cache is protected by a mutex, but pages are read from the file without holding the lock, and the structures can be shared between threads.
+//!
+//! [`DiskGBWT`] uses a [`DiskBWT`] to provide the sequence navigation and subpath search queries of [`crate::GBWT`], using the same implementation of the queries.
+//! Document array samples and metadata are not loaded, and locate queries are not supported.
+//!
+//! The module is only available on Unix-like systems, as it reads the pages with positional reads.
+//!
+//! # Examples
+//!
+//! ```
+//! use gbwt::Orientation;
+//! use gbwt::disk::DiskGBWT;
+//! use gbwt::support;
+//!
+//! let filename = support::get_test_data("example.gbwt");
+//! let index = DiskGBWT::open(&filename).unwrap();
+//!
+//! // Extract path 3 in reverse orientation.
+//! let path: Vec<usize> = index.sequence(support::encode_path(3, Orientation::Reverse)).unwrap().collect();
+//! assert_eq!(path, vec![35, 33, 29, 27, 23]);
+//!
+//! // Subpath search.
+//! let state = index.find(support::encode_node(12, Orientation::Forward)).unwrap();
+//! let state = index.extend(&state, support::encode_node(14, Orientation::Forward)).unwrap();
+//! assert_eq!(state.len(), 2);
+//!
+//! // The example is small enough to fit in a single page.
+//! let statistics = index.statistics();
+//! assert_eq!(statistics.misses, 1);
+//! assert!(statistics.hits > 0);
+//! ```
+
+use crate::{Pos, SearchState, BidirectionalState};
+use crate::ENDMARKER;
+use crate::bwt::{DecompressedRecord, Record};
+use crate::bwt;
+use crate::gbwt::RecordSource;
+use crate::headers::{Header, GBWTPayload};
+use crate::support::{LruCache, Tags};
+
+use simple_sds::ops::{BitVec, Select};
+use simple_sds::serialize::Serialize;
+use simple_sds::sparse_vector::SparseVector;
+
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::iter::FusedIterator;
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{cmp, io, mem};
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// Page cache statistics for a [`DiskBWT`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStatistics {
+    /// Number of page accesses served from the cache.
+    pub hits: usize,
+
+    /// Number of page accesses that required reading the page from the file.
+    pub misses: usize,
+}
+
+impl CacheStatistics {
+    /// Returns the total number of page accesses.
+    #[inline]
+    pub fn accesses(&self) -> usize {
+        self.hits + self.misses
+    }
+
+    /// Returns the fraction of page accesses served from the cache, or `0.0` if there have been no accesses.
+    pub fn hit_rate(&self) -> f64 {
+        if self.accesses() == 0 { 0.0 } else { self.hits as f64 / self.accesses() as f64 }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// A BWT that reads the node records from a file on demand.
+///
+/// The record index is loaded into memory, while the concatenated records remain in the file.
+/// When a record is needed, the pages containing it are read from the file or taken from the page cache.
+/// The cache holds at most `capacity` pages of `page_size` bytes, and the least recently used page is evicted when the cache is full.
+/// Pages are read with positional reads without holding the cache lock, so threads missing the cache do not block each other.
+/// If two threads miss the same page at the same time, both read it.
+/// The records are decoded with [`Record::new`], as in [`crate::bwt::BWT`].
+///
+/// Because the file may change or become unreadable after opening it, reading a record may fail.
+/// Such failures are reported as I/O errors by [`DiskBWT::record_bytes`].
+/// The query functions of [`DiskGBWT`] treat them as missing records.
+#[derive(Debug)]
+pub struct DiskBWT {
+    index: SparseVector,
+    // Offset of the record data in the file.
+    offset: usize,
+    // Length of the record data in bytes.
+    data_len: usize,
+    page_size: usize,
+    file: File,
+    cache: Mutex<PageCache>,
+}
+
+// Recently used pages and access statistics.
+#[derive(Debug)]
+struct PageCache {
+    pages: LruCache<usize, Arc<[u8]>>,
+    statistics: CacheStatistics,
+}
+
+/// Construction and cache management.
+impl DiskBWT {
+    /// Default page size in bytes.
+    pub const DEFAULT_PAGE_SIZE: usize = 64 * 1024;
+
+    /// Default number of pages in the cache.
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    /// Loads the record index from the reader and prepares reading the records from the file.
+    ///
+    /// The reader must be positioned at the start of a serialized BWT in the file, and it will be positioned after the BWT.
+    /// Page size `0` is treated as `1`.
+    /// Capacity `0` disables the cache, and each page access reads the page from the file.
+    pub fn open<T: Read + Seek>(reader: &mut T, file: File, page_size: usize, capacity: usize) -> io::Result<Self> {
+        let index = SparseVector::load(reader)?;
+
+        // A byte vector is serialized as its length followed by the bytes padded to a multiple of 8 bytes.
+        let mut buf = [0u8; mem::size_of::<u64>()];
+        reader.read_exact(&mut buf)?;
+        let data_len = u64::from_ne_bytes(buf) as usize;
+        if index.len() != data_len {
+            return Err(Error::new(ErrorKind::InvalidData, "DiskBWT: Index / data length mismatch"));
+        }
+        bwt::check_offsets(&index, data_len)?;
+        let offset = reader.stream_position()? as usize;
+        let file_len = file.metadata()?.len() as usize;
+        let padded = match data_len.checked_next_multiple_of(mem::size_of::<u64>()) {
+            Some(padded) if padded <= file_len.saturating_sub(offset) => padded,
+            _ => return Err(Error::new(ErrorKind::UnexpectedEof, "DiskBWT: Record data extends past the end of the file")),
+        };
+        reader.seek(SeekFrom::Start((offset + padded) as u64))?;
+
+        Ok(DiskBWT {
+            index, offset, data_len,
+            page_size: cmp::max(page_size, 1),
+            file,
+            cache: Mutex::new(PageCache {
+                pages: LruCache::new(capacity),
+                statistics: CacheStatistics::default(),
+            }),
+        })
+    }
+
+    /// Returns the page size in bytes.
+    #[inline]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the maximum number of pages in the cache.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.lock().pages.capacity()
+    }
+
+    /// Returns the number of pages currently in the cache.
+    pub fn cached_pages(&self) -> usize {
+        self.lock().pages.len()
+    }
+
+    /// Returns the cache statistics since opening the BWT or since the last reset.
+    pub fn statistics(&self) -> CacheStatistics {
+        self.lock().statistics
+    }
+
+    /// Resets the cache statistics.
+    pub fn reset_statistics(&self) {
+        self.lock().statistics = CacheStatistics::default();
+    }
+
+    /// Removes all pages from the cache.
+    ///
+    /// The cache statistics are not affected.
+    pub fn clear_cache(&self) {
+        self.lock().pages.clear();
+    }
+
+    // Returns the cache, even if another thread panicked while holding the lock.
+    // The cache remains consistent, as pages are inserted only after they have been read.
+    fn lock(&self) -> MutexGuard<'_, PageCache> {
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Returns the given page from the cache or reads it from the file.
+    // The lock is not held while reading the page.
+    fn page(&self, page: usize) -> io::Result<Arc<[u8]>> {
+        {
+            let mut cache = self.lock();
+            if let Some(bytes) = cache.pages.get(&page) {
+                let bytes = Arc::clone(bytes);
+                cache.statistics.hits += 1;
+                return Ok(bytes);
+            }
+            cache.statistics.misses += 1;
+        }
+
+        let start = page * self.page_size;
+        let end = cmp::min(start + self.page_size, self.data_len);
+        let mut bytes = vec![0u8; end - start];
+        self.file.read_exact_at(&mut bytes, (self.offset + start) as u64)?;
+        let bytes: Arc<[u8]> = Arc::from(bytes);
+
+        self.lock().pages.insert(page, Arc::clone(&bytes));
+        Ok(bytes)
+    }
+}
+
+/// Records.
+impl DiskBWT {
+    /// Returns the number of records in the BWT.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.index.count_ones()
+    }
+
+    /// Returns `true` if the BWT is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a copy of the encoded `i`th record, or [`None`] if there is no such record.
+    ///
+    /// The bytes can be decoded with [`Record::new`].
+    /// Returns an error if reading the record from the file fails.
+    pub fn record_bytes(&self, i: usize) -> io::Result<Option<Vec<u8>>> {
+        self.with_record_bytes(i, |bytes| bytes.to_vec())
+    }
+
+    /// Calls `f` with the encoded `i`th record and returns the result, or [`None`] if there is no such record.
+    ///
+    /// If the record is contained in a single page, the bytes are borrowed from the page without copying them.
+    /// Returns an error if reading the record from the file fails.
+    pub fn with_record_bytes<T, F: FnOnce(&[u8]) -> T>(&self, i: usize, f: F) -> io::Result<Option<T>> {
+        let range = match self.record_range(i) {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        if range.is_empty() {
+            return Ok(Some(f(&[])));
+        }
+
+        let first = range.start / self.page_size;
+        let last = (range.end - 1) / self.page_size;
+        if first == last {
+            let bytes = self.page(first)?;
+            let page_start = first * self.page_size;
+            return Ok(Some(f(&bytes[range.start - page_start..range.end - page_start])));
+        }
+
+        // The record spans multiple pages.
+        let mut buffer: Vec<u8> = Vec::with_capacity(range.len());
+        for page in first..=last {
+            let bytes = self.page(page)?;
+            let page_start = page * self.page_size;
+            let start = cmp::max(range.start, page_start) - page_start;
+            let end = cmp::min(range.end, page_start + bytes.len()) - page_start;
+            buffer.extend_from_slice(&bytes[start..end]);
+        }
+        Ok(Some(f(&buffer)))
+    }
+
+    // Returns the range of bytes for the `i`th record, or `None` if there is no such record.
+    fn record_range(&self, i: usize) -> Option<Range<usize>> {
+        if i >= self.len() {
+            return None;
+        }
+        let mut iter = self.index.select_iter(i);
+        let (_, start) = iter.next()?;
+        let limit = if i + 1 < self.len() { iter.next()?.1 } else { self.data_len };
+        Some(start..limit)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// A GBWT index that reads the node records from the file on demand.
+///
+/// The header and the record index of the BWT are loaded into memory, while the records are accessed through a [`DiskBWT`].
+/// The endmarker record is decompressed when the index is opened, as in [`crate::GBWT`].
+/// The index provides the same sequence navigation and subpath search queries as [`crate::GBWT`].
+/// See module-level documentation for an example.
+///
+/// If reading a record from the file fails, the query functions return [`None`], as if the record did not exist.
+/// Use [`DiskBWT::record_bytes`] for detecting I/O errors.
+#[derive(Debug)]
+pub struct DiskGBWT {
+    header: Header<GBWTPayload>,
+    bwt: DiskBWT,
+    endmarker: Vec<Pos>,
+}
+
+/// Construction and cache management.
+impl DiskGBWT {
+    /// Opens the GBWT index in the given file with the default page size and cache capacity.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<Self, crate::Error> {
+        Self::open_with_cache(filename, DiskBWT::DEFAULT_PAGE_SIZE, DiskBWT::DEFAULT_CAPACITY)
+    }
+
+    /// Opens the GBWT index in the given file with the given page size and cache capacity.
+    ///
+    /// See [`DiskBWT::open`] for the parameters.
+    /// Returns [`crate::Error::InvalidHeader`] if the file does not contain a supported GBWT index and [`crate::Error::Io`] if reading the file fails.
+    pub fn open_with_cache<P: AsRef<Path>>(filename: P, page_size: usize, capacity: usize) -> Result<Self, crate::Error> {
+        let file = File::open(filename)?;
+        let mut reader = BufReader::new(file.try_clone()?);
+
+        let header = Header::<GBWTPayload>::load(&mut reader)?;
+        header.validate()?;
+        let _ = Tags::load(&mut reader)?;
+        let bwt = DiskBWT::open(&mut reader, file, page_size, capacity)?;
+        if bwt.len() != header.payload().alphabet_size.saturating_sub(header.payload().offset) {
+            return Err(crate::Error::InvalidData(String::from("DiskGBWT: Invalid number of records in the BWT")));
+        }
+
+        // Decompress the endmarker, as the record can be poorly compressible.
+        let record = bwt.with_record_bytes(ENDMARKER, |bytes| bwt::check_record(ENDMARKER, bytes))?.transpose()?.flatten();
+        let len = record.as_ref().map_or(0, DecompressedRecord::len);
+        if len != header.payload().sequences {
+            return Err(crate::Error::InvalidData(String::from("DiskGBWT: Endmarker length does not match the number of sequences")));
+        }
+        let endmarker = record.map(|record| record.decompress()).unwrap_or_default();
+
+        Ok(DiskGBWT {
+            header, bwt, endmarker,
+        })
+    }
+
+    /// Returns the underlying BWT.
+    #[inline]
+    pub fn bwt(&self) -> &DiskBWT {
+        &self.bwt
+    }
+
+    /// Returns the cache statistics of the underlying BWT.
+    #[inline]
+    pub fn statistics(&self) -> CacheStatistics {
+        self.bwt.statistics()
+    }
+
+}
+
+impl RecordSource for DiskGBWT {
+    type Record<'a> = Record<'a>;
+
+    fn alphabet_size(&self) -> usize {
+        DiskGBWT::alphabet_size(self)
+    }
+
+    fn alphabet_offset(&self) -> usize {
+        DiskGBWT::alphabet_offset(self)
+    }
+
+    fn is_bidirectional(&self) -> bool {
+        DiskGBWT::is_bidirectional(self)
+    }
+
+    fn with_record<T, F: for<'a> FnOnce(&Self::Record<'a>) -> Option<T>>(&self, node: usize, f: F) -> Option<T> {
+        let record_id = self.record_id(node)?;
+        let result = self.bwt.with_record_bytes(record_id, |bytes| Record::new(record_id, bytes).and_then(|record| f(&record)));
+        result.ok().flatten().flatten()
+    }
+}
+
+/// Index statistics.
+impl DiskGBWT {
+    /// Returns the total length of the sequences in the index.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.header.payload().size
+    }
+
+    /// Returns `true` if the index is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of sequences in the index.
+    #[inline]
+    pub fn sequences(&self) -> usize {
+        self.header.payload().sequences
+    }
+
+    /// Returns the size of the alphabet.
+    #[inline]
+    pub fn alphabet_size(&self) -> usize {
+        self.header.payload().alphabet_size
+    }
+
+    /// Returns the alphabet offset for the effective alphabet.
+    #[inline]
+    pub fn alphabet_offset(&self) -> usize {
+        self.header.payload().offset
+    }
+
+    /// Returns the smallest node identifier in the effective alphabet.
+    #[inline]
+    pub fn first_node(&self) -> usize {
+        self.alphabet_offset() + 1
+    }
+
+    /// Returns `true` if node identifier `id` is in the effective alphabet.
+    #[inline]
+    pub fn has_node(&self, id: usize) -> bool {
+        id > self.alphabet_offset() && id < self.alphabet_size()
+    }
+
+    /// Returns `true` if the index is bidirectional.
+    #[inline]
+    pub fn is_bidirectional(&self) -> bool {
+        self.header.is_set(GBWTPayload::FLAG_BIDIRECTIONAL)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Sequence navigation.
+impl DiskGBWT {
+    /// Returns the first position in sequence `id`.
+    ///
+    /// The return value is [`None`] if no such sequence exists or the sequence is empty.
+    pub fn start(&self, id: usize) -> Option<Pos> {
+        if id < self.endmarker.len() && self.endmarker[id].node != ENDMARKER {
+            Some(self.endmarker[id])
+        } else {
+            None
+        }
+    }
+
+    /// Follows the sequence forward and returns the next position, or [`None`] if no such position exists.
+    pub fn forward(&self, pos: Pos) -> Option<Pos> {
+        RecordSource::forward(self, pos)
+    }
+
+    /// Follows the sequence backward and returns the previous position, or [`None`] if no such position exists.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not bidirectional.
+    pub fn backward(&self, pos: Pos) -> Option<Pos> {
+        RecordSource::backward(self, pos)
+    }
+
+    /// Returns an iterator over sequence `id`, or [`None`] if there is no such sequence.
+    pub fn sequence(&self, id: usize) -> Option<DiskSequenceIter<'_>> {
+        if id >= self.sequences() {
+            return None;
+        }
+        Some(DiskSequenceIter {
+            parent: self,
+            next: self.start(id),
+            remaining: self.len(),
+        })
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Subpath search.
+impl DiskGBWT {
+    /// Returns a search state for all occurrences of the given node, or [`None`] if no such node exists.
+    pub fn find(&self, node: usize) -> Option<SearchState> {
+        RecordSource::find(self, node)
+    }
+
+    /// Extends the search by the given node forward and returns the new search state, or [`None`] if no such extensions exist.
+    ///
+    /// See [`crate::GBWT::extend`] for details.
+    pub fn extend(&self, state: &SearchState, node: usize) -> Option<SearchState> {
+        RecordSource::extend(self, state, node)
+    }
+
+    /// Returns a bidirectional search state for all occurrences of the given node, or [`None`] if no such node exists.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn bd_find(&self, node: usize) -> Option<BidirectionalState> {
+        RecordSource::bd_find(self, node)
+    }
+
+    /// Extends the search by the given node forward and returns the new search state, or [`None`] if no such extensions exist.
+    ///
+    /// See [`crate::GBWT::extend_forward`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn extend_forward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+        RecordSource::extend_forward(self, state, node)
+    }
+
+    /// Extends the search by the given node backward and returns the new search state, or [`None`] if no such extensions exist.
+    ///
+    /// See [`crate::GBWT::extend_backward`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    pub fn extend_backward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+        RecordSource::extend_backward(self, state, node)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// An iterator over a sequence in [`DiskGBWT`].
+///
+/// The type of `Item` is [`usize`].
+/// See module-level documentation for an example.
+#[derive(Clone, Debug)]
+pub struct DiskSequenceIter<'a> {
+    parent: &'a DiskGBWT,
+    // The next position.
+    next: Option<Pos>,
+    // Upper bound for the number of remaining nodes, in case a malformed index contains a cycle.
+    remaining: usize,
+}
+
+impl<'a> Iterator for DiskSequenceIter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.next?;
+        if self.remaining == 0 {
+            self.next = None;
+            return None;
+        }
+        self.remaining -= 1;
+        self.next = self.parent.forward(pos);
+        Some(pos.node)
+    }
+}
+
+impl<'a> FusedIterator for DiskSequenceIter<'a> {}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,167 @@
+use super::*;
+
+use crate::Orientation;
+use crate::support;
+use crate::test_support::{self, load_index};
+
+use simple_sds::serialize;
+
+//-----------------------------------------------------------------------------
+
+fn open_index(test_file: &'static str, page_size: usize, capacity: usize) -> DiskGBWT {
+    let filename = support::get_test_data(test_file);
+    DiskGBWT::open_with_cache(&filename, page_size, capacity).unwrap()
+}
+
+fn test_navigation(test_file: &'static str, page_size: usize, capacity: usize) {
+    let index = load_index(test_file);
+    let disk = open_index(test_file, page_size, capacity);
+    assert_eq!(disk.sequences(), index.sequences(), "Invalid number of sequences");
+    assert_eq!(disk.len(), index.len(), "Invalid total length");
+    assert_eq!(disk.alphabet_size(), index.alphabet_size(), "Invalid alphabet size");
+    assert_eq!(disk.alphabet_offset(), index.alphabet_offset(), "Invalid alphabet offset");
+    assert_eq!(disk.is_bidirectional(), index.is_bidirectional(), "Invalid bidirectional flag");
+
+    test_support::check_navigation(&index, &disk);
+    for i in 0..index.sequences() {
+        assert_eq!(disk.start(i), index.start(i), "Invalid start for sequence {}", i);
+        let truth: Vec<usize> = index.sequence(i).unwrap().collect();
+        let extracted: Vec<usize> = disk.sequence(i).unwrap().collect();
+        assert_eq!(extracted, truth, "Invalid sequence {}", i);
+    }
+    assert!(disk.sequence(index.sequences()).is_none(), "Got an iterator for a past-the-end sequence id");
+    assert!(disk.bwt().cached_pages() <= capacity, "The cache exceeds its capacity");
+}
+
+#[test]
+fn navigation() {
+    test_navigation("example.gbwt", DiskBWT::DEFAULT_PAGE_SIZE, DiskBWT::DEFAULT_CAPACITY);
+}
+
+#[test]
+fn navigation_with_empty() {
+    test_navigation("with-empty.gbwt", DiskBWT::DEFAULT_PAGE_SIZE, DiskBWT::DEFAULT_CAPACITY);
+}
+
+#[test]
+fn navigation_small_pages() {
+    // Records span multiple pages.
+    test_navigation("example.gbwt", 3, 4);
+    test_navigation("example.gbwt", 1, 2);
+    test_navigation("with-empty.gbwt", 5, 0);
+}
+
+//-----------------------------------------------------------------------------
+
+fn test_search(test_file: &'static str, page_size: usize, capacity: usize) {
+    let index = load_index(test_file);
+    let disk = open_index(test_file, page_size, capacity);
+    test_support::check_search(&index, &disk);
+}
+
+#[test]
+fn search() {
+    test_search("example.gbwt", DiskBWT::DEFAULT_PAGE_SIZE, DiskBWT::DEFAULT_CAPACITY);
+    test_search("example.gbwt", 4, 3);
+}
+
+#[test]
+fn search_with_empty() {
+    test_search("with-empty.gbwt", DiskBWT::DEFAULT_PAGE_SIZE, DiskBWT::DEFAULT_CAPACITY);
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn records() {
+    let index = load_index("example.gbwt");
+    let disk = open_index("example.gbwt", 7, 3);
+    let bwt: &crate::bwt::BWT = index.as_ref();
+    assert_eq!(disk.bwt().len(), bwt.len(), "Invalid number of records");
+    for i in 0..bwt.len() {
+        let bytes = disk.bwt().record_bytes(i).unwrap().unwrap();
+        let record = Record::new(i, &bytes);
+        match (record, bwt.record(i)) {
+            (Some(record), Some(truth)) => assert_eq!(record.decompress(), truth.decompress(), "Invalid record {}", i),
+            (None, None) => {},
+            _ => panic!("Invalid record {}", i),
+        }
+    }
+    assert_eq!(disk.bwt().record_bytes(bwt.len()).unwrap(), None, "Got a past-the-end record");
+
+    // Borrowed records.
+    for i in 0..bwt.len() {
+        let decompressed = disk.bwt().with_record_bytes(i, |bytes| Record::new(i, bytes).map(|record| record.decompress())).unwrap().flatten();
+        assert_eq!(decompressed, bwt.record(i).map(|record| record.decompress()), "Invalid borrowed record {}", i);
+    }
+    assert_eq!(disk.bwt().with_record_bytes(bwt.len(), |bytes| bytes.len()).unwrap(), None, "Got a past-the-end borrowed record");
+}
+
+#[test]
+fn lru_eviction() {
+    let disk = open_index("example.gbwt", 8, 2);
+    let bwt = disk.bwt();
+    for page in [0, 1, 0, 2] {
+        let _ = bwt.page(page).unwrap();
+    }
+    assert_eq!(bwt.statistics(), CacheStatistics { hits: 1, misses: 3 }, "Invalid statistics before eviction checks");
+    assert_eq!(bwt.cached_pages(), 2, "Invalid number of cached pages");
+
+    // Page 1 was the least recently used page when page 2 was inserted.
+    let _ = bwt.page(0).unwrap();
+    assert_eq!(bwt.statistics().hits, 2, "The most recently used page was evicted");
+    let _ = bwt.page(1).unwrap();
+    assert_eq!(bwt.statistics().misses, 4, "The least recently used page was not evicted");
+}
+
+#[test]
+fn statistics() {
+    let disk = open_index("example.gbwt", 4, 2);
+    assert_eq!(disk.statistics(), CacheStatistics::default(), "Nonzero statistics after opening the index");
+    assert_eq!(disk.statistics().hit_rate(), 0.0, "Nonzero hit rate without accesses");
+
+    let node = support::encode_node(12, Orientation::Forward);
+    let _ = disk.find(node).unwrap();
+    let first = disk.statistics();
+    assert!(first.misses > 0, "No misses after the first access");
+    assert_eq!(first.hits, 0, "Cache hits after the first access");
+
+    let _ = disk.find(node).unwrap();
+    let second = disk.statistics();
+    assert_eq!(second.accesses(), 2 * first.accesses(), "Invalid number of accesses after the second access");
+    assert_eq!(second.misses, first.misses, "Cache misses after the second access");
+    assert!(second.hit_rate() > 0.0, "Zero hit rate after the second access");
+    assert!(disk.bwt().cached_pages() <= 2, "The cache exceeds its capacity");
+
+    disk.bwt().reset_statistics();
+    assert_eq!(disk.statistics(), CacheStatistics::default(), "Nonzero statistics after reset");
+    disk.bwt().clear_cache();
+    assert_eq!(disk.bwt().cached_pages(), 0, "The cache is not empty after clearing");
+    let _ = disk.find(node).unwrap();
+    assert_eq!(disk.statistics().hits, 0, "Cache hits after clearing the cache");
+}
+
+#[test]
+fn no_cache() {
+    let disk = open_index("example.gbwt", DiskBWT::DEFAULT_PAGE_SIZE, 0);
+    let node = support::encode_node(12, Orientation::Forward);
+    let _ = disk.find(node).unwrap();
+    let _ = disk.find(node).unwrap();
+    assert_eq!(disk.statistics(), CacheStatistics { hits: 0, misses: 2 }, "Invalid statistics without a cache");
+    assert_eq!(disk.bwt().cached_pages(), 0, "Pages were cached without a cache");
+}
+
+#[test]
+fn invalid_files() {
+    assert!(DiskGBWT::open(support::get_test_data("example.gg")).is_err(), "Opened a graph file as a GBWT index");
+    assert!(DiskGBWT::open(support::get_test_data("nonexistent.gbwt")).is_err(), "Opened a nonexistent file");
+
+    // Truncated file. Remove the file before checking the result.
+    let filename = serialize::temp_file_name("disk-truncated");
+    let bytes = std::fs::read(support::get_test_data("example.gbwt")).unwrap();
+    let result = std::fs::write(&filename, &bytes[..80]).map(|_| DiskGBWT::open(&filename).is_err());
+    let _ = std::fs::remove_file(&filename);
+    assert!(result.unwrap(), "Opened a truncated file");
+}
+
+//-----------------------------------------------------------------------------
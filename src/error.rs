@@ -0,0 +1,116 @@
+//! Structured errors.
+//!
+//! [`Error`] describes the ways loading a file or using a fallible API can fail.
+//! Loaders implementing [`simple_sds::serialize::Serialize`] must return [`std::io::Error`].
+//! They wrap an [`Error`] in an I/O error with the appropriate [`std::io::ErrorKind`], and converting the I/O error back with [`Error::from`] recovers the original error.
+//!
+//! # Examples
+//!
+//! ```
+//! use gbwt::{Error, GBWT, GBZ};
+//! use gbwt::support;
+//! use simple_sds::serialize;
+//!
+//! // A GBWT file is not a GBZ file.
+//! let filename = support::get_test_data("example.gbwt");
+//! let result: Result<GBZ, Error> = serialize::load_from(&filename).map_err(Error::from);
+//! assert!(matches!(result, Err(Error::InvalidHeader { kind: "GBZHeader", .. })));
+//!
+//! // Nonexistent files.
+//! let result = GBZ::open_mmap(support::get_test_data("nonexistent.gbz"));
+//! assert!(matches!(result, Err(Error::Io(_))));
+//!
+//! // Nonexistent paths.
+//! let filename = support::get_test_data("example.gbz");
+//! let gbz: GBZ = serialize::load_from(&filename).unwrap();
+//! assert!(matches!(gbz.subgraph(gbz.paths(), 0..1, 10), Err(Error::PathNotFound(6))));
+//! ```
+
+use std::{error, fmt, io};
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// An error in loading a file or in using a fallible API.
+#[derive(Debug)]
+pub enum Error {
+    /// The file header has an invalid tag, version, or flags.
+    InvalidHeader {
+        /// Name of the header type.
+        kind: &'static str,
+        /// File format version in the header.
+        version: u32,
+        /// Why the header was rejected.
+        reason: &'static str,
+    },
+
+    /// The data or the arguments are invalid or inconsistent.
+    InvalidData(String),
+
+    /// The components of a structure do not match each other, such as a GBWT index and a graph, or a GBWT index and its metadata.
+    MetadataMismatch(&'static str),
+
+    /// There is no node with the given identifier.
+    NodeNotFound(usize),
+
+    /// There is no path with the given identifier.
+    PathNotFound(usize),
+
+    /// An I/O error.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidHeader { kind, version, reason } => write!(f, "{}: {} (version {})", kind, reason, version),
+            Error::InvalidData(msg) => write!(f, "{}", msg),
+            Error::MetadataMismatch(msg) => write!(f, "{}", msg),
+            Error::NodeNotFound(id) => write!(f, "Node {} not found", id),
+            Error::PathNotFound(id) => write!(f, "Path {} not found", id),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Recovers the original error if the I/O error wraps an [`Error`].
+    ///
+    /// Other I/O errors of kind [`io::ErrorKind::InvalidData`] with a message become [`Error::InvalidData`].
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::InvalidData && err.get_ref().is_some() {
+            let inner = err.into_inner().unwrap();
+            return match inner.downcast::<Error>() {
+                Ok(inner) => *inner,
+                Err(inner) => Error::InvalidData(inner.to_string()),
+            };
+        }
+        if matches!(err.get_ref(), Some(inner) if inner.is::<Error>()) {
+            return *err.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            Error::NodeNotFound(_) | Error::PathNotFound(_) => io::Error::new(io::ErrorKind::NotFound, err),
+            _ => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,203 @@
+use super::*;
+
+use crate::{GBWT, GBZ, Graph, Metadata, Pos};
+use crate::headers::{Header, GBZPayload};
+use crate::support;
+
+use simple_sds::serialize::{self, Serialize};
+
+use std::error::Error as StdError;
+use std::panic::{self, AssertUnwindSafe};
+use std::fs;
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn io_round_trip() {
+    let errors = vec![
+        Error::InvalidHeader { kind: "GBZHeader", version: 2, reason: "Unsupported version" },
+        Error::InvalidData(String::from("Invalid data")),
+        Error::MetadataMismatch("Metadata mismatch"),
+        Error::NodeNotFound(12),
+        Error::PathNotFound(3),
+    ];
+    for error in errors {
+        let message = error.to_string();
+        let io_error = io::Error::from(error);
+        assert_eq!(io_error.to_string(), message, "Invalid message in the I/O error");
+        let recovered = Error::from(io_error);
+        assert_eq!(recovered.to_string(), message, "Invalid message in the recovered error");
+        assert!(recovered.source().is_none(), "A structured error has a source");
+    }
+
+    let recovered = Error::from(io::Error::from(Error::NodeNotFound(12)));
+    assert!(matches!(recovered, Error::NodeNotFound(12)), "Could not recover the structured error");
+    assert_eq!(io::Error::from(Error::PathNotFound(3)).kind(), io::ErrorKind::NotFound, "Invalid kind for a missing path");
+    assert_eq!(io::Error::from(Error::MetadataMismatch("")).kind(), io::ErrorKind::InvalidData, "Invalid kind for a metadata mismatch");
+}
+
+#[test]
+fn io_errors() {
+    let error = Error::from(io::Error::new(io::ErrorKind::UnexpectedEof, "End of file"));
+    assert!(matches!(error, Error::Io(ref err) if err.kind() == io::ErrorKind::UnexpectedEof), "Invalid error for an I/O error");
+    assert!(error.source().is_some(), "An I/O error has no source");
+
+    let error = Error::from(io::Error::new(io::ErrorKind::InvalidData, "Tags: Duplicate keys"));
+    assert!(matches!(error, Error::InvalidData(ref msg) if msg == "Tags: Duplicate keys"), "Invalid error for invalid data");
+
+    let io_error = io::Error::from(io::ErrorKind::InvalidData);
+    assert!(matches!(Error::from(io_error), Error::Io(_)), "Invalid error for invalid data without a message");
+}
+
+#[test]
+fn loaders() {
+    // Loading a file of the wrong type.
+    let filename = support::get_test_data("example.gbwt");
+    let result: Result<GBZ, Error> = serialize::load_from(&filename).map_err(Error::from);
+    assert!(matches!(result, Err(Error::InvalidHeader { kind: "GBZHeader", reason: "Invalid tag", .. })), "Loaded a GBWT file as a GBZ graph");
+    let filename = support::get_test_data("example.gbz");
+    let result: Result<Graph, Error> = serialize::load_from(&filename).map_err(Error::from);
+    assert!(matches!(result, Err(Error::InvalidHeader { kind: "GraphHeader", .. })), "Loaded a GBZ file as a graph");
+    let filename = support::get_test_data("example.gg");
+    let result: Result<GBWT, Error> = serialize::load_from(&filename).map_err(Error::from);
+    assert!(matches!(result, Err(Error::InvalidHeader { kind: "GBWTHeader", .. })), "Loaded a graph file as a GBWT index");
+
+    // Unsupported version.
+    let mut header = Header::<GBZPayload>::new();
+    let mut buffer: Vec<u8> = Vec::new();
+    header.serialize(&mut buffer).unwrap();
+    let version = header.version() + 1;
+    buffer[4..8].copy_from_slice(&version.to_le_bytes());
+    header = Header::<GBZPayload>::load(&mut buffer.as_slice()).unwrap();
+    assert!(matches!(header.validate(), Err(Error::InvalidHeader { kind: "GBZHeader", version: v, reason: "Unsupported version" }) if v == version), "Accepted an unsupported version");
+
+    // Missing files.
+    let result: Result<GBZ, Error> = serialize::load_from(support::get_test_data("nonexistent.gbz")).map_err(Error::from);
+    assert!(matches!(result, Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::NotFound), "Loaded a nonexistent file");
+}
+
+//-----------------------------------------------------------------------------
+
+// Loading a truncated or mutated file must return an error or a structure that can be validated and queried without panicking.
+// The loaders validate the structure after loading it and return `true` if loading succeeded.
+type Loader = fn(&[u8]) -> bool;
+
+// Maximum number of steps when following a sequence, as a malformed index may contain cycles.
+const QUERY_STEPS: usize = 1000;
+
+// Runs navigation and search queries without assuming that the records are valid.
+fn run_queries(index: &GBWT) {
+    for id in 0..index.sequences() {
+        let _ = index.sequence(id).map(|iter| iter.take(QUERY_STEPS).count());
+    }
+    for node in index.first_node()..index.alphabet_size() {
+        if let Some(state) = index.find(node) {
+            let _ = index.extend(&state, node);
+        }
+        let _ = index.forward(Pos::new(node, 0));
+        if index.is_bidirectional() {
+            let _ = index.backward(Pos::new(node, 0));
+        }
+    }
+}
+
+fn load_gbwt(mut bytes: &[u8]) -> bool {
+    GBWT::load(&mut bytes).map(|index| {
+        index.validate();
+        run_queries(&index);
+    }).is_ok()
+}
+
+fn load_gbz(mut bytes: &[u8]) -> bool {
+    GBZ::load(&mut bytes).map(|gbz| {
+        gbz.validate();
+        run_queries(gbz.as_ref());
+    }).is_ok()
+}
+
+fn load_graph(mut bytes: &[u8]) -> bool {
+    Graph::load(&mut bytes).is_ok()
+}
+
+fn load_metadata(mut bytes: &[u8]) -> bool {
+    Metadata::load(&mut bytes).is_ok()
+}
+
+const TEST_FILES: [(&str, Loader); 8] = [
+    ("example.gbwt", load_gbwt), ("translation.gbwt", load_gbwt), ("with-empty.gbwt", load_gbwt),
+    ("example.gbz", load_gbz), ("translation.gbz", load_gbz),
+    ("example.gg", load_graph), ("translation.gg", load_graph),
+    ("example.meta", load_metadata),
+];
+
+#[test]
+fn truncated_files() {
+    for (name, load) in TEST_FILES.iter() {
+        let bytes = fs::read(support::get_test_data(name)).unwrap();
+        assert!(load(&bytes), "{}: Could not load the original file", name);
+        for len in 0..bytes.len() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| load(&bytes[..len])));
+            assert_eq!(result.ok(), Some(false), "{}: Did not get an error for a file truncated to {} bytes", name, len);
+        }
+    }
+}
+
+// Returns the 64-bit word starting at the given offset.
+fn word_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_ne_bytes(buf)
+}
+
+// Returns `true` if the word at the given offset could be a length field of a serialized structure.
+// A length in bits, bytes, or elements cannot exceed the number of bits in the remaining input.
+// Small values are always plausible.
+fn plausible_length(bytes: &[u8], offset: usize) -> bool {
+    let value = word_at(bytes, offset);
+    let remaining = (bytes.len() - offset) as u64;
+    value < (1 << 16) || value <= 8 * remaining
+}
+
+#[test]
+fn mutated_files() {
+    // The loaders in simple-sds allocate memory for a structure before reading it.
+    // Mutations that turn a plausible length into an implausible one are skipped, as they could cause a large allocation.
+    let masks: [u8; 3] = [0x01, 0x80, 0xFF];
+    for (name, load) in TEST_FILES.iter() {
+        let original = fs::read(support::get_test_data(name)).unwrap();
+        for offset in 0..original.len() {
+            let word = offset - offset % 8;
+            if word + 8 > original.len() {
+                continue;
+            }
+            for mask in masks {
+                let mut bytes = original.clone();
+                bytes[offset] ^= mask;
+                if plausible_length(&original, word) && !plausible_length(&bytes, word) {
+                    continue;
+                }
+                let result = panic::catch_unwind(AssertUnwindSafe(|| load(&bytes)));
+                assert!(result.is_ok(), "{}: Panic with mask {:#04x} at offset {}", name, mask, offset);
+            }
+        }
+    }
+}
+
+#[test]
+fn truncated_mapped_files() {
+    let original = fs::read(support::get_test_data("example.gbz")).unwrap();
+    let filename = serialize::temp_file_name("truncated-gbz");
+
+    // Remove the file before checking the results.
+    let mut accepted: Vec<usize> = Vec::new();
+    for len in (0..original.len()).step_by(8) {
+        let result = fs::write(&filename, &original[..len]).map(|_| panic::catch_unwind(|| GBZ::open_mmap(&filename).is_err()));
+        if !matches!(result, Ok(Ok(true))) {
+            accepted.push(len);
+        }
+    }
+    let _ = fs::remove_file(&filename);
+    assert!(accepted.is_empty(), "Did not get an error for mapped files truncated to {:?} bytes", accepted);
+}
+
+//-----------------------------------------------------------------------------
@@ -6,23 +6,34 @@
 //! > Bioinformatics, 2020.
 //! > DOI: [10.1093/bioinformatics/btz575](https://doi.org/10.1093/bioinformatics/btz575)
 //!
-//! At the moment, this implementation only supports GBWT indexes built with other tools.
+//! Small indexes can be built in memory using [`GBWTBuilder`]; larger indexes should be built with other tools.
+//! Metadata can be built with [`MetadataBuilder`] or edited in place and attached to an index with [`GBWT::set_metadata`].
 //! See also the original [C++ implementation](https://github.com/jltsiren/gbwt).
 
-use crate::{ENDMARKER, SOURCE_KEY, SOURCE_VALUE};
+use crate::{ENDMARKER, REF_SAMPLE, REFERENCE_SAMPLES_KEY, REFERENCE_SAMPLES_SEPARATOR, SOURCE_KEY, SOURCE_VALUE};
 use crate::{Orientation, Pos};
-use crate::bwt::{BWT, Record};
+use crate::bwt::{BWT, BWTBuilder, NodeRecord, Record};
 use crate::headers::{Header, GBWTPayload, MetadataPayload};
-use crate::support::{Dictionary, StringIter, Tags};
+use crate::support::{Dictionary, Run, SharedMap, StringIter, Tags};
 use crate::support;
 
+use simple_sds::bit_vector::BitVector;
+use simple_sds::int_vector::IntVector;
+use simple_sds::ops::{Vector, Access, BitVec, Rank, Select, PredSucc};
+use simple_sds::raw_vector::{RawVector, AccessRaw};
 use simple_sds::serialize::{Serialize, Serializable};
-use simple_sds::serialize;
+use simple_sds::sparse_vector::{SparseVector, SparseBuilder};
+use simple_sds::bits;
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::io::{Error, ErrorKind};
-use std::iter::FusedIterator;
+use std::iter::{Copied, FusedIterator};
 use std::ops::Range;
-use std::{io, slice};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::{cmp, fmt, io, slice};
 
 #[cfg(test)]
 mod tests;
@@ -96,7 +107,9 @@ pub struct GBWT {
     tags: Tags,
     bwt: BWT,
     endmarker: Vec<Pos>,
+    da_samples: Option<DASamples>,
     metadata: Option<Metadata>,
+    sequence_samples: Option<SequenceSamples>,
 }
 
 /// Index statistics.
@@ -172,6 +185,21 @@ impl GBWT {
     }
 }
 
+/// Document array samples.
+impl GBWT {
+    /// Returns `true` if the index contains document array samples.
+    ///
+    /// The samples are required for [`GBWT::locate`] and [`GBWT::locate_pos`].
+    pub fn has_da_samples(&self) -> bool {
+        self.da_samples.is_some()
+    }
+
+    /// Returns a reference to the document array samples, or [`None`] if there are no samples.
+    pub fn da_samples(&self) -> Option<&DASamples> {
+        self.da_samples.as_ref()
+    }
+}
+
 /// Metadata.
 impl GBWT {
     /// Returns `true` if the index contains metadata.
@@ -183,6 +211,38 @@ impl GBWT {
     pub fn metadata(&self) -> Option<&Metadata> {
         self.metadata.as_ref()
     }
+
+    /// Attaches the given metadata to the index, replacing any existing metadata.
+    ///
+    /// Returns [`crate::Error::MetadataMismatch`] if the metadata has path names but the number of paths does not match the number of sequences.
+    /// In a bidirectional index, there must be one path name for every two sequences.
+    pub fn set_metadata(&mut self, metadata: Metadata) -> Result<(), crate::Error> {
+        if metadata.has_path_names() {
+            let expected = if self.is_bidirectional() { self.sequences() / 2 } else { self.sequences() };
+            if metadata.paths() != expected {
+                return Err(crate::Error::MetadataMismatch("GBWT: Invalid path count in the metadata"));
+            }
+        }
+        self.header.set(GBWTPayload::FLAG_METADATA);
+        self.metadata = Some(metadata);
+        self.update_reference_samples_tag();
+        Ok(())
+    }
+
+    /// Removes the metadata from the index.
+    pub fn remove_metadata(&mut self) {
+        self.header.unset(GBWTPayload::FLAG_METADATA);
+        self.metadata = None;
+        self.update_reference_samples_tag();
+    }
+
+    // Sets the reference samples tag to match the metadata.
+    fn update_reference_samples_tag(&mut self) {
+        match self.metadata.as_ref().and_then(|metadata| metadata.reference_samples_tag()) {
+            Some(value) => self.tags.insert(REFERENCE_SAMPLES_KEY, &value),
+            None => { let _ = self.tags.remove(REFERENCE_SAMPLES_KEY); },
+        }
+    }
 }
 
 impl AsRef<BWT> for GBWT {
@@ -193,6 +253,254 @@ impl AsRef<BWT> for GBWT {
 
 //-----------------------------------------------------------------------------
 
+// A source of node records for the sequence navigation and subpath search queries.
+// The queries are implemented once here and shared by `GBWT`, `CachedGBWT`, and `DiskGBWT`, which only differ in how they access the records.
+pub(crate) trait RecordSource {
+    // The type of the records passed to the callbacks.
+    type Record<'a>: NodeRecord;
+
+    fn alphabet_size(&self) -> usize;
+
+    fn alphabet_offset(&self) -> usize;
+
+    fn is_bidirectional(&self) -> bool;
+
+    // Calls `f` with the record for the given node and returns the result.
+    // Returns `None` if the node is neither the endmarker nor in the effective alphabet, or if the record cannot be decoded.
+    fn with_record<T, F: for<'a> FnOnce(&Self::Record<'a>) -> Option<T>>(&self, node: usize, f: F) -> Option<T>;
+
+    fn first_node(&self) -> usize {
+        self.alphabet_offset() + 1
+    }
+
+    fn has_node(&self, id: usize) -> bool {
+        id > self.alphabet_offset() && id < self.alphabet_size()
+    }
+
+    // Returns the record identifier for the node, or `None` if there is no record for it.
+    fn record_id(&self, node: usize) -> Option<usize> {
+        if node == ENDMARKER {
+            Some(ENDMARKER)
+        } else if self.has_node(node) {
+            Some(node - self.alphabet_offset())
+        } else {
+            None
+        }
+    }
+
+    fn forward(&self, pos: Pos) -> Option<Pos> {
+        // This also catches the endmarker.
+        if pos.node < self.first_node() {
+            return None;
+        }
+        self.with_record(pos.node, |record| record.lf(pos.offset))
+    }
+
+    fn backward(&self, pos: Pos) -> Option<Pos> {
+        assert!(self.is_bidirectional(), "Following sequences backward requires a bidirectional GBWT");
+        // This also catches the endmarker.
+        if pos.node < self.first_node() {
+            return None;
+        }
+        let predecessor = self.with_record(support::flip_node(pos.node), |record| record.predecessor_at(pos.offset))?;
+        let offset = self.with_record(predecessor, |record| record.offset_to(pos))?;
+        Some(Pos::new(predecessor, offset))
+    }
+
+    fn find(&self, node: usize) -> Option<SearchState> {
+        // This also catches the endmarker.
+        if node < self.first_node() {
+            return None;
+        }
+        let len = self.with_record(node, |record| Some(record.len()))?;
+        Some(SearchState {
+            node,
+            range: 0..len,
+        })
+    }
+
+    fn extend(&self, state: &SearchState, node: usize) -> Option<SearchState> {
+        // This also catches the endmarker.
+        if node < self.first_node() {
+            return None;
+        }
+        let range = self.with_record(state.node, |record| record.follow(state.range.clone(), node))?;
+        Some(SearchState {
+            node, range,
+        })
+    }
+
+    fn bd_find(&self, node: usize) -> Option<BidirectionalState> {
+        assert!(self.is_bidirectional(), "Bidirectional search requires a bidirectional GBWT");
+        let state = self.find(node)?;
+        let reverse = SearchState {
+            node: support::flip_node(state.node),
+            range: state.range.clone(),
+        };
+        Some(BidirectionalState {
+            forward: state,
+            reverse,
+        })
+    }
+
+    fn extend_forward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+        assert!(self.is_bidirectional(), "Bidirectional search requires a bidirectional GBWT");
+        // This also catches the endmarker.
+        if node < self.first_node() {
+            return None;
+        }
+        self.with_record(state.forward.node, |record| bd_extend(record, state, node))
+    }
+
+    fn extend_backward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+        let result = self.extend_forward(&state.flip(), support::flip_node(node))?;
+        Some(result.flip())
+    }
+
+    // Returns a search state for the pattern, or `None` if there are no occurrences.
+    fn forward_search(&self, pattern: &[usize]) -> Option<SearchState> {
+        let mut state = self.find(*pattern.first()?)?;
+        for node in pattern[1..].iter() {
+            state = self.extend(&state, *node)?;
+        }
+        Some(state)
+    }
+
+    fn longest_match(&self, query: &[usize]) -> Option<(Range<usize>, SearchState)> {
+        let last = *query.last()?;
+        if self.is_bidirectional() {
+            let mut state = self.bd_find(last)?;
+            let mut start = query.len() - 1;
+            while start > 0 {
+                match self.extend_backward(&state, query[start - 1]) {
+                    Some(next) => {
+                        state = next;
+                        start -= 1;
+                    },
+                    None => break,
+                }
+            }
+            return Some((start..query.len(), state.forward));
+        }
+
+        // If a suffix occurs in the index, all shorter suffixes also occur.
+        // We double the length of the suffix until it no longer occurs and then find the longest one with binary search.
+        let mut state = self.find(last)?;
+        let mut low = 1;
+        let mut high = None;
+        while low < query.len() {
+            let len = cmp::min(2 * low, query.len());
+            match self.forward_search(&query[query.len() - len..]) {
+                Some(next) => {
+                    state = next;
+                    low = len;
+                },
+                None => {
+                    high = Some(len);
+                    break;
+                },
+            }
+        }
+        if let Some(mut high) = high {
+            while high - low > 1 {
+                let len = low + (high - low) / 2;
+                match self.forward_search(&query[query.len() - len..]) {
+                    Some(next) => {
+                        state = next;
+                        low = len;
+                    },
+                    None => high = len,
+                }
+            }
+        }
+        Some((query.len() - low..query.len(), state))
+    }
+
+    fn maximal_matches(&self, query: &[usize]) -> Vec<(Range<usize>, SearchState)> {
+        assert!(self.is_bidirectional(), "Maximal exact matches require a bidirectional GBWT");
+        let mut result: Vec<(Range<usize>, SearchState)> = Vec::new();
+
+        let mut offset = 0;
+        while offset < query.len() {
+            let mut state = match self.bd_find(query[offset]) {
+                Some(state) => state,
+                None => {
+                    offset += 1;
+                    continue;
+                },
+            };
+
+            // Longest match ending at the offset.
+            let mut start = offset;
+            while start > 0 {
+                match self.extend_backward(&state, query[start - 1]) {
+                    Some(next) => {
+                        state = next;
+                        start -= 1;
+                    },
+                    None => break,
+                }
+            }
+
+            // Extend the match forward.
+            let mut end = offset + 1;
+            while end < query.len() {
+                match self.extend_forward(&state, query[end]) {
+                    Some(next) => {
+                        state = next;
+                        end += 1;
+                    },
+                    None => break,
+                }
+            }
+
+            result.push((start..end, state.forward));
+            offset = end;
+        }
+
+        result
+    }
+}
+
+// Extends the bidirectional search state forward using the record for `state.forward.node`.
+fn bd_extend<R: NodeRecord>(record: &R, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
+    let (range, offset) = record.bd_follow(state.forward.range.clone(), node)?;
+    let forward = SearchState {
+        node, range,
+    };
+    let pos = state.reverse.range.start + offset;
+    let reverse = SearchState {
+        node: state.reverse.node,
+        range: pos..pos + forward.len(),
+    };
+    Some(BidirectionalState {
+        forward, reverse,
+    })
+}
+
+impl RecordSource for GBWT {
+    type Record<'a> = Record<'a>;
+
+    fn alphabet_size(&self) -> usize {
+        GBWT::alphabet_size(self)
+    }
+
+    fn alphabet_offset(&self) -> usize {
+        GBWT::alphabet_offset(self)
+    }
+
+    fn is_bidirectional(&self) -> bool {
+        GBWT::is_bidirectional(self)
+    }
+
+    fn with_record<T, F: for<'a> FnOnce(&Self::Record<'a>) -> Option<T>>(&self, node: usize, f: F) -> Option<T> {
+        let record = self.bwt.record(self.record_id(node)?)?;
+        f(&record)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
 /// Sequence navigation.
 impl GBWT {
     /// Returns the first position in sequence `id`.
@@ -208,12 +516,7 @@ impl GBWT {
 
     /// Follows the sequence forward and returns the next position, or [`None`] if no such position exists.
     pub fn forward(&self, pos: Pos) -> Option<Pos> {
-        // This also catches the endmarker.
-        if pos.node < self.first_node() {
-            return None;
-        }
-        let record = self.bwt.record(self.node_to_record(pos.node))?;
-        record.lf(pos.offset)
+        RecordSource::forward(self, pos)
     }
 
     /// Follows the sequence backward and returns the previous position, or [`None`] if no such position exists.
@@ -222,31 +525,153 @@ impl GBWT {
     ///
     /// Panics if the index is not bidirectional.
     pub fn backward(&self, pos: Pos) -> Option<Pos> {
-        assert!(self.is_bidirectional(), "Following sequences backward requires a bidirectional GBWT");
-        // This also catches the endmarker.
-        if pos.node <= self.first_node() {
+        RecordSource::backward(self, pos)
+    }
+
+    /// Returns an iterator over sequence `id`, or [`None`] if there is no such sequence.
+    pub fn sequence(&self, id: usize) -> Option<SequenceIter> {
+        if id >= self.sequences() {
             return None;
         }
+        Some(SequenceIter {
+            parent: self,
+            next: self.start(id),
+            remaining: self.len(),
+        })
+    }
 
-        let reverse_id = self.node_to_record(support::flip_node(pos.node));
-        let record = self.bwt.record(reverse_id)?;
-        let predecessor = record.predecessor_at(pos.offset)?;
-        let pred_record = self.bwt.record(self.node_to_record(predecessor))?;
-        let offset = pred_record.offset_to(pos)?;
+    /// Returns an iterator over sequence `id` in reverse order, or [`None`] if there is no such sequence.
+    ///
+    /// In a unidirectional index, this buffers the entire sequence.
+    /// See [`ReverseSequenceIter`] for details.
+    pub fn sequence_rev(&self, id: usize) -> Option<ReverseSequenceIter> {
+        if id >= self.sequences() {
+            return None;
+        }
+        if self.is_bidirectional() {
+            // The reverse sequence visits the same nodes in the opposite order and orientation.
+            return Some(ReverseSequenceIter {
+                reverse: Some(SequenceIter {
+                    parent: self,
+                    next: self.start(support::flip_path(id)),
+                    remaining: self.len(),
+                }),
+                buffer: Vec::new(),
+            });
+        }
+        let buffer: Vec<usize> = self.sequence(id)?.collect();
+        Some(ReverseSequenceIter {
+            reverse: None,
+            buffer,
+        })
+    }
 
-        Some(Pos::new(predecessor, offset))
+    /// Returns the position at offset `offset` of sequence `id`, or [`None`] if there is no such position.
+    ///
+    /// Uses sequence samples if they are present (see [`GBWT::build_sequence_samples`]).
+    /// Otherwise the sequence is followed from the start.
+    pub fn position_at(&self, id: usize, offset: usize) -> Option<Pos> {
+        let (mut pos, mut remaining) = match self.sequence_samples.as_ref() {
+            Some(samples) => samples.sample_before(id, offset)?,
+            None => (self.start(id)?, offset),
+        };
+        // No sequence is longer than the index, even if a malformed index contains a cycle.
+        if remaining >= self.len() {
+            return None;
+        }
+        while remaining > 0 {
+            pos = self.forward(pos)?;
+            remaining -= 1;
+        }
+        Some(pos)
     }
 
-    /// Returns an iterator over sequence `id`, or [`None`] if there is no such sequence.
-    pub fn sequence(&self, id: usize) -> Option<SequenceIter> {
+    /// Returns an iterator over sequence `id` starting from offset `offset`, or [`None`] if there is no such sequence.
+    ///
+    /// If the offset is past the end of the sequence, the iterator is empty.
+    /// Uses sequence samples if they are present (see [`GBWT::build_sequence_samples`]).
+    pub fn sequence_from(&self, id: usize, offset: usize) -> Option<SequenceIter> {
         if id >= self.sequences() {
             return None;
         }
         Some(SequenceIter {
             parent: self,
-            next: self.start(id),
+            next: self.position_at(id, offset),
+            remaining: self.len(),
         })
     }
+
+    /// Returns the length of sequence `id`, or [`None`] if there is no such sequence.
+    ///
+    /// Uses sequence samples if they are present (see [`GBWT::build_sequence_samples`]).
+    /// Otherwise the sequence is followed from the start.
+    pub fn sequence_len(&self, id: usize) -> Option<usize> {
+        if id >= self.sequences() {
+            return None;
+        }
+        match self.sequence_samples.as_ref() {
+            Some(samples) => Some(samples.sequence_len(id)),
+            None => Some(self.sequence(id)?.count()),
+        }
+    }
+
+    /// Extracts the given offset range of sequence `id` as a vector of nodes.
+    ///
+    /// The range is truncated to the length of the sequence.
+    /// Returns an empty vector if there is no such sequence.
+    /// Uses sequence samples if they are present (see [`GBWT::build_sequence_samples`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, Orientation};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    /// index.build_sequence_samples(2);
+    ///
+    /// // Extract the last two nodes of path 3 in reverse orientation.
+    /// let id = support::encode_path(3, Orientation::Reverse);
+    /// let len = index.sequence_len(id).unwrap();
+    /// assert_eq!(len, 5);
+    /// assert_eq!(index.extract(id, len - 2..len), vec![27, 23]);
+    /// ```
+    pub fn extract(&self, id: usize, range: Range<usize>) -> Vec<usize> {
+        match self.sequence_from(id, range.start) {
+            Some(iter) => iter.take(range.len()).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Sequence samples.
+impl GBWT {
+    /// Returns `true` if the index contains sequence samples.
+    ///
+    /// Sequence samples speed up [`GBWT::position_at`], [`GBWT::sequence_from`], [`GBWT::sequence_len`], and [`GBWT::extract`].
+    /// They are not serialized with the index.
+    pub fn has_sequence_samples(&self) -> bool {
+        self.sequence_samples.is_some()
+    }
+
+    /// Returns a reference to the sequence samples, or [`None`] if there are no samples.
+    pub fn sequence_samples(&self) -> Option<&SequenceSamples> {
+        self.sequence_samples.as_ref()
+    }
+
+    /// Samples every `interval`th position on each sequence, replacing any existing samples.
+    ///
+    /// Values smaller than `1` are treated as `1`.
+    pub fn build_sequence_samples(&mut self, interval: usize) {
+        self.sequence_samples = Some(SequenceSamples::new(self, interval));
+    }
+
+    /// Removes the sequence samples.
+    pub fn remove_sequence_samples(&mut self) {
+        self.sequence_samples = None;
+    }
 }
 
 //-----------------------------------------------------------------------------
@@ -255,17 +680,7 @@ impl GBWT {
 impl GBWT {
     /// Returns a search state for all occurrences of the given node, or [`None`] if no such node exists.
     pub fn find(&self, node: usize) -> Option<SearchState> {
-        // This also catches the endmarker.
-        if node < self.first_node() {
-            return None;
-        }
-        if let Some(record) = self.bwt.record(self.node_to_record(node)) {
-            return Some(SearchState {
-                node,
-                range: 0..record.len(),
-            });
-        }
-        None
+        RecordSource::find(self, node)
     }
 
     /// Extends the search by the given node forward and returns the new search state, or [`None`] if no such extensions exist.
@@ -278,18 +693,7 @@ impl GBWT {
     /// * `state`: A search state corresponding to a set of substring occurrences.
     /// * `node`: Node to extend the substrings with.
     pub fn extend(&self, state: &SearchState, node: usize) -> Option<SearchState> {
-        // This also catches the endmarker.
-        if node < self.first_node() {
-            return None;
-        }
-        if let Some(record) = self.bwt.record(self.node_to_record(state.node)) {
-            if let Some(range) = record.follow(state.range.clone(), node) {
-                return Some(SearchState {
-                    node, range,
-                })
-            }
-        }
-        None
+        RecordSource::extend(self, state, node)
     }
 
     /// Returns a bidirectional search state for all occurrences of the given node, or [`None`] if no such node exists.
@@ -298,18 +702,7 @@ impl GBWT {
     ///
     /// Will panic if the index is not bidirectional.
     pub fn bd_find(&self, node: usize) -> Option<BidirectionalState> {
-        assert!(self.is_bidirectional(), "Bidirectional search requires a bidirectional GBWT");
-        if let Some(state) = self.find(node) {
-            let reverse = SearchState {
-                node: support::flip_node(state.node),
-                range: state.range.clone(),
-            };
-            return Some(BidirectionalState {
-                forward: state,
-                reverse,
-            });
-        }
-        None
+        RecordSource::bd_find(self, node)
     }
 
     /// Extends the search by the given node forward and returns the new search state, or [`None`] if no such extensions exist.
@@ -326,13 +719,7 @@ impl GBWT {
     ///
     /// Will panic if the index is not bidirectional.
     pub fn extend_forward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
-        assert!(self.is_bidirectional(), "Bidirectional search requires a bidirectional GBWT");
-        // This also catches the endmarker.
-        if node < self.first_node() {
-            return None;
-        }
-        let record = self.bwt.record(self.node_to_record(state.forward.node))?;
-        Self::bd_internal(&record, state, node)
+        RecordSource::extend_forward(self, state, node)
     }
 
     /// Extends the search by the given node backward and returns the new search state, or [`None`] if no such extensions exist.
@@ -349,174 +736,1231 @@ impl GBWT {
     ///
     /// Will panic if the index is not bidirectional.
     pub fn extend_backward(&self, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
-        if let Some(result) = self.extend_forward(&state.flip(), support::flip_node(node)) {
-            return Some(result.flip());
-        }
-        None
+        RecordSource::extend_backward(self, state, node)
     }
 
     // Internal implementation of bidirectional search. Extends the state forward.
     #[doc(hidden)]
     pub fn bd_internal(record: &Record, state: &BidirectionalState, node: usize) -> Option<BidirectionalState> {
-        let (range, offset) = record.bd_follow(state.forward.range.clone(), node)?;
-        let forward = SearchState {
-            node, range,
-        };
-        let pos = state.reverse.range.start + offset;
-        let reverse = SearchState {
-            node: state.reverse.node,
-            range: pos..pos + forward.len(),
-        };
-        Some(BidirectionalState {
-            forward, reverse,
-        })
+        bd_extend(record, state, node)
     }
 }
 
 //-----------------------------------------------------------------------------
 
-impl Serialize for GBWT {
-    fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
-        self.header.serialize(writer)
+/// Matching queries.
+impl GBWT {
+    /// Returns the longest suffix of the query that occurs in the index as a range of query offsets and a search state, or [`None`] if no such suffix exists.
+    ///
+    /// In a bidirectional index, the suffix is found by extending the search backward one node at a time.
+    /// This takes O(m) extension steps, where `m` is the length of the match.
+    ///
+    /// A unidirectional index supports only forward extension, and each suffix must be searched separately.
+    /// Because all suffixes shorter than the match also occur in the index, the length of the suffix is doubled until it no longer occurs, and the longest occurring suffix is then found with binary search.
+    /// This takes O(m log m) extension steps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, Orientation};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let index: GBWT = serialize::load_from(&filename).unwrap();
+    ///
+    /// let query = vec![
+    ///     support::encode_node(13, Orientation::Forward),
+    ///     support::encode_node(12, Orientation::Forward),
+    ///     support::encode_node(14, Orientation::Forward),
+    ///     support::encode_node(15, Orientation::Forward),
+    /// ];
+    /// let (range, state) = index.longest_match(&query).unwrap();
+    /// assert_eq!(range, 1..4);
+    /// assert_eq!(state.len(), 2);
+    /// ```
+    pub fn longest_match(&self, query: &[usize]) -> Option<(Range<usize>, SearchState)> {
+        RecordSource::longest_match(self, query)
+    }
+
+    /// Returns all maximal exact matches between the query and the index.
+    ///
+    /// A maximal exact match is a substring of the query that occurs in the index but cannot be extended in either direction without losing all occurrences.
+    /// Each match is reported as a range of query offsets and a search state for the occurrences.
+    /// The matches are in sorted order by their starting offsets.
+    ///
+    /// The algorithm finds the longest match ending at the first query offset not covered by the previous match using backward search and then extends it forward as far as possible.
+    /// Every maximal exact match is found this way, because a match ending within the previous match would be contained in it.
+    ///
+    /// The forward extensions visit each query offset at most once.
+    /// The backward extension revisits the overlap with the previous match.
+    /// Shortening the previous match from the left instead would require longest common prefix information, which the GBWT does not store.
+    /// The total number of extension steps is therefore O(n + k), where `n` is the length of the query and `k` is the total length of the matches.
+    /// This is linear in the length of the query, unless the matches overlap heavily.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the index is not bidirectional.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, Orientation};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let index: GBWT = serialize::load_from(&filename).unwrap();
+    ///
+    /// let query = vec![
+    ///     support::encode_node(11, Orientation::Forward),
+    ///     support::encode_node(12, Orientation::Forward),
+    ///     support::encode_node(14, Orientation::Forward),
+    ///     support::encode_node(16, Orientation::Forward),
+    ///     support::encode_node(17, Orientation::Forward),
+    /// ];
+    /// let matches = index.maximal_matches(&query);
+    /// let ranges: Vec<_> = matches.iter().map(|(range, _)| range.clone()).collect();
+    /// assert_eq!(ranges, vec![0..3, 2..5]);
+    /// ```
+    pub fn maximal_matches(&self, query: &[usize]) -> Vec<(Range<usize>, SearchState)> {
+        RecordSource::maximal_matches(self, query)
     }
+}
 
-    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
-        self.tags.serialize(writer)?;
-        self.bwt.serialize(writer)?;
-        serialize::absent_option(writer)?; // Document array samples.
-        self.metadata.serialize(writer)?;
-        Ok(())
-    }
+//-----------------------------------------------------------------------------
 
-    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
-        let header = Header::<GBWTPayload>::load(reader)?;
-        if let Err(msg) = header.validate() {
-            return Err(Error::new(ErrorKind::InvalidData, msg));
+/// Locate queries.
+impl GBWT {
+    /// Returns the identifier of the sequence containing the given position, or [`None`] if there is no such position.
+    ///
+    /// Follows the sequence forward until a sampled position is found.
+    /// Positions in the endmarker record are located directly, as offset `i` in the endmarker corresponds to the start of sequence `i`.
+    /// Other positions cannot be located without document array samples, and the return value is [`None`] if the index does not contain them.
+    /// The return value is also [`None`] if a malformed index does not reach a sample within [`GBWT::len`] steps.
+    pub fn locate_pos(&self, pos: Pos) -> Option<usize> {
+        if pos.node == ENDMARKER {
+            return if pos.offset < self.sequences() { Some(pos.offset) } else { None };
+        }
+        let samples = self.da_samples.as_ref()?;
+        if !self.has_node(pos.node) {
+            return None;
         }
 
-        let mut tags = Tags::load(reader)?;
-        tags.insert(SOURCE_KEY, SOURCE_VALUE);
-
-        let bwt = BWT::load(reader)?;
-
-        // Decompress the endmarker, as the record can be poorly compressible.
-        let endmarker = if bwt.is_empty() { Vec::new() } else { bwt.record(ENDMARKER).unwrap().decompress() };
+        // A malformed index may contain a cycle without samples, but no sequence is longer than the index.
+        let mut pos = pos;
+        for _ in 0..self.len() {
+            if let Some(id) = samples.try_locate(self.node_to_record(pos.node), pos.offset) {
+                return Some(id);
+            }
+            pos = self.forward(pos)?;
+        }
+        None
+    }
 
-        serialize::skip_option(reader)?; // Document array samples.
+    /// Returns the identifiers of the sequences containing the occurrences in the search state.
+    ///
+    /// The identifiers are in sorted order without duplicates.
+    /// Returns an empty vector if the search state is empty or invalid.
+    /// Occurrences outside the endmarker cannot be located without document array samples, and the result is empty if the index does not contain them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, Orientation};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let index: GBWT = serialize::load_from(&filename).unwrap();
+    /// assert!(index.has_da_samples());
+    ///
+    /// // Subpath (12, forward), (14, forward) occurs on paths 0 and 2.
+    /// let state = index.find(support::encode_node(12, Orientation::Forward)).unwrap();
+    /// let state = index.extend(&state, support::encode_node(14, Orientation::Forward)).unwrap();
+    /// let expected = vec![
+    ///     support::encode_path(0, Orientation::Forward),
+    ///     support::encode_path(2, Orientation::Forward),
+    /// ];
+    /// assert_eq!(index.locate(&state), expected);
+    /// ```
+    pub fn locate(&self, state: &SearchState) -> Vec<usize> {
+        self.distinct_sequences(state).into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Returns the distinct sequences containing the occurrences in the search state as (sequence identifier, number of occurrences) pairs.
+    ///
+    /// The pairs are sorted by sequence identifier.
+    /// Returns an empty vector if the search state is empty or invalid.
+    /// Occurrences outside the endmarker cannot be located without document array samples, and the result is empty if the index does not contain them.
+    /// For a [`BidirectionalState`], use the forward state.
+    ///
+    /// The occurrences are followed forward in parallel as offset ranges.
+    /// In each step, the record for each node is scanned once, and the sampled positions in the ranges are found without visiting the other positions.
+    /// The ranges are split at sampled positions, and the remaining occurrences continue to the next step.
+    /// Hence the running time depends on the number of ranges and sampled positions rather than the number of occurrences.
+    /// If an occurrence reaches the end of a sequence without a sampled position, the samples are invalid and the result is empty.
+    pub fn distinct_sequences(&self, state: &SearchState) -> Vec<(usize, usize)> {
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        if state.node == ENDMARKER {
+            for offset in state.range.start..cmp::min(state.range.end, self.sequences()) {
+                *counts.entry(offset).or_insert(0) += 1;
+            }
+            return counts.into_iter().collect();
+        }
+        let samples = match self.da_samples.as_ref() {
+            Some(samples) => samples,
+            None => return Vec::new(),
+        };
+        if !self.has_node(state.node) || state.is_empty() {
+            return Vec::new();
+        }
+
+        let mut active: Vec<(usize, Range<usize>)> = vec![(state.node, state.range.clone())];
+        while !active.is_empty() {
+            active.sort_unstable_by_key(|(node, range)| (*node, range.start));
+            let mut next: Vec<(usize, Range<usize>)> = Vec::new();
+            let mut start = 0;
+            while start < active.len() {
+                let node = active[start].0;
+                let limit = start + active[start..].iter().take_while(|(n, _)| *n == node).count();
+                let record_id = self.node_to_record(node);
+                let record = match self.bwt.record(record_id) {
+                    Some(record) => record,
+                    None => {
+                        start = limit;
+                        continue;
+                    },
+                };
+
+                // Merge adjacent ranges and split them at sampled positions.
+                let mut unsampled: Vec<Range<usize>> = Vec::new();
+                let mut merged: Vec<Range<usize>> = Vec::new();
+                for (_, range) in active[start..limit].iter() {
+                    match merged.last_mut() {
+                        Some(prev) if prev.end >= range.start => prev.end = cmp::max(prev.end, range.end),
+                        _ => merged.push(range.clone()),
+                    }
+                }
+                for range in merged {
+                    let mut from = range.start;
+                    for (offset, id) in samples.sampled_range(record_id, range.clone()) {
+                        *counts.entry(id).or_insert(0) += 1;
+                        if from < offset {
+                            unsampled.push(from..offset);
+                        }
+                        from = offset + 1;
+                    }
+                    if from < range.end {
+                        unsampled.push(from..range.end);
+                    }
+                }
+
+                for (_, successor, range) in record.follow_ranges(&unsampled) {
+                    if successor == ENDMARKER {
+                        return Vec::new();
+                    }
+                    next.push((successor, range));
+                }
+                start = limit;
+            }
+            active = next;
+        }
+
+        counts.into_iter().collect()
+    }
+
+    /// Returns the distinct paths containing the occurrences in the search state as (path identifier, number of occurrences) pairs.
+    ///
+    /// In a bidirectional index, both orientations of a path count as the same path.
+    /// Otherwise path identifiers are the same as sequence identifiers.
+    /// The pairs are sorted by path identifier.
+    /// See [`GBWT::distinct_sequences`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, Orientation};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let index: GBWT = serialize::load_from(&filename).unwrap();
+    ///
+    /// // Node (14, forward) is visited by paths 0, 2, and 3.
+    /// let state = index.find(support::encode_node(14, Orientation::Forward)).unwrap();
+    /// assert_eq!(index.distinct_paths(&state), vec![(0, 1), (2, 1), (3, 1)]);
+    ///
+    /// // The occurrences are divided between the samples.
+    /// let samples = index.distinct_samples(&state).unwrap();
+    /// assert_eq!(samples.iter().map(|(_, count)| count).sum::<usize>(), 3);
+    /// ```
+    pub fn distinct_paths(&self, state: &SearchState) -> Vec<(usize, usize)> {
+        let sequences = self.distinct_sequences(state);
+        if !self.is_bidirectional() {
+            return sequences;
+        }
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for (id, count) in sequences {
+            *counts.entry(support::path_id(id)).or_insert(0) += count;
+        }
+        counts.into_iter().collect()
+    }
+
+    /// Returns the distinct samples containing the occurrences in the search state as (sample identifier, number of occurrences) pairs.
+    ///
+    /// The pairs are sorted by sample identifier.
+    /// Returns [`None`] if the index does not contain path names.
+    /// See [`GBWT::distinct_sequences`] for details.
+    pub fn distinct_samples(&self, state: &SearchState) -> Option<Vec<(usize, usize)>> {
+        self.distinct_path_fields(state, |path_name| path_name.sample())
+    }
+
+    /// Returns the distinct contigs containing the occurrences in the search state as (contig identifier, number of occurrences) pairs.
+    ///
+    /// The pairs are sorted by contig identifier.
+    /// Returns [`None`] if the index does not contain path names.
+    /// See [`GBWT::distinct_sequences`] for details.
+    pub fn distinct_contigs(&self, state: &SearchState) -> Option<Vec<(usize, usize)>> {
+        self.distinct_path_fields(state, |path_name| path_name.contig())
+    }
+
+    // Maps the distinct paths to the given field of the path name and merges the counts.
+    fn distinct_path_fields<F: Fn(&PathName) -> usize>(&self, state: &SearchState, field: F) -> Option<Vec<(usize, usize)>> {
+        let metadata = self.metadata()?;
+        if !metadata.has_path_names() {
+            return None;
+        }
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for (id, count) in self.distinct_paths(state) {
+            let path_name = metadata.path(id)?;
+            *counts.entry(field(&path_name)).or_insert(0) += count;
+        }
+        Some(counts.into_iter().collect())
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Memory mapping.
+impl GBWT {
+    /// Opens the index in the given file using a shared read-only memory mapping.
+    ///
+    /// The BWT and the sample and contig names in the metadata are borrowed from the mapping.
+    /// The names are decompressed into memory on first access; see [`Metadata::is_mapped`].
+    /// The endmarker record, the document array samples, and the rest of the metadata are copied into memory, exactly as when loading the index with [`simple_sds::serialize::load_from`].
+    /// Multiple processes opening the same file share the mapped pages through the page cache, and the BWT is read from disk only when needed.
+    ///
+    /// Returns [`crate::Error::Io`] if the file cannot be mapped and the same errors as when loading the index otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::GBWT;
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let mapped = GBWT::open_mmap(&filename).unwrap();
+    /// let loaded: GBWT = serialize::load_from(&filename).unwrap();
+    ///
+    /// assert!(mapped.is_mapped());
+    /// assert_eq!(mapped, loaded);
+    /// ```
+    pub fn open_mmap<P: AsRef<Path>>(filename: P) -> Result<Self, crate::Error> {
+        let map = SharedMap::open(filename)?;
+        let mut reader = io::Cursor::new(map.bytes());
+        Self::load_mapped(&mut reader, &map).map_err(crate::Error::from)
+    }
+
+    /// Loads the index from a reader over the bytes of a memory mapped file.
+    ///
+    /// The BWT and the metadata are borrowed from the mapping; see [`BWT::load_mapped`] and [`Metadata::load_mapped`].
+    pub fn load_mapped(reader: &mut io::Cursor<&[u8]>, map: &Arc<SharedMap>) -> io::Result<Self> {
+        Self::load_with(reader, |reader| BWT::load_mapped(reader, map), |reader| {
+            // An optional structure is serialized as its size in elements followed by the structure, with size 0 for an absent structure.
+            let size = usize::load(reader)?;
+            if size == 0 { Ok(None) } else { Metadata::load_mapped(reader, map).map(Some) }
+        })
+    }
+
+    /// Returns `true` if the BWT is borrowed from a memory mapping.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        self.bwt.is_mapped()
+    }
+
+    // Loads the index using the given functions for loading the BWT and the metadata.
+    fn load_with<T, F, G>(reader: &mut T, load_bwt: F, load_metadata: G) -> io::Result<Self>
+        where T: io::Read, F: FnOnce(&mut T) -> io::Result<BWT>, G: FnOnce(&mut T) -> io::Result<Option<Metadata>>
+    {
+        let header = Header::<GBWTPayload>::load(reader)?;
+        header.validate()?;
+
+        let mut tags = Tags::load(reader)?;
+        tags.insert(SOURCE_KEY, SOURCE_VALUE);
+
+        let bwt = load_bwt(reader)?;
+        if bwt.len() != header.payload().alphabet_size - header.payload().offset {
+            return Err(crate::Error::InvalidData(String::from("GBWT: Invalid number of records in the BWT")).into());
+        }
+
+        // Decompress the endmarker, as the record can be poorly compressible.
+        // The record is decoded with checks, as the other records are only decoded on demand.
+        let endmarker = if bwt.is_empty() {
+            Vec::new()
+        } else {
+            let record = bwt.checked_record(ENDMARKER)?;
+            let len = record.as_ref().map_or(0, |record| record.len());
+            if len != header.payload().sequences {
+                return Err(crate::Error::InvalidData(String::from("GBWT: Endmarker length does not match the number of sequences")).into());
+            }
+            record.map(|record| record.decompress()).unwrap_or_default()
+        };
+
+        // Document array samples.
+        let da_samples = Option::<DASamples>::load(reader)?;
+        if let Some(samples) = da_samples.as_ref() {
+            if samples.records() != bwt.len() {
+                return Err(crate::Error::MetadataMismatch("GBWT: Document array samples do not match the number of records").into());
+            }
+            if samples.max_id().map_or(false, |id| id >= header.payload().sequences) {
+                return Err(crate::Error::InvalidData(String::from("GBWT: Document array samples refer to nonexistent sequences")).into());
+            }
+        }
 
         // Metadata.
-        let metadata = Option::<Metadata>::load(reader)?;
+        let mut metadata = load_metadata(reader)?;
         if header.is_set(GBWTPayload::FLAG_METADATA) != metadata.is_some() {
-            return Err(Error::new(ErrorKind::InvalidData, "GBWT: Invalid metadata flag in the header"));
+            return Err(crate::Error::MetadataMismatch("GBWT: Invalid metadata flag in the header").into());
         }
         if let Some(meta) = metadata.as_ref() {
             if meta.has_path_names() {
                 let expected = if header.is_set(GBWTPayload::FLAG_BIDIRECTIONAL) { header.payload().sequences / 2 } else { header.payload().sequences };
                 if meta.paths() > 0 && meta.paths() != expected {
-                    return Err(Error::new(ErrorKind::InvalidData, "GBWT: Invalid path count in the metadata"));
+                    return Err(crate::Error::MetadataMismatch("GBWT: Invalid path count in the metadata").into());
                 }
             }
         }
+        if let (Some(meta), Some(value)) = (metadata.as_mut(), tags.get(REFERENCE_SAMPLES_KEY)) {
+            meta.set_reference_samples_tag(value);
+        }
 
         Ok(GBWT {
-            header, tags, bwt, endmarker, metadata,
+            header, tags, bwt, endmarker, da_samples, metadata,
+            sequence_samples: None,
         })
     }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Validation.
+impl GBWT {
+    /// Checks the integrity of the index and returns a report of the problems found.
+    ///
+    /// Loading an index only checks the headers, the sizes of the structures, and the endmarker record, while this decodes every record.
+    /// The following properties are checked:
+    ///
+    /// * Every record can be decoded, and the successor nodes exist.
+    /// * The incoming edges to each node cover the offsets in the node exactly, which makes LF-mapping consistent between the predecessors and the successors.
+    /// * The total length of the records and the number of sequence ends match the header.
+    /// * Each sequence can be extracted, and the sequences in a bidirectional index are reverses of each other.
+    /// * Path names in the metadata are unique and refer to existing samples and contigs.
+    ///
+    /// The sequences are only extracted if the records are valid.
+    /// The time complexity is proportional to the total length of the sequences times the size of a record.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::GBWT;
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let index: GBWT = serialize::load_from(&filename).unwrap();
+    ///
+    /// let report = index.validate();
+    /// assert!(report.is_ok());
+    /// assert_eq!(report.sequences, index.sequences());
+    /// assert_eq!(report.path_names, 6);
+    /// ```
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        self.validate_records(&mut report);
+        if report.is_ok() {
+            self.validate_sequences(&mut report);
+        }
+        if let Some(metadata) = self.metadata() {
+            Self::validate_metadata(metadata, &mut report);
+        }
+        report
+    }
+
+    // Decodes all records and checks that the edges are consistent.
+    fn validate_records(&self, report: &mut ValidationReport) {
+        if self.bwt.len() != self.effective_size() {
+            report.error(format!("GBWT: Expected {} records, found {}", self.effective_size(), self.bwt.len()));
+        }
+
+        // For each record, the incoming edges as (predecessor record, offset, count).
+        let mut incoming: Vec<Vec<(usize, usize, usize)>> = vec![Vec::new(); self.bwt.len()];
+        let mut lengths: Vec<usize> = Vec::with_capacity(self.bwt.len());
+        let mut total_len: usize = 0;
+        let mut sequence_ends: usize = 0;
+        for record_id in 0..self.bwt.len() {
+            let record = match self.bwt.checked_record(record_id) {
+                Ok(Some(record)) => record,
+                Ok(None) => {
+                    lengths.push(0);
+                    continue;
+                },
+                Err(err) => {
+                    report.error(err.to_string());
+                    lengths.push(0);
+                    continue;
+                },
+            };
+            report.records += 1;
+            report.edges += record.outdegree();
+            lengths.push(record.len());
+            total_len = total_len.saturating_add(record.len());
+
+            let mut counts: Vec<usize> = vec![0; record.outdegree()];
+            for run in record.runs() {
+                counts[run.value] += run.len;
+            }
+            for (rank, count) in counts.into_iter().enumerate() {
+                let successor = record.successor(rank);
+                if successor == ENDMARKER {
+                    sequence_ends = sequence_ends.saturating_add(count);
+                } else if !self.has_node(successor) || self.node_to_record(successor) >= self.bwt.len() {
+                    report.error(format!("Record {}: Successor {} does not exist", record_id, successor));
+                } else if count > 0 {
+                    incoming[self.node_to_record(successor)].push((record_id, record.offset(rank), count));
+                }
+            }
+        }
+
+        // The incoming edges must cover the offsets in each record in order.
+        for (record_id, edges) in incoming.iter().enumerate() {
+            let mut expected = 0;
+            for &(predecessor, offset, count) in edges.iter() {
+                if offset != expected {
+                    report.error(format!("Record {}: Edge from record {} starts at offset {} instead of {}", record_id, predecessor, offset, expected));
+                }
+                expected = offset.saturating_add(count);
+            }
+            if record_id != ENDMARKER && expected != lengths[record_id] {
+                report.error(format!("Record {}: Incoming edges end at offset {} but the length is {}", record_id, expected, lengths[record_id]));
+            }
+        }
+
+        if total_len != self.len() {
+            report.error(format!("GBWT: Expected total length {}, found {}", self.len(), total_len));
+        }
+        if sequence_ends != self.sequences() {
+            report.error(format!("GBWT: Expected {} sequences, found {} sequence ends", self.sequences(), sequence_ends));
+        }
+        if !self.bwt.is_empty() && lengths[ENDMARKER] != self.sequences() {
+            report.error(format!("GBWT: Expected {} sequences, found {} sequence starts", self.sequences(), lengths[ENDMARKER]));
+        }
+    }
+
+    // Extracts all sequences and checks that bidirectional sequences are reverses of each other.
+    fn validate_sequences(&self, report: &mut ValidationReport) {
+        if self.is_bidirectional() && self.sequences() % 2 != 0 {
+            report.error(format!("GBWT: Bidirectional index with an odd number of sequences ({})", self.sequences()));
+            return;
+        }
+
+        // The records are valid, but a cycle could still make a sequence infinite.
+        let mut remaining = self.len().saturating_sub(self.sequences());
+        let mut forward: Vec<usize> = Vec::new();
+        for id in 0..self.sequences() {
+            let mut sequence: Vec<usize> = Vec::new();
+            for node in self.sequence(id).unwrap() {
+                if remaining == 0 {
+                    report.error(format!("Sequence {}: The sequences are longer than the index", id));
+                    return;
+                }
+                sequence.push(node);
+                remaining -= 1;
+            }
+            report.sequences += 1;
+
+            if self.is_bidirectional() {
+                if id % 2 == 0 {
+                    forward = sequence;
+                } else if !sequence.iter().rev().map(|&node| support::flip_node(node)).eq(forward.iter().copied()) {
+                    report.error(format!("Sequence {}: Not the reverse of sequence {}", id, id - 1));
+                }
+            }
+        }
+        if remaining > 0 {
+            report.error(format!("GBWT: The sequences are {} nodes shorter than the index", remaining));
+        }
+    }
+
+    // Checks that the path names are unique and refer to existing samples and contigs.
+    fn validate_metadata(metadata: &Metadata, report: &mut ValidationReport) {
+        let mut names: HashSet<&PathName> = HashSet::with_capacity(metadata.paths());
+        for (id, path_name) in metadata.path_iter().enumerate() {
+            if !names.insert(path_name) {
+                report.error(format!("Path {}: Duplicate path name {:?}", id, path_name));
+            }
+            if path_name.sample() >= metadata.samples() {
+                report.error(format!("Path {}: Sample {} does not exist", id, path_name.sample()));
+            }
+            if path_name.contig() >= metadata.contigs() {
+                report.error(format!("Path {}: Contig {} does not exist", id, path_name.contig()));
+            }
+            report.path_names += 1;
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+impl Serialize for GBWT {
+    fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.header.serialize(writer)
+    }
+
+    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.tags.serialize(writer)?;
+        self.bwt.serialize(writer)?;
+        self.da_samples.serialize(writer)?;
+        self.metadata.serialize(writer)?;
+        Ok(())
+    }
+
+    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
+        Self::load_with(reader, BWT::load, Option::<Metadata>::load)
+    }
 
     fn size_in_elements(&self) -> usize {
-        self.header.size_in_elements() + self.tags.size_in_elements() + self.bwt.size_in_elements() + serialize::absent_option_size() + self.metadata.size_in_elements()
+        self.header.size_in_elements() + self.tags.size_in_elements() + self.bwt.size_in_elements() + self.da_samples.size_in_elements() + self.metadata.size_in_elements()
     }
 }
 
 //-----------------------------------------------------------------------------
 
-/// A state of unidirectional search in [`GBWT`].
+/// A report on the integrity of an index from [`GBWT::validate`] or [`crate::GBZ::validate`].
 ///
-/// The state consists of the last matched GBWT node identifier and an offset range in that node.
-/// This information is equivalent to a BWT range in a normal FM-index.
+/// The counters describe how much of the index was checked.
+/// At most [`ValidationReport::MAX_ERRORS`] error messages are stored, and the rest are only counted.
 ///
-/// Because `SearchState` contains a [`Range`], it does not implement [`Copy`].
-/// As search states are often reused, they are passed by reference instead of value.
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
-pub struct SearchState {
-    /// GBWT node identifier for the last matched node.
-    pub node: usize,
-    /// Offset range in the node.
-    pub range: Range<usize>,
+/// # Examples
+///
+/// ```
+/// use gbwt::GBZ;
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.gbz");
+/// let gbz: GBZ = serialize::load_from(&filename).unwrap();
+///
+/// let report = gbz.validate();
+/// assert!(report.is_ok());
+/// assert_eq!(report.errors(), 0);
+/// assert_eq!(report.nodes, gbz.nodes());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Number of non-empty records that were decoded.
+    pub records: usize,
+    /// Number of edges in the decoded records.
+    pub edges: usize,
+    /// Number of sequences that were extracted.
+    pub sequences: usize,
+    /// Number of path names that were checked.
+    pub path_names: usize,
+    /// Number of graph nodes that were checked.
+    pub nodes: usize,
+    messages: Vec<String>,
+    omitted: usize,
 }
 
-impl SearchState {
-    /// Returns the number of matching substring occurrences (the length of the offset range).
+impl ValidationReport {
+    /// Maximum number of stored error messages.
+    pub const MAX_ERRORS: usize = 100;
+
+    /// Returns `true` if no errors were found.
     #[inline]
-    pub fn len(&self) -> usize {
-        self.range.len()
+    pub fn is_ok(&self) -> bool {
+        self.errors() == 0
     }
 
-    /// Returns `true` if there are no matching substring occurrences.
+    /// Returns the total number of errors found.
     #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.range.is_empty()
+    pub fn errors(&self) -> usize {
+        self.messages.len() + self.omitted
+    }
+
+    /// Returns the stored error messages.
+    #[inline]
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+
+    // Records an error.
+    pub(crate) fn error(&mut self, message: String) {
+        if self.messages.len() < Self::MAX_ERRORS {
+            self.messages.push(message);
+        } else {
+            self.omitted += 1;
+        }
     }
 }
 
-/// A state of bidirectional search in a bidirectional [`GBWT`].
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Records: {}, edges: {}, sequences: {}, path names: {}, nodes: {}", self.records, self.edges, self.sequences, self.path_names, self.nodes)?;
+        if self.is_ok() {
+            return writeln!(f, "No errors found");
+        }
+        writeln!(f, "Found {} errors", self.errors())?;
+        for message in self.messages.iter() {
+            writeln!(f, "  {}", message)?;
+        }
+        if self.omitted > 0 {
+            writeln!(f, "  ({} more errors omitted)", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// A structure for building a [`GBWT`] index from a collection of paths.
 ///
-/// The state consists of forward and reverse search states.
-/// It usually corresponds to all occurrences of a substring `pattern`.
-/// The forward state is then the search state for `pattern`, while the reverse state is for the reverse pattern obtained with [`support::reverse_path`].
+/// Paths are given as sequences of GBWT node identifiers.
+/// If the index is bidirectional, the paths should use the node encoding in [`support::encode_node`], and the builder adds the reverse orientation of each path automatically.
+/// Path `i` then becomes sequences [`support::encode_path`]`(i, Orientation::Forward)` and [`support::encode_path`]`(i, Orientation::Reverse)`.
 ///
-/// Because `BidirectionalState` contains a [`Range`], it does not implement [`Copy`].
-/// As search states are often reused, they are passed by reference instead of value.
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
-pub struct BidirectionalState {
-    /// GBWT search state for the forward pattern.
-    pub forward: SearchState,
-    /// GBWT search state for the reverse pattern.
-    pub reverse: SearchState,
+/// If path names are used, each path must have a name.
+/// Sample and contig names are optional.
+/// The index is built entirely in memory, and the construction is intended for moderately sized collections of paths.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{GBWT, Orientation, PathName};
+/// use gbwt::gbwt::GBWTBuilder;
+/// use gbwt::support;
+/// use std::convert::TryFrom;
+///
+/// let first = vec![
+///     support::encode_node(1, Orientation::Forward),
+///     support::encode_node(2, Orientation::Forward),
+///     support::encode_node(4, Orientation::Forward),
+/// ];
+/// let second = vec![
+///     support::encode_node(1, Orientation::Forward),
+///     support::encode_node(3, Orientation::Reverse),
+///     support::encode_node(4, Orientation::Forward),
+/// ];
+///
+/// let mut builder = GBWTBuilder::new(true);
+/// builder.insert_with_name(&first, PathName::from_fields(0, 0, 1, 0)).unwrap();
+/// builder.insert_with_name(&second, PathName::from_fields(0, 0, 2, 0)).unwrap();
+/// builder.set_sample_names(&["sample"]);
+/// builder.set_contig_names(&["chr1"]);
+/// let index = GBWT::try_from(builder).unwrap();
+///
+/// assert!(index.is_bidirectional());
+/// assert_eq!(index.sequences(), 4);
+/// let extracted: Vec<usize> = index.sequence(support::encode_path(1, Orientation::Forward)).unwrap().collect();
+/// assert_eq!(extracted, second);
+///
+/// let metadata = index.metadata().unwrap();
+/// assert_eq!(metadata.pan_sn_path(1), Some("sample#2#chr1".to_string()));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GBWTBuilder {
+    bidirectional: bool,
+    sample_interval: usize,
+    reserved: Range<usize>,
+    paths: Vec<Vec<usize>>,
+    path_names: Vec<PathName>,
+    sample_names: Vec<String>,
+    contig_names: Vec<String>,
 }
 
-impl BidirectionalState {
-    /// Returns the number of matching substring occurrences (the length of the offset range).
+impl GBWTBuilder {
+    /// Creates a new builder for a unidirectional or a bidirectional index.
+    pub fn new(bidirectional: bool) -> Self {
+        GBWTBuilder {
+            bidirectional,
+            sample_interval: DASamples::SAMPLE_INTERVAL,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of paths inserted so far.
     #[inline]
     pub fn len(&self) -> usize {
-        self.forward.len()
+        self.paths.len()
     }
 
-    /// Returns `true` if there are no matching substring occurrences.
+    /// Returns `true` if no paths have been inserted.
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.forward.is_empty()
+        self.len() == 0
     }
 
-    /// Returns a new search state with the forward and reverse states flipped.
-    pub fn flip(&self) -> BidirectionalState {
-        BidirectionalState {
-            forward: self.reverse.clone(),
-            reverse: self.forward.clone(),
+    /// Returns `true` if the builder creates a bidirectional index.
+    #[inline]
+    pub fn is_bidirectional(&self) -> bool {
+        self.bidirectional
+    }
+
+    /// Sets the distance between document array samples on each sequence.
+    ///
+    /// Values smaller than `1` are treated as `1`.
+    pub fn set_sample_interval(&mut self, sample_interval: usize) {
+        self.sample_interval = cmp::max(sample_interval, 1);
+    }
+
+    /// Reserves a range of GBWT node identifiers in the alphabet of the index.
+    ///
+    /// The index will contain records for the nodes in the range, even if they are not used on any path.
+    /// This is useful when the node identifiers must match those in a [`crate::Graph`].
+    /// The endmarker is never reserved, and an empty range has no effect.
+    pub fn reserve_nodes(&mut self, nodes: Range<usize>) {
+        self.reserved = nodes;
+    }
+
+    /// Inserts a path without a name.
+    ///
+    /// Returns an error if the path or its reverse in a bidirectional index contains the endmarker.
+    pub fn insert(&mut self, path: &[usize]) -> Result<(), crate::Error> {
+        if path.iter().any(|node| *node == ENDMARKER || (self.bidirectional && support::flip_node(*node) == ENDMARKER)) {
+            return Err(crate::Error::InvalidData(String::from("GBWTBuilder: Cannot insert a path containing the endmarker")));
         }
+        self.paths.push(path.to_vec());
+        Ok(())
     }
 
-    /// Returns the first node on the path corresponding to the search state.
+    /// Inserts a path with the given name.
     ///
-    /// The return value consists of a node identifier in the original graph and the orientation of the node.
-    #[inline]
-    pub fn from(&self) -> (usize, Orientation) {
-        support::decode_node(support::flip_node(self.reverse.node))
+    /// Returns an error if the path or its reverse in a bidirectional index contains the endmarker.
+    pub fn insert_with_name(&mut self, path: &[usize], name: PathName) -> Result<(), crate::Error> {
+        self.insert(path)?;
+        self.path_names.push(name);
+        Ok(())
     }
 
-    /// Returns the last node on the path corresponding to the search state.
+    /// Sets the sample names.
     ///
-    /// The return value consists of a node identifier in the original graph and the orientation of the node.
-    #[inline]
-    pub fn to(&self) -> (usize, Orientation) {
-        support::decode_node(self.forward.node)
+    /// Sample identifier `i` in the path names refers to `names[i]`.
+    pub fn set_sample_names<T: AsRef<str>>(&mut self, names: &[T]) {
+        self.sample_names = names.iter().map(|name| name.as_ref().to_string()).collect();
+    }
+
+    /// Sets the contig names.
+    ///
+    /// Contig identifier `i` in the path names refers to `names[i]`.
+    pub fn set_contig_names<T: AsRef<str>>(&mut self, names: &[T]) {
+        self.contig_names = names.iter().map(|name| name.as_ref().to_string()).collect();
     }
 }
 
-//-----------------------------------------------------------------------------
+impl TryFrom<GBWTBuilder> for GBWT {
+    type Error = crate::Error;
 
-/// An iterator over a sequence in [`GBWT`].
-///
+    fn try_from(source: GBWTBuilder) -> Result<Self, Self::Error> {
+        let metadata = if source.path_names.is_empty() && source.sample_names.is_empty() && source.contig_names.is_empty() {
+            None
+        } else {
+            if !source.path_names.is_empty() && source.path_names.len() != source.paths.len() {
+                return Err(crate::Error::MetadataMismatch("GBWTBuilder: Some paths do not have names"));
+            }
+            Some(Metadata::from_names(source.path_names, &source.sample_names, &source.contig_names)?)
+        };
+
+        let mut sequences: Vec<Vec<usize>> = Vec::with_capacity(if source.bidirectional { 2 * source.paths.len() } else { source.paths.len() });
+        for path in source.paths {
+            if source.bidirectional {
+                let reverse = support::reverse_path(&path);
+                sequences.push(path);
+                sequences.push(reverse);
+            } else {
+                sequences.push(path);
+            }
+        }
+
+        let mut min_node = sequences.iter().flatten().copied().min();
+        let mut max_node = sequences.iter().flatten().copied().max();
+        if !source.reserved.is_empty() {
+            let start = cmp::max(source.reserved.start, ENDMARKER + 1);
+            min_node = Some(min_node.map_or(start, |node| cmp::min(node, start)));
+            max_node = Some(max_node.map_or(source.reserved.end - 1, |node| cmp::max(node, source.reserved.end - 1)));
+        }
+        let (offset, alphabet_size) = match (min_node, max_node) {
+            (Some(min_node), Some(max_node)) => (min_node - 1, max_node + 1),
+            _ => (0, if sequences.is_empty() { 0 } else { 1 }),
+        };
+        let mut dynamic = DynamicBWT::new(offset, alphabet_size);
+        dynamic.insert(&sequences);
+
+        Ok(dynamic.into_gbwt(source.bidirectional, metadata, source.sample_interval))
+    }
+}
+
+/// Merging.
+impl GBWT {
+    /// Merges the indexes into a new index containing the union of the sequences.
+    ///
+    /// The sequences of `sources[0]` come first, followed by the sequences of `sources[1]`, and so on.
+    /// If the node ranges of the indexes are disjoint, the records are simply concatenated.
+    /// Otherwise the sequences of the other indexes are inserted into the first index.
+    /// The merged index contains document array samples with the default sample interval.
+    ///
+    /// If the indexes contain metadata, the metadata is merged as described in [`Metadata::merge`].
+    ///
+    /// Returns an error if there are no indexes, if some indexes are bidirectional and others are not, if some indexes contain metadata and others do not, or if metadata merging fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::GBWT;
+    /// use gbwt::gbwt::GBWTBuilder;
+    /// use std::convert::TryFrom;
+    ///
+    /// let mut first = GBWTBuilder::new(false);
+    /// first.insert(&[1, 2, 4]).unwrap();
+    /// let first = GBWT::try_from(first).unwrap();
+    /// let mut second = GBWTBuilder::new(false);
+    /// second.insert(&[1, 3, 4]).unwrap();
+    /// second.insert(&[2, 4]).unwrap();
+    /// let second = GBWT::try_from(second).unwrap();
+    ///
+    /// let merged = GBWT::merge(&[first, second]).unwrap();
+    /// assert_eq!(merged.sequences(), 3);
+    /// let extracted: Vec<usize> = merged.sequence(1).unwrap().collect();
+    /// assert_eq!(extracted, vec![1, 3, 4]);
+    /// let state = merged.find(4).unwrap();
+    /// assert_eq!(merged.locate(&state), vec![0, 1, 2]);
+    /// ```
+    pub fn merge(sources: &[GBWT]) -> Result<GBWT, crate::Error> {
+        if sources.is_empty() {
+            return Err(crate::Error::InvalidData(String::from("GBWT: Cannot merge an empty set of indexes")));
+        }
+        let bidirectional = sources[0].is_bidirectional();
+        if sources.iter().any(|index| index.is_bidirectional() != bidirectional) {
+            return Err(crate::Error::InvalidData(String::from("GBWT: Cannot merge unidirectional and bidirectional indexes")));
+        }
+
+        let has_metadata = sources[0].has_metadata();
+        if sources.iter().any(|index| index.has_metadata() != has_metadata) {
+            return Err(crate::Error::MetadataMismatch("GBWT: Cannot merge indexes with and without metadata"));
+        }
+        let metadata = if has_metadata {
+            let metadata: Vec<&Metadata> = sources.iter().map(|index| index.metadata().unwrap()).collect();
+            Some(Metadata::merge(&metadata)?)
+        } else {
+            None
+        };
+
+        // Determine the alphabet and whether the node ranges are disjoint.
+        let mut ranges: Vec<Range<usize>> = sources.iter()
+            .filter(|index| index.effective_size() > 1)
+            .map(|index| index.first_node()..index.alphabet_size())
+            .collect();
+        ranges.sort_unstable_by_key(|range| range.start);
+        let disjoint = ranges.windows(2).all(|pair| pair[0].end <= pair[1].start);
+        let offset = ranges.first().map(|range| range.start - 1).unwrap_or(0);
+        let alphabet_size = if sources.iter().all(|index| index.sequences() == 0) {
+            0
+        } else {
+            ranges.iter().map(|range| range.end).max().unwrap_or(1)
+        };
+
+        let mut dynamic = DynamicBWT::new(offset, alphabet_size);
+        if disjoint {
+            for index in sources.iter() {
+                dynamic.append_disjoint(index);
+            }
+        } else {
+            dynamic.append_disjoint(&sources[0]);
+            for index in sources[1..].iter() {
+                let sequences: Vec<Vec<usize>> = (0..index.sequences())
+                    .map(|id| index.sequence(id).map(|iter| iter.collect()).unwrap_or_default())
+                    .collect();
+                dynamic.insert(&sequences);
+            }
+        }
+
+        Ok(dynamic.into_gbwt(bidirectional, metadata, DASamples::SAMPLE_INTERVAL))
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// A BWT with uncompressed records, used for building and merging GBWT indexes.
+// Sequences are inserted in parallel, one node at a time, using the LF-mapping in the current BWT.
+#[derive(Clone, Debug)]
+struct DynamicBWT {
+    offset: usize,
+    alphabet_size: usize,
+    sequences: usize,
+    size: usize,
+    // Successor nodes for each offset in each record.
+    bodies: Vec<Vec<usize>>,
+    // For each record, the number of occurrences of the node in each predecessor record.
+    incoming: Vec<BTreeMap<usize, usize>>,
+}
+
+impl DynamicBWT {
+    // Creates an empty BWT for the given alphabet.
+    fn new(offset: usize, alphabet_size: usize) -> Self {
+        let records = alphabet_size.saturating_sub(offset);
+        DynamicBWT {
+            offset, alphabet_size,
+            sequences: 0,
+            size: 0,
+            bodies: vec![Vec::new(); records],
+            incoming: vec![BTreeMap::new(); records],
+        }
+    }
+
+    fn record_id(&self, node: usize) -> usize {
+        if node == ENDMARKER { ENDMARKER } else { node - self.offset }
+    }
+
+    // Appends a value to the given record.
+    fn push(&mut self, record_id: usize, value: usize) {
+        self.bodies[record_id].push(value);
+        if value != ENDMARKER {
+            let successor = self.record_id(value);
+            *self.incoming[successor].entry(record_id).or_insert(0) += 1;
+        }
+    }
+
+    // Returns the number of occurrences of `node` in records before `record_id`.
+    fn occurrences_before(&self, record_id: usize, node: usize) -> usize {
+        self.incoming[self.record_id(node)].range(..record_id).map(|(_, count)| count).sum()
+    }
+
+    // Copies the records of the index, which must not share any nodes with the current BWT.
+    // The sequences of the index get identifiers after the existing sequences.
+    fn append_disjoint(&mut self, index: &GBWT) {
+        for record_id in 1..index.bwt.len() {
+            if let Some(record) = index.bwt.record(record_id) {
+                let target = self.record_id(index.record_to_node(record_id));
+                for pos in record.decompress() {
+                    self.push(target, pos.node);
+                }
+            }
+        }
+        for pos in index.endmarker.iter() {
+            self.push(ENDMARKER, pos.node);
+        }
+        self.sequences += index.sequences();
+        self.size += index.len();
+    }
+
+    // Inserts the sequences into the BWT.
+    // The sequences get identifiers after the existing sequences.
+    fn insert(&mut self, sequences: &[Vec<usize>]) {
+        // Active insertions as (sequence index, position of the value in the sequence, record id, offset).
+        // The offset refers to the position in the record that stores the value.
+        let mut active: Vec<(usize, usize, usize, usize)> = Vec::new();
+        for (i, sequence) in sequences.iter().enumerate() {
+            let value = sequence.first().copied().unwrap_or(ENDMARKER);
+            self.push(ENDMARKER, value);
+            if value != ENDMARKER {
+                active.push((i, 0, ENDMARKER, self.sequences + i));
+            }
+        }
+        self.sequences += sequences.len();
+        self.size += sequences.iter().fold(0, |sum, sequence| sum + sequence.len() + 1);
+
+        while !active.is_empty() {
+            // Determine the positions of the next nodes using LF in the current BWT.
+            let mut next: Vec<(usize, usize, usize, usize)> = Vec::with_capacity(active.len());
+            active.sort_unstable_by_key(|(_, _, record, offset)| (*record, *offset));
+            let mut start = 0;
+            while start < active.len() {
+                let record = active[start].2;
+                let mut counts: HashMap<usize, usize> = HashMap::new();
+                let mut scanned = 0;
+                for &(i, pos, _, offset) in active[start..].iter().take_while(|x| x.2 == record) {
+                    while scanned < offset {
+                        *counts.entry(self.bodies[record][scanned]).or_insert(0) += 1;
+                        scanned += 1;
+                    }
+                    let node = sequences[i][pos];
+                    let rank = counts.get(&node).copied().unwrap_or(0);
+                    next.push((i, pos + 1, self.record_id(node), self.occurrences_before(record, node) + rank));
+                    start += 1;
+                }
+            }
+
+            // Insert the new values into the records in sorted order.
+            next.sort_unstable_by_key(|(_, _, record, offset)| (*record, *offset));
+            for &(i, pos, record, offset) in next.iter() {
+                let value = sequences[i].get(pos).copied().unwrap_or(ENDMARKER);
+                self.bodies[record].insert(offset, value);
+                if value != ENDMARKER {
+                    let successor = self.record_id(value);
+                    *self.incoming[successor].entry(record).or_insert(0) += 1;
+                }
+            }
+            next.retain(|(i, pos, _, _)| *pos < sequences[*i].len());
+            active = next;
+        }
+    }
+
+    // Encodes the records.
+    fn bwt(&self) -> BWT {
+        let mut builder = BWTBuilder::new();
+        for (record, body) in self.bodies.iter().enumerate() {
+            let successors: BTreeSet<usize> = body.iter().copied().collect();
+            let mut edges: Vec<Pos> = Vec::with_capacity(successors.len());
+            let mut ranks: HashMap<usize, usize> = HashMap::with_capacity(successors.len());
+            for (rank, &node) in successors.iter().enumerate() {
+                let offset = if node == ENDMARKER { 0 } else { self.occurrences_before(record, node) };
+                edges.push(Pos::new(node, offset));
+                ranks.insert(node, rank);
+            }
+            let mut runs: Vec<Run> = Vec::new();
+            for node in body.iter() {
+                let rank = ranks[node];
+                match runs.last_mut() {
+                    Some(run) if run.value == rank => run.len += 1,
+                    _ => runs.push(Run::new(rank, 1)),
+                }
+            }
+            builder.append(&edges, &runs);
+        }
+        BWT::from(builder)
+    }
+
+    // Builds the final index with document array samples.
+    fn into_gbwt(self, bidirectional: bool, metadata: Option<Metadata>, sample_interval: usize) -> GBWT {
+        let bwt = self.bwt();
+        let endmarker = bwt.record(ENDMARKER).map(|record| record.decompress()).unwrap_or_default();
+
+        let mut header = Header::<GBWTPayload>::new();
+        header.payload_mut().sequences = self.sequences;
+        header.payload_mut().size = self.size;
+        header.payload_mut().offset = if bwt.is_empty() { 0 } else { self.offset };
+        header.payload_mut().alphabet_size = if bwt.is_empty() { 0 } else { self.alphabet_size };
+        if bidirectional {
+            header.set(GBWTPayload::FLAG_BIDIRECTIONAL);
+        }
+        if metadata.is_some() {
+            header.set(GBWTPayload::FLAG_METADATA);
+        }
+
+        let mut tags = Tags::new();
+        tags.insert(SOURCE_KEY, SOURCE_VALUE);
+
+        let mut result = GBWT {
+            header, tags, bwt, endmarker,
+            da_samples: None,
+            metadata,
+            sequence_samples: None,
+        };
+        result.da_samples = Some(DASamples::new(&result, sample_interval));
+        result.update_reference_samples_tag();
+        result
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// A state of unidirectional search in [`GBWT`].
+///
+/// The state consists of the last matched GBWT node identifier and an offset range in that node.
+/// This information is equivalent to a BWT range in a normal FM-index.
+///
+/// Because `SearchState` contains a [`Range`], it does not implement [`Copy`].
+/// As search states are often reused, they are passed by reference instead of value.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SearchState {
+    /// GBWT node identifier for the last matched node.
+    pub node: usize,
+    /// Offset range in the node.
+    pub range: Range<usize>,
+}
+
+impl SearchState {
+    /// Returns the number of matching substring occurrences (the length of the offset range).
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Returns `true` if there are no matching substring occurrences.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+}
+
+/// A state of bidirectional search in a bidirectional [`GBWT`].
+///
+/// The state consists of forward and reverse search states.
+/// It usually corresponds to all occurrences of a substring `pattern`.
+/// The forward state is then the search state for `pattern`, while the reverse state is for the reverse pattern obtained with [`support::reverse_path`].
+///
+/// Because `BidirectionalState` contains a [`Range`], it does not implement [`Copy`].
+/// As search states are often reused, they are passed by reference instead of value.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct BidirectionalState {
+    /// GBWT search state for the forward pattern.
+    pub forward: SearchState,
+    /// GBWT search state for the reverse pattern.
+    pub reverse: SearchState,
+}
+
+impl BidirectionalState {
+    /// Returns the number of matching substring occurrences (the length of the offset range).
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.forward.len()
+    }
+
+    /// Returns `true` if there are no matching substring occurrences.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.forward.is_empty()
+    }
+
+    /// Returns a new search state with the forward and reverse states flipped.
+    pub fn flip(&self) -> BidirectionalState {
+        BidirectionalState {
+            forward: self.reverse.clone(),
+            reverse: self.forward.clone(),
+        }
+    }
+
+    /// Returns the first node on the path corresponding to the search state.
+    ///
+    /// The return value consists of a node identifier in the original graph and the orientation of the node.
+    #[inline]
+    pub fn from(&self) -> (usize, Orientation) {
+        support::decode_node(support::flip_node(self.reverse.node))
+    }
+
+    /// Returns the last node on the path corresponding to the search state.
+    ///
+    /// The return value consists of a node identifier in the original graph and the orientation of the node.
+    #[inline]
+    pub fn to(&self) -> (usize, Orientation) {
+        support::decode_node(self.forward.node)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// An iterator over a sequence in [`GBWT`].
+///
 /// The type of `Item` is [`usize`].
 ///
 /// # Examples
@@ -538,267 +1982,1418 @@ pub struct SequenceIter<'a> {
     parent: &'a GBWT,
     // The next position.
     next: Option<Pos>,
+    // Upper bound for the number of remaining nodes, in case a malformed index contains a cycle.
+    remaining: usize,
+}
+
+impl<'a> Iterator for SequenceIter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.next?;
+        if self.remaining == 0 {
+            self.next = None;
+            return None;
+        }
+        self.remaining -= 1;
+        self.next = self.parent.forward(pos);
+        Some(pos.node)
+    }
+}
+
+impl<'a> FusedIterator for SequenceIter<'a> {}
+
+/// An iterator over a sequence in [`GBWT`] in reverse order.
+///
+/// The type of `Item` is [`usize`].
+/// The iterator returns the original nodes of the sequence from the last to the first.
+///
+/// In a bidirectional index, the iterator follows the reverse sequence `id ^ 1` forward and flips the orientations of the nodes.
+/// Hence it visits each node once and does not use any additional space.
+///
+/// A unidirectional index cannot be traversed backward.
+/// [`GBWT::backward`] finds the predecessor from the record of the reverse node, and a unidirectional index does not contain such records.
+/// Without them, finding the predecessor of a position would require scanning every record with an edge to the node.
+/// Instead, the iterator follows the sequence forward and buffers its nodes when it is created.
+/// This takes O(n) time and O(n) words of space for a sequence of length `n`.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{GBWT, Orientation};
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.gbwt");
+/// let index: GBWT = serialize::load_from(&filename).unwrap();
+///
+/// // Extract path 3 in forward orientation from the end.
+/// let path: Vec<usize> = index.sequence_rev(support::encode_path(3, Orientation::Forward)).unwrap().collect();
+/// assert_eq!(path, vec![34, 32, 28, 26, 22]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReverseSequenceIter<'a> {
+    // The reverse sequence in a bidirectional index.
+    reverse: Option<SequenceIter<'a>>,
+    // Buffered nodes in a unidirectional index.
+    buffer: Vec<usize>,
+}
+
+impl<'a> Iterator for ReverseSequenceIter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reverse.as_mut() {
+            Some(iter) => iter.next().map(support::flip_node),
+            None => self.buffer.pop(),
+        }
+    }
+}
+
+impl<'a> FusedIterator for ReverseSequenceIter<'a> {}
+
+//-----------------------------------------------------------------------------
+
+/// Document array samples for locate queries in [`GBWT`].
+///
+/// The document array maps each GBWT position to the identifier of the sequence containing it.
+/// Only some positions are sampled, and the sequence identifier for other positions is found by following the sequence forward until a sampled position is reached.
+/// The sampling guarantees that each sequence contains a sampled position before the endmarker.
+///
+/// The structure consists of the following components:
+///
+/// * `sampled_records`: Marks the records that contain sampled positions.
+/// * `bwt_ranges`: Marks the start of the offset range of each sampled record in the concatenation of those ranges.
+/// * `sampled_offsets`: Marks the sampled positions in the concatenated offset ranges.
+/// * `array`: Sequence identifiers for the sampled positions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DASamples {
+    sampled_records: BitVector,
+    bwt_ranges: SparseVector,
+    sampled_offsets: SparseVector,
+    array: IntVector,
+}
+
+impl DASamples {
+    /// Default distance between samples on a sequence.
+    pub const SAMPLE_INTERVAL: usize = 1024;
+
+    /// Builds document array samples for the given index.
+    ///
+    /// The last position of each sequence is always sampled, and the distance between samples is at most `sample_interval`.
+    /// If a sequence is empty, its position in the endmarker record is sampled.
+    /// Values smaller than `1` are treated as `1`.
+    pub fn new(index: &GBWT, sample_interval: usize) -> Self {
+        let sample_interval = cmp::max(sample_interval, 1);
+
+        // Samples as (record id, offset, sequence id).
+        let mut samples: Vec<(usize, usize, usize)> = Vec::new();
+        for id in 0..index.sequences() {
+            let mut positions: Vec<Pos> = Vec::new();
+            let mut pos = index.start(id);
+            while let Some(p) = pos {
+                if positions.len() >= index.len() {
+                    break;
+                }
+                positions.push(p);
+                pos = index.forward(p);
+            }
+            if positions.is_empty() {
+                samples.push((ENDMARKER, id, id));
+            }
+            for (i, p) in positions.iter().rev().enumerate() {
+                if i % sample_interval == 0 {
+                    samples.push((index.node_to_record(p.node), p.offset, id));
+                }
+            }
+        }
+        samples.sort_unstable();
+
+        // Concatenate the offset ranges of the sampled records.
+        let mut sampled_records = RawVector::with_len(index.bwt.len(), false);
+        let mut range_starts: Vec<usize> = Vec::new();
+        let mut sampled_offsets: Vec<usize> = Vec::with_capacity(samples.len());
+        let mut total_len = 0;
+        let mut prev: Option<usize> = None;
+        for &(record_id, offset, _) in samples.iter() {
+            if prev != Some(record_id) {
+                if let Some(prev_id) = prev {
+                    total_len += index.bwt.record(prev_id).map(|record| record.len()).unwrap_or(0);
+                }
+                sampled_records.set_bit(record_id, true);
+                range_starts.push(total_len);
+                prev = Some(record_id);
+            }
+            sampled_offsets.push(total_len + offset);
+        }
+        if let Some(prev_id) = prev {
+            total_len += index.bwt.record(prev_id).map(|record| record.len()).unwrap_or(0);
+        }
+
+        let mut sampled_records = BitVector::from(sampled_records);
+        sampled_records.enable_rank();
+        let max_id = samples.iter().map(|(_, _, id)| *id).max().unwrap_or(0);
+        let mut array = IntVector::with_capacity(samples.len(), bits::bit_len(max_id as u64)).unwrap();
+        array.extend(samples.iter().map(|(_, _, id)| *id));
+
+        DASamples {
+            sampled_records,
+            bwt_ranges: Self::sparse_vector(total_len, &range_starts),
+            sampled_offsets: Self::sparse_vector(total_len, &sampled_offsets),
+            array,
+        }
+    }
+
+    // Builds a sparse vector of the given length with the given (sorted) set bits.
+    fn sparse_vector(len: usize, values: &[usize]) -> SparseVector {
+        let mut builder = SparseBuilder::new(len, values.len()).unwrap();
+        for value in values.iter() {
+            unsafe { builder.set_unchecked(*value); }
+        }
+        SparseVector::try_from(builder).unwrap()
+    }
+
+    /// Returns the number of sampled positions.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    /// Returns `true` if there are no sampled positions.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of records covered by the samples.
+    ///
+    /// This should be the same as the number of records in the BWT.
+    #[inline]
+    pub fn records(&self) -> usize {
+        self.sampled_records.len()
+    }
+
+    /// Returns the largest sequence identifier in the samples, or [`None`] if there are no samples.
+    pub fn max_id(&self) -> Option<usize> {
+        (0..self.len()).map(|i| self.array.get(i) as usize).max()
+    }
+
+    /// Returns the sequence identifier for the given position, or [`None`] if the position has not been sampled.
+    ///
+    /// # Arguments
+    ///
+    /// * `record_id`: Record identifier.
+    /// * `offset`: Offset in the record.
+    pub fn try_locate(&self, record_id: usize, offset: usize) -> Option<usize> {
+        if record_id >= self.records() || !self.sampled_records.get(record_id) {
+            return None;
+        }
+        let rank = self.sampled_records.rank(record_id);
+        let start = self.bwt_ranges.select(rank)?;
+        let limit = self.bwt_ranges.select(rank + 1).unwrap_or(self.bwt_ranges.len());
+        let offset = start.checked_add(offset)?;
+        if offset >= limit || !self.sampled_offsets.get(offset) {
+            return None;
+        }
+        Some(self.array.get(self.sampled_offsets.rank(offset)) as usize)
+    }
+
+    /// Returns the sampled positions in the given offset range of the record as (offset, sequence identifier) pairs.
+    ///
+    /// The pairs are sorted by offset.
+    /// The time complexity is proportional to the number of sampled positions in the range rather than the length of the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `record_id`: Record identifier.
+    /// * `range`: Offset range in the record.
+    pub fn sampled_range(&self, record_id: usize, range: Range<usize>) -> Vec<(usize, usize)> {
+        let mut result: Vec<(usize, usize)> = Vec::new();
+        if range.is_empty() || record_id >= self.records() || !self.sampled_records.get(record_id) {
+            return result;
+        }
+        let rank = self.sampled_records.rank(record_id);
+        let start = match self.bwt_ranges.select(rank) {
+            Some(start) => start,
+            None => return result,
+        };
+        let limit = self.bwt_ranges.select(rank + 1).unwrap_or(self.bwt_ranges.len());
+        let limit = cmp::min(limit, start.saturating_add(range.end));
+        let first = match start.checked_add(range.start) {
+            Some(first) if first < limit => first,
+            _ => return result,
+        };
+        for (sample_rank, offset) in self.sampled_offsets.successor(first) {
+            if offset >= limit {
+                break;
+            }
+            result.push((offset - start, self.array.get(sample_rank) as usize));
+        }
+        result
+    }
+}
+
+impl Serialize for DASamples {
+    fn serialize_header<T: io::Write>(&self, _: &mut T) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.sampled_records.serialize(writer)?;
+        self.bwt_ranges.serialize(writer)?;
+        self.sampled_offsets.serialize(writer)?;
+        self.array.serialize(writer)?;
+        Ok(())
+    }
+
+    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
+        let mut sampled_records = BitVector::load(reader)?;
+        sampled_records.enable_rank();
+
+        let bwt_ranges = SparseVector::load(reader)?;
+        if bwt_ranges.count_ones() != sampled_records.count_ones() {
+            return Err(Error::new(ErrorKind::InvalidData, "DASamples: Sampled record count mismatch"));
+        }
+
+        let sampled_offsets = SparseVector::load(reader)?;
+        if sampled_offsets.len() != bwt_ranges.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "DASamples: BWT range / sampled offset length mismatch"));
+        }
+
+        let array = IntVector::load(reader)?;
+        if array.len() != sampled_offsets.count_ones() {
+            return Err(Error::new(ErrorKind::InvalidData, "DASamples: Sample count mismatch"));
+        }
+
+        Ok(DASamples {
+            sampled_records, bwt_ranges, sampled_offsets, array,
+        })
+    }
+
+    fn size_in_elements(&self) -> usize {
+        self.sampled_records.size_in_elements() + self.bwt_ranges.size_in_elements() + self.sampled_offsets.size_in_elements() + self.array.size_in_elements()
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Sampled positions along the sequences in [`GBWT`].
+///
+/// Position `i * interval` of each sequence is sampled, making it possible to start iterating from any offset of a sequence with less than `interval` LF steps.
+/// The structure also stores the length of each sequence.
+/// The samples are built in memory with [`GBWT::build_sequence_samples`] and they are not serialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceSamples {
+    interval: usize,
+    lengths: Vec<usize>,
+    positions: Vec<Vec<Pos>>,
+}
+
+impl SequenceSamples {
+    /// Builds sequence samples for the given index with the given sample interval.
+    ///
+    /// Values smaller than `1` are treated as `1`.
+    pub fn new(index: &GBWT, interval: usize) -> Self {
+        let interval = cmp::max(interval, 1);
+        let mut lengths: Vec<usize> = Vec::with_capacity(index.sequences());
+        let mut positions: Vec<Vec<Pos>> = Vec::with_capacity(index.sequences());
+        for id in 0..index.sequences() {
+            let mut samples: Vec<Pos> = Vec::new();
+            let mut len = 0;
+            let mut pos = index.start(id);
+            while let Some(p) = pos {
+                if len >= index.len() {
+                    break;
+                }
+                if len % interval == 0 {
+                    samples.push(p);
+                }
+                len += 1;
+                pos = index.forward(p);
+            }
+            lengths.push(len);
+            positions.push(samples);
+        }
+        SequenceSamples {
+            interval, lengths, positions,
+        }
+    }
+
+    /// Returns the sample interval.
+    #[inline]
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    /// Returns the number of sequences.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Returns `true` if there are no sequences.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the length of sequence `id`.
+    ///
+    /// # Panics
+    ///
+    /// May panic if `id >= self.len()`.
+    #[inline]
+    pub fn sequence_len(&self, id: usize) -> usize {
+        self.lengths[id]
+    }
+
+    /// Returns the last sampled position at or before offset `offset` of sequence `id` and the distance from it to the offset.
+    ///
+    /// Returns [`None`] if there is no such sequence or the offset is past the end of the sequence.
+    pub fn sample_before(&self, id: usize, offset: usize) -> Option<(Pos, usize)> {
+        if id >= self.len() || offset >= self.lengths[id] {
+            return None;
+        }
+        let pos = self.positions[id][offset / self.interval];
+        Some((pos, offset % self.interval))
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Metadata for the paths in a GBWT index.
+///
+/// The metadata contains some basic statistics about the paths, and it may also contain names for paths, samples, and contigs.
+/// Samples correspond to biological samples.
+/// Contigs usually correspond to contigs in the reference sequence as well as to connected components in the graph.
+/// The number of haplotypes is an estimate for the number of full-length paths in a connected component.
+///
+/// Path names are structured names associated with each path in the original graph.
+/// If the GBWT index is bidirectional, it contains two sequences for each path name.
+/// Each path name is a unique combination of four fields: sample, contig, phase, and fragment (see [`PathName`]).
+/// The first two must be in the intervals `0..self.samples()` and `0..self.contigs()`, respectively.
+///
+/// Each path and sample has a [`PathSense`].
+/// The reference samples are not stored in the metadata itself but in the [`crate::REFERENCE_SAMPLES_KEY`] tag of the GBWT index containing it.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{Metadata};
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.meta");
+/// let metadata: Metadata = serialize::load_from(&filename).unwrap();
+///
+/// assert!(metadata.has_path_names());
+/// assert_eq!(metadata.paths(), 6);
+/// assert_eq!(metadata.pan_sn_path(3), Some("sample#2#A".to_string()));
+/// let path = metadata.path(3).unwrap();
+///
+/// assert!(metadata.has_sample_names());
+/// assert_eq!(metadata.sample(path.sample()), Some("sample"));
+///
+/// assert!(metadata.has_contig_names());
+/// assert_eq!(metadata.contig(path.contig()), Some("A"));
+///
+/// // Find the paths over contig B.
+/// let id = metadata.contig_id("B").unwrap();
+/// let paths: Vec<usize> = metadata.paths_for_contig(id).collect();
+/// assert_eq!(paths, vec![1, 4, 5]);
+///
+/// // Find a specific path.
+/// let sample_id = metadata.sample_id("sample").unwrap();
+/// assert_eq!(metadata.find_path(sample_id, id, 1, 0), Some(4));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    header: Header<MetadataPayload>,
+    path_names: Vec<PathName>,
+    sample_names: Dictionary,
+    contig_names: Dictionary,
+    reference_samples: BTreeSet<usize>,
+    path_index: LazyPathIndex,
+}
+
+/// Paths.
+impl Metadata {
+    /// Returns `true` if the metadata contains path names.
+    pub fn has_path_names(&self) -> bool {
+        self.header.is_set(MetadataPayload::FLAG_PATH_NAMES)
+    }
+
+    /// Returns the number path names in the metadata.
+    ///
+    /// If there are path names, each name corresponds to a path in the original graph.
+    /// In a bidirectional GBWT, there are twice as many sequences as paths.
+    pub fn paths(&self) -> usize {
+        self.path_names.len()
+    }
+
+    /// Returns the name of the path with the given identifier, or [`None`] if there is no such name.
+    ///
+    /// Valid identifiers are in the interval `0..self.paths()`.
+    pub fn path(&self, id: usize) -> Option<PathName> {
+        if id < self.paths() {
+            Some(self.path_names[id])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of the path with the given identifier in the [PanSN format](https://github.com/pangenome/PanSN-spec), or [`None`] if there is no such name.
+    ///
+    /// Valid identifiers are in the interval `0..self.paths()`.
+    /// `'#'` will be used as the separator character.
+    pub fn pan_sn_path(&self, id: usize) -> Option<String> {
+        let path_name = self.path(id)?;
+        let result = format!("{}#{}#{}", self.sample_name(path_name.sample()), path_name.phase(), self.contig_name(path_name.contig()));
+        Some(result)
+    }
+
+    /// Returns the identifiers of the paths matching the given PanSN name in sorted order.
+    ///
+    /// Sample and contig names are resolved using [`Metadata::sample_id`] and [`Metadata::contig_id`].
+    /// If there are no sample / contig names, the name must be the identifier as in [`Metadata::pan_sn_path`].
+    /// If the name specifies a fragment, there is at most one matching path.
+    /// Otherwise all fragments of the (sample, phase, contig) combination match.
+    pub fn pan_sn_paths(&self, name: &PanSN) -> Vec<usize> {
+        let sample = if self.has_sample_names() { self.sample_id(&name.sample) } else { name.sample.parse::<usize>().ok() };
+        let contig = if self.has_contig_names() { self.contig_id(&name.contig) } else { name.contig.parse::<usize>().ok() };
+        let (sample, contig) = match (sample, contig) {
+            (Some(sample), Some(contig)) => (sample, contig),
+            _ => return Vec::new(),
+        };
+
+        if let Some(fragment) = name.fragment {
+            self.find_path(sample, contig, name.phase, fragment).into_iter().collect()
+        } else {
+            self.paths_for_sample(sample).filter(|id| {
+                let path_name = self.path_names[*id];
+                path_name.contig() == contig && path_name.phase() == name.phase
+            }).collect()
+        }
+    }
+
+    /// Parses a name in the [PanSN format](https://github.com/pangenome/PanSN-spec) and returns the identifier of the matching path.
+    ///
+    /// See [`PanSN`] for the supported formats and [`Metadata::pan_sn_paths`] for resolving the name.
+    /// Returns an error if the name cannot be parsed, if there is no matching path, or if the name is ambiguous.
+    /// A name is ambiguous when it does not specify a fragment and the fragment field is in use.
+    /// Use [`Metadata::pan_sn_paths`] to list the matching paths in that case.
+    ///
+    /// The metadata does not store path lengths, so names with a fragment end such as `contig[start-end]` are rejected.
+    /// Use [`GBZ::resolve_pan_sn`](crate::GBZ::resolve_pan_sn) to resolve them against the actual path length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::Metadata;
+    /// use gbwt::gbwt::MetadataBuilder;
+    /// use std::convert::TryFrom;
+    ///
+    /// let mut builder = MetadataBuilder::new();
+    /// builder.add_named_path("HG002", "chr20", 1, 0).unwrap();
+    /// builder.add_named_path("HG002", "chr20", 2, 0).unwrap();
+    /// builder.add_named_path("HG002", "chr20", 2, 1000).unwrap();
+    /// let metadata = Metadata::try_from(builder).unwrap();
+    ///
+    /// assert_eq!(metadata.resolve_pan_sn("HG002#1#chr20").ok(), Some(0));
+    /// assert_eq!(metadata.resolve_pan_sn("HG002#2#chr20[1000]").ok(), Some(2));
+    /// assert!(metadata.resolve_pan_sn("HG002#2#chr20[1000-2000]").is_err());
+    /// assert_eq!(metadata.resolve_pan_sn("HG002#2#chr20#0").ok(), Some(1));
+    /// assert!(metadata.resolve_pan_sn("HG002#2#chr20").is_err());
+    /// assert!(metadata.resolve_pan_sn("HG002#3#chr20").is_err());
+    /// ```
+    pub fn resolve_pan_sn(&self, name: &str) -> Result<usize, crate::Error> {
+        let name = PanSN::from_str(name)?;
+        if name.end.is_some() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot verify the fragment end without the path length")));
+        }
+        self.resolve_parsed_pan_sn(&name)
+    }
+
+    // Resolves a parsed PanSN name to a unique path identifier without checking the fragment end.
+    pub(crate) fn resolve_parsed_pan_sn(&self, name: &PanSN) -> Result<usize, crate::Error> {
+        let paths = self.pan_sn_paths(name);
+        match paths.len() {
+            0 => Err(crate::Error::InvalidData(String::from("Metadata: No path matches the PanSN name"))),
+            1 => Ok(paths[0]),
+            _ => Err(crate::Error::InvalidData(String::from("Metadata: The PanSN name matches multiple fragments"))),
+        }
+    }
+
+    /// Returns an iterator over path names.
+    pub fn path_iter(&self) -> slice::Iter<PathName> {
+        self.path_names.iter()
+    }
+
+    /// Returns the identifier of the path with the given fields, or [`None`] if there is no such path.
+    ///
+    /// The lookup uses a secondary index that is built when it is first needed.
+    pub fn find_path(&self, sample: usize, contig: usize, phase: usize, fragment: usize) -> Option<usize> {
+        self.find_path_name(&PathName::from_fields(sample, contig, phase, fragment))
+    }
+
+    /// Returns the identifier of the path with the given name, or [`None`] if there is no such path.
+    ///
+    /// The lookup uses a secondary index that is built when it is first needed.
+    pub fn find_path_name(&self, path_name: &PathName) -> Option<usize> {
+        self.path_index().names.get(path_name).copied()
+    }
+
+    /// Returns an iterator over the identifiers of the paths for the given sample in sorted order.
+    ///
+    /// The iterator is empty if there is no such sample or no path names.
+    /// The lookup uses a secondary index that is built when it is first needed.
+    pub fn paths_for_sample(&self, id: usize) -> Copied<slice::Iter<usize>> {
+        self.path_index().samples.get(id).map(|paths| paths.as_slice()).unwrap_or(&[]).iter().copied()
+    }
+
+    /// Returns an iterator over the identifiers of the paths for the given contig in sorted order.
+    ///
+    /// The iterator is empty if there is no such contig or no path names.
+    /// The lookup uses a secondary index that is built when it is first needed.
+    pub fn paths_for_contig(&self, id: usize) -> Copied<slice::Iter<usize>> {
+        self.path_index().contigs.get(id).map(|paths| paths.as_slice()).unwrap_or(&[]).iter().copied()
+    }
+
+    // Returns the secondary index for path lookups, building it if necessary.
+    fn path_index(&self) -> &PathIndex {
+        self.path_index.0.get_or_init(|| PathIndex::new(&self.path_names))
+    }
+}
+
+/// Samples.
+impl Metadata {
+    /// Returns `true` if the metadata contains sample names.
+    pub fn has_sample_names(&self) -> bool {
+        self.header.is_set(MetadataPayload::FLAG_SAMPLE_NAMES)
+    }
+
+    /// Returns the number samples.
+    pub fn samples(&self) -> usize {
+        self.header.payload().sample_count
+    }
+
+    /// Returns the number of haplotypes.
+    ///
+    /// This generally corresponds to the number of full-length paths in a graph component.
+    pub fn haplotypes(&self) -> usize {
+        self.header.payload().haplotype_count
+    }
+
+    /// Returns the name of the sample with the given identifier, or [`None`] if there is no such sample or name.
+    ///
+    /// Valid identifiers are in the interval `0..self.samples()`.
+    /// Also returns [`None`] if the name exists but is not valid UTF-8.
+    pub fn sample(&self, id: usize) -> Option<&str> {
+        if self.has_sample_names() && id < self.samples() {
+            self.sample_names.str(id).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of the sample with the given identifier.
+    ///
+    /// Returns a string representation of the the sample identifier when [`Metadata::sample`] would return [`None`].
+    pub fn sample_name(&self, id: usize) -> String {
+        if let Some(name) = self.sample(id) {
+            name.to_string()
+        } else {
+            id.to_string()
+        }
+    }
+ 
+    /// Returns the sample idenfier corresponding to the given sample name, or [`None`] if there is no such sample.
+    pub fn sample_id(&self, name: &str) -> Option<usize> {
+        self.sample_names.id(name)
+    }
+
+    /// Returns an iterator over sample names.
+    pub fn sample_iter(&self) -> StringIter {
+        self.sample_names.as_ref().iter()
+    }
+}
+
+/// Contigs.
+impl Metadata {
+    /// Returns `true` if the metadata contains contig names.
+    pub fn has_contig_names(&self) -> bool {
+        self.header.is_set(MetadataPayload::FLAG_CONTIG_NAMES)
+    }
+
+    /// Returns the number contigs.
+    ///
+    /// A contig usually corresponds to a graph component.
+    pub fn contigs(&self) -> usize {
+        self.header.payload().contig_count
+    }
+
+    /// Returns the name of the contig with the given identifier, or [`None`] if there is no such contig or name.
+    ///
+    /// Valid identifiers are in the interval `0..self.contigs()`.
+    /// Also returns [`None`] if the name exists but is not valid UTF-8.
+    pub fn contig(&self, id: usize) -> Option<&str> {
+        if self.has_contig_names() && id < self.contigs() {
+            self.contig_names.str(id).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the name of the contig with the given identifier.
+    ///
+    /// Returns a string representation of the the contig identifier when [`Metadata::contig`] would return [`None`].
+    pub fn contig_name(&self, id: usize) -> String {
+        if let Some(name) = self.contig(id) {
+            name.to_string()
+        } else {
+            id.to_string()
+        }
+    }
+
+    /// Returns the contig idenfier corresponding to the given contig name, or [`None`] if there is no such contig.
+    pub fn contig_id(&self, name: &str) -> Option<usize> {
+        self.contig_names.id(name)
+    }
+
+    /// Returns an iterator over contig names.
+    pub fn contig_iter(&self) -> StringIter {
+        self.contig_names.as_ref().iter()
+    }
+}
+
+/// Construction.
+impl Metadata {
+    // Builds metadata from path names and optional sample / contig names.
+    // If names are not given, the number of samples / contigs is determined from the path names.
+    fn from_names(path_names: Vec<PathName>, sample_names: &[String], contig_names: &[String]) -> Result<Self, crate::Error> {
+        let mut header = Header::<MetadataPayload>::new();
+
+        let mut unique: HashSet<PathName> = HashSet::with_capacity(path_names.len());
+        let mut haplotypes: HashSet<(usize, usize)> = HashSet::new();
+        let mut samples = sample_names.len();
+        let mut contigs = contig_names.len();
+        for path_name in path_names.iter() {
+            if !unique.insert(*path_name) {
+                return Err(crate::Error::InvalidData(String::from("Metadata: Duplicate path names")));
+            }
+            haplotypes.insert((path_name.sample(), path_name.phase()));
+            if !sample_names.is_empty() && path_name.sample() >= sample_names.len() {
+                return Err(crate::Error::InvalidData(String::from("Metadata: Path name refers to a nonexistent sample")));
+            }
+            if !contig_names.is_empty() && path_name.contig() >= contig_names.len() {
+                return Err(crate::Error::InvalidData(String::from("Metadata: Path name refers to a nonexistent contig")));
+            }
+            samples = cmp::max(samples, path_name.sample() + 1);
+            contigs = cmp::max(contigs, path_name.contig() + 1);
+        }
+
+        header.payload_mut().sample_count = samples;
+        header.payload_mut().haplotype_count = haplotypes.len();
+        header.payload_mut().contig_count = contigs;
+        if !path_names.is_empty() {
+            header.set(MetadataPayload::FLAG_PATH_NAMES);
+        }
+        if !sample_names.is_empty() {
+            header.set(MetadataPayload::FLAG_SAMPLE_NAMES);
+        }
+        if !contig_names.is_empty() {
+            header.set(MetadataPayload::FLAG_CONTIG_NAMES);
+        }
+
+        Ok(Metadata {
+            header,
+            path_names,
+            sample_names: Dictionary::try_from(sample_names).map_err(|msg| crate::Error::InvalidData(format!("Metadata: {}", msg)))?,
+            contig_names: Dictionary::try_from(contig_names).map_err(|msg| crate::Error::InvalidData(format!("Metadata: {}", msg)))?,
+            reference_samples: BTreeSet::new(),
+            path_index: LazyPathIndex::default(),
+        })
+    }
+
+    /// Merges the metadata objects in the given order.
+    ///
+    /// If all sources have sample names, samples with the same name are merged, and sample identifiers in path names are remapped accordingly.
+    /// Otherwise the samples of each source get new identifiers after the samples of the previous sources, and there are no sample names.
+    /// Contigs are handled in the same way.
+    /// The merged metadata contains path names if the sources have path names.
+    ///
+    /// Returns an error if some sources have path names and others do not, if a path name or a reference sample refers to a nonexistent sample / contig, or if the merged path names are not unique.
+    pub fn merge(sources: &[&Metadata]) -> Result<Metadata, crate::Error> {
+        let (sample_names, samples, sample_maps) = Self::merge_names(sources, |source| {
+            if source.has_sample_names() { Some(&source.sample_names) } else { None }
+        }, |source| source.samples());
+        let (contig_names, contigs, contig_maps) = Self::merge_names(sources, |source| {
+            if source.has_contig_names() { Some(&source.contig_names) } else { None }
+        }, |source| source.contigs());
+
+        let has_path_names = sources.first().map_or(false, |source| source.has_path_names());
+        if sources.iter().any(|source| source.has_path_names() != has_path_names) {
+            return Err(crate::Error::MetadataMismatch("Metadata: Cannot merge metadata with and without path names"));
+        }
+        let mut path_names: Vec<PathName> = Vec::new();
+        for (i, source) in sources.iter().enumerate() {
+            for path_name in source.path_iter() {
+                let sample = sample_maps[i].get(path_name.sample()).copied().ok_or_else(|| crate::Error::InvalidData(String::from("Metadata: Path name refers to a nonexistent sample")))?;
+                let contig = contig_maps[i].get(path_name.contig()).copied().ok_or_else(|| crate::Error::InvalidData(String::from("Metadata: Path name refers to a nonexistent contig")))?;
+                path_names.push(PathName::from_fields(sample, contig, path_name.phase(), path_name.fragment()));
+            }
+        }
+
+        let mut result = Self::from_names(path_names, &sample_names, &contig_names)?;
+        if !sample_names.is_empty() {
+            for (i, source) in sources.iter().enumerate() {
+                for sample in source.reference_samples.iter() {
+                    let sample = sample_maps[i].get(*sample).copied().ok_or_else(|| crate::Error::InvalidData(String::from("Metadata: Reference sample does not exist")))?;
+                    result.reference_samples.insert(sample);
+                }
+            }
+        }
+        let has_path_names = result.has_path_names();
+        let payload = result.header.payload_mut();
+        payload.sample_count = cmp::max(payload.sample_count, samples);
+        payload.contig_count = cmp::max(payload.contig_count, contigs);
+        if !has_path_names {
+            let haplotypes = sources.iter().map(|source| source.haplotypes());
+            payload.haplotype_count = if sample_names.is_empty() { haplotypes.sum() } else { haplotypes.max().unwrap_or(0) };
+        }
+
+        Ok(result)
+    }
+
+    // Merges the names if all sources have them, or assigns new identifiers otherwise.
+    // Returns the merged names, the merged count, and the identifier mapping for each source.
+    fn merge_names<N, C>(sources: &[&Metadata], names: N, count: C) -> (Vec<String>, usize, Vec<Vec<usize>>)
+    where
+        N: Fn(&Metadata) -> Option<&Dictionary>,
+        C: Fn(&Metadata) -> usize,
+    {
+        let mut merged: Vec<String> = Vec::new();
+        let mut mappings: Vec<Vec<usize>> = Vec::with_capacity(sources.len());
+        if sources.iter().all(|source| names(source).is_some()) {
+            let mut ids: HashMap<String, usize> = HashMap::new();
+            for source in sources.iter() {
+                let mut mapping: Vec<usize> = Vec::new();
+                for name in names(source).unwrap().as_ref().iter() {
+                    let name = String::from_utf8_lossy(name).to_string();
+                    let id = *ids.entry(name.clone()).or_insert_with(|| {
+                        merged.push(name);
+                        merged.len() - 1
+                    });
+                    mapping.push(id);
+                }
+                mappings.push(mapping);
+            }
+            (merged, ids.len(), mappings)
+        } else {
+            let mut total = 0;
+            for source in sources.iter() {
+                mappings.push((total..total + count(source)).collect());
+                total += count(source);
+            }
+            (merged, total, mappings)
+        }
+    }
+}
+
+/// Editing.
+impl Metadata {
+    /// Adds a path with the given name and returns its identifier.
+    ///
+    /// If the metadata has sample / contig names, the sample / contig must already exist.
+    /// Otherwise the number of samples / contigs is increased as necessary.
+    /// The number of haplotypes becomes the number of distinct (sample, phase) combinations in the path names.
+    /// The corresponding path must also be added to the GBWT index for the metadata to remain valid.
+    ///
+    /// Returns an error if the path name already exists or refers to a nonexistent sample / contig.
+    pub fn add_path(&mut self, path_name: PathName) -> Result<usize, crate::Error> {
+        if self.has_sample_names() && path_name.sample() >= self.samples() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Path name refers to a nonexistent sample")));
+        }
+        if self.has_contig_names() && path_name.contig() >= self.contigs() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Path name refers to a nonexistent contig")));
+        }
+        if self.find_path_name(&path_name).is_some() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Duplicate path names")));
+        }
+
+        let id = self.paths();
+        self.path_names.push(path_name);
+        if let Some(index) = self.path_index.0.get_mut() {
+            index.insert(path_name, id);
+        }
+        self.header.set(MetadataPayload::FLAG_PATH_NAMES);
+        let payload = self.header.payload_mut();
+        payload.sample_count = cmp::max(payload.sample_count, path_name.sample() + 1);
+        payload.contig_count = cmp::max(payload.contig_count, path_name.contig() + 1);
+        self.update_haplotypes();
+
+        Ok(id)
+    }
+
+    /// Adds a sample with the given name and returns its identifier.
+    ///
+    /// This rebuilds the sample dictionary, which takes time proportional to the total length of the names.
+    /// Use [`MetadataBuilder`] for adding or renaming many samples.
+    ///
+    /// Returns an error if the sample already exists or if the metadata has samples without names.
+    pub fn add_sample(&mut self, name: &str) -> Result<usize, crate::Error> {
+        if !self.has_sample_names() && self.samples() > 0 {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot add a named sample when the existing samples have no names")));
+        }
+        self.sample_names = Self::add_name(&self.sample_names, name)?;
+        self.header.set(MetadataPayload::FLAG_SAMPLE_NAMES);
+        self.header.payload_mut().sample_count = self.sample_names.len();
+        Ok(self.samples() - 1)
+    }
+
+    /// Adds a contig with the given name and returns its identifier.
+    ///
+    /// This rebuilds the contig dictionary, which takes time proportional to the total length of the names.
+    /// Use [`MetadataBuilder`] for adding or renaming many contigs.
+    ///
+    /// Returns an error if the contig already exists or if the metadata has contigs without names.
+    pub fn add_contig(&mut self, name: &str) -> Result<usize, crate::Error> {
+        if !self.has_contig_names() && self.contigs() > 0 {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot add a named contig when the existing contigs have no names")));
+        }
+        self.contig_names = Self::add_name(&self.contig_names, name)?;
+        self.header.set(MetadataPayload::FLAG_CONTIG_NAMES);
+        self.header.payload_mut().contig_count = self.contig_names.len();
+        Ok(self.contigs() - 1)
+    }
+
+    /// Renames the sample with the given identifier.
+    ///
+    /// This rebuilds the sample dictionary.
+    /// Use [`MetadataBuilder`] for renaming many samples.
+    ///
+    /// Returns an error if there are no sample names, if there is no such sample, or if another sample already has the name.
+    pub fn rename_sample(&mut self, id: usize, name: &str) -> Result<(), crate::Error> {
+        if !self.has_sample_names() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot rename a sample without sample names")));
+        }
+        self.sample_names = Self::rename(&self.sample_names, id, name)?;
+        Ok(())
+    }
+
+    /// Renames the contig with the given identifier.
+    ///
+    /// This rebuilds the contig dictionary.
+    /// Use [`MetadataBuilder`] for renaming many contigs.
+    ///
+    /// Returns an error if there are no contig names, if there is no such contig, or if another contig already has the name.
+    pub fn rename_contig(&mut self, id: usize, name: &str) -> Result<(), crate::Error> {
+        if !self.has_contig_names() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot rename a contig without contig names")));
+        }
+        self.contig_names = Self::rename(&self.contig_names, id, name)?;
+        Ok(())
+    }
+
+    /// Removes the sample with the given identifier and all paths referring to it.
+    ///
+    /// Returns the identifiers of the removed paths in sorted order, or an error if there is no such sample.
+    /// Later samples and paths get identifiers shifted down to fill the gaps.
+    /// The corresponding paths must also be removed from the GBWT index for the metadata to remain valid.
+    pub fn remove_sample(&mut self, id: usize) -> Result<Vec<usize>, crate::Error> {
+        if id >= self.samples() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot remove a nonexistent sample")));
+        }
+
+        let mut removed: Vec<usize> = Vec::new();
+        let mut path_names: Vec<PathName> = Vec::with_capacity(self.paths());
+        for (path_id, path_name) in self.path_names.iter().enumerate() {
+            match path_name.sample().cmp(&id) {
+                cmp::Ordering::Less => path_names.push(*path_name),
+                cmp::Ordering::Equal => removed.push(path_id),
+                cmp::Ordering::Greater => path_names.push(PathName::from_fields(path_name.sample() - 1, path_name.contig(), path_name.phase(), path_name.fragment())),
+            }
+        }
+        self.path_names = path_names;
+        self.path_index = LazyPathIndex::default();
+        self.update_haplotypes();
+        if self.path_names.is_empty() {
+            self.header.unset(MetadataPayload::FLAG_PATH_NAMES);
+        }
+
+        if self.has_sample_names() {
+            let names: Vec<String> = Self::names(&self.sample_names).into_iter().enumerate()
+                .filter(|(sample, _)| *sample != id)
+                .map(|(_, name)| name)
+                .collect();
+            self.sample_names = Dictionary::try_from(names).map_err(|msg| crate::Error::InvalidData(format!("Metadata: {}", msg)))?;
+        }
+        self.header.payload_mut().sample_count -= 1;
+        self.reference_samples = self.reference_samples.iter().filter(|sample| **sample != id).map(|sample| if *sample > id { sample - 1 } else { *sample }).collect();
+
+        Ok(removed)
+    }
+
+    // Recomputes the number of haplotypes from path names, if there are any.
+    fn update_haplotypes(&mut self) {
+        if self.has_path_names() {
+            let haplotypes: HashSet<(usize, usize)> = self.path_names.iter().map(|path_name| (path_name.sample(), path_name.phase())).collect();
+            self.header.payload_mut().haplotype_count = haplotypes.len();
+        }
+    }
+
+    // Returns the names in the dictionary as strings.
+    fn names(dictionary: &Dictionary) -> Vec<String> {
+        dictionary.as_ref().iter().map(|name| String::from_utf8_lossy(name).to_string()).collect()
+    }
+
+    // Returns a copy of the dictionary with the given name appended to it.
+    fn add_name(dictionary: &Dictionary, name: &str) -> Result<Dictionary, crate::Error> {
+        if dictionary.id(name).is_some() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Duplicate name")));
+        }
+        let mut names = Self::names(dictionary);
+        names.push(name.to_string());
+        Dictionary::try_from(names).map_err(|msg| crate::Error::InvalidData(format!("Metadata: {}", msg)))
+    }
+
+    // Returns a copy of the dictionary with the given name for string `id`.
+    fn rename(dictionary: &Dictionary, id: usize, name: &str) -> Result<Dictionary, crate::Error> {
+        if id >= dictionary.len() {
+            return Err(crate::Error::InvalidData(String::from("Metadata: Cannot rename a nonexistent name")));
+        }
+        match dictionary.id(name) {
+            Some(existing) if existing == id => return Ok(dictionary.clone()),
+            Some(_) => return Err(crate::Error::InvalidData(String::from("Metadata: Duplicate name"))),
+            None => {},
+        }
+        let mut names = Self::names(dictionary);
+        names[id] = name.to_string();
+        Dictionary::try_from(names).map_err(|msg| crate::Error::InvalidData(format!("Metadata: {}", msg)))
+    }
+}
+
+/// Path senses.
+impl Metadata {
+    /// Returns the sense of the sample with the given identifier, or [`None`] if there is no such sample.
+    pub fn sample_sense(&self, id: usize) -> Option<PathSense> {
+        if id >= self.samples() {
+            None
+        } else if self.sample(id) == Some(REF_SAMPLE) {
+            Some(PathSense::Generic)
+        } else if self.reference_samples.contains(&id) {
+            Some(PathSense::Reference)
+        } else {
+            Some(PathSense::Haplotype)
+        }
+    }
+
+    /// Returns the sense of the path with the given identifier, or [`None`] if there is no such path.
+    pub fn path_sense(&self, id: usize) -> Option<PathSense> {
+        let path_name = self.path(id)?;
+        self.sample_sense(path_name.sample())
+    }
+
+    /// Returns the identifiers of the samples with the given sense in sorted order.
+    pub fn samples_with_sense(&self, sense: PathSense) -> Vec<usize> {
+        (0..self.samples()).filter(|id| self.sample_sense(*id) == Some(sense)).collect()
+    }
+
+    /// Returns the identifiers of the paths with the given sense in sorted order.
+    pub fn paths_with_sense(&self, sense: PathSense) -> Vec<usize> {
+        (0..self.paths()).filter(|id| self.path_sense(*id) == Some(sense)).collect()
+    }
+
+    /// Changes the sense of the sample with the given identifier between [`PathSense::Reference`] and [`PathSense::Haplotype`].
+    ///
+    /// The sense of the paths for the sample changes accordingly.
+    /// Returns an error if there is no such sample, if generic sense is involved, or if a sample without a name would become a reference sample.
+    /// Generic sense is determined by sample name [`REF_SAMPLE`].
+    /// The change takes effect in the GBWT tags when the metadata is attached to an index with [`GBWT::set_metadata`].
+    pub fn set_sample_sense(&mut self, id: usize, sense: PathSense) -> Result<(), crate::Error> {
+        let current = self.sample_sense(id).ok_or_else(|| crate::Error::InvalidData(String::from("Metadata: Cannot change the sense of a nonexistent sample")))?;
+        if current == sense {
+            return Ok(());
+        }
+        match sense {
+            PathSense::Generic => return Err(crate::Error::InvalidData(String::from("Metadata: Cannot change the sense of a sample to generic"))),
+            _ if current == PathSense::Generic => return Err(crate::Error::InvalidData(String::from("Metadata: Cannot change the sense of a generic sample"))),
+            PathSense::Reference => {
+                if !self.has_sample_names() {
+                    return Err(crate::Error::InvalidData(String::from("Metadata: Reference samples must have names")));
+                }
+                self.reference_samples.insert(id);
+            },
+            PathSense::Haplotype => {
+                self.reference_samples.remove(&id);
+            },
+        }
+        Ok(())
+    }
+
+    // Returns the value of the reference samples tag, or [`None`] if there are no reference samples.
+    fn reference_samples_tag(&self) -> Option<String> {
+        if self.reference_samples.is_empty() {
+            return None;
+        }
+        let names: Vec<String> = self.reference_samples.iter().map(|id| self.sample_name(*id)).collect();
+        Some(names.join(&REFERENCE_SAMPLES_SEPARATOR.to_string()))
+    }
+
+    // Sets the reference samples from the value of the reference samples tag.
+    // Names that do not correspond to samples are ignored.
+    fn set_reference_samples_tag(&mut self, value: &str) {
+        self.reference_samples.clear();
+        for name in value.split(REFERENCE_SAMPLES_SEPARATOR) {
+            if let Some(id) = self.sample_id(name) {
+                if name != REF_SAMPLE {
+                    self.reference_samples.insert(id);
+                }
+            }
+        }
+    }
+}
+
+/// Memory mapping.
+impl Metadata {
+    /// Loads the metadata from a reader over the bytes of a memory mapped file.
+    ///
+    /// The sample and contig names are borrowed from the mapping; see [`Dictionary::load_mapped`].
+    /// The reader must be positioned at the start of serialized metadata, and it will be positioned after the metadata.
+    pub fn load_mapped(reader: &mut io::Cursor<&[u8]>, map: &Arc<SharedMap>) -> io::Result<Self> {
+        Self::load_with(reader, |reader| Dictionary::load_mapped(reader, map))
+    }
+
+    /// Returns `true` if the sample and contig names are borrowed from a memory mapping.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        self.sample_names.is_mapped()
+    }
+
+    // Loads the metadata using the given function for loading the dictionaries.
+    fn load_with<T: io::Read, F: FnMut(&mut T) -> io::Result<Dictionary>>(reader: &mut T, mut load_dictionary: F) -> io::Result<Self> {
+        let header = Header::<MetadataPayload>::load(reader)?;
+        header.validate()?;
+
+        let path_names = Vec::<PathName>::load(reader)?;
+        if header.is_set(MetadataPayload::FLAG_PATH_NAMES) == path_names.is_empty() {
+            return Err(crate::Error::MetadataMismatch("Metadata: Path name flag does not match the presence of path names").into());
+        }
+
+        let sample_names = load_dictionary(reader)?;
+        if header.is_set(MetadataPayload::FLAG_SAMPLE_NAMES) {
+            if header.payload().sample_count != sample_names.len() {
+                return Err(crate::Error::MetadataMismatch("Metadata: Sample count does not match the number of sample names").into());
+            }
+        } else if !sample_names.is_empty() {
+            return Err(crate::Error::MetadataMismatch("Metadata: Sample names are present without the sample name flag").into());
+        }
+
+        let contig_names = load_dictionary(reader)?;
+        if header.is_set(MetadataPayload::FLAG_CONTIG_NAMES) {
+            if header.payload().contig_count != contig_names.len() {
+                return Err(crate::Error::MetadataMismatch("Metadata: Contig count does not match the number of contig names").into());
+            }
+        } else if !contig_names.is_empty() {
+            return Err(crate::Error::MetadataMismatch("Metadata: Contig names are present without the contig name flag").into());
+        }
+
+        Ok(Metadata {
+            header, path_names, sample_names, contig_names,
+            reference_samples: BTreeSet::new(),
+            path_index: LazyPathIndex::default(),
+        })
+    }
+}
+
+impl Serialize for Metadata {
+    fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.header.serialize(writer)
+    }
+
+    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.path_names.serialize(writer)?;
+        self.sample_names.serialize(writer)?;
+        self.contig_names.serialize(writer)?;
+        Ok(())
+    }
+
+    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
+        Self::load_with(reader, Dictionary::load)
+    }
+
+    fn size_in_elements(&self) -> usize {
+        self.header.size_in_elements() + self.path_names.size_in_elements() + self.sample_names.size_in_elements() + self.contig_names.size_in_elements()
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// A secondary index for path lookups in `Metadata`.
+#[derive(Clone, Debug, Default)]
+struct PathIndex {
+    names: HashMap<PathName, usize>,
+    samples: Vec<Vec<usize>>,
+    contigs: Vec<Vec<usize>>,
 }
 
-impl<'a> Iterator for SequenceIter<'a> {
-    type Item = usize;
+impl PathIndex {
+    fn new(path_names: &[PathName]) -> Self {
+        let mut result = PathIndex {
+            names: HashMap::with_capacity(path_names.len()),
+            samples: Vec::new(),
+            contigs: Vec::new(),
+        };
+        for (id, path_name) in path_names.iter().enumerate() {
+            result.insert(*path_name, id);
+        }
+        result
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(pos) = self.next {
-            self.next = self.parent.forward(pos);
-            Some(pos.node)
-        } else {
-            None
+    // Paths must be inserted in increasing order of identifiers.
+    fn insert(&mut self, path_name: PathName, id: usize) {
+        self.names.insert(path_name, id);
+        if path_name.sample() >= self.samples.len() {
+            self.samples.resize(path_name.sample() + 1, Vec::new());
         }
+        self.samples[path_name.sample()].push(id);
+        if path_name.contig() >= self.contigs.len() {
+            self.contigs.resize(path_name.contig() + 1, Vec::new());
+        }
+        self.contigs[path_name.contig()].push(id);
     }
 }
 
-impl<'a> FusedIterator for SequenceIter<'a> {}
+// A lazily built `PathIndex`.
+// The index is derived from the path names, so it does not affect equality.
+#[derive(Clone, Debug, Default)]
+struct LazyPathIndex(OnceLock<PathIndex>);
+
+impl PartialEq for LazyPathIndex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for LazyPathIndex {}
 
 //-----------------------------------------------------------------------------
 
-/// Metadata for the paths in a GBWT index.
+/// A structure for building [`Metadata`] incrementally.
 ///
-/// The metadata contains some basic statistics about the paths, and it may also contain names for paths, samples, and contigs.
-/// Samples correspond to biological samples.
-/// Contigs usually correspond to contigs in the reference sequence as well as to connected components in the graph.
-/// The number of haplotypes is an estimate for the number of full-length paths in a connected component.
+/// Samples and contigs can be added by name, and paths can refer to them by name or by identifier.
+/// Path names are checked for uniqueness when they are added.
+/// The identifiers in path names are checked against the named samples and contigs when the metadata is built.
+/// If there are no sample / contig names, the number of samples / contigs is determined from the path names.
 ///
-/// Path names are structured names associated with each path in the original graph.
-/// If the GBWT index is bidirectional, it contains two sequences for each path name.
-/// Each path name is a unique combination of four fields: sample, contig, phase, and fragment (see [`PathName`]).
-/// The first two must be in the intervals `0..self.samples()` and `0..self.contigs()`, respectively.
+/// Existing metadata can be converted into a builder for batch edits.
+/// The builder indexes the names with hash maps, so adding or renaming a sample / contig takes constant time.
+/// The dictionaries are built only once, when the builder is converted back into metadata.
 ///
 /// # Examples
 ///
 /// ```
-/// use gbwt::{Metadata};
-/// use gbwt::support;
-/// use simple_sds::serialize;
-///
-/// let filename = support::get_test_data("example.meta");
-/// let metadata: Metadata = serialize::load_from(&filename).unwrap();
-///
-/// assert!(metadata.has_path_names());
-/// assert_eq!(metadata.paths(), 6);
-/// assert_eq!(metadata.pan_sn_path(3), Some("sample#2#A".to_string()));
-/// let path = metadata.path(3).unwrap();
+/// use gbwt::{Metadata, PathName};
+/// use gbwt::gbwt::MetadataBuilder;
+/// use std::convert::TryFrom;
 ///
-/// assert!(metadata.has_sample_names());
-/// assert_eq!(metadata.sample(path.sample()), Some("sample"));
+/// let mut builder = MetadataBuilder::new();
+/// builder.add_named_path("GRCh38", "chr1", 0, 0).unwrap();
+/// builder.add_named_path("HG002", "chr1", 1, 0).unwrap();
+/// builder.add_named_path("HG002", "chr1", 2, 0).unwrap();
+/// let chr2 = builder.add_contig("chr2").unwrap();
+/// builder.add_path(PathName::from_fields(0, chr2, 0, 0)).unwrap();
+/// assert!(builder.add_named_path("HG002", "chr1", 1, 0).is_err());
 ///
-/// assert!(metadata.has_contig_names());
-/// assert_eq!(metadata.contig(path.contig()), Some("A"));
+/// let metadata = Metadata::try_from(builder).unwrap();
+/// assert_eq!(metadata.paths(), 4);
+/// assert_eq!(metadata.samples(), 2);
+/// assert_eq!(metadata.haplotypes(), 3);
+/// assert_eq!(metadata.contigs(), 2);
+/// assert_eq!(metadata.pan_sn_path(3), Some("GRCh38#0#chr2".to_string()));
 ///
-/// // Find the paths over contig B.
-/// let id = metadata.contig_id("B").unwrap();
-/// let mut paths = Vec::<usize>::new();
-/// for (i, path) in metadata.path_iter().enumerate() {
-///     if path.contig() == id {
-///         paths.push(i);
-///     }
+/// // Batch edits.
+/// let mut builder = MetadataBuilder::from(metadata);
+/// for i in 0..100 {
+///     builder.add_sample(&format!("sample_{}", i)).unwrap();
 /// }
-/// assert_eq!(paths, vec![1, 4, 5]);
+/// builder.rename_sample(0, "CHM13").unwrap();
+/// let metadata = Metadata::try_from(builder).unwrap();
+/// assert_eq!(metadata.samples(), 102);
+/// assert_eq!(metadata.sample_id("CHM13"), Some(0));
 /// ```
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Metadata {
-    header: Header<MetadataPayload>,
+#[derive(Clone, Debug, Default)]
+pub struct MetadataBuilder {
     path_names: Vec<PathName>,
-    sample_names: Dictionary,
-    contig_names: Dictionary,
+    unique: HashSet<PathName>,
+    sample_names: Vec<String>,
+    sample_ids: HashMap<String, usize>,
+    contig_names: Vec<String>,
+    contig_ids: HashMap<String, usize>,
+    // Counts and reference samples from existing metadata.
+    sample_count: usize,
+    haplotype_count: usize,
+    contig_count: usize,
+    reference_samples: BTreeSet<usize>,
 }
 
-/// Paths.
-impl Metadata {
-    /// Returns `true` if the metadata contains path names.
-    pub fn has_path_names(&self) -> bool {
-        self.header.is_set(MetadataPayload::FLAG_PATH_NAMES)
+impl MetadataBuilder {
+    /// Returns an empty builder.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Returns the number path names in the metadata.
-    ///
-    /// If there are path names, each name corresponds to a path in the original graph.
-    /// In a bidirectional GBWT, there are twice as many sequences as paths.
+    /// Returns the number of path names in the builder.
     pub fn paths(&self) -> usize {
         self.path_names.len()
     }
 
-    /// Returns the name of the path with the given identifier, or [`None`] if there is no such name.
-    ///
-    /// Valid identifiers are in the interval `0..self.paths()`.
-    pub fn path(&self, id: usize) -> Option<PathName> {
-        if id < self.paths() {
-            Some(self.path_names[id])
-        } else {
-            None
-        }
-    }
-
-    /// Returns the name of the path with the given identifier in the [PanSN format](https://github.com/pangenome/PanSN-spec), or [`None`] if there is no such name.
-    ///
-    /// Valid identifiers are in the interval `0..self.paths()`.
-    /// `'#'` will be used as the separator character.
-    pub fn pan_sn_path(&self, id: usize) -> Option<String> {
-        let path_name = self.path(id)?;
-        let result = format!("{}#{}#{}", self.sample_name(path_name.sample()), path_name.phase(), self.contig_name(path_name.contig()));
-        Some(result)
-    }
-
-    /// Returns an iterator over path names.
-    pub fn path_iter(&self) -> slice::Iter<PathName> {
-        self.path_names.iter()
+    /// Returns the number of named samples in the builder.
+    pub fn samples(&self) -> usize {
+        self.sample_names.len()
     }
-}
 
-/// Samples.
-impl Metadata {
-    /// Returns `true` if the metadata contains sample names.
-    pub fn has_sample_names(&self) -> bool {
-        self.header.is_set(MetadataPayload::FLAG_SAMPLE_NAMES)
+    /// Returns the number of named contigs in the builder.
+    pub fn contigs(&self) -> usize {
+        self.contig_names.len()
     }
 
-    /// Returns the number samples.
-    pub fn samples(&self) -> usize {
-        self.header.payload().sample_count
+    /// Returns the identifier of the sample with the given name, or [`None`] if there is no such sample.
+    pub fn sample_id(&self, name: &str) -> Option<usize> {
+        self.sample_ids.get(name).copied()
     }
 
-    /// Returns the number of haplotypes.
-    ///
-    /// This generally corresponds to the number of full-length paths in a graph component.
-    pub fn haplotypes(&self) -> usize {
-        self.header.payload().haplotype_count
+    /// Returns the identifier of the contig with the given name, or [`None`] if there is no such contig.
+    pub fn contig_id(&self, name: &str) -> Option<usize> {
+        self.contig_ids.get(name).copied()
     }
 
-    /// Returns the name of the sample with the given identifier, or [`None`] if there is no such sample or name.
+    /// Adds a sample with the given name and returns its identifier.
     ///
-    /// Valid identifiers are in the interval `0..self.samples()`.
-    /// Also returns [`None`] if the name exists but is not valid UTF-8.
-    pub fn sample(&self, id: usize) -> Option<&str> {
-        if self.has_sample_names() && id < self.samples() {
-            self.sample_names.str(id).ok()
-        } else {
-            None
+    /// Returns an error if the sample already exists or if the builder has samples without names.
+    pub fn add_sample(&mut self, name: &str) -> Result<usize, crate::Error> {
+        if self.sample_names.is_empty() && self.sample_count > 0 {
+            return Err(crate::Error::InvalidData(String::from("MetadataBuilder: Cannot add a named sample when the existing samples have no names")));
         }
+        Self::add_name(&mut self.sample_names, &mut self.sample_ids, name).ok_or_else(|| crate::Error::InvalidData(String::from("MetadataBuilder: Duplicate sample name")))
     }
 
-    /// Returns the name of the sample with the given identifier.
+    /// Adds a contig with the given name and returns its identifier.
     ///
-    /// Returns a string representation of the the sample identifier when [`Metadata::sample`] would return [`None`].
-    pub fn sample_name(&self, id: usize) -> String {
-        if let Some(name) = self.sample(id) {
-            name.to_string()
-        } else {
-            id.to_string()
+    /// Returns an error if the contig already exists or if the builder has contigs without names.
+    pub fn add_contig(&mut self, name: &str) -> Result<usize, crate::Error> {
+        if self.contig_names.is_empty() && self.contig_count > 0 {
+            return Err(crate::Error::InvalidData(String::from("MetadataBuilder: Cannot add a named contig when the existing contigs have no names")));
         }
-    }
- 
-    /// Returns the sample idenfier corresponding to the given sample name, or [`None`] if there is no such sample.
-    pub fn sample_id(&self, name: &str) -> Option<usize> {
-        self.sample_names.id(name)
-    }
-
-    /// Returns an iterator over sample names.
-    pub fn sample_iter(&self) -> StringIter {
-        self.sample_names.as_ref().iter()
-    }
-}
-
-/// Contigs.
-impl Metadata {
-    /// Returns `true` if the metadata contains contig names.
-    pub fn has_contig_names(&self) -> bool {
-        self.header.is_set(MetadataPayload::FLAG_CONTIG_NAMES)
+        Self::add_name(&mut self.contig_names, &mut self.contig_ids, name).ok_or_else(|| crate::Error::InvalidData(String::from("MetadataBuilder: Duplicate contig name")))
     }
 
-    /// Returns the number contigs.
+    /// Renames the sample with the given identifier.
     ///
-    /// A contig usually corresponds to a graph component.
-    pub fn contigs(&self) -> usize {
-        self.header.payload().contig_count
+    /// Returns an error if there is no such named sample or if another sample already has the name.
+    pub fn rename_sample(&mut self, id: usize, name: &str) -> Result<(), crate::Error> {
+        Self::rename(&mut self.sample_names, &mut self.sample_ids, id, name)
     }
 
-    /// Returns the name of the contig with the given identifier, or [`None`] if there is no such contig or name.
+    /// Renames the contig with the given identifier.
     ///
-    /// Valid identifiers are in the interval `0..self.contigs()`.
-    /// Also returns [`None`] if the name exists but is not valid UTF-8.
-    pub fn contig(&self, id: usize) -> Option<&str> {
-        if self.has_contig_names() && id < self.contigs() {
-            self.contig_names.str(id).ok()
-        } else {
-            None
-        }
+    /// Returns an error if there is no such named contig or if another contig already has the name.
+    pub fn rename_contig(&mut self, id: usize, name: &str) -> Result<(), crate::Error> {
+        Self::rename(&mut self.contig_names, &mut self.contig_ids, id, name)
     }
 
-    /// Returns the name of the contig with the given identifier.
+    /// Adds a path with the given name and returns its identifier.
     ///
-    /// Returns a string representation of the the contig identifier when [`Metadata::contig`] would return [`None`].
-    pub fn contig_name(&self, id: usize) -> String {
-        if let Some(name) = self.contig(id) {
-            name.to_string()
-        } else {
-            id.to_string()
+    /// Returns an error if the path name already exists.
+    pub fn add_path(&mut self, path_name: PathName) -> Result<usize, crate::Error> {
+        if !self.unique.insert(path_name) {
+            return Err(crate::Error::InvalidData(String::from("MetadataBuilder: Duplicate path name")));
         }
+        self.path_names.push(path_name);
+        Ok(self.paths() - 1)
     }
 
-    /// Returns the contig idenfier corresponding to the given contig name, or [`None`] if there is no such contig.
-    pub fn contig_id(&self, name: &str) -> Option<usize> {
-        self.contig_names.id(name)
-    }
-
-    /// Returns an iterator over contig names.
-    pub fn contig_iter(&self) -> StringIter {
-        self.contig_names.as_ref().iter()
+    /// Adds a path with the given sample name, contig name, phase, and fragment and returns its identifier.
+    ///
+    /// The sample and the contig are added if they do not exist yet.
+    /// Returns an error if the path name already exists.
+    pub fn add_named_path(&mut self, sample: &str, contig: &str, phase: usize, fragment: usize) -> Result<usize, crate::Error> {
+        let sample = match self.sample_id(sample) {
+            Some(id) => id,
+            None => self.add_sample(sample)?,
+        };
+        let contig = match self.contig_id(contig) {
+            Some(id) => id,
+            None => self.add_contig(contig)?,
+        };
+        self.add_path(PathName::from_fields(sample, contig, phase, fragment))
     }
-}
 
-impl Serialize for Metadata {
-    fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
-        self.header.serialize(writer)
+    // Adds the name if it does not exist and returns its identifier.
+    fn add_name(names: &mut Vec<String>, ids: &mut HashMap<String, usize>, name: &str) -> Option<usize> {
+        if ids.contains_key(name) {
+            return None;
+        }
+        ids.insert(name.to_string(), names.len());
+        names.push(name.to_string());
+        Some(names.len() - 1)
     }
 
-    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
-        self.path_names.serialize(writer)?;
-        self.sample_names.serialize(writer)?;
-        self.contig_names.serialize(writer)?;
+    // Renames string `id`.
+    fn rename(names: &mut [String], ids: &mut HashMap<String, usize>, id: usize, name: &str) -> Result<(), crate::Error> {
+        if id >= names.len() {
+            return Err(crate::Error::InvalidData(String::from("MetadataBuilder: Cannot rename a nonexistent name")));
+        }
+        match ids.get(name) {
+            Some(existing) if *existing == id => return Ok(()),
+            Some(_) => return Err(crate::Error::InvalidData(String::from("MetadataBuilder: Duplicate name"))),
+            None => {},
+        }
+        ids.remove(&names[id]);
+        ids.insert(name.to_string(), id);
+        names[id] = name.to_string();
         Ok(())
     }
+}
 
-    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
-        let header = Header::<MetadataPayload>::load(reader)?;
-        if let Err(msg) = header.validate() {
-            return Err(Error::new(ErrorKind::InvalidData, msg));
-        }
-
-        let path_names = Vec::<PathName>::load(reader)?;
-        if header.is_set(MetadataPayload::FLAG_PATH_NAMES) == path_names.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidData, "Metadata: Path name flag does not match the presence of path names"));
+impl From<Metadata> for MetadataBuilder {
+    fn from(source: Metadata) -> Self {
+        let sample_names = Metadata::names(&source.sample_names);
+        let sample_ids = sample_names.iter().enumerate().map(|(id, name)| (name.clone(), id)).collect();
+        let contig_names = Metadata::names(&source.contig_names);
+        let contig_ids = contig_names.iter().enumerate().map(|(id, name)| (name.clone(), id)).collect();
+        MetadataBuilder {
+            unique: source.path_names.iter().copied().collect(),
+            sample_count: source.samples(),
+            haplotype_count: source.haplotypes(),
+            contig_count: source.contigs(),
+            path_names: source.path_names,
+            sample_names,
+            sample_ids,
+            contig_names,
+            contig_ids,
+            reference_samples: source.reference_samples,
         }
+    }
+}
 
-        let sample_names = Dictionary::load(reader)?;
-        if header.is_set(MetadataPayload::FLAG_SAMPLE_NAMES) {
-            if header.payload().sample_count != sample_names.len() {
-                return Err(Error::new(ErrorKind::InvalidData, "Metadata: Sample count does not match the number of sample names"));
-            }
-        } else if !sample_names.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidData, "Metadata: Sample names are present without the sample name flag"));
+impl TryFrom<MetadataBuilder> for Metadata {
+    type Error = crate::Error;
+
+    fn try_from(source: MetadataBuilder) -> Result<Self, Self::Error> {
+        let mut result = Metadata::from_names(source.path_names, &source.sample_names, &source.contig_names)?;
+        let has_path_names = result.has_path_names();
+        let payload = result.header.payload_mut();
+        payload.sample_count = cmp::max(payload.sample_count, source.sample_count);
+        payload.contig_count = cmp::max(payload.contig_count, source.contig_count);
+        if !has_path_names {
+            payload.haplotype_count = source.haplotype_count;
         }
-
-        let contig_names = Dictionary::load(reader)?;
-        if header.is_set(MetadataPayload::FLAG_CONTIG_NAMES) {
-            if header.payload().contig_count != contig_names.len() {
-                return Err(Error::new(ErrorKind::InvalidData, "Metadata: Contig count does not match the number of contig names"));
-            }
-        } else if !contig_names.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidData, "Metadata: Contig names are present without the contig name flag"));
+        if source.reference_samples.iter().any(|sample| *sample >= result.samples()) {
+            return Err(crate::Error::InvalidData(String::from("MetadataBuilder: Reference sample does not exist")));
         }
-
-        Ok(Metadata {
-            header, path_names, sample_names, contig_names,
-        })
-    }
-
-    fn size_in_elements(&self) -> usize {
-        self.header.size_in_elements() + self.path_names.size_in_elements() + self.sample_names.size_in_elements() + self.contig_names.size_in_elements()
+        result.reference_samples = source.reference_samples;
+        Ok(result)
     }
 }
 
@@ -870,3 +3465,161 @@ impl PathName {
 impl Serializable for PathName {}
 
 //-----------------------------------------------------------------------------
+
+/// The sense of a path, as in the C++ GBWTGraph.
+///
+/// * Generic paths are named paths corresponding to P-lines in GFA.
+///   They have sample name [`REF_SAMPLE`], and the contig name is the name of the path.
+/// * Reference paths belong to samples listed in the [`crate::REFERENCE_SAMPLES_KEY`] tag of the GBWT index.
+///   They are usually written as W-lines in GFA, but they are intended to be used as reference coordinates.
+/// * Haplotype paths are all other paths.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{GBWT, PathSense};
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.gbwt");
+/// let mut index: GBWT = serialize::load_from(&filename).unwrap();
+/// let mut metadata = index.metadata().unwrap().clone();
+/// assert_eq!(metadata.paths_with_sense(PathSense::Generic), vec![0, 1]);
+/// assert_eq!(metadata.paths_with_sense(PathSense::Haplotype), vec![2, 3, 4, 5]);
+///
+/// // Promote the sample to a reference sample.
+/// let sample = metadata.sample_id("sample").unwrap();
+/// metadata.set_sample_sense(sample, PathSense::Reference).unwrap();
+/// assert_eq!(metadata.path_sense(2), Some(PathSense::Reference));
+/// index.set_metadata(metadata).unwrap();
+/// assert_eq!(index.metadata().unwrap().samples_with_sense(PathSense::Reference), vec![sample]);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSense {
+    /// A path intended to be used as reference coordinates.
+    Reference,
+    /// A named path without a sample.
+    Generic,
+    /// A haplotype path of a sample.
+    Haplotype,
+}
+
+//-----------------------------------------------------------------------------
+
+/// A path name parsed from a string in the [PanSN format](https://github.com/pangenome/PanSN-spec).
+///
+/// The following formats are supported:
+///
+/// * `sample#phase#contig`: A path name without a fragment.
+/// * `sample#phase#contig#fragment`: A path name with an explicit fragment field.
+/// * `sample#phase#contig[start]` or `sample#phase#contig[start-end]`: A fragment starting at offset `start` of the contig.
+///   The starting offset is used as the fragment field.
+/// * `contig`, `contig[start]`, or `contig[start-end]`: A named / reference path with sample name [`REF_SAMPLE`] and phase 0.
+///
+/// The names can be resolved to path identifiers with [`Metadata::pan_sn_paths`] and [`Metadata::resolve_pan_sn`].
+/// Names with a fragment end must be resolved with [`GBZ::resolve_pan_sn`](crate::GBZ::resolve_pan_sn), which checks the end against the path length.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::gbwt::PanSN;
+/// use std::str::FromStr;
+///
+/// let name = PanSN::from_str("HG002#1#chr20[1000-2000]").unwrap();
+/// assert_eq!(name.sample, "HG002");
+/// assert_eq!(name.phase, 1);
+/// assert_eq!(name.contig, "chr20");
+/// assert_eq!(name.fragment, Some(1000));
+/// assert_eq!(name.end, Some(2000));
+///
+/// let name = PanSN::from_str("chrM").unwrap();
+/// assert_eq!(name.sample, gbwt::REF_SAMPLE);
+/// assert_eq!(name.fragment, None);
+///
+/// assert!(PanSN::from_str("HG002#chr20").is_err());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PanSN {
+    /// Sample name.
+    pub sample: String,
+
+    /// Phase / haplotype identifier.
+    pub phase: usize,
+
+    /// Contig name.
+    pub contig: String,
+
+    /// Fragment identifier / starting offset, if specified.
+    pub fragment: Option<usize>,
+
+    /// End offset of the fragment, if specified.
+    pub end: Option<usize>,
+}
+
+impl PanSN {
+    // Parses the contig name with an optional `[start]` or `[start-end]` suffix.
+    fn parse_contig(contig: &str) -> Result<(String, Option<usize>, Option<usize>), crate::Error> {
+        if !contig.ends_with(']') {
+            return Ok((contig.to_string(), None, None));
+        }
+        let open = contig.rfind('[').ok_or_else(|| crate::Error::InvalidData(String::from("PanSN: Unmatched ']' in the contig name")))?;
+        let interval = &contig[open + 1..contig.len() - 1];
+        let (start, end) = match interval.split_once('-') {
+            Some((start, end)) => (start, Some(end)),
+            None => (interval, None),
+        };
+        let start = start.parse::<usize>().map_err(|_| crate::Error::InvalidData(String::from("PanSN: Invalid fragment start")))?;
+        let end = match end {
+            Some(end) => Some(end.parse::<usize>().map_err(|_| crate::Error::InvalidData(String::from("PanSN: Invalid fragment end")))?),
+            None => None,
+        };
+        if let Some(end) = end {
+            if end < start {
+                return Err(crate::Error::InvalidData(String::from("PanSN: Fragment end is before the start")));
+            }
+        }
+        Ok((contig[..open].to_string(), Some(start), end))
+    }
+}
+
+impl FromStr for PanSN {
+    type Err = crate::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = name.split('#').collect();
+        let (sample, phase, contig, fragment) = match fields.len() {
+            1 => (REF_SAMPLE, 0, fields[0], None),
+            3 | 4 => {
+                let phase = fields[1].parse::<usize>().map_err(|_| crate::Error::InvalidData(String::from("PanSN: Invalid phase")))?;
+                let fragment = if fields.len() == 4 {
+                    Some(fields[3].parse::<usize>().map_err(|_| crate::Error::InvalidData(String::from("PanSN: Invalid fragment")))?)
+                } else {
+                    None
+                };
+                (fields[0], phase, fields[2], fragment)
+            },
+            _ => return Err(crate::Error::InvalidData(String::from("PanSN: Invalid number of fields"))),
+        };
+        if sample.is_empty() {
+            return Err(crate::Error::InvalidData(String::from("PanSN: Empty sample name")));
+        }
+
+        let (contig, start, end) = PanSN::parse_contig(contig)?;
+        if contig.is_empty() {
+            return Err(crate::Error::InvalidData(String::from("PanSN: Empty contig name")));
+        }
+        if fragment.is_some() && start.is_some() {
+            return Err(crate::Error::InvalidData(String::from("PanSN: Both fragment field and fragment interval are specified")));
+        }
+
+        Ok(PanSN {
+            sample: sample.to_string(),
+            phase,
+            contig,
+            fragment: fragment.or(start),
+            end,
+        })
+    }
+}
+
+//-----------------------------------------------------------------------------
@@ -4,8 +4,10 @@ use crate::Orientation;
 
 use simple_sds::serialize;
 
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::convert::TryFrom;
+use std::str::FromStr;
 
 //-----------------------------------------------------------------------------
 
@@ -80,6 +82,26 @@ fn serialize_with_empty() {
     serialize::test(&index, "gbwt-with-empty", None, true);
 }
 
+#[test]
+fn memory_mapping() {
+    for name in ["example.gbwt", "with-empty.gbwt", "translation.gbwt"] {
+        let filename = support::get_test_data(name);
+        let loaded: GBWT = serialize::load_from(&filename).unwrap();
+        let mapped = GBWT::open_mmap(&filename).unwrap();
+        assert!(mapped.is_mapped(), "[{}]: The BWT is not memory mapped", name);
+        assert!(!loaded.is_mapped(), "[{}]: The loaded BWT is memory mapped", name);
+        if let Some(metadata) = mapped.metadata() {
+            assert!(metadata.is_mapped(), "[{}]: The metadata is not memory mapped", name);
+        }
+        assert_eq!(mapped, loaded, "[{}]: Memory mapped index differs from the loaded index", name);
+        assert_eq!(mapped.size_in_bytes(), loaded.size_in_bytes(), "[{}]: Invalid size for the memory mapped index", name);
+        for id in 0..loaded.sequences() {
+            assert!(mapped.sequence(id).unwrap().eq(loaded.sequence(id).unwrap()), "[{}]: Invalid sequence {}", name, id);
+        }
+        serialize::test(&mapped, &format!("gbwt-mapped-{}", name), None, true);
+    }
+}
+
 //-----------------------------------------------------------------------------
 
 fn extract_sequence(index: &GBWT, id: usize) -> Vec<usize> {
@@ -210,6 +232,22 @@ fn backward_with_empty() {
     test_backward("with-empty.gbwt");
 }
 
+#[test]
+fn backward_first_node() {
+    // The first node of the alphabet is in the middle of the path.
+    let path = vec![
+        support::encode_node(2, Orientation::Forward),
+        support::encode_node(1, Orientation::Forward),
+        support::encode_node(3, Orientation::Reverse),
+    ];
+    let index = build_from(&[path.clone()]);
+    for i in 0..index.sequences() {
+        let mut truth = extract_sequence(&index, i);
+        truth.reverse();
+        assert_eq!(extract_backward(&index, i), truth, "Invalid reverse sequence {}", i);
+    }
+}
+
 fn test_sequence(test_file: &'static str) {
     let filename = support::get_test_data(test_file);
     let index: GBWT = serialize::load_from(&filename).unwrap();
@@ -234,6 +272,437 @@ fn sequence_with_empty() {
     test_sequence("with-empty.gbwt");
 }
 
+fn check_random_access(index: &GBWT, name: &str) {
+    for id in 0..index.sequences() {
+        let truth = extract_sequence(index, id);
+        assert_eq!(index.sequence_len(id), Some(truth.len()), "{}: Invalid length for sequence {}", name, id);
+        for start in 0..truth.len() + 2 {
+            let expected = if start < truth.len() { Some(truth[start]) } else { None };
+            assert_eq!(index.position_at(id, start).map(|pos| pos.node), expected, "{}: Invalid position at offset {} of sequence {}", name, start, id);
+            let iterated: Vec<usize> = index.sequence_from(id, start).unwrap().collect();
+            assert_eq!(iterated, truth[cmp::min(start, truth.len())..].to_vec(), "{}: Invalid iteration from offset {} of sequence {}", name, start, id);
+            for limit in start..truth.len() + 2 {
+                let expected = truth[cmp::min(start, truth.len())..cmp::min(limit, truth.len())].to_vec();
+                assert_eq!(index.extract(id, start..limit), expected, "{}: Invalid extraction of range {}..{} of sequence {}", name, start, limit, id);
+            }
+        }
+    }
+    assert!(index.sequence_len(index.sequences()).is_none(), "{}: Got a length for a past-the-end sequence", name);
+    assert!(index.sequence_from(index.sequences(), 0).is_none(), "{}: Got an iterator for a past-the-end sequence", name);
+    assert!(index.extract(index.sequences(), 0..1).is_empty(), "{}: Extracted a past-the-end sequence", name);
+}
+
+fn test_random_access(test_file: &'static str) {
+    let filename = support::get_test_data(test_file);
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    assert!(!index.has_sequence_samples(), "The index contains sequence samples after loading");
+    check_random_access(&index, "No samples");
+
+    for interval in [1, 2, 3, 100] {
+        index.build_sequence_samples(interval);
+        assert!(index.has_sequence_samples(), "No sequence samples with interval {}", interval);
+        assert_eq!(index.sequence_samples().unwrap().interval(), interval, "Invalid sample interval");
+        check_random_access(&index, &format!("Interval {}", interval));
+    }
+
+    index.remove_sequence_samples();
+    assert!(!index.has_sequence_samples(), "The index contains sequence samples after removing them");
+}
+
+fn test_sequence_rev(test_file: &'static str) {
+    let filename = support::get_test_data(test_file);
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+
+    for samples in [false, true] {
+        if samples {
+            index.build_sequence_samples(2);
+        }
+        for i in 0..index.sequences() {
+            let mut truth = extract_sequence(&index, i);
+            truth.reverse();
+            let iterated: Vec<usize> = index.sequence_rev(i).unwrap().collect();
+            assert_eq!(iterated, truth, "Invalid reverse sequence {} (samples: {})", i, samples);
+        }
+        assert!(index.sequence_rev(index.sequences()).is_none(), "Got a reverse iterator for a past-the-end sequence id");
+    }
+}
+
+#[test]
+fn sequence_rev() {
+    test_sequence_rev("example.gbwt");
+}
+
+#[test]
+fn sequence_rev_with_empty() {
+    test_sequence_rev("with-empty.gbwt");
+}
+
+#[test]
+fn sequence_rev_unidirectional() {
+    let paths = true_paths(true);
+    let mut builder = GBWTBuilder::new(false);
+    for path in paths.iter() {
+        builder.insert(path).unwrap();
+    }
+    let index = GBWT::try_from(builder).unwrap();
+    for (i, path) in paths.iter().enumerate() {
+        let mut truth = path.clone();
+        truth.reverse();
+        let iterated: Vec<usize> = index.sequence_rev(i).unwrap().collect();
+        assert_eq!(iterated, truth, "Invalid reverse sequence {}", i);
+    }
+}
+
+#[test]
+fn sequence_rev_first_node() {
+    // The first node of the alphabet is in the middle of the path.
+    let path = vec![
+        support::encode_node(2, Orientation::Forward),
+        support::encode_node(1, Orientation::Forward),
+        support::encode_node(3, Orientation::Forward),
+    ];
+    let index = build_from(&[path.clone()]);
+    let mut truth = path.clone();
+    truth.reverse();
+    let iterated: Vec<usize> = index.sequence_rev(0).unwrap().collect();
+    assert_eq!(iterated, truth, "Invalid reverse sequence");
+}
+
+#[test]
+fn random_access() {
+    test_random_access("example.gbwt");
+}
+
+#[test]
+fn random_access_with_empty() {
+    test_random_access("with-empty.gbwt");
+}
+
+//-----------------------------------------------------------------------------
+
+fn test_locate(test_file: &'static str) {
+    let filename = support::get_test_data(test_file);
+    let index: GBWT = serialize::load_from(&filename).unwrap();
+    assert!(index.has_da_samples(), "The index does not contain document array samples");
+
+    // Locate every position on every sequence.
+    for i in 0..index.sequences() {
+        assert_eq!(index.locate_pos(Pos::new(ENDMARKER, i)), Some(i), "Invalid sequence id for endmarker offset {}", i);
+        let mut pos = index.start(i);
+        while let Some(p) = pos {
+            assert_eq!(index.locate_pos(p), Some(i), "Invalid sequence id for position {:?} on sequence {}", p, i);
+            pos = index.forward(p);
+        }
+    }
+    assert!(index.locate_pos(Pos::new(ENDMARKER, index.sequences())).is_none(), "Located a past-the-end endmarker offset");
+
+    // Offsets past the end of a record must not return the samples of the next record.
+    let samples = index.da_samples.as_ref().unwrap();
+    for record_id in 0..index.bwt.len() {
+        let len = index.bwt.record(record_id).map(|record| record.len()).unwrap_or(0);
+        for offset in len..len + 3 {
+            assert!(samples.try_locate(record_id, offset).is_none(), "Located invalid offset {} in record {}", offset, record_id);
+        }
+    }
+    for node in true_nodes() {
+        let len = index.bwt.record(index.node_to_record(node)).unwrap().len();
+        assert!(index.locate_pos(Pos::new(node, len)).is_none(), "Located a past-the-end offset in node {}", node);
+        assert!(index.locate_pos(Pos::new(node, usize::MAX)).is_none(), "Located offset usize::MAX in node {}", node);
+    }
+
+    // Huge offsets must not overflow.
+    for record_id in 0..index.bwt.len() {
+        assert!(samples.try_locate(record_id, usize::MAX).is_none(), "Located offset usize::MAX in record {}", record_id);
+        assert!(samples.sampled_range(record_id, usize::MAX - 1..usize::MAX).is_empty(), "Found samples at huge offsets in record {}", record_id);
+    }
+
+    // Locate all occurrences of each node.
+    let sequences: Vec<Vec<usize>> = (0..index.sequences()).map(|i| extract_sequence(&index, i)).collect();
+    for node in true_nodes() {
+        let state = index.find(node).unwrap();
+        let truth: Vec<usize> = (0..sequences.len()).filter(|i| sequences[*i].contains(&node)).collect();
+        assert_eq!(index.locate(&state), truth, "Invalid sequence ids for node {}", node);
+    }
+}
+
+#[test]
+fn locate() {
+    test_locate("example.gbwt");
+}
+
+#[test]
+fn locate_with_empty() {
+    test_locate("with-empty.gbwt");
+}
+
+#[test]
+fn locate_without_samples() {
+    let filename = support::get_test_data("example.gbwt");
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    index.da_samples = None;
+    assert!(!index.has_da_samples(), "The index contains document array samples");
+
+    for i in 0..index.sequences() {
+        assert_eq!(index.locate_pos(Pos::new(ENDMARKER, i)), Some(i), "Invalid sequence id for endmarker offset {}", i);
+        if let Some(pos) = index.start(i) {
+            assert!(index.locate_pos(pos).is_none(), "Located position {:?} without samples", pos);
+        }
+    }
+    for node in true_nodes() {
+        let state = index.find(node).unwrap();
+        assert!(index.locate(&state).is_empty(), "Located node {} without samples", node);
+        assert!(index.distinct_sequences(&state).is_empty(), "Found distinct sequences for node {} without samples", node);
+    }
+}
+
+// Counts the values with multiplicity.
+fn count_values<I: IntoIterator<Item = usize>>(values: I) -> Vec<(usize, usize)> {
+    let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+fn check_distinct(index: &GBWT, state: &SearchState) {
+    let located: Vec<usize> = state.range.clone().filter_map(|offset| index.locate_pos(Pos::new(state.node, offset))).collect();
+    assert_eq!(index.distinct_sequences(state), count_values(located.iter().copied()), "Invalid distinct sequences for {:?}", state);
+    assert_eq!(index.distinct_paths(state), count_values(located.iter().map(|id| support::path_id(*id))), "Invalid distinct paths for {:?}", state);
+
+    if let Some(metadata) = index.metadata() {
+        let samples = located.iter().map(|id| metadata.path(support::path_id(*id)).unwrap().sample());
+        assert_eq!(index.distinct_samples(state), Some(count_values(samples)), "Invalid distinct samples for {:?}", state);
+        let contigs = located.iter().map(|id| metadata.path(support::path_id(*id)).unwrap().contig());
+        assert_eq!(index.distinct_contigs(state), Some(count_values(contigs)), "Invalid distinct contigs for {:?}", state);
+    } else {
+        assert!(index.distinct_samples(state).is_none(), "Found distinct samples without metadata");
+        assert!(index.distinct_contigs(state).is_none(), "Found distinct contigs without metadata");
+    }
+}
+
+fn test_distinct(index: &GBWT) {
+    for node in true_nodes() {
+        let state = index.find(node).unwrap();
+        check_distinct(index, &state);
+        for next in true_nodes() {
+            if let Some(extended) = index.extend(&state, next) {
+                check_distinct(index, &extended);
+            }
+        }
+        let partial = SearchState {
+            node,
+            range: 1..state.range.end + 2,
+        };
+        check_distinct(index, &partial);
+    }
+
+    let endmarker = SearchState {
+        node: ENDMARKER,
+        range: 0..index.sequences() + 1,
+    };
+    assert_eq!(index.distinct_sequences(&endmarker), count_values(0..index.sequences()), "Invalid distinct sequences for the endmarker");
+
+    let invalid = SearchState {
+        node: index.alphabet_size(),
+        range: 0..1,
+    };
+    assert!(index.distinct_sequences(&invalid).is_empty(), "Found distinct sequences for an invalid node");
+
+    // Sampled positions in offset ranges.
+    let samples = index.da_samples.as_ref().unwrap();
+    for record_id in 0..index.bwt.len() {
+        let len = index.bwt.record(record_id).map(|record| record.len()).unwrap_or(0);
+        for start in 0..len + 1 {
+            for limit in start..len + 2 {
+                let truth: Vec<(usize, usize)> = (start..limit).filter_map(|offset| samples.try_locate(record_id, offset).map(|id| (offset, id))).collect();
+                assert_eq!(samples.sampled_range(record_id, start..limit), truth, "Invalid sampled positions in range {}..{} of record {}", start, limit, record_id);
+            }
+        }
+    }
+}
+
+#[test]
+fn distinct() {
+    let index: GBWT = serialize::load_from(&support::get_test_data("example.gbwt")).unwrap();
+    test_distinct(&index);
+}
+
+#[test]
+fn distinct_with_empty() {
+    let index: GBWT = serialize::load_from(&support::get_test_data("with-empty.gbwt")).unwrap();
+    test_distinct(&index);
+}
+
+#[test]
+fn distinct_dense_samples() {
+    test_distinct(&build_index(false, 1));
+    test_distinct(&build_index(true, 2));
+}
+
+//-----------------------------------------------------------------------------
+
+fn build_index(with_empty: bool, sample_interval: usize) -> GBWT {
+    let mut builder = GBWTBuilder::new(true);
+    builder.set_sample_interval(sample_interval);
+    for path in true_paths(with_empty) {
+        builder.insert(&path).unwrap();
+    }
+    GBWT::try_from(builder).unwrap()
+}
+
+fn test_builder(test_file: &'static str, with_empty: bool) {
+    let filename = support::get_test_data(test_file);
+    let truth: GBWT = serialize::load_from(&filename).unwrap();
+    let index = build_index(with_empty, 2);
+
+    // Statistics and records.
+    check_statistics(&index, truth.len(), truth.sequences(), truth.alphabet_size(), truth.alphabet_offset());
+    assert_eq!(index.bwt.len(), truth.bwt.len(), "Invalid number of records");
+    for i in 0..truth.bwt.len() {
+        assert_eq!(index.bwt.compressed_record(i), truth.bwt.compressed_record(i), "Invalid record {}", i);
+    }
+    assert_eq!(index.endmarker, truth.endmarker, "Invalid endmarker");
+    assert!(!index.has_metadata(), "The index contains metadata");
+
+    // Sequences and document array samples.
+    for i in 0..truth.sequences() {
+        assert_eq!(extract_sequence(&index, i), extract_sequence(&truth, i), "Invalid sequence {}", i);
+    }
+    for i in 0..index.sequences() {
+        let mut pos = index.start(i);
+        while let Some(p) = pos {
+            assert_eq!(index.locate_pos(p), Some(i), "Invalid sequence id for position {:?} on sequence {}", p, i);
+            pos = index.forward(p);
+        }
+    }
+
+    serialize::test(&index, "built-gbwt", None, true);
+}
+
+#[test]
+fn builder() {
+    test_builder("example.gbwt", false);
+}
+
+#[test]
+fn builder_with_empty() {
+    test_builder("with-empty.gbwt", true);
+}
+
+#[test]
+fn builder_samples() {
+    let truth = build_index(false, 1);
+    let index = build_index(false, DASamples::SAMPLE_INTERVAL);
+    assert_eq!(truth.da_samples().unwrap().len(), truth.len() - truth.sequences(), "Invalid number of samples with interval 1");
+    assert_eq!(index.da_samples().unwrap().len(), index.sequences(), "Invalid number of samples with the default interval");
+    for i in 0..index.sequences() {
+        let mut pos = index.start(i);
+        while let Some(p) = pos {
+            assert_eq!(index.locate_pos(p), Some(i), "Invalid sequence id for position {:?} on sequence {}", p, i);
+            pos = index.forward(p);
+        }
+    }
+}
+
+#[test]
+fn builder_empty() {
+    let index = GBWT::try_from(GBWTBuilder::new(false)).unwrap();
+    assert!(index.is_empty(), "The index is not empty");
+    assert_eq!(index.sequences(), 0, "Invalid number of sequences");
+    assert!(index.find(1).is_none(), "Found a node in an empty index");
+    serialize::test(&index, "empty-gbwt", None, true);
+}
+
+#[test]
+fn builder_invalid_paths() {
+    let mut builder = GBWTBuilder::new(true);
+    assert!(builder.insert(&[ENDMARKER]).is_err(), "Inserted a path containing the endmarker");
+    assert!(builder.insert(&[support::flip_node(ENDMARKER)]).is_err(), "Inserted a path with the endmarker in the reverse orientation");
+    assert!(builder.is_empty(), "Invalid paths were inserted");
+
+    builder.insert_with_name(&true_paths(false)[0], PathName::from_fields(0, 0, 0, 0)).unwrap();
+    builder.insert(&true_paths(false)[1]).unwrap();
+    assert!(matches!(GBWT::try_from(builder), Err(crate::Error::MetadataMismatch(_))), "Built an index with missing path names");
+}
+
+fn build_from(paths: &[Vec<usize>]) -> GBWT {
+    let mut builder = GBWTBuilder::new(true);
+    for path in paths {
+        builder.insert(path).unwrap();
+    }
+    GBWT::try_from(builder).unwrap()
+}
+
+fn check_merged(merged: &GBWT, truth: &GBWT, name: &str) {
+    assert_eq!(merged.len(), truth.len(), "{}: Invalid total length", name);
+    assert_eq!(merged.sequences(), truth.sequences(), "{}: Invalid number of sequences", name);
+    assert_eq!(merged.alphabet_size(), truth.alphabet_size(), "{}: Invalid alphabet size", name);
+    assert_eq!(merged.alphabet_offset(), truth.alphabet_offset(), "{}: Invalid alphabet offset", name);
+    assert!(merged.is_bidirectional(), "{}: The index is not bidirectional", name);
+    for i in 0..truth.bwt.len() {
+        assert_eq!(merged.bwt.compressed_record(i), truth.bwt.compressed_record(i), "{}: Invalid record {}", name, i);
+    }
+    for i in 0..merged.sequences() {
+        let mut pos = merged.start(i);
+        while let Some(p) = pos {
+            assert_eq!(merged.locate_pos(p), Some(i), "{}: Invalid sequence id for position {:?} on sequence {}", name, p, i);
+            pos = merged.forward(p);
+        }
+    }
+}
+
+fn test_merge(with_empty: bool) {
+    let paths = true_paths(with_empty);
+    let truth = build_from(&paths);
+
+    // The first component uses nodes 11 to 17 and the second component nodes 21 to 25.
+    let (first, second): (Vec<Vec<usize>>, Vec<Vec<usize>>) = paths.iter().cloned().partition(|path| {
+        path.first().map(|node| support::node_id(*node) < 20).unwrap_or(true)
+    });
+    let mut reordered = first.clone();
+    reordered.extend(second.iter().cloned());
+    let merged = GBWT::merge(&[build_from(&first), build_from(&second)]).unwrap();
+    check_merged(&merged, &build_from(&reordered), "Disjoint");
+
+    // Overlapping node ranges require insertion.
+    let mid = paths.len() / 2;
+    let merged = GBWT::merge(&[build_from(&paths[..mid]), build_from(&paths[mid..])]).unwrap();
+    check_merged(&merged, &truth, "Overlapping");
+
+    let merged = GBWT::merge(&[truth.clone()]).unwrap();
+    check_merged(&merged, &truth, "Single");
+}
+
+#[test]
+fn merge() {
+    test_merge(false);
+}
+
+#[test]
+fn merge_with_empty() {
+    test_merge(true);
+}
+
+#[test]
+fn merge_invalid() {
+    assert!(GBWT::merge(&[]).is_err(), "Merged an empty set of indexes");
+
+    let bidirectional = build_from(&true_paths(false));
+    let mut builder = GBWTBuilder::new(false);
+    builder.insert(&true_paths(false)[0]).unwrap();
+    let unidirectional = GBWT::try_from(builder).unwrap();
+    assert!(GBWT::merge(&[bidirectional, unidirectional]).is_err(), "Merged unidirectional and bidirectional indexes");
+
+    // Duplicate path names.
+    let filename = support::get_test_data("example.gbwt");
+    let index: GBWT = serialize::load_from(&filename).unwrap();
+    assert!(GBWT::merge(&[index.clone(), index.clone()]).is_err(), "Merged indexes with identical path names");
+
+    // Only some indexes contain metadata.
+    let without_metadata = build_from(&true_paths(false));
+    assert!(matches!(GBWT::merge(&[index, without_metadata]), Err(crate::Error::MetadataMismatch(_))), "Merged indexes with and without metadata");
+}
+
 //-----------------------------------------------------------------------------
 
 fn true_nodes() -> HashSet<usize> {
@@ -460,6 +929,218 @@ fn bd_extend_with_empty() {
 
 //-----------------------------------------------------------------------------
 
+fn test_queries() -> Vec<Vec<usize>> {
+    let forward = |id| support::encode_node(id, Orientation::Forward);
+    let reverse = |id| support::encode_node(id, Orientation::Reverse);
+    let mut result = true_paths(false);
+    result.push(Vec::new());
+    result.push(vec![forward(11), forward(12), forward(14), forward(16), forward(17)]);
+    result.push(vec![forward(13), forward(12), forward(14), forward(15)]);
+    result.push(vec![forward(11), forward(99), forward(14), forward(15), forward(17), forward(21), forward(22)]);
+    result.push(vec![reverse(17), reverse(15), reverse(14), reverse(12), forward(12), forward(14)]);
+    result.push(vec![forward(21), forward(22), forward(24), reverse(23), reverse(21), forward(21), forward(22), forward(24), forward(25)]);
+    result.push(vec![forward(99)]);
+    result
+}
+
+fn test_longest_match(test_file: &'static str, with_empty: bool) {
+    let filename = support::get_test_data(test_file);
+    let index: GBWT = serialize::load_from(&filename).unwrap();
+    let paths = true_paths(with_empty);
+
+    for query in test_queries() {
+        let truth = (0..query.len()).find(|start| count_occurrences(&paths, &query[*start..]) > 0);
+        let result = index.longest_match(&query);
+        match truth {
+            Some(start) => {
+                let (range, state) = result.unwrap();
+                assert_eq!(range, start..query.len(), "Invalid longest match for {:?}", query);
+                assert_eq!(state.len(), count_occurrences(&paths, &query[start..]), "Invalid number of occurrences for the longest match of {:?}", query);
+                assert_eq!(state.node, query[query.len() - 1], "Invalid node for the longest match of {:?}", query);
+            },
+            None => assert!(result.is_none(), "Found a longest match for {:?}", query),
+        }
+    }
+}
+
+#[test]
+fn longest_match() {
+    test_longest_match("example.gbwt", false);
+}
+
+#[test]
+fn longest_match_with_empty() {
+    test_longest_match("with-empty.gbwt", true);
+}
+
+#[test]
+fn longest_match_unidirectional() {
+    let paths = true_paths(false);
+    let mut builder = GBWTBuilder::new(false);
+    for path in paths.iter() {
+        builder.insert(path).unwrap();
+    }
+    let index = GBWT::try_from(builder).unwrap();
+
+    for query in test_queries() {
+        let occurrences = |subpath: &[usize]| paths.iter().map(|path| {
+            (0..path.len()).filter(|i| path[*i..].starts_with(subpath)).count()
+        }).sum::<usize>();
+        let truth = (0..query.len()).find(|start| occurrences(&query[*start..]) > 0);
+        let result = index.longest_match(&query);
+        match truth {
+            Some(start) => {
+                let (range, state) = result.unwrap();
+                assert_eq!(range, start..query.len(), "Invalid longest match for {:?}", query);
+                assert_eq!(state.len(), occurrences(&query[start..]), "Invalid number of occurrences for the longest match of {:?}", query);
+            },
+            None => assert!(result.is_none(), "Found a longest match for {:?}", query),
+        }
+    }
+}
+
+fn test_maximal_matches(test_file: &'static str, with_empty: bool) {
+    let filename = support::get_test_data(test_file);
+    let index: GBWT = serialize::load_from(&filename).unwrap();
+    let paths = true_paths(with_empty);
+
+    for query in test_queries() {
+        // Brute force maximal exact matches.
+        let occurs = |range: Range<usize>| count_occurrences(&paths, &query[range]) > 0;
+        let mut truth: Vec<Range<usize>> = Vec::new();
+        for start in 0..query.len() {
+            for end in start + 1..query.len() + 1 {
+                let left_maximal = start == 0 || !occurs(start - 1..end);
+                let right_maximal = end == query.len() || !occurs(start..end + 1);
+                if occurs(start..end) && left_maximal && right_maximal {
+                    truth.push(start..end);
+                }
+            }
+        }
+
+        let result = index.maximal_matches(&query);
+        let ranges: Vec<Range<usize>> = result.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, truth, "Invalid maximal matches for {:?}", query);
+        for (range, state) in result {
+            assert_eq!(state.len(), count_occurrences(&paths, &query[range.clone()]), "Invalid number of occurrences for match {:?} of {:?}", range, query);
+            assert_eq!(state.node, query[range.end - 1], "Invalid node for match {:?} of {:?}", range, query);
+        }
+    }
+}
+
+#[test]
+fn maximal_matches() {
+    test_maximal_matches("example.gbwt", false);
+}
+
+#[test]
+fn maximal_matches_with_empty() {
+    test_maximal_matches("with-empty.gbwt", true);
+}
+
+// Counts the number of records used by the queries.
+struct CountingSource<'a> {
+    index: &'a GBWT,
+    steps: Cell<usize>,
+}
+
+impl<'a> CountingSource<'a> {
+    fn new(index: &'a GBWT) -> Self {
+        CountingSource {
+            index,
+            steps: Cell::new(0),
+        }
+    }
+
+    // Returns the number of records used since the last call.
+    fn take_steps(&self) -> usize {
+        self.steps.replace(0)
+    }
+}
+
+impl<'a> RecordSource for CountingSource<'a> {
+    type Record<'b> = Record<'b>;
+
+    fn alphabet_size(&self) -> usize {
+        self.index.alphabet_size()
+    }
+
+    fn alphabet_offset(&self) -> usize {
+        self.index.alphabet_offset()
+    }
+
+    fn is_bidirectional(&self) -> bool {
+        self.index.is_bidirectional()
+    }
+
+    fn with_record<T, F: for<'b> FnOnce(&Self::Record<'b>) -> Option<T>>(&self, node: usize, f: F) -> Option<T> {
+        self.steps.set(self.steps.get() + 1);
+        RecordSource::with_record(self.index, node, f)
+    }
+}
+
+// Returns a path that repeats the cycle the given number of times.
+fn repeat_cycle(cycle: &[usize], times: usize) -> Vec<usize> {
+    cycle.iter().copied().cycle().take(cycle.len() * times).collect()
+}
+
+#[test]
+fn longest_match_steps() {
+    let cycle = [2, 4, 6];
+    let mut builder = GBWTBuilder::new(false);
+    builder.insert(&repeat_cycle(&cycle, 10)).unwrap();
+    builder.insert(&[10, 8]).unwrap();
+    let index = GBWT::try_from(builder).unwrap();
+    let source = CountingSource::new(&index);
+
+    // Every suffix of the repetitive part matches 30 nodes, but only the last node of the query occurs.
+    let mut query = repeat_cycle(&cycle, 20);
+    query.push(8);
+    let (range, state) = RecordSource::longest_match(&source, &query).unwrap();
+    assert_eq!(range, query.len() - 1..query.len(), "Invalid longest match for the repetitive query");
+    assert_eq!(state, index.find(8).unwrap(), "Invalid search state for the repetitive query");
+    let steps = source.take_steps();
+    assert!(steps <= 4, "Too many extension steps for the repetitive query: {}", steps);
+
+    // The entire query occurs in the index.
+    let query = repeat_cycle(&cycle, 10);
+    let (range, state) = RecordSource::longest_match(&source, &query).unwrap();
+    assert_eq!(range, 0..query.len(), "Invalid longest match for the full path");
+    assert_eq!(state.len(), 1, "Invalid number of occurrences for the full path");
+    let steps = source.take_steps();
+    assert!(steps <= 4 * query.len(), "Too many extension steps for the full path: {}", steps);
+}
+
+#[test]
+fn maximal_matches_steps() {
+    let cycle = [
+        support::encode_node(1, Orientation::Forward),
+        support::encode_node(2, Orientation::Forward),
+        support::encode_node(3, Orientation::Forward),
+    ];
+    let mut builder = GBWTBuilder::new(true);
+    builder.insert(&repeat_cycle(&cycle, 10)).unwrap();
+    let index = GBWT::try_from(builder).unwrap();
+    let source = CountingSource::new(&index);
+
+    // Each maximal match is a copy of the path starting at a different copy of the cycle in the query.
+    let query = repeat_cycle(&cycle, 20);
+    let result = RecordSource::maximal_matches(&source, &query);
+    let steps = source.take_steps();
+    let truth: Vec<Range<usize>> = (0..11).map(|i| 3 * i..3 * i + 30).collect();
+    let ranges: Vec<Range<usize>> = result.iter().map(|(range, _)| range.clone()).collect();
+    assert_eq!(ranges, truth, "Invalid maximal matches for the repetitive query");
+    for (range, state) in result.iter() {
+        assert_eq!(state.len(), 1, "Invalid number of occurrences for match {:?}", range);
+    }
+
+    // Each match costs a search and a failed extension in both directions in addition to its length.
+    let bound = query.len() + result.iter().map(|(range, _)| range.len() + 3).sum::<usize>();
+    assert!(steps <= bound, "Too many extension steps for the repetitive query: {} (bound {})", steps, bound);
+}
+
+//-----------------------------------------------------------------------------
+
 const SAMPLES: usize = 5;
 const CONTIGS: usize = 4;
 const PHASES: usize = 2;
@@ -503,6 +1184,8 @@ fn create_metadata(paths: bool, samples: bool, contigs: bool) -> Metadata {
         path_names: path_names,
         sample_names: Dictionary::try_from(sample_names).unwrap(),
         contig_names: Dictionary::try_from(contig_names).unwrap(),
+        reference_samples: BTreeSet::new(),
+        path_index: LazyPathIndex::default(),
     }
 }
 
@@ -594,6 +1277,394 @@ fn metadata() {
     test_metadata(false, false, true, "Contigs");
 }
 
+#[test]
+fn metadata_from_names() {
+    let truth = create_metadata(true, true, true);
+    let sample_names: Vec<String> = truth.sample_iter().map(|name| String::from_utf8_lossy(name).to_string()).collect();
+    let contig_names: Vec<String> = truth.contig_iter().map(|name| String::from_utf8_lossy(name).to_string()).collect();
+    let path_names: Vec<PathName> = truth.path_iter().copied().collect();
+
+    let metadata = Metadata::from_names(path_names.clone(), &sample_names, &contig_names).unwrap();
+    assert_eq!(metadata, truth, "Invalid metadata");
+
+    let mut duplicates = path_names.clone();
+    duplicates.push(path_names[0]);
+    assert!(Metadata::from_names(duplicates, &sample_names, &contig_names).is_err(), "Built metadata with duplicate path names");
+    assert!(Metadata::from_names(path_names, &sample_names[0..1], &contig_names).is_err(), "Built metadata with missing sample names");
+}
+
+#[test]
+fn metadata_merge() {
+    let first = Metadata::from_names(
+        vec![PathName::from_fields(0, 0, 0, 0), PathName::from_fields(1, 0, 0, 0)],
+        &[String::from("A"), String::from("B")], &[String::from("chr1")]
+    ).unwrap();
+    let second = Metadata::from_names(
+        vec![PathName::from_fields(0, 0, 0, 0), PathName::from_fields(1, 1, 1, 0)],
+        &[String::from("C"), String::from("A")], &[String::from("chr2"), String::from("chr1")]
+    ).unwrap();
+
+    // Names are unified.
+    let merged = Metadata::merge(&[&first, &second]).unwrap();
+    assert_eq!(merged.samples(), 3, "Invalid number of samples");
+    assert_eq!(merged.contigs(), 2, "Invalid number of contigs");
+    assert_eq!(merged.haplotypes(), 4, "Invalid number of haplotypes");
+    let truth = vec!["A#0#chr1", "B#0#chr1", "C#0#chr2", "A#1#chr1"];
+    for (i, name) in truth.iter().enumerate() {
+        assert_eq!(merged.pan_sn_path(i), Some(name.to_string()), "Invalid path name {}", i);
+    }
+
+    // Identifiers are offset without names.
+    let without_samples = Metadata::from_names(second.path_names.clone(), &[], &[String::from("chr2"), String::from("chr1")]).unwrap();
+    let merged = Metadata::merge(&[&first, &without_samples]).unwrap();
+    assert!(!merged.has_sample_names(), "Merged metadata has sample names");
+    assert_eq!(merged.samples(), 4, "Invalid number of samples without names");
+    assert_eq!(merged.path(3), Some(PathName::from_fields(3, 0, 1, 0)), "Invalid remapped path name");
+
+    // Duplicate path names.
+    assert!(Metadata::merge(&[&first, &first]).is_err(), "Merged metadata with duplicate path names");
+
+    // Only some sources have path names.
+    let without_paths = Metadata::from_names(Vec::new(), &[String::from("D")], &[String::from("chr1")]).unwrap();
+    assert!(Metadata::merge(&[&first, &without_paths]).is_err(), "Merged metadata with and without path names");
+
+    // Path names and reference samples must refer to existing samples.
+    let mut invalid = second.clone();
+    invalid.path_names.push(PathName::from_fields(2, 0, 0, 0));
+    assert!(Metadata::merge(&[&first, &invalid]).is_err(), "Merged metadata with a nonexistent sample");
+    let mut invalid = second.clone();
+    invalid.reference_samples.insert(2);
+    assert!(Metadata::merge(&[&first, &invalid]).is_err(), "Merged metadata with a nonexistent reference sample");
+}
+
+fn check_path_lookup(metadata: &Metadata, name: &str) {
+    for (id, path_name) in metadata.path_iter().enumerate() {
+        assert_eq!(metadata.find_path(path_name.sample(), path_name.contig(), path_name.phase(), path_name.fragment()), Some(id), "{}: Path {} not found", name, id);
+        assert_eq!(metadata.find_path_name(path_name), Some(id), "{}: Path {} not found by name", name, id);
+    }
+    assert_eq!(metadata.find_path(metadata.samples(), 0, 0, 0), None, "{}: Found a path with a nonexistent sample", name);
+
+    for sample in 0..metadata.samples() + 1 {
+        let truth: Vec<usize> = metadata.path_iter().enumerate().filter(|(_, path_name)| path_name.sample() == sample).map(|(id, _)| id).collect();
+        let paths: Vec<usize> = metadata.paths_for_sample(sample).collect();
+        assert_eq!(paths, truth, "{}: Invalid paths for sample {}", name, sample);
+    }
+    for contig in 0..metadata.contigs() + 1 {
+        let truth: Vec<usize> = metadata.path_iter().enumerate().filter(|(_, path_name)| path_name.contig() == contig).map(|(id, _)| id).collect();
+        let paths: Vec<usize> = metadata.paths_for_contig(contig).collect();
+        assert_eq!(paths, truth, "{}: Invalid paths for contig {}", name, contig);
+    }
+}
+
+#[test]
+fn path_lookup() {
+    let mut metadata = create_metadata(true, true, true);
+    check_path_lookup(&metadata, "Initial");
+
+    // The index is updated when paths are added.
+    let sample = metadata.add_sample("new_sample").unwrap();
+    metadata.add_path(PathName::from_fields(sample, 1, 0, 0)).unwrap();
+    metadata.add_path(PathName::from_fields(0, 2, 0, 1)).unwrap();
+    check_path_lookup(&metadata, "Added");
+
+    // The index is rebuilt when paths are removed.
+    metadata.remove_sample(1).unwrap();
+    check_path_lookup(&metadata, "Removed");
+
+    // Equality does not depend on the index.
+    let fresh = create_metadata(true, true, true);
+    let used = create_metadata(true, true, true);
+    let _ = used.find_path(0, 0, 0, 0);
+    assert_eq!(fresh, used, "Equality depends on the path index");
+
+    let metadata = create_metadata(false, true, true);
+    assert_eq!(metadata.find_path(0, 0, 0, 0), None, "Found a path without path names");
+    assert_eq!(metadata.paths_for_sample(0).count(), 0, "Found sample paths without path names");
+    assert_eq!(metadata.paths_for_contig(0).count(), 0, "Found contig paths without path names");
+}
+
+#[test]
+fn metadata_builder() {
+    let truth = create_metadata(true, true, true);
+    let mut builder = MetadataBuilder::new();
+    for path_name in truth.path_iter() {
+        let sample = truth.sample(path_name.sample()).unwrap();
+        let contig = truth.contig(path_name.contig()).unwrap();
+        builder.add_named_path(sample, contig, path_name.phase(), path_name.fragment()).unwrap();
+    }
+    assert_eq!(builder.paths(), truth.paths(), "Invalid number of paths in the builder");
+    assert_eq!(builder.samples(), truth.samples(), "Invalid number of samples in the builder");
+    assert_eq!(builder.contigs(), truth.contigs(), "Invalid number of contigs in the builder");
+    assert!(builder.add_path(truth.path(0).unwrap()).is_err(), "Added a duplicate path name");
+    assert!(builder.add_sample(truth.sample(0).unwrap()).is_err(), "Added a duplicate sample name");
+    assert!(builder.add_contig(truth.contig(0).unwrap()).is_err(), "Added a duplicate contig name");
+    let metadata = Metadata::try_from(builder).unwrap();
+    assert_eq!(metadata, truth, "Invalid metadata from the builder");
+
+    // Paths without sample / contig names.
+    let mut builder = MetadataBuilder::new();
+    for path_name in truth.path_iter() {
+        builder.add_path(*path_name).unwrap();
+    }
+    let metadata = Metadata::try_from(builder).unwrap();
+    assert_eq!(metadata, create_metadata(true, false, false), "Invalid metadata without names");
+
+    // Paths referring to nonexistent samples.
+    let mut builder = MetadataBuilder::new();
+    builder.add_sample("sample").unwrap();
+    builder.add_path(PathName::from_fields(1, 0, 0, 0)).unwrap();
+    assert!(Metadata::try_from(builder).is_err(), "Built metadata with a nonexistent sample");
+}
+
+#[test]
+fn metadata_builder_from_metadata() {
+    // Round trip through the builder.
+    for paths in [false, true] {
+        for samples in [false, true] {
+            for contigs in [false, true] {
+                let truth = create_metadata(paths, samples, contigs);
+                let builder = MetadataBuilder::from(truth.clone());
+                let metadata = Metadata::try_from(builder).unwrap();
+                assert_eq!(metadata, truth, "Invalid metadata after a round trip (paths {}, samples {}, contigs {})", paths, samples, contigs);
+            }
+        }
+    }
+
+    // Batch edits.
+    let mut truth = create_metadata(true, true, true);
+    let mut builder = MetadataBuilder::from(truth.clone());
+    for i in 0..SAMPLES {
+        let name = format!("new_sample_{}", i);
+        let id = builder.add_sample(&name).unwrap();
+        assert_eq!(truth.add_sample(&name).unwrap(), id, "Invalid identifier for sample {}", name);
+        builder.add_path(PathName::from_fields(id, 0, 0, 0)).unwrap();
+        truth.add_path(PathName::from_fields(id, 0, 0, 0)).unwrap();
+    }
+    builder.rename_sample(0, "first").unwrap();
+    truth.rename_sample(0, "first").unwrap();
+    builder.rename_contig(1, "renamed").unwrap();
+    truth.rename_contig(1, "renamed").unwrap();
+    builder.rename_contig(1, "renamed").unwrap();
+    assert!(builder.rename_contig(1, "contig_0").is_err(), "Renamed a contig to a duplicate name");
+    assert!(builder.rename_sample(2 * SAMPLES, "other").is_err(), "Renamed a nonexistent sample");
+    assert_eq!(builder.contig_id("contig_1"), None, "Found the old contig name");
+    let metadata = Metadata::try_from(builder).unwrap();
+    assert_eq!(metadata, truth, "Invalid metadata after batch edits");
+    assert_eq!(metadata.haplotypes(), SAMPLES * PHASES + SAMPLES, "Invalid number of haplotypes after batch edits");
+
+    // Named samples cannot be mixed with unnamed ones.
+    let mut builder = MetadataBuilder::from(create_metadata(true, false, false));
+    assert!(builder.add_sample("sample").is_err(), "Added a named sample to unnamed samples");
+    assert!(builder.add_contig("contig").is_err(), "Added a named contig to unnamed contigs");
+}
+
+#[test]
+fn metadata_editing() {
+    let mut metadata = create_metadata(true, true, true);
+
+    // Adding samples and paths.
+    assert!(metadata.add_sample("sample_0").is_err(), "Added a duplicate sample");
+    let sample = metadata.add_sample("new_sample").unwrap();
+    assert_eq!(sample, SAMPLES, "Invalid identifier for a new sample");
+    assert_eq!(metadata.sample_id("new_sample"), Some(sample), "New sample not found");
+    let path = metadata.add_path(PathName::from_fields(sample, 0, 0, 0)).unwrap();
+    assert_eq!(path, SAMPLES * CONTIGS * PHASES, "Invalid identifier for a new path");
+    assert_eq!(metadata.haplotypes(), SAMPLES * PHASES + 1, "Invalid number of haplotypes after adding a path");
+    metadata.add_path(PathName::from_fields(sample, 1, 0, 0)).unwrap();
+    assert_eq!(metadata.haplotypes(), SAMPLES * PHASES + 1, "A path for an existing haplotype changed the number of haplotypes");
+    assert!(metadata.add_path(PathName::from_fields(sample, 0, 0, 0)).is_err(), "Added a duplicate path");
+    assert!(metadata.add_path(PathName::from_fields(sample + 1, 0, 0, 0)).is_err(), "Added a path with a nonexistent sample");
+    assert!(metadata.add_path(PathName::from_fields(sample, CONTIGS, 0, 0)).is_err(), "Added a path with a nonexistent contig");
+
+    // Renaming.
+    metadata.rename_contig(1, "renamed").unwrap();
+    assert_eq!(metadata.contig(1), Some("renamed"), "Contig was not renamed");
+    assert_eq!(metadata.contig_id("contig_1"), None, "Found the old contig name");
+    metadata.rename_contig(1, "renamed").unwrap();
+    assert!(metadata.rename_contig(1, "contig_0").is_err(), "Renamed a contig to a duplicate name");
+    assert!(metadata.rename_contig(CONTIGS, "other").is_err(), "Renamed a nonexistent contig");
+    metadata.rename_sample(0, "first").unwrap();
+    assert_eq!(metadata.sample_id("first"), Some(0), "Sample was not renamed");
+
+    // Removing the original samples.
+    for _ in 0..SAMPLES {
+        let removed = metadata.remove_sample(0).unwrap();
+        let truth: Vec<usize> = (0..CONTIGS * PHASES).collect();
+        assert_eq!(removed, truth, "Invalid removed paths");
+    }
+    assert_eq!(metadata.samples(), 1, "Invalid number of samples after removal");
+    assert_eq!(metadata.sample(0), Some("new_sample"), "Invalid remaining sample");
+    assert_eq!(metadata.paths(), 2, "Invalid number of paths after removal");
+    assert_eq!(metadata.path(0), Some(PathName::from_fields(0, 0, 0, 0)), "Invalid remaining path");
+    assert_eq!(metadata.haplotypes(), 1, "Invalid number of haplotypes after removal");
+    assert!(metadata.remove_sample(1).is_err(), "Removed a nonexistent sample");
+
+    // Removing all paths clears the flag.
+    metadata.remove_sample(0).unwrap();
+    assert!(!metadata.has_path_names(), "Path name flag is set without paths");
+    let mut buffer: Vec<u8> = Vec::new();
+    metadata.serialize(&mut buffer).unwrap();
+    let loaded = Metadata::load(&mut buffer.as_slice()).unwrap();
+    assert_eq!(loaded, metadata, "Serialization changed the edited metadata");
+
+    // Named samples cannot be mixed with unnamed ones.
+    let mut metadata = create_metadata(true, false, false);
+    assert!(metadata.add_sample("sample").is_err(), "Added a named sample to unnamed samples");
+    assert!(metadata.rename_sample(0, "sample").is_err(), "Renamed an unnamed sample");
+}
+
+#[test]
+fn set_metadata() {
+    let filename = support::get_test_data("example.gbwt");
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    let metadata = index.metadata().unwrap().clone();
+
+    index.remove_metadata();
+    assert!(!index.has_metadata(), "Index has metadata after removal");
+    assert!(index.metadata().is_none(), "Found metadata after removal");
+
+    let mut invalid = metadata.clone();
+    invalid.add_path(PathName::from_fields(0, 0, 100, 0)).unwrap();
+    assert!(matches!(index.set_metadata(invalid), Err(crate::Error::MetadataMismatch(_))), "Attached metadata with an invalid path count");
+
+    index.set_metadata(metadata.clone()).unwrap();
+    assert!(index.has_metadata(), "Index does not have metadata");
+    assert_eq!(index.metadata(), Some(&metadata), "Invalid attached metadata");
+    let mut buffer: Vec<u8> = Vec::new();
+    index.serialize(&mut buffer).unwrap();
+    let loaded = GBWT::load(&mut buffer.as_slice()).unwrap();
+    assert_eq!(loaded.metadata(), Some(&metadata), "Invalid metadata after serialization");
+}
+
+#[test]
+fn pan_sn_parsing() {
+    let name = |sample: &str, phase: usize, contig: &str, fragment: Option<usize>, end: Option<usize>| -> PanSN {
+        PanSN { sample: sample.to_string(), phase, contig: contig.to_string(), fragment, end, }
+    };
+    let valid = vec![
+        ("HG002#1#chr20", name("HG002", 1, "chr20", None, None)),
+        ("HG002#1#chr20#3", name("HG002", 1, "chr20", Some(3), None)),
+        ("HG002#2#chr20[1000]", name("HG002", 2, "chr20", Some(1000), None)),
+        ("HG002#2#chr20[1000-2000]", name("HG002", 2, "chr20", Some(1000), Some(2000))),
+        ("HG002#0#HLA[A][5]", name("HG002", 0, "HLA[A]", Some(5), None)),
+        ("chr20", name(REF_SAMPLE, 0, "chr20", None, None)),
+        ("chr20[10-20]", name(REF_SAMPLE, 0, "chr20", Some(10), Some(20))),
+    ];
+    for (source, truth) in valid.iter() {
+        assert_eq!(PanSN::from_str(source).ok().as_ref(), Some(truth), "Invalid parse for {}", source);
+    }
+
+    let invalid = vec![
+        "", "HG002#chr20", "HG002#x#chr20", "HG002#1#chr20#x", "#1#chr20", "HG002#1#",
+        "HG002#1#chr20]", "HG002#1#chr20[x]", "HG002#1#chr20[10-x]", "HG002#1#chr20[20-10]",
+        "HG002#1#chr20[10]#2", "a#1#b#2#c",
+    ];
+    for source in invalid.iter() {
+        assert!(PanSN::from_str(source).is_err(), "Parsed an invalid name: {}", source);
+    }
+}
+
+#[test]
+fn pan_sn_resolution() {
+    let filename = support::get_test_data("example.meta");
+    let metadata: Metadata = serialize::load_from(&filename).unwrap();
+    for id in 0..metadata.paths() {
+        let name = metadata.pan_sn_path(id).unwrap();
+        assert_eq!(metadata.resolve_pan_sn(&name).ok(), Some(id), "Could not resolve {}", name);
+    }
+    assert_eq!(metadata.resolve_pan_sn("B").ok(), Some(1), "Could not resolve a reference path");
+    assert!(metadata.resolve_pan_sn("sample#3#A").is_err(), "Resolved a nonexistent phase");
+    assert!(metadata.resolve_pan_sn("other#1#A").is_err(), "Resolved a nonexistent sample");
+    assert!(metadata.resolve_pan_sn("sample#1#C").is_err(), "Resolved a nonexistent contig");
+    assert!(metadata.resolve_pan_sn("sample#1#A#1").is_err(), "Resolved a nonexistent fragment");
+
+    // Identifiers without names.
+    let metadata = create_metadata(true, false, false);
+    for id in 0..metadata.paths() {
+        let name = metadata.pan_sn_path(id).unwrap();
+        assert_eq!(metadata.resolve_pan_sn(&name).ok(), Some(id), "Could not resolve {} without names", name);
+    }
+
+    // Ambiguous names.
+    let mut metadata = create_metadata(true, true, true);
+    let fragment = metadata.add_path(PathName::from_fields(0, 0, 0, 100)).unwrap();
+    let name = metadata.pan_sn_path(0).unwrap();
+    assert!(metadata.resolve_pan_sn(&name).is_err(), "Resolved an ambiguous name");
+    let parsed = PanSN::from_str(&name).unwrap();
+    assert_eq!(metadata.pan_sn_paths(&parsed), vec![0, fragment], "Invalid paths for an ambiguous name");
+    assert_eq!(metadata.resolve_pan_sn(&format!("{}[100]", name)).ok(), Some(fragment), "Could not resolve a fragment start");
+    assert!(metadata.resolve_pan_sn(&format!("{}[100-200]", name)).is_err(), "Resolved a fragment end without the path length");
+    assert_eq!(metadata.resolve_pan_sn(&format!("{}#0", name)).ok(), Some(0), "Could not resolve a fragment field");
+}
+
+#[test]
+fn path_senses() {
+    let filename = support::get_test_data("example.gbwt");
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    let mut metadata = index.metadata().unwrap().clone();
+    let ref_sample = metadata.sample_id(REF_SAMPLE).unwrap();
+    let sample = metadata.sample_id("sample").unwrap();
+
+    assert_eq!(metadata.sample_sense(ref_sample), Some(PathSense::Generic), "Invalid sense for the reference sample");
+    assert_eq!(metadata.sample_sense(sample), Some(PathSense::Haplotype), "Invalid sense for a haplotype sample");
+    assert_eq!(metadata.sample_sense(metadata.samples()), None, "Found a sense for a nonexistent sample");
+    assert_eq!(metadata.path_sense(metadata.paths()), None, "Found a sense for a nonexistent path");
+    for id in 0..metadata.paths() {
+        let truth = if metadata.path(id).unwrap().sample() == ref_sample { PathSense::Generic } else { PathSense::Haplotype };
+        assert_eq!(metadata.path_sense(id), Some(truth), "Invalid sense for path {}", id);
+    }
+    assert!(metadata.samples_with_sense(PathSense::Reference).is_empty(), "Found reference samples");
+
+    // Generic sense cannot be changed.
+    assert!(metadata.set_sample_sense(ref_sample, PathSense::Reference).is_err(), "Promoted a generic sample");
+    assert!(metadata.set_sample_sense(sample, PathSense::Generic).is_err(), "Changed a sample to generic");
+    assert!(metadata.set_sample_sense(metadata.samples(), PathSense::Reference).is_err(), "Promoted a nonexistent sample");
+
+    // Promote the sample and store the sense in the tags.
+    metadata.set_sample_sense(sample, PathSense::Reference).unwrap();
+    assert_eq!(metadata.samples_with_sense(PathSense::Reference), vec![sample], "Invalid reference samples after promotion");
+    assert_eq!(metadata.paths_with_sense(PathSense::Reference), metadata.paths_for_sample(sample).collect::<Vec<usize>>(), "Invalid reference paths");
+    index.set_metadata(metadata.clone()).unwrap();
+    assert_eq!(index.tags.get(REFERENCE_SAMPLES_KEY), Some(&String::from("sample")), "Invalid reference samples tag");
+    let mut buffer: Vec<u8> = Vec::new();
+    index.serialize(&mut buffer).unwrap();
+    let loaded = GBWT::load(&mut buffer.as_slice()).unwrap();
+    assert_eq!(loaded.metadata(), Some(&metadata), "Reference samples were not loaded from the tags");
+
+    // Removing the sample removes the reference sample.
+    let mut removed = metadata.clone();
+    removed.remove_sample(sample).unwrap();
+    assert!(removed.samples_with_sense(PathSense::Reference).is_empty(), "Found reference samples after removal");
+
+    // Demote the sample.
+    metadata.set_sample_sense(sample, PathSense::Haplotype).unwrap();
+    assert!(metadata.paths_with_sense(PathSense::Reference).is_empty(), "Found reference paths after demotion");
+    index.set_metadata(metadata).unwrap();
+    assert!(!index.tags.contains_key(REFERENCE_SAMPLES_KEY), "Reference samples tag exists after demotion");
+
+    // Reference samples require names.
+    let mut metadata = create_metadata(true, false, false);
+    assert!(metadata.set_sample_sense(0, PathSense::Reference).is_err(), "Promoted a sample without a name");
+}
+
+#[test]
+fn reference_samples_tag() {
+    let mut metadata = create_metadata(true, true, true);
+    assert_eq!(metadata.reference_samples_tag(), None, "Found a tag without reference samples");
+
+    // Multiple samples in the format used by the C++ implementation; unknown names are ignored.
+    metadata.set_reference_samples_tag("sample_3#unknown#sample_1");
+    assert_eq!(metadata.samples_with_sense(PathSense::Reference), vec![1, 3], "Invalid reference samples from the tag");
+    assert_eq!(metadata.reference_samples_tag(), Some(String::from("sample_1#sample_3")), "Invalid tag for multiple reference samples");
+
+    // Changes are reflected in the tag.
+    metadata.set_sample_sense(4, PathSense::Reference).unwrap();
+    metadata.set_sample_sense(1, PathSense::Haplotype).unwrap();
+    assert_eq!(metadata.reference_samples_tag(), Some(String::from("sample_3#sample_4")), "Invalid tag after changing senses");
+    metadata.set_reference_samples_tag("");
+    assert!(metadata.samples_with_sense(PathSense::Reference).is_empty(), "Found reference samples from an empty tag");
+}
+
 #[test]
 fn path_names() {
     let name = PathName::new();
@@ -601,3 +1672,172 @@ fn path_names() {
 }
 
 //-----------------------------------------------------------------------------
+
+// Rebuilds the BWT of the index after modifying the edges and the runs of each record.
+fn modified_bwt<F: FnMut(usize, &mut Vec<Pos>, &mut Vec<Run>)>(index: &GBWT, mut modify: F) -> BWT {
+    let mut builder = BWTBuilder::new();
+    for record_id in 0..index.bwt.len() {
+        match index.bwt.checked_record(record_id).unwrap() {
+            Some(record) => {
+                let mut edges: Vec<Pos> = (0..record.outdegree()).map(|rank| Pos::new(record.successor(rank), record.offset(rank))).collect();
+                let mut runs: Vec<Run> = record.runs().to_vec();
+                modify(record_id, &mut edges, &mut runs);
+                builder.append(&edges, &runs);
+            },
+            None => builder.append(&[], &[]),
+        }
+    }
+    BWT::from(builder)
+}
+
+#[test]
+fn validate() {
+    for (test_file, with_empty) in [("example.gbwt", false), ("with-empty.gbwt", true)] {
+        let filename = support::get_test_data(test_file);
+        let index: GBWT = serialize::load_from(&filename).unwrap();
+        let report = index.validate();
+        assert!(report.is_ok(), "{}: Found errors in a valid index: {:?}", test_file, report.messages());
+        assert_eq!(report.records, index.bwt.iter().count(), "{}: Invalid number of decoded records", test_file);
+        assert_eq!(report.sequences, index.sequences(), "{}: Invalid number of extracted sequences", test_file);
+
+        let built = build_index(with_empty, 2);
+        let report = built.validate();
+        assert!(report.is_ok(), "{}: Found errors in a built index: {:?}", test_file, report.messages());
+        assert_eq!(report.path_names, 0, "{}: Found path names in a built index", test_file);
+    }
+
+    let empty = GBWT::try_from(GBWTBuilder::new(false)).unwrap();
+    assert!(empty.validate().is_ok(), "Found errors in an empty index");
+}
+
+#[test]
+fn validate_invalid_edges() {
+    let filename = support::get_test_data("example.gbwt");
+    let truth: GBWT = serialize::load_from(&filename).unwrap();
+
+    // An edge starts at the wrong offset.
+    let mut index = truth.clone();
+    index.bwt = modified_bwt(&truth, |_, edges, _| {
+        if let Some(edge) = edges.iter_mut().find(|edge| edge.node != ENDMARKER && edge.offset > 0) {
+            edge.offset -= 1;
+        }
+    });
+    let report = index.validate();
+    assert!(!report.is_ok(), "Did not find invalid edge offsets");
+    assert_eq!(report.sequences, 0, "Extracted sequences from an invalid index");
+
+    // An edge to a nonexistent node.
+    let mut index = truth.clone();
+    let invalid_node = truth.alphabet_size() + 10;
+    index.bwt = modified_bwt(&truth, |record_id, edges, _| {
+        if record_id == 1 {
+            edges.last_mut().unwrap().node = invalid_node;
+        }
+    });
+    let report = index.validate();
+    assert!(report.messages().iter().any(|msg| msg.contains(&format!("Successor {} does not exist", invalid_node))), "Did not find an edge to a nonexistent node: {:?}", report.messages());
+}
+
+#[test]
+fn validate_corrupted_record() {
+    let filename = support::get_test_data("example.gbwt");
+    let truth: GBWT = serialize::load_from(&filename).unwrap();
+
+    // A non-empty record without runs.
+    let target = truth.bwt.id_iter().find(|&record_id| record_id != ENDMARKER).unwrap();
+    let mut index = truth.clone();
+    index.bwt = modified_bwt(&truth, |record_id, _, runs| {
+        if record_id == target {
+            runs.clear();
+        }
+    });
+
+    // Loading only decodes the endmarker, so the record is found by validation.
+    let mut buffer: Vec<u8> = Vec::new();
+    index.serialize(&mut buffer).unwrap();
+    let loaded = GBWT::load(&mut buffer.as_slice()).unwrap();
+    let report = loaded.validate();
+    let prefix = format!("BWT: Record {}:", target);
+    assert!(report.messages().iter().any(|msg| msg.starts_with(&prefix)), "Did not find the corrupted record: {:?}", report.messages());
+    assert_eq!(report.sequences, 0, "Extracted sequences from an index with a corrupted record");
+}
+
+#[test]
+fn invalid_da_samples() {
+    let filename = support::get_test_data("example.gbwt");
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+
+    // Every sample refers to a nonexistent sequence.
+    let mut samples = index.da_samples.take().unwrap();
+    let mut array = IntVector::with_capacity(samples.len(), bits::bit_len(index.sequences() as u64)).unwrap();
+    array.extend(std::iter::repeat(index.sequences()).take(samples.len()));
+    samples.array = array;
+    index.da_samples = Some(samples);
+
+    let mut buffer: Vec<u8> = Vec::new();
+    index.serialize(&mut buffer).unwrap();
+    let result = GBWT::load(&mut buffer.as_slice()).map_err(crate::Error::from);
+    assert!(matches!(result, Err(crate::Error::InvalidData(_))), "Loaded document array samples referring to nonexistent sequences");
+}
+
+#[test]
+fn validate_invalid_sequences() {
+    // Forward-only sequences in an index claimed to be bidirectional.
+    let paths = true_paths(false);
+    let mut builder = GBWTBuilder::new(false);
+    builder.insert(&paths[0]).unwrap();
+    builder.insert(&paths[1]).unwrap();
+    let mut index = GBWT::try_from(builder).unwrap();
+    assert!(index.validate().is_ok(), "Found errors in a valid unidirectional index");
+    index.header.set(GBWTPayload::FLAG_BIDIRECTIONAL);
+    let report = index.validate();
+    assert_eq!(report.errors(), 1, "Invalid number of errors: {:?}", report.messages());
+    assert!(report.messages()[0].contains("Not the reverse"), "Invalid error message: {}", report.messages()[0]);
+
+    // The header claims a different total length.
+    let filename = support::get_test_data("example.gbwt");
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    index.header.payload_mut().size += 1;
+    assert!(!index.validate().is_ok(), "Did not find an invalid total length");
+}
+
+#[test]
+fn cyclic_sequences() {
+    let mut builder = GBWTBuilder::new(false);
+    builder.insert(&[2, 4, 6]).unwrap();
+    let truth = GBWT::try_from(builder).unwrap();
+
+    // Node 4 leads back to node 2, and only the last position of the sequence is sampled.
+    let mut index = truth.clone();
+    let target = truth.node_to_record(4);
+    index.bwt = modified_bwt(&truth, |record_id, edges, _| {
+        if record_id == target {
+            edges[0] = Pos::new(2, 0);
+        }
+    });
+    index.da_samples = Some(DASamples::new(&truth, 10));
+
+    assert!(index.sequence(0).unwrap().count() <= index.len(), "Sequence iteration did not stop in a cycle");
+    assert!(index.sequence_from(0, 1).unwrap().count() <= index.len(), "Sequence iteration from an offset did not stop in a cycle");
+    assert!(index.sequence_len(0).unwrap() <= index.len(), "Sequence length was not bounded in a cycle");
+    assert!(index.position_at(0, index.len()).is_none(), "Found a position past the length of the index");
+    assert!(index.locate_pos(Pos::new(2, 0)).is_none(), "Located a position in a cycle without samples");
+    let samples = DASamples::new(&index, 1);
+    assert!(samples.len() <= index.len(), "Sampled too many positions in a cycle");
+    index.build_sequence_samples(1);
+    assert!(index.extract(0, 0..usize::MAX).len() <= index.len(), "Extracted too many nodes in a cycle");
+}
+
+#[test]
+fn validate_metadata() {
+    let filename = support::get_test_data("example.gbwt");
+    let mut index: GBWT = serialize::load_from(&filename).unwrap();
+    let mut metadata = index.metadata().unwrap().clone();
+    metadata.path_names[1] = metadata.path_names[0];
+    index.metadata = Some(metadata);
+    let report = index.validate();
+    assert_eq!(report.errors(), 1, "Invalid number of errors: {:?}", report.messages());
+    assert!(report.messages()[0].contains("Duplicate path name"), "Invalid error message: {}", report.messages()[0]);
+}
+
+//-----------------------------------------------------------------------------
@@ -15,10 +15,10 @@
 use crate::{ENDMARKER, SOURCE_KEY, SOURCE_VALUE};
 use crate::{Graph, Segment, GBWT, BidirectionalState, Orientation};
 use crate::bwt::Record;
-use crate::gbwt::{SequenceIter, Metadata};
+use crate::gbwt::{SequenceIter, Metadata, PanSN, ValidationReport};
 use crate::graph::SegmentIter as GraphSegmentIter;
 use crate::headers::{Header, GBZPayload};
-use crate::support::{DisjointSets, Tags};
+use crate::support::{DisjointSets, SharedMap, Tags};
 use crate::support;
 
 use simple_sds::bit_vector::{BitVector, OneIter, Identity};
@@ -29,6 +29,8 @@ use simple_sds::serialize::Serialize;
 use std::io::{Error, ErrorKind};
 use std::iter::FusedIterator;
 use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
 use std::io;
 
 #[cfg(test)]
@@ -144,6 +146,238 @@ impl GBZ {
     fn sequence_to_graph_node(&self, sequence_id: usize) -> usize {
         support::node_id(sequence_id * 2 + self.index.first_node())
     }
+
+    // Marks the sequences corresponding to nodes that exist in the GBWT index.
+    fn real_nodes(index: &GBWT, potential_nodes: usize) -> BitVector {
+        let mut real_nodes = RawVector::with_len(potential_nodes, false);
+        for record_id in index.as_ref().id_iter() {
+            if record_id == ENDMARKER {
+                continue;
+            }
+            let gbwt_node = index.record_to_node(record_id);
+            let sequence_id = Self::gbwt_node_to_sequence(index, gbwt_node);
+            // An odd alphabet may contain a forward node without a sequence.
+            if support::node_orientation(gbwt_node) == Orientation::Forward && sequence_id < potential_nodes {
+                real_nodes.set_bit(sequence_id, true);
+            }
+        }
+        BitVector::from(real_nodes)
+    }
+
+    // Checks that the parts are compatible and returns the number of potential nodes.
+    fn check_parts(index: &GBWT, graph: &Graph) -> Result<usize, crate::Error> {
+        if !index.is_bidirectional() {
+            return Err(crate::Error::InvalidData(String::from("GBZ: The GBWT index is not bidirectional")));
+        }
+        let potential_nodes = index.alphabet_size().saturating_sub(index.first_node()) / 2;
+        if graph.sequences() != potential_nodes {
+            return Err(crate::Error::MetadataMismatch("GBZ: Mismatch between GBWT alphabet size and Graph sequence count"));
+        }
+        Ok(potential_nodes)
+    }
+}
+
+/// Construction.
+impl GBZ {
+    /// Creates a GBZ graph from a GBWT index and a graph.
+    ///
+    /// Returns [`crate::Error::InvalidData`] if the GBWT index is not bidirectional and [`crate::Error::MetadataMismatch`] if the number of sequences in the graph does not match the alphabet of the index.
+    /// These are the same checks as when loading a GBZ graph.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, GBZ, Graph};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let index: GBWT = serialize::load_from(&support::get_test_data("example.gbwt")).unwrap();
+    /// let graph: Graph = serialize::load_from(&support::get_test_data("example.gg")).unwrap();
+    /// let gbz = GBZ::new(index, graph).unwrap();
+    /// assert_eq!(gbz.nodes(), 12);
+    /// assert_eq!(gbz.sequence(13), Some("T".as_bytes()));
+    ///
+    /// let (index, graph) = gbz.into_parts();
+    /// assert_eq!(index.sequences(), 12);
+    /// assert_eq!(graph.sequences(), 15);
+    /// ```
+    pub fn new(index: GBWT, graph: Graph) -> Result<Self, crate::Error> {
+        let potential_nodes = Self::check_parts(&index, &graph)?;
+        let mut tags = Tags::new();
+        tags.insert(SOURCE_KEY, SOURCE_VALUE);
+        let real_nodes = Self::real_nodes(&index, potential_nodes);
+        Ok(GBZ {
+            header: Header::<GBZPayload>::new(),
+            tags,
+            index,
+            graph,
+            real_nodes,
+        })
+    }
+
+    /// Splits the GBZ graph into the GBWT index and the graph.
+    ///
+    /// The GBZ tags are discarded, but the tags of the GBWT index are retained.
+    pub fn into_parts(self) -> (GBWT, Graph) {
+        (self.index, self.graph)
+    }
+}
+
+/// Memory mapping.
+impl GBZ {
+    /// Opens the GBZ graph in the given file using a shared read-only memory mapping.
+    ///
+    /// The BWT of the GBWT index, the node sequences, the segment names, and the sample and contig names in the metadata are borrowed from the mapping, and the file remains mapped as long as the graph exists.
+    /// Multiple processes opening the same file share the mapped pages through the page cache, and the BWT is read from disk only when needed.
+    ///
+    /// The strings are stored as packed [`crate::support::StringArray`] structures, and each array is decompressed into memory on first access; see [`SharedMap`].
+    /// Everything else is copied into memory, exactly as when loading the graph with [`simple_sds::serialize::load_from`].
+    /// This includes the path names, the document array samples, and the endmarker record of the GBWT index.
+    ///
+    /// Returns [`crate::Error::Io`] if the file cannot be mapped and the same errors as when loading the graph otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::GBZ;
+    /// use gbwt::support;
+    ///
+    /// let filename = support::get_test_data("example.gbz");
+    /// let gbz = GBZ::open_mmap(&filename).unwrap();
+    ///
+    /// assert!(gbz.is_mapped());
+    /// assert_eq!(gbz.nodes(), 12);
+    /// assert_eq!(gbz.sequence(13), Some("T".as_bytes()));
+    /// ```
+    pub fn open_mmap<P: AsRef<Path>>(filename: P) -> Result<Self, crate::Error> {
+        let map = SharedMap::open(filename)?;
+        let mut reader = io::Cursor::new(map.bytes());
+        Self::load_with(&mut reader, |reader| GBWT::load_mapped(reader, &map), |reader| Graph::load_mapped(reader, &map)).map_err(crate::Error::from)
+    }
+
+    /// Returns `true` if the BWT of the GBWT index and the node sequences are borrowed from a memory mapping.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        self.index.is_mapped() && self.graph.is_mapped()
+    }
+
+    // Loads the GBZ graph using the given functions for loading the GBWT index and the graph.
+    fn load_with<T, F, G>(reader: &mut T, load_index: F, load_graph: G) -> io::Result<Self>
+        where T: io::Read, F: FnOnce(&mut T) -> io::Result<GBWT>, G: FnOnce(&mut T) -> io::Result<Graph>
+    {
+        let header = Header::<GBZPayload>::load(reader)?;
+        header.validate()?;
+
+        let mut tags = Tags::load(reader)?;
+        tags.insert(SOURCE_KEY, SOURCE_VALUE);
+
+        let index = load_index(reader)?;
+        if !index.is_bidirectional() {
+            return Err(Error::new(ErrorKind::InvalidData, "GBZ: The GBWT index is not bidirectional"));
+        }
+
+        let graph = load_graph(reader)?;
+        let potential_nodes = Self::check_parts(&index, &graph)?;
+
+        let real_nodes = Self::real_nodes(&index, potential_nodes);
+        Ok(GBZ {
+            header,
+            tags,
+            index,
+            graph,
+            real_nodes,
+        })
+    }
+}
+
+/// Validation.
+impl GBZ {
+    /// Checks the integrity of the GBZ graph and returns a report of the problems found.
+    ///
+    /// In addition to the checks in [`GBWT::validate`], this checks every node in the graph (see [`GBZ::node_iter`]).
+    /// The node must have a non-empty sequence, and the edges from it must lead to existing nodes with a matching edge in the other direction.
+    /// If there is a node-to-segment translation, the node identifiers must match those in the GBWT index, and each node must belong to a named segment.
+    /// Consecutive nodes within a segment must be connected by an edge, and there can be no other edges to / from the interior of a segment.
+    /// Edges and the translation are only checked if the GBWT index is valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::GBZ;
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("translation.gbz");
+    /// let gbz: GBZ = serialize::load_from(&filename).unwrap();
+    ///
+    /// let report = gbz.validate();
+    /// assert!(report.is_ok());
+    /// assert_eq!(report.sequences, 2 * gbz.paths());
+    /// ```
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = self.index.validate();
+        let check_edges = report.is_ok();
+        let check_translation = check_edges && self.has_translation() && self.validate_translation(&mut report);
+        for node_id in self.node_iter() {
+            if self.sequence_len(node_id) == Some(0) {
+                report.error(format!("Node {}: Empty sequence", node_id));
+            }
+            if check_edges {
+                self.validate_edges(node_id, &mut report);
+            }
+            if check_translation {
+                self.validate_segment(node_id, &mut report);
+            }
+            report.nodes += 1;
+        }
+        report
+    }
+
+    // Checks that the translation and the GBWT index agree on the sequence of each node.
+    // Returns `true` if the segments can be checked.
+    fn validate_translation(&self, report: &mut ValidationReport) -> bool {
+        if self.index.first_node() > support::encode_node(1, Orientation::Forward) {
+            report.error(String::from("GBZ: Node-to-segment translation does not start from the first node of the GBWT index"));
+            return false;
+        }
+        true
+    }
+
+    // Checks that the edges from the node lead to existing nodes and that the reverse edges exist.
+    fn validate_edges(&self, node_id: usize, report: &mut ValidationReport) {
+        for orientation in [Orientation::Forward, Orientation::Reverse] {
+            let successors = match self.successors(node_id, orientation) {
+                Some(iter) => iter,
+                None => continue,
+            };
+            for (next_id, next_orientation) in successors {
+                if !self.has_node(next_id) {
+                    report.error(format!("Node {}: Edge to nonexistent node {}", node_id, next_id));
+                    continue;
+                }
+                let has_reverse = self.predecessors(next_id, next_orientation).is_some_and(|mut iter| iter.any(|prev| prev == (node_id, orientation)));
+                if !has_reverse {
+                    report.error(format!("Node {}: Edge to node {} has no reverse edge", node_id, next_id));
+                }
+            }
+        }
+    }
+
+    // Checks that the node belongs to a named segment and that the nodes of the segment form a chain.
+    fn validate_segment(&self, node_id: usize, report: &mut ValidationReport) {
+        let segment = self.graph.node_to_segment(node_id);
+        if segment.name.is_empty() {
+            report.error(format!("Node {}: Segment {} has no name", node_id, segment.id));
+        }
+        let forward: Vec<(usize, Orientation)> = self.successors(node_id, Orientation::Forward).map(|iter| iter.collect()).unwrap_or_default();
+        if node_id + 1 < segment.nodes.end && forward != [(node_id + 1, Orientation::Forward)] {
+            report.error(format!("Node {}: Successors do not match the next node in segment {}", node_id, segment.id));
+        }
+        let backward: Vec<(usize, Orientation)> = self.predecessors(node_id, Orientation::Forward).map(|iter| iter.collect()).unwrap_or_default();
+        if node_id > segment.nodes.start && backward != [(node_id - 1, Orientation::Forward)] {
+            report.error(format!("Node {}: Predecessors do not match the previous node in segment {}", node_id, segment.id));
+        }
+    }
 }
 
 /// Nodes and edges.
@@ -397,6 +631,57 @@ impl GBZ {
         self.index.metadata()
     }
 
+    /// Attaches the given metadata to the GBWT index, replacing any existing metadata.
+    ///
+    /// Returns [`crate::Error::MetadataMismatch`] if the metadata has path names but the number of paths does not match the index.
+    /// See [`GBWT::set_metadata`] for details.
+    pub fn set_metadata(&mut self, metadata: Metadata) -> Result<(), crate::Error> {
+        self.index.set_metadata(metadata)
+    }
+
+    /// Removes the metadata from the GBWT index.
+    pub fn remove_metadata(&mut self) {
+        self.index.remove_metadata();
+    }
+
+    /// Returns the length of the given path in base pairs, or [`None`] if there is no such path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path_id`: Path identifier in the original graph.
+    pub fn path_len(&self, path_id: usize) -> Option<usize> {
+        self.checked_path_len(path_id).ok()
+    }
+
+    // Returns the length of the given path in base pairs or the identifier of the missing path / node.
+    fn checked_path_len(&self, path_id: usize) -> Result<usize, crate::Error> {
+        let iter = self.path(path_id, Orientation::Forward).ok_or(crate::Error::PathNotFound(path_id))?;
+        let mut result = 0;
+        for (node_id, _) in iter {
+            result += self.sequence_len(node_id).ok_or(crate::Error::NodeNotFound(node_id))?;
+        }
+        Ok(result)
+    }
+
+    /// Parses a name in the [PanSN format](https://github.com/pangenome/PanSN-spec) and returns the identifier of the matching path.
+    ///
+    /// This works like [`Metadata::resolve_pan_sn`], but a name of the form `contig[start-end]` is also accepted.
+    /// The end must then match the length of the path: `end - start == self.path_len(path_id)`.
+    /// Returns [`crate::Error::PathNotFound`] or [`crate::Error::NodeNotFound`] if the resolved path cannot be traversed in the graph.
+    /// Other failures, such as missing metadata, a name that cannot be resolved, or an end that does not match the path length, result in [`crate::Error::InvalidData`].
+    pub fn resolve_pan_sn(&self, name: &str) -> Result<usize, crate::Error> {
+        let metadata = self.metadata().ok_or_else(|| crate::Error::InvalidData(String::from("GBZ: The index does not contain metadata")))?;
+        let name = PanSN::from_str(name)?;
+        let path_id = metadata.resolve_parsed_pan_sn(&name)?;
+        if let (Some(start), Some(end)) = (name.fragment, name.end) {
+            let len = self.checked_path_len(path_id)?;
+            if end - start != len {
+                return Err(crate::Error::InvalidData(String::from("GBZ: The fragment end does not match the path length")));
+            }
+        }
+        Ok(path_id)
+    }
+
     /// Returns a search state corresponding to the given node and orientation, or [`None`] if there is no such node.
     ///
     /// See [`StateIter`] for an example.
@@ -512,44 +797,7 @@ impl Serialize for GBZ {
     }
 
     fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
-        let header = Header::<GBZPayload>::load(reader)?;
-        if let Err(msg) = header.validate() {
-            return Err(Error::new(ErrorKind::InvalidData, msg));
-        }
-
-        let mut tags = Tags::load(reader)?;
-        tags.insert(SOURCE_KEY, SOURCE_VALUE);
-
-        let index = GBWT::load(reader)?;
-        if !index.is_bidirectional() {
-            return Err(Error::new(ErrorKind::InvalidData, "GBZ: The GBWT index is not bidirectional"));
-        }
-        let potential_nodes = (index.alphabet_size() - index.first_node()) / 2;
-
-        let graph = Graph::load(reader)?;
-        if graph.sequences() != potential_nodes {
-            return Err(Error::new(ErrorKind::InvalidData, "GBZ: Mismatch between GBWT alphabet size and Graph sequence count"));
-        }
-
-        // Cache real nodes.
-        let mut real_nodes = RawVector::with_len(potential_nodes, false);
-        for record_id in index.as_ref().id_iter() {
-            if record_id == ENDMARKER {
-                continue;
-            }
-            let gbwt_node = index.record_to_node(record_id);
-            if support::node_orientation(gbwt_node) == Orientation::Forward {
-                real_nodes.set_bit(Self::gbwt_node_to_sequence(&index, gbwt_node), true);
-            }
-        }
-
-        Ok(GBZ {
-            header,
-            tags,
-            index,
-            graph,
-            real_nodes: BitVector::from(real_nodes),
-        })
+        Self::load_with(reader, GBWT::load, Graph::load)
     }
 
     fn size_in_elements(&self) -> usize {
@@ -1,10 +1,13 @@
 use super::*;
 
+use crate::gbwt::GBWTBuilder;
+use crate::graph::GraphBuilder;
 use crate::support;
 
 use simple_sds::serialize;
 
 use std::collections::{BTreeSet, BTreeMap, HashSet};
+use std::convert::TryFrom;
 
 //-----------------------------------------------------------------------------
 
@@ -255,6 +258,27 @@ fn states() {
     check_states(&gbz);
 }
 
+#[test]
+fn pan_sn_resolution() {
+    let filename = support::get_test_data("example.gbz");
+    let gbz: GBZ = serialize::load_from(&filename).unwrap();
+    let metadata = gbz.metadata().unwrap();
+
+    for path_id in 0..gbz.paths() {
+        let len: usize = gbz.path(path_id, Orientation::Forward).unwrap().map(|(id, _)| gbz.sequence(id).unwrap().len()).sum();
+        assert_eq!(gbz.path_len(path_id), Some(len), "Invalid length for path {}", path_id);
+
+        let name = metadata.pan_sn_path(path_id).unwrap();
+        let start = metadata.path(path_id).unwrap().fragment();
+        assert_eq!(gbz.resolve_pan_sn(&name).ok(), Some(path_id), "Could not resolve {}", name);
+        let interval = format!("{}[{}-{}]", name, start, start + len);
+        assert_eq!(gbz.resolve_pan_sn(&interval).ok(), Some(path_id), "Could not resolve {}", interval);
+        let interval = format!("{}[{}-{}]", name, start, start + len + 1);
+        assert!(matches!(gbz.resolve_pan_sn(&interval), Err(crate::Error::InvalidData(_))), "Resolved {} with an invalid end", interval);
+    }
+    assert_eq!(gbz.path_len(gbz.paths()), None, "Got a length for a nonexistent path");
+}
+
 #[test]
 fn no_translation() {
     let filename = support::get_test_data("example.gbz");
@@ -477,3 +501,141 @@ fn weakly_connected_components() {
 }
 
 //-----------------------------------------------------------------------------
+
+#[test]
+fn construction() {
+    for name in ["example", "translation"] {
+        let gbz: GBZ = serialize::load_from(&support::get_test_data(&format!("{}.gbz", name))).unwrap();
+        let (index, graph) = gbz.clone().into_parts();
+        assert_eq!(&index, gbz.as_ref() as &GBWT, "[{}]: Invalid GBWT index from parts", name);
+        assert_eq!(&graph, gbz.as_ref() as &Graph, "[{}]: Invalid graph from parts", name);
+
+        let rebuilt = GBZ::new(index, graph).unwrap();
+        assert_eq!(rebuilt.nodes(), gbz.nodes(), "[{}]: Invalid number of nodes", name);
+        assert!(rebuilt.node_iter().eq(gbz.node_iter()), "[{}]: Invalid nodes", name);
+        assert_eq!(rebuilt.real_nodes, gbz.real_nodes, "[{}]: Invalid real nodes", name);
+        assert_eq!(rebuilt.has_translation(), gbz.has_translation(), "[{}]: Invalid translation flag", name);
+        serialize::test(&rebuilt, &format!("gbz-new-{}", name), None, true);
+    }
+
+    // Separate files.
+    let index: GBWT = serialize::load_from(&support::get_test_data("example.gbwt")).unwrap();
+    let graph: Graph = serialize::load_from(&support::get_test_data("example.gg")).unwrap();
+    let gbz = GBZ::new(index, graph).unwrap();
+    let truth: GBZ = serialize::load_from(&support::get_test_data("example.gbz")).unwrap();
+    assert!(gbz.node_iter().eq(truth.node_iter()), "Invalid nodes from separate files");
+    for path_id in 0..truth.paths() {
+        assert!(gbz.path(path_id, Orientation::Forward).unwrap().eq(truth.path(path_id, Orientation::Forward).unwrap()), "Invalid path {} from separate files", path_id);
+    }
+}
+
+#[test]
+fn invalid_construction() {
+    let index: GBWT = serialize::load_from(&support::get_test_data("example.gbwt")).unwrap();
+    let graph: Graph = serialize::load_from(&support::get_test_data("translation.gg")).unwrap();
+    assert!(matches!(GBZ::new(index, graph), Err(crate::Error::MetadataMismatch(_))), "Built a GBZ with mismatched sequence count");
+
+    let mut builder = GBWTBuilder::new(false);
+    builder.insert(&[support::encode_node(11, Orientation::Forward)]).unwrap();
+    let index = GBWT::try_from(builder).unwrap();
+    let graph: Graph = serialize::load_from(&support::get_test_data("example.gg")).unwrap();
+    assert!(matches!(GBZ::new(index, graph), Err(crate::Error::InvalidData(_))), "Built a GBZ from a unidirectional index");
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn memory_mapping() {
+    for file in ["example.gbz", "translation.gbz"] {
+        let filename = support::get_test_data(file);
+        let loaded: GBZ = serialize::load_from(&filename).unwrap();
+        let mapped = GBZ::open_mmap(&filename).unwrap();
+        assert!(mapped.is_mapped(), "[{}]: The GBZ graph is not memory mapped", file);
+        assert!(!loaded.is_mapped(), "[{}]: The loaded GBZ graph is memory mapped", file);
+        assert!(mapped.graph.is_mapped(), "[{}]: The sequences are not memory mapped", file);
+        if let Some(metadata) = mapped.metadata() {
+            assert!(metadata.is_mapped(), "[{}]: The metadata is not memory mapped", file);
+        }
+        assert_eq!(mapped.graph, loaded.graph, "[{}]: Memory mapped graph differs from the loaded graph", file);
+        assert_eq!(mapped.nodes(), loaded.nodes(), "[{}]: Invalid number of nodes", file);
+        assert!(mapped.node_iter().eq(loaded.node_iter()), "[{}]: Invalid nodes", file);
+        for node_id in loaded.node_iter() {
+            assert_eq!(mapped.sequence(node_id), loaded.sequence(node_id), "[{}]: Invalid sequence for node {}", file, node_id);
+            for orientation in [Orientation::Forward, Orientation::Reverse] {
+                assert!(mapped.successors(node_id, orientation).unwrap().eq(loaded.successors(node_id, orientation).unwrap()), "[{}]: Invalid successors for node {} {}", file, node_id, name(orientation));
+            }
+        }
+        for path_id in 0..loaded.paths() {
+            assert!(mapped.path(path_id, Orientation::Forward).unwrap().eq(loaded.path(path_id, Orientation::Forward).unwrap()), "[{}]: Invalid path {}", file, path_id);
+        }
+        serialize::test(&mapped, &format!("gbz-mapped-{}", file), None, true);
+    }
+
+    // Dropping the graph must not invalidate other structures mapping the same file.
+    let filename = support::get_test_data("example.gbz");
+    let first = GBZ::open_mmap(&filename).unwrap();
+    let second = first.clone();
+    drop(first);
+    assert_eq!(second.sequence(13), Some("T".as_bytes()), "Invalid sequence after dropping a clone");
+}
+
+#[test]
+fn invalid_memory_mapping() {
+    assert!(matches!(GBZ::open_mmap(support::get_test_data("example.gbwt")), Err(crate::Error::InvalidHeader { .. })), "Opened a GBWT file as a GBZ graph");
+    assert!(matches!(GBZ::open_mmap(support::get_test_data("nonexistent.gbz")), Err(crate::Error::Io(_))), "Opened a nonexistent file");
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn validate() {
+    for file in ["example.gbz", "translation.gbz"] {
+        let gbz: GBZ = serialize::load_from(&support::get_test_data(file)).unwrap();
+        let report = gbz.validate();
+        assert!(report.is_ok(), "[{}]: Found errors in a valid graph: {:?}", file, report.messages());
+        assert_eq!(report.nodes, gbz.node_iter().count(), "[{}]: Invalid number of checked nodes", file);
+        assert_eq!(report.sequences, 2 * gbz.paths(), "[{}]: Invalid number of extracted sequences", file);
+        assert_eq!(report.path_names, gbz.paths(), "[{}]: Invalid number of checked path names", file);
+    }
+
+    // A node visited by the paths has an empty sequence.
+    let gbz: GBZ = serialize::load_from(&support::get_test_data("example.gbz")).unwrap();
+    let mut builder = GraphBuilder::new();
+    for node_id in gbz.node_iter() {
+        let sequence = if node_id == 13 { String::new() } else { String::from_utf8(gbz.sequence(node_id).unwrap().to_vec()).unwrap() };
+        builder.add_node(node_id, &sequence).unwrap();
+    }
+    let (index, _) = gbz.into_parts();
+    let gbz = GBZ::new(index, Graph::try_from(builder).unwrap()).unwrap();
+    let report = gbz.validate();
+    assert_eq!(report.errors(), 1, "Invalid number of errors: {:?}", report.messages());
+    assert_eq!(report.messages()[0], "Node 13: Empty sequence", "Invalid error message");
+
+    // Segments must be named chains of nodes.
+    let mut builder = GBWTBuilder::new(true);
+    builder.insert(&[2, 4, 6]).unwrap();
+    builder.insert(&[2, 6]).unwrap();
+    let index = GBWT::try_from(builder).unwrap();
+    let translated = |segments: &[(&str, Range<usize>)]| {
+        let mut builder = GraphBuilder::new();
+        for (node_id, sequence) in [(1, "GA"), (2, "T"), (3, "CA")] {
+            builder.add_node(node_id, sequence).unwrap();
+        }
+        for (name, nodes) in segments.iter() {
+            builder.add_segment(name, nodes.clone()).unwrap();
+        }
+        GBZ::new(index.clone(), Graph::try_from(builder).unwrap()).unwrap()
+    };
+    let gbz = translated(&[("a", 1..2), ("b", 2..3), ("c", 3..4)]);
+    let report = gbz.validate();
+    assert!(report.is_ok(), "Found errors in a valid translation: {:?}", report.messages());
+    let gbz = translated(&[("a", 1..3), ("", 3..4)]);
+    let report = gbz.validate();
+    let truth = vec![
+        String::from("Node 1: Successors do not match the next node in segment 0"),
+        String::from("Node 3: Segment 1 has no name"),
+    ];
+    assert_eq!(report.messages(), truth.as_slice(), "Invalid errors for an invalid translation");
+}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,779 @@
+//! GFA: Conversions between GBZ graphs and the GFA1 format.
+//!
+//! [`read_gfa`] builds a [`GBZ`] graph from the segments, links, paths, and walks in a [GFA1](https://github.com/GFA-spec/GFA-spec/blob/master/GFA1.md) file.
+//! Like the [C++ implementation](https://github.com/jltsiren/gbwtgraph), it represents the graph using the GBWT index of the paths and walks.
+//! Edges that are not used on any path or walk are therefore not stored.
+//!
+//! If all segment names are positive integers and all segments are short enough, segment names are used as node identifiers.
+//! Otherwise the graph contains a node-to-segment translation, and long segments are chopped into multiple nodes.
+//!
+//! [`GfaWriter`] writes a GBZ graph or a subset of its paths in the GFA1 format to any [`Write`] implementation.
+//!
+//! # Examples
+//!
+//! ```
+//! use gbwt::PathSense;
+//! use gbwt::gfa;
+//! use gbwt::support;
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let filename = support::get_test_data("translation.gfa");
+//! let reader = BufReader::new(File::open(&filename).unwrap());
+//! let gbz = gfa::read_gfa(reader, 2).unwrap();
+//!
+//! // Segment `s14` with sequence `CAG` is chopped into two nodes.
+//! assert!(gbz.has_translation());
+//! let segment = gbz.node_to_segment(6).unwrap();
+//! assert_eq!(segment.name, b"s14");
+//! assert_eq!(segment.nodes, 5..7);
+//!
+//! // P-lines become generic paths and W-lines haplotype paths.
+//! let metadata = gbz.metadata().unwrap();
+//! assert_eq!(metadata.paths_with_sense(PathSense::Generic), vec![0]);
+//! assert_eq!(metadata.paths_with_sense(PathSense::Haplotype), vec![1, 2]);
+//! assert_eq!(metadata.pan_sn_path(2), Some(String::from("sample#2#A")));
+//! ```
+
+use crate::{GBWT, GBZ, Graph, Orientation, Segment, REF_SAMPLE};
+use crate::gbwt::{GBWTBuilder, MetadataBuilder, Metadata, PanSN, PathSense};
+use crate::graph::GraphBuilder;
+use crate::support;
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io::{BufRead, Error, ErrorKind, Write};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::{io, thread};
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// Default maximum length of a node in the GBZ graph built from GFA.
+pub const MAX_NODE_LENGTH: usize = 1024;
+
+// A segment as (name, sequence).
+type GfaSegment = (String, String);
+
+// A path or a walk with the fields of the path name.
+#[derive(Clone, Debug)]
+struct GfaPath {
+    line: usize,
+    sample: String,
+    contig: String,
+    phase: usize,
+    fragment: usize,
+    reference: bool,
+    steps: Vec<(String, Orientation)>,
+}
+
+fn invalid_data(line: usize, message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("GFA: {} on line {}", message, line))
+}
+
+//-----------------------------------------------------------------------------
+
+/// Builds a GBZ graph from a GFA1 file.
+///
+/// The reader understands S-lines, L-lines, P-lines, and W-lines and ignores the other lines.
+/// Each path and walk becomes a path in the GBWT index, with the name determined as follows:
+///
+/// * W-line fields are used as the sample name, the phase, the contig name, and the fragment (starting offset).
+///   A missing starting offset `*` is interpreted as `0`.
+/// * P-line names in the [PanSN format](https://github.com/pangenome/PanSN-spec) `sample#phase#contig` are parsed, and the samples become reference samples.
+/// * Other P-line names become generic paths with the name as the contig name.
+///
+/// If some segment names are not positive integers or some segments are longer than `max_node_len`, the graph contains a node-to-segment translation.
+/// Node identifiers are then assigned to the segments in the order they appear in the file, and each segment is chopped into nodes of length at most `max_node_len`.
+/// Segments that are not used on any path or walk are retained in the translation, but their nodes are not in the graph.
+///
+/// Returns an error if the file is not valid GFA1, if it contains features that are not supported, or if there are no paths or walks.
+/// Links must have no overlaps, and segments must have sequences.
+/// Path names must be unique.
+///
+/// # Arguments
+///
+/// * `reader`: A reader for the GFA file.
+/// * `max_node_len`: Maximum length of a node in the graph; see [`MAX_NODE_LENGTH`] for the default value.
+pub fn read_gfa<T: BufRead>(reader: T, max_node_len: usize) -> io::Result<GBZ> {
+    if max_node_len == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "GFA: Maximum node length must be positive"));
+    }
+
+    let mut segments: Vec<GfaSegment> = Vec::new();
+    let mut segment_ids: HashMap<String, usize> = HashMap::new();
+    let mut links: Vec<(usize, String, String)> = Vec::new();
+    let mut paths: Vec<GfaPath> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_num = i + 1;
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[0] {
+            "S" => {
+                let segment = parse_segment(&fields, line_num)?;
+                if segment_ids.insert(segment.0.clone(), segments.len()).is_some() {
+                    return Err(invalid_data(line_num, "Duplicate segment name"));
+                }
+                segments.push(segment);
+            },
+            "L" => links.push(parse_link(&fields, line_num)?),
+            "P" => paths.push(parse_path(&fields, line_num)?),
+            "W" => paths.push(parse_walk(&fields, line_num)?),
+            _ => {},
+        }
+    }
+
+    // Check that links and paths refer to existing segments.
+    for (line, from, to) in links.iter() {
+        if !segment_ids.contains_key(from) || !segment_ids.contains_key(to) {
+            return Err(invalid_data(*line, "Link refers to a nonexistent segment"));
+        }
+    }
+    let mut used = vec![false; segments.len()];
+    let mut resolved: Vec<Vec<(usize, Orientation)>> = Vec::with_capacity(paths.len());
+    for path in paths.iter() {
+        let mut steps: Vec<(usize, Orientation)> = Vec::with_capacity(path.steps.len());
+        for (name, orientation) in path.steps.iter() {
+            let id = *segment_ids.get(name).ok_or_else(|| invalid_data(path.line, "Path refers to a nonexistent segment"))?;
+            used[id] = true;
+            steps.push((id, *orientation));
+        }
+        resolved.push(steps);
+    }
+    if resolved.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "GFA: The file contains no paths or walks"));
+    }
+
+    // Nodes and sequences.
+    let translation = segments.iter().any(|(name, sequence)| !is_node_id(name) || sequence.len() > max_node_len);
+    let (ranges, graph, reserved) = if translation {
+        build_translated_graph(&segments, &used, max_node_len)?
+    } else {
+        build_graph(&segments, &used)?
+    };
+
+    // GBWT index and metadata.
+    let mut builder = GBWTBuilder::new(true);
+    builder.reserve_nodes(reserved);
+    let mut metadata = MetadataBuilder::new();
+    for (path, steps) in paths.iter().zip(resolved.iter()) {
+        let mut nodes: Vec<usize> = Vec::new();
+        for (id, orientation) in steps.iter() {
+            match orientation {
+                Orientation::Forward => nodes.extend(ranges[*id].clone().map(|node_id| support::encode_node(node_id, Orientation::Forward))),
+                Orientation::Reverse => nodes.extend(ranges[*id].clone().rev().map(|node_id| support::encode_node(node_id, Orientation::Reverse))),
+            }
+        }
+        builder.insert(&nodes).map_err(|_| invalid_data(path.line, "Path contains an invalid node"))?;
+        metadata.add_named_path(&path.sample, &path.contig, path.phase, path.fragment).map_err(|_| invalid_data(path.line, "Duplicate path name"))?;
+    }
+    let mut metadata = Metadata::try_from(metadata)?;
+    for path in paths.iter().filter(|path| path.reference) {
+        let sample_id = metadata.sample_id(&path.sample).unwrap();
+        metadata.set_sample_sense(sample_id, PathSense::Reference).map_err(|err| invalid_data(path.line, &err.to_string()))?;
+    }
+    let mut index = GBWT::try_from(builder)?;
+    index.set_metadata(metadata)?;
+
+    GBZ::new(index, graph).map_err(io::Error::from)
+}
+
+//-----------------------------------------------------------------------------
+
+// Returns `true` if the segment name can be used as a node identifier.
+fn is_node_id(name: &str) -> bool {
+    match name.parse::<usize>() {
+        Ok(id) => id > 0 && id.to_string() == name,
+        Err(_) => false,
+    }
+}
+
+// Returns the node range for each segment, the graph, and the range of GBWT nodes to reserve.
+// Segment names are node identifiers, and only the used segments are included in the graph.
+fn build_graph(segments: &[GfaSegment], used: &[bool]) -> io::Result<(Vec<Range<usize>>, Graph, Range<usize>)> {
+    let ranges: Vec<Range<usize>> = segments.iter().map(|(name, _)| {
+        let id = name.parse::<usize>().unwrap();
+        id..id + 1
+    }).collect();
+
+    let mut builder = GraphBuilder::new();
+    for ((range, (_, sequence)), _) in ranges.iter().zip(segments.iter()).zip(used.iter()).filter(|(_, used)| **used) {
+        builder.add_node(range.start, sequence)?;
+    }
+    let graph = Graph::try_from(builder)?;
+    Ok((ranges, graph, 0..0))
+}
+
+// Returns the node range for each segment, the graph, and the range of GBWT nodes to reserve.
+// Node identifiers start from `1`, and all segments are included in the translation.
+fn build_translated_graph(segments: &[GfaSegment], used: &[bool], max_node_len: usize) -> io::Result<(Vec<Range<usize>>, Graph, Range<usize>)> {
+    let mut ranges: Vec<Range<usize>> = Vec::with_capacity(segments.len());
+    let mut builder = GraphBuilder::new();
+    let mut next = 1;
+    for ((name, sequence), used) in segments.iter().zip(used.iter()) {
+        let start = next;
+        let mut offset = 0;
+        while offset < sequence.len() {
+            let end = std::cmp::min(offset + max_node_len, sequence.len());
+            if *used {
+                builder.add_node(next, &sequence[offset..end])?;
+            }
+            next += 1;
+            offset = end;
+        }
+        builder.add_segment(name, start..next)?;
+        ranges.push(start..next);
+    }
+
+    let reserved = support::encode_node(1, Orientation::Forward)..support::encode_node(next, Orientation::Forward);
+    let graph = Graph::try_from(builder)?;
+    Ok((ranges, graph, reserved))
+}
+
+//-----------------------------------------------------------------------------
+
+// Parses an S-line.
+fn parse_segment(fields: &[&str], line: usize) -> io::Result<GfaSegment> {
+    if fields.len() < 3 {
+        return Err(invalid_data(line, "Missing fields in a segment"));
+    }
+    let (name, sequence) = (fields[1], fields[2]);
+    if name.is_empty() {
+        return Err(invalid_data(line, "Empty segment name"));
+    }
+    if sequence.is_empty() || sequence == "*" {
+        return Err(invalid_data(line, "Segment without a sequence"));
+    }
+    if !sequence.is_ascii() {
+        return Err(invalid_data(line, "Invalid segment sequence"));
+    }
+    Ok((name.to_string(), sequence.to_string()))
+}
+
+// Parses an L-line into (line, from, to).
+fn parse_link(fields: &[&str], line: usize) -> io::Result<(usize, String, String)> {
+    if fields.len() < 5 {
+        return Err(invalid_data(line, "Missing fields in a link"));
+    }
+    parse_orientation(fields[2], line)?;
+    parse_orientation(fields[4], line)?;
+    if fields.len() > 5 && fields[5] != "*" && fields[5] != "0M" {
+        return Err(invalid_data(line, "Overlaps are not supported"));
+    }
+    Ok((line, fields[1].to_string(), fields[3].to_string()))
+}
+
+fn parse_orientation(field: &str, line: usize) -> io::Result<Orientation> {
+    match field {
+        "+" => Ok(Orientation::Forward),
+        "-" => Ok(Orientation::Reverse),
+        _ => Err(invalid_data(line, "Invalid orientation")),
+    }
+}
+
+// Parses a P-line.
+fn parse_path(fields: &[&str], line: usize) -> io::Result<GfaPath> {
+    if fields.len() < 3 {
+        return Err(invalid_data(line, "Missing fields in a path"));
+    }
+    let name = fields[1];
+    if name.is_empty() {
+        return Err(invalid_data(line, "Empty path name"));
+    }
+    let (sample, contig, phase, fragment) = match PanSN::from_str(name) {
+        Ok(pan_sn) => (pan_sn.sample, pan_sn.contig, pan_sn.phase, pan_sn.fragment.unwrap_or(0)),
+        Err(_) => (String::from(REF_SAMPLE), name.to_string(), 0, 0),
+    };
+    let reference = sample != REF_SAMPLE;
+
+    let mut steps: Vec<(String, Orientation)> = Vec::new();
+    for step in fields[2].split(',') {
+        if step.len() < 2 {
+            return Err(invalid_data(line, "Invalid path step"));
+        }
+        let (segment, orientation) = step.split_at(step.len() - 1);
+        steps.push((segment.to_string(), parse_orientation(orientation, line)?));
+    }
+
+    Ok(GfaPath {
+        line, sample, contig, phase, fragment, reference, steps,
+    })
+}
+
+// Parses a W-line.
+fn parse_walk(fields: &[&str], line: usize) -> io::Result<GfaPath> {
+    if fields.len() < 7 {
+        return Err(invalid_data(line, "Missing fields in a walk"));
+    }
+    let sample = fields[1].to_string();
+    let phase = fields[2].parse::<usize>().map_err(|_| invalid_data(line, "Invalid haplotype index"))?;
+    let contig = fields[3].to_string();
+    if sample.is_empty() || contig.is_empty() {
+        return Err(invalid_data(line, "Empty sample or sequence name"));
+    }
+    let fragment = if fields[4] == "*" {
+        0
+    } else {
+        fields[4].parse::<usize>().map_err(|_| invalid_data(line, "Invalid starting offset"))?
+    };
+
+    let mut steps: Vec<(String, Orientation)> = Vec::new();
+    let walk = fields[6];
+    let mut start = 0;
+    while start < walk.len() {
+        let orientation = match walk.as_bytes()[start] {
+            b'>' => Orientation::Forward,
+            b'<' => Orientation::Reverse,
+            _ => return Err(invalid_data(line, "Invalid walk step")),
+        };
+        let end = walk[start + 1..].find(|c| c == '>' || c == '<').map_or(walk.len(), |offset| start + 1 + offset);
+        if end == start + 1 {
+            return Err(invalid_data(line, "Invalid walk step"));
+        }
+        steps.push((walk[start + 1..end].to_string(), orientation));
+        start = end;
+    }
+    if steps.is_empty() {
+        return Err(invalid_data(line, "Empty walk"));
+    }
+
+    Ok(GfaPath {
+        line, sample, contig, phase, fragment,
+        reference: false,
+        steps,
+    })
+}
+
+//-----------------------------------------------------------------------------
+
+/// Path modes for [`GfaWriter`].
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum PathMode {
+    /// Generic paths as P-lines with the contig name and other paths as W-lines.
+    #[default]
+    Default,
+    /// All paths as P-lines with names in the [PanSN format](https://github.com/pangenome/PanSN-spec).
+    PanSN,
+    /// Generic paths as P-lines with the contig name.
+    RefOnly,
+}
+
+/// Order of segments and links for [`GfaWriter`].
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum SegmentOrder {
+    /// Segments in identifier order.
+    #[default]
+    Identifier,
+    /// Segments in lexicographic order by name.
+    Name,
+}
+
+/// A writer for the GFA1 representation of a [`GBZ`] graph.
+///
+/// The output consists of a header line, S-lines for the segments, L-lines for the links, and P-lines / W-lines for the paths as determined by the [`PathMode`].
+/// If the graph contains a node-to-segment translation, segments and links are written using the translation.
+/// Otherwise each node becomes a segment named by the node identifier.
+/// Only links in canonical orientation are written:
+///
+/// * A link from the forward orientation of a segment is canonical if the other segment has the same or a greater identifier.
+/// * A link from the reverse orientation is canonical if the other segment has a greater identifier or if the link is a self-loop to the forward orientation.
+///
+/// By default, all S-lines come before the L-lines.
+/// With [`GfaWriter::set_interleave_links`], each S-line is followed by the canonical links from the segment.
+///
+/// Paths can be selected with [`GfaWriter::set_path_filter`].
+/// Path lines can be built in parallel with [`GfaWriter::set_threads`], but they are always written in path identifier order within each type of line.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{GBZ, PathSense};
+/// use gbwt::gfa::{GfaWriter, PathMode};
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.gbz");
+/// let gbz: GBZ = serialize::load_from(&filename).unwrap();
+///
+/// // Write only the paths for contig A as P-lines with PanSN names.
+/// let mut writer = GfaWriter::new(&gbz);
+/// writer.set_path_mode(PathMode::PanSN);
+/// writer.set_path_filter(|metadata, path_id| {
+///     let path_name = metadata.path(path_id).unwrap();
+///     metadata.contig_name(path_name.contig()) == "A"
+/// });
+/// let mut output: Vec<u8> = Vec::new();
+/// writer.write(&mut output).unwrap();
+///
+/// let gfa = String::from_utf8(output).unwrap();
+/// let lines: Vec<&str> = gfa.lines().collect();
+/// assert_eq!(lines[0], "H\tVN:Z:1.1");
+/// assert_eq!(lines.iter().filter(|line| line.starts_with('S')).count(), 12);
+/// assert_eq!(lines.iter().filter(|line| line.starts_with('P')).count(), 3);
+/// assert_eq!(lines.last(), Some(&"P\tsample#2#A\t11+,13+,14+,16+,17+\t*"));
+/// ```
+pub struct GfaWriter<'a> {
+    gbz: &'a GBZ,
+    path_mode: PathMode,
+    path_filter: Option<Box<dyn Fn(&Metadata, usize) -> bool + 'a>>,
+    segment_order: SegmentOrder,
+    interleave_links: bool,
+    threads: usize,
+}
+
+// Types of path lines.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+enum LineType {
+    PLine,
+    PanSN,
+    WLine,
+}
+
+impl<'a> GfaWriter<'a> {
+    /// Number of paths in each batch built by a thread.
+    pub const BATCH_SIZE: usize = 16;
+
+    /// Creates a new writer for the graph with the default settings.
+    pub fn new(gbz: &'a GBZ) -> Self {
+        GfaWriter {
+            gbz,
+            path_mode: PathMode::default(),
+            path_filter: None,
+            segment_order: SegmentOrder::default(),
+            interleave_links: false,
+            threads: 1,
+        }
+    }
+
+    /// Sets the path mode.
+    pub fn set_path_mode(&mut self, path_mode: PathMode) {
+        self.path_mode = path_mode;
+    }
+
+    /// Writes only the paths selected by the filter.
+    ///
+    /// The filter is called with the metadata and the path identifier.
+    /// Segments and links are not affected by the filter.
+    pub fn set_path_filter<F: Fn(&Metadata, usize) -> bool + 'a>(&mut self, filter: F) {
+        self.path_filter = Some(Box::new(filter));
+    }
+
+    /// Sets the order of the segments.
+    pub fn set_segment_order(&mut self, segment_order: SegmentOrder) {
+        self.segment_order = segment_order;
+    }
+
+    /// Writes the links after each segment instead of after all segments.
+    pub fn set_interleave_links(&mut self, interleave_links: bool) {
+        self.interleave_links = interleave_links;
+    }
+
+    /// Sets the number of threads used for building path lines.
+    ///
+    /// Values smaller than `1` are treated as `1`.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = std::cmp::max(threads, 1);
+    }
+
+    /// Writes the graph in the GFA format.
+    ///
+    /// Returns an error if the graph contains paths but no metadata with path names, if writing fails, or if a thread building path lines panics.
+    pub fn write<T: Write>(&self, output: &mut T) -> io::Result<()> {
+        let metadata = self.gbz.metadata().filter(|metadata| metadata.has_path_names());
+        if metadata.is_none() && self.gbz.paths() > 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "GFA: Writing paths requires path names"));
+        }
+
+        write_header(output)?;
+        match self.gbz.segment_iter() {
+            Some(iter) => {
+                if self.segment_order == SegmentOrder::Name {
+                    let mut segments: Vec<Segment> = iter.collect();
+                    segments.sort_by(|a, b| a.name.cmp(b.name));
+                    self.write_segment_graph(|| segments.iter().cloned(), output)?;
+                } else {
+                    self.write_segment_graph(|| self.gbz.segment_iter().unwrap(), output)?;
+                }
+            },
+            None => {
+                if self.segment_order == SegmentOrder::Name {
+                    let mut nodes: Vec<usize> = self.gbz.node_iter().collect();
+                    nodes.sort_by_cached_key(|node_id| node_id.to_string());
+                    self.write_node_graph(|| nodes.iter().copied(), output)?;
+                } else {
+                    self.write_node_graph(|| self.gbz.node_iter(), output)?;
+                }
+            },
+        }
+
+        if let Some(metadata) = metadata {
+            let selected: Vec<usize> = (0..metadata.paths()).filter(|path_id| match &self.path_filter {
+                Some(filter) => filter(metadata, *path_id),
+                None => true,
+            }).collect();
+            let generic: Vec<usize> = selected.iter().copied().filter(|path_id| metadata.path_sense(*path_id) == Some(PathSense::Generic)).collect();
+            match self.path_mode {
+                PathMode::Default => {
+                    let others: Vec<usize> = selected.iter().copied().filter(|path_id| metadata.path_sense(*path_id) != Some(PathSense::Generic)).collect();
+                    self.write_paths(&generic, LineType::PLine, output)?;
+                    self.write_paths(&others, LineType::WLine, output)?;
+                },
+                PathMode::PanSN => self.write_paths(&selected, LineType::PanSN, output)?,
+                PathMode::RefOnly => self.write_paths(&generic, LineType::PLine, output)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes segments and links when there is a node-to-segment translation.
+    fn write_segment_graph<'b, I, F, T>(&self, segments: F, output: &mut T) -> io::Result<()>
+        where I: Iterator<Item = Segment<'b>>, F: Fn() -> I, T: Write
+    {
+        for segment in segments() {
+            write_segment(segment.name, segment.sequence, output)?;
+            if self.interleave_links {
+                self.write_segment_links(&segment, output)?;
+            }
+        }
+        if !self.interleave_links {
+            for segment in segments() {
+                self.write_segment_links(&segment, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_segment_links<T: Write>(&self, segment: &Segment, output: &mut T) -> io::Result<()> {
+        for (successor, orientation) in self.gbz.segment_successors(segment, Orientation::Forward).unwrap() {
+            if successor.id >= segment.id {
+                write_link((segment.name, Orientation::Forward), (successor.name, orientation), output)?;
+            }
+        }
+        for (successor, orientation) in self.gbz.segment_successors(segment, Orientation::Reverse).unwrap() {
+            if successor.id > segment.id || (successor.id == segment.id && orientation == Orientation::Forward) {
+                write_link((segment.name, Orientation::Reverse), (successor.name, orientation), output)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes nodes as segments and edges as links when there is no translation.
+    fn write_node_graph<I, F, T>(&self, nodes: F, output: &mut T) -> io::Result<()>
+        where I: Iterator<Item = usize>, F: Fn() -> I, T: Write
+    {
+        for node_id in nodes() {
+            write_segment(node_id.to_string().as_bytes(), self.gbz.sequence(node_id).unwrap(), output)?;
+            if self.interleave_links {
+                self.write_node_links(node_id, output)?;
+            }
+        }
+        if !self.interleave_links {
+            for node_id in nodes() {
+                self.write_node_links(node_id, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_node_links<T: Write>(&self, node_id: usize, output: &mut T) -> io::Result<()> {
+        let name = node_id.to_string();
+        for (successor, orientation) in self.gbz.successors(node_id, Orientation::Forward).unwrap() {
+            if successor >= node_id {
+                write_link((name.as_bytes(), Orientation::Forward), (successor.to_string().as_bytes(), orientation), output)?;
+            }
+        }
+        for (successor, orientation) in self.gbz.successors(node_id, Orientation::Reverse).unwrap() {
+            if successor > node_id || (successor == node_id && orientation == Orientation::Forward) {
+                write_link((name.as_bytes(), Orientation::Reverse), (successor.to_string().as_bytes(), orientation), output)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Builds the path lines in parallel batches and writes them in order.
+    // The worker threads take the next batch from a shared counter and send the lines to this thread, which buffers the batches that arrive early.
+    fn write_paths<T: Write>(&self, paths: &[usize], line_type: LineType, output: &mut T) -> io::Result<()> {
+        if self.threads == 1 {
+            for path_id in paths.iter() {
+                output.write_all(&path_line(self.gbz, *path_id, line_type)?)?;
+            }
+            return Ok(());
+        }
+
+        let gbz = self.gbz;
+        let batches: Vec<&[usize]> = paths.chunks(Self::BATCH_SIZE).collect();
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::sync_channel::<(usize, io::Result<Vec<u8>>)>(self.threads);
+            let handles: Vec<_> = (0..self.threads).map(|_| {
+                let sender = sender.clone();
+                let (batches, next) = (&batches, &next);
+                scope.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= batches.len() || sender.send((i, path_lines(gbz, batches[i], line_type))).is_err() {
+                        break;
+                    }
+                })
+            }).collect();
+            drop(sender);
+
+            let mut result = Self::write_batches(&receiver, batches.len(), output);
+            // Dropping the receiver stops the workers if writing failed.
+            drop(receiver);
+            for handle in handles {
+                if handle.join().is_err() && result.is_ok() {
+                    result = Err(Error::new(ErrorKind::Other, "GFA: A worker thread panicked"));
+                }
+            }
+            result
+        })
+    }
+
+    // Receives the batches of path lines and writes them in order.
+    fn write_batches<T: Write>(receiver: &mpsc::Receiver<(usize, io::Result<Vec<u8>>)>, batches: usize, output: &mut T) -> io::Result<()> {
+        let mut pending: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        let mut written = 0;
+        while written < batches {
+            // The channel is closed early only if a worker thread panicked.
+            let (i, lines) = match receiver.recv() {
+                Ok(batch) => batch,
+                Err(_) => return Err(Error::new(ErrorKind::Other, "GFA: A worker thread panicked")),
+            };
+            pending.insert(i, lines?);
+            while let Some(lines) = pending.remove(&written) {
+                output.write_all(&lines)?;
+                written += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// Writes the header line.
+pub(crate) fn write_header<T: Write>(output: &mut T) -> io::Result<()> {
+    output.write_all(b"H\tVN:Z:1.1\n")
+}
+
+pub(crate) fn write_segment<T: Write>(name: &[u8], sequence: &[u8], output: &mut T) -> io::Result<()> {
+    output.write_all(b"S\t")?;
+    output.write_all(name)?;
+    output.write_all(b"\t")?;
+    output.write_all(sequence)?;
+    output.write_all(b"\n")
+}
+
+pub(crate) fn write_link<T: Write>(from: (&[u8], Orientation), to: (&[u8], Orientation), output: &mut T) -> io::Result<()> {
+    output.write_all(b"L\t")?;
+    output.write_all(from.0)?;
+    match from.1 {
+        Orientation::Forward => output.write_all(b"\t+\t")?,
+        Orientation::Reverse => output.write_all(b"\t-\t")?,
+    }
+    output.write_all(to.0)?;
+    match to.1 {
+        Orientation::Forward => output.write_all(b"\t+\t*\n"),
+        Orientation::Reverse => output.write_all(b"\t-\t*\n"),
+    }
+}
+
+// Writes a P-line with the given name visiting the given oriented segments.
+pub(crate) fn write_p_line<T, N, I>(name: &[u8], path: I, output: &mut T) -> io::Result<()>
+    where T: Write, N: AsRef<[u8]>, I: Iterator<Item = (N, Orientation)>
+{
+    output.write_all(b"P\t")?;
+    output.write_all(name)?;
+    output.write_all(b"\t")?;
+    for (i, (segment, orientation)) in path.enumerate() {
+        if i > 0 {
+            output.write_all(b",")?;
+        }
+        output.write_all(segment.as_ref())?;
+        match orientation {
+            Orientation::Forward => output.write_all(b"+")?,
+            Orientation::Reverse => output.write_all(b"-")?,
+        }
+    }
+    output.write_all(b"\t*\n")
+}
+
+// Writes a W-line visiting the given oriented segments.
+// The sequence offsets are written as `*` if the interval is not known.
+pub(crate) fn write_w_line<T, N, I>(sample: &str, phase: usize, contig: &str, interval: Option<Range<usize>>, path: I, output: &mut T) -> io::Result<()>
+    where T: Write, N: AsRef<[u8]>, I: Iterator<Item = (N, Orientation)>
+{
+    let (start, end) = match interval {
+        Some(interval) => (interval.start.to_string(), interval.end.to_string()),
+        None => (String::from("*"), String::from("*")),
+    };
+    output.write_all(format!("W\t{}\t{}\t{}\t{}\t{}\t", sample, phase, contig, start, end).as_bytes())?;
+    for (segment, orientation) in path {
+        match orientation {
+            Orientation::Forward => output.write_all(b">")?,
+            Orientation::Reverse => output.write_all(b"<")?,
+        }
+        output.write_all(segment.as_ref())?;
+    }
+    output.write_all(b"\n")
+}
+
+// Builds the path lines for a batch of paths.
+fn path_lines(gbz: &GBZ, paths: &[usize], line_type: LineType) -> io::Result<Vec<u8>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    for path_id in paths.iter() {
+        buffer.extend(path_line(gbz, *path_id, line_type)?);
+    }
+    Ok(buffer)
+}
+
+fn path_line(gbz: &GBZ, path_id: usize, line_type: LineType) -> io::Result<Vec<u8>> {
+    let metadata = gbz.metadata().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "GFA: Writing paths requires metadata"))?;
+    let path_name = metadata.path(path_id).ok_or_else(|| Error::new(ErrorKind::NotFound, "GFA: Path not found"))?;
+    let mut buffer: Vec<u8> = Vec::new();
+    match line_type {
+        LineType::PLine => p_line(gbz, path_id, &metadata.contig_name(path_name.contig()), &mut buffer)?,
+        LineType::PanSN => p_line(gbz, path_id, &metadata.pan_sn_path(path_id).unwrap_or_default(), &mut buffer)?,
+        LineType::WLine => {
+            let sample = metadata.sample_name(path_name.sample());
+            let contig = metadata.contig_name(path_name.contig());
+            let start = path_name.fragment();
+            match gbz.segment_path(path_id, Orientation::Forward) {
+                Some(iter) => {
+                    let mut path: Vec<(&[u8], Orientation)> = Vec::new();
+                    let mut len: usize = 0;
+                    for (segment, orientation) in iter {
+                        path.push((segment.name, orientation));
+                        len += segment.sequence.len();
+                    }
+                    write_w_line(&sample, path_name.phase(), &contig, Some(start..start + len), path.into_iter(), &mut buffer)?;
+                },
+                None => {
+                    let path: Vec<(usize, Orientation)> = gbz.path(path_id, Orientation::Forward).into_iter().flatten().collect();
+                    let len: usize = path.iter().map(|(node_id, _)| gbz.sequence_len(*node_id).unwrap_or(0)).sum();
+                    let path = path.into_iter().map(|(node_id, orientation)| (node_id.to_string(), orientation));
+                    write_w_line(&sample, path_name.phase(), &contig, Some(start..start + len), path, &mut buffer)?;
+                },
+            }
+        },
+    }
+    Ok(buffer)
+}
+
+fn p_line<T: Write>(gbz: &GBZ, path_id: usize, name: &str, output: &mut T) -> io::Result<()> {
+    match gbz.segment_path(path_id, Orientation::Forward) {
+        Some(iter) => write_p_line(name.as_bytes(), iter.map(|(segment, orientation)| (segment.name, orientation)), output),
+        None => {
+            let path = gbz.path(path_id, Orientation::Forward).into_iter().flatten();
+            write_p_line(name.as_bytes(), path.map(|(node_id, orientation)| (node_id.to_string(), orientation)), output)
+        },
+    }
+}
+
+//-----------------------------------------------------------------------------
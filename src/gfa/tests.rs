@@ -0,0 +1,271 @@
+use super::*;
+
+use crate::test_support::load_gbz;
+
+use simple_sds::serialize;
+
+use std::fs::File;
+use std::io::BufReader;
+
+//-----------------------------------------------------------------------------
+
+fn parse_gfa(filename: &'static str, max_node_len: usize) -> GBZ {
+    let filename = support::get_test_data(filename);
+    let reader = BufReader::new(File::open(&filename).unwrap());
+    read_gfa(reader, max_node_len).unwrap()
+}
+
+fn parse_str(gfa: &str) -> io::Result<GBZ> {
+    read_gfa(gfa.as_bytes(), MAX_NODE_LENGTH)
+}
+
+// Compares the graph built from GFA to the graph built with the C++ implementation.
+fn check_gbz(gbz: &GBZ, truth: &GBZ, name: &str) {
+    assert_eq!(gbz.nodes(), truth.nodes(), "[{}]: Invalid number of nodes", name);
+    assert_eq!(gbz.min_node(), truth.min_node(), "[{}]: Invalid minimum node", name);
+    assert_eq!(gbz.max_node(), truth.max_node(), "[{}]: Invalid maximum node", name);
+    for node_id in truth.node_iter() {
+        assert!(gbz.has_node(node_id), "[{}]: Node {} is missing", name, node_id);
+        assert_eq!(gbz.sequence(node_id), truth.sequence(node_id), "[{}]: Invalid sequence for node {}", name, node_id);
+        for orientation in [Orientation::Forward, Orientation::Reverse] {
+            let edges: Vec<(usize, Orientation)> = gbz.successors(node_id, orientation).unwrap().collect();
+            let expected: Vec<(usize, Orientation)> = truth.successors(node_id, orientation).unwrap().collect();
+            assert_eq!(edges, expected, "[{}]: Invalid successors for node {} ({:?})", name, node_id, orientation);
+        }
+    }
+    assert_eq!(gbz.node_iter().count(), truth.node_iter().count(), "[{}]: Invalid number of nodes from an iterator", name);
+
+    assert_eq!(gbz.has_translation(), truth.has_translation(), "[{}]: Invalid translation flag", name);
+    if truth.has_translation() {
+        assert!(gbz.segment_iter().unwrap().eq(truth.segment_iter().unwrap()), "[{}]: Invalid segments", name);
+    }
+
+    assert_eq!(gbz.paths(), truth.paths(), "[{}]: Invalid number of paths", name);
+    let metadata = gbz.metadata().unwrap();
+    let expected = truth.metadata().unwrap();
+    for path_id in 0..truth.paths() {
+        assert!(gbz.path(path_id, Orientation::Forward).unwrap().eq(truth.path(path_id, Orientation::Forward).unwrap()), "[{}]: Invalid path {}", name, path_id);
+        assert_eq!(metadata.pan_sn_path(path_id), expected.pan_sn_path(path_id), "[{}]: Invalid name for path {}", name, path_id);
+        assert_eq!(metadata.path_sense(path_id), expected.path_sense(path_id), "[{}]: Invalid sense for path {}", name, path_id);
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn example_gfa() {
+    let gbz = parse_gfa("example.gfa", MAX_NODE_LENGTH);
+    assert!(!gbz.has_translation(), "The graph should not contain a translation");
+    let truth = load_gbz("example.gbz");
+    check_gbz(&gbz, &truth, "example");
+    serialize::test(&gbz, "gfa-example", None, true);
+}
+
+#[test]
+fn translation_gfa() {
+    let gbz = parse_gfa("translation.gfa", 2);
+    let truth = load_gbz("translation.gbz");
+    check_gbz(&gbz, &truth, "translation");
+    serialize::test(&gbz, "gfa-translation", None, true);
+}
+
+#[test]
+fn chopping() {
+    // Integer segment names, but node 2 is too long.
+    let gfa = "S\t1\tGATTACA\nS\t2\tCAT\nL\t1\t+\t2\t-\t0M\nW\tsample\t1\tchr\t*\t*\t>1<2\n";
+    let gbz = read_gfa(gfa.as_bytes(), 3).unwrap();
+    assert!(gbz.has_translation(), "The graph should contain a translation");
+    assert_eq!(gbz.nodes(), 4, "Invalid number of nodes");
+    let expected = vec![
+        (1, Orientation::Forward), (2, Orientation::Forward), (3, Orientation::Forward), (4, Orientation::Reverse)
+    ];
+    let path: Vec<(usize, Orientation)> = gbz.path(0, Orientation::Forward).unwrap().collect();
+    assert_eq!(path, expected, "Invalid path");
+    let sequences: Vec<&[u8]> = (1..5).map(|node_id| gbz.sequence(node_id).unwrap()).collect();
+    assert_eq!(sequences, vec![b"GAT" as &[u8], b"TAC", b"A", b"CAT"], "Invalid node sequences");
+    let segment = gbz.node_to_segment(2).unwrap();
+    assert_eq!(segment.name, b"1", "Invalid segment name");
+    assert_eq!(segment.sequence, b"GATTACA", "Invalid segment sequence");
+
+    // Without chopping, the segment names are node identifiers.
+    let gbz = read_gfa(gfa.as_bytes(), MAX_NODE_LENGTH).unwrap();
+    assert!(!gbz.has_translation(), "The graph should not contain a translation");
+    assert_eq!(gbz.sequence(1), Some(b"GATTACA" as &[u8]), "Invalid sequence without chopping");
+}
+
+#[test]
+fn pan_sn_paths() {
+    let gfa = "S\t1\tGA\nS\t2\tT\nP\tGRCh38#0#chr1\t1+,2+\t*\nP\tchrM\t2-\t*\nW\tHG002\t1\tchr1\t10\t13\t>1>2\n";
+    let gbz = parse_str(gfa).unwrap();
+    let metadata = gbz.metadata().unwrap();
+    assert_eq!(metadata.paths(), 3, "Invalid number of paths");
+    assert_eq!(metadata.path_sense(0), Some(PathSense::Reference), "Invalid sense for a PanSN path");
+    assert_eq!(metadata.path_sense(1), Some(PathSense::Generic), "Invalid sense for a generic path");
+    assert_eq!(metadata.path_sense(2), Some(PathSense::Haplotype), "Invalid sense for a walk");
+    assert_eq!(metadata.pan_sn_path(0), Some(String::from("GRCh38#0#chr1")), "Invalid name for a PanSN path");
+    assert_eq!(metadata.contig_name(metadata.path(1).unwrap().contig()), "chrM", "Invalid contig for a generic path");
+    assert_eq!(metadata.path(2).unwrap().fragment(), 10, "Invalid fragment for a walk");
+
+
+    // The reference samples are stored in the tags.
+    serialize::test(&gbz, "gfa-pan-sn", None, true);
+}
+
+#[test]
+fn invalid_gfa() {
+    let valid = "S\t1\tGA\nS\t2\tT\nL\t1\t+\t2\t+\t*\nP\tchr\t1+,2+\t*\n";
+    assert!(parse_str(valid).is_ok(), "Failed to parse a valid GFA");
+    assert!(read_gfa(valid.as_bytes(), 0).is_err(), "Accepted zero maximum node length");
+
+    let cases = vec![
+        ("no paths", "S\t1\tGA\nS\t2\tT\nL\t1\t+\t2\t+\t*\n"),
+        ("duplicate segment", "S\t1\tGA\nS\t1\tT\nP\tchr\t1+\t*\n"),
+        ("missing sequence", "S\t1\t*\nP\tchr\t1+\t*\n"),
+        ("missing segment", "S\t1\tGA\nP\tchr\t1+,2+\t*\n"),
+        ("invalid link", "S\t1\tGA\nL\t1\t+\t3\t+\t*\nP\tchr\t1+\t*\n"),
+        ("overlap", "S\t1\tGA\nS\t2\tT\nL\t1\t+\t2\t+\t1M\nP\tchr\t1+,2+\t*\n"),
+        ("orientation", "S\t1\tGA\nP\tchr\t1x\t*\n"),
+        ("walk", "S\t1\tGA\nW\tsample\t1\tchr\t0\t2\t1\n"),
+        ("haplotype", "S\t1\tGA\nW\tsample\tx\tchr\t0\t2\t>1\n"),
+        ("duplicate path", "S\t1\tGA\nP\tchr\t1+\t*\nP\tchr\t1-\t*\n"),
+    ];
+    for (name, gfa) in cases {
+        assert!(parse_str(gfa).is_err(), "Accepted invalid GFA: {}", name);
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+fn write_string(writer: &GfaWriter) -> String {
+    let mut output: Vec<u8> = Vec::new();
+    writer.write(&mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+fn count_lines(gfa: &str, prefix: char) -> usize {
+    gfa.lines().filter(|line| line.starts_with(prefix)).count()
+}
+
+#[test]
+fn write_and_parse() {
+    let truth = load_gbz("example.gbz");
+    let mut writer = GfaWriter::new(&truth);
+    let gfa = write_string(&writer);
+    assert!(gfa.starts_with("H\tVN:Z:1.1\n"), "Missing header line");
+    let gbz = parse_str(&gfa).unwrap();
+    check_gbz(&gbz, &truth, "round trip");
+
+    writer.set_threads(3);
+    assert_eq!(write_string(&writer), gfa, "Parallel output differs from sequential output");
+}
+
+#[test]
+fn parallel_batches() {
+    // Enough paths for several batches per thread.
+    let mut gfa = String::from("S\t1\tGA\nS\t2\tT\nL\t1\t+\t2\t+\t0M\n");
+    for i in 0..5 * GfaWriter::BATCH_SIZE + 3 {
+        let path = if i % 2 == 0 { "1+,2+" } else { "2-,1-" };
+        gfa.push_str(&format!("P\tpath_{}\t{}\t*\n", i, path));
+    }
+    let gbz = parse_str(&gfa).unwrap();
+
+    let mut writer = GfaWriter::new(&gbz);
+    let sequential = write_string(&writer);
+    for threads in [2, 3, 8] {
+        writer.set_threads(threads);
+        assert_eq!(write_string(&writer), sequential, "Output with {} threads differs from sequential output", threads);
+    }
+}
+
+#[test]
+fn write_translation() {
+    let gbz = load_gbz("translation.gbz");
+    let writer = GfaWriter::new(&gbz);
+    let gfa = write_string(&writer);
+
+    // The unused segment is not in the graph.
+    let filename = support::get_test_data("translation.gfa");
+    let original = std::fs::read_to_string(&filename).unwrap();
+    let expected: Vec<&str> = original.lines().filter(|line| line.starts_with('S') && !line.contains("unused")).collect();
+    let segments: Vec<&str> = gfa.lines().filter(|line| line.starts_with('S')).collect();
+    assert_eq!(segments, expected, "Invalid segments");
+    let expected: Vec<&str> = original.lines().filter(|line| line.starts_with('W')).collect();
+    let walks: Vec<&str> = gfa.lines().filter(|line| line.starts_with('W')).collect();
+    assert_eq!(walks, expected, "Invalid walks");
+    assert_eq!(count_lines(&gfa, 'L'), count_lines(&original, 'L'), "Invalid number of links");
+}
+
+#[test]
+fn path_modes() {
+    let gbz = load_gbz("example.gbz");
+    let mut writer = GfaWriter::new(&gbz);
+    let gfa = write_string(&writer);
+    assert_eq!((count_lines(&gfa, 'P'), count_lines(&gfa, 'W')), (2, 4), "Invalid path lines in default mode");
+
+    writer.set_path_mode(PathMode::RefOnly);
+    let gfa = write_string(&writer);
+    assert_eq!((count_lines(&gfa, 'P'), count_lines(&gfa, 'W')), (2, 0), "Invalid path lines in reference-only mode");
+
+    writer.set_path_mode(PathMode::PanSN);
+    let gfa = write_string(&writer);
+    assert_eq!((count_lines(&gfa, 'P'), count_lines(&gfa, 'W')), (6, 0), "Invalid path lines in PanSN mode");
+    assert!(gfa.lines().any(|line| line == "P\tsample#1#B\t21+,22+,24+,23-,21-\t*"), "Missing PanSN path");
+}
+
+#[test]
+fn path_filter() {
+    let gbz = load_gbz("example.gbz");
+    let mut writer = GfaWriter::new(&gbz);
+    writer.set_path_filter(|metadata, path_id| metadata.path(path_id).unwrap().phase() == 2);
+    let gfa = write_string(&writer);
+    assert_eq!(count_lines(&gfa, 'S'), gbz.nodes(), "The filter changed the segments");
+    let walks: Vec<&str> = gfa.lines().filter(|line| line.starts_with('P') || line.starts_with('W')).collect();
+    assert_eq!(walks, vec![
+        "W\tsample\t2\tA\t0\t5\t>11>13>14>16>17",
+        "W\tsample\t2\tB\t0\t4\t>21>22>24>25",
+    ], "Invalid filtered paths");
+}
+
+#[test]
+fn segment_order() {
+    let gbz = load_gbz("translation.gbz");
+    let default = write_string(&GfaWriter::new(&gbz));
+
+    let mut writer = GfaWriter::new(&gbz);
+    writer.set_segment_order(SegmentOrder::Name);
+    writer.set_interleave_links(true);
+    let gfa = write_string(&writer);
+
+    let mut expected: Vec<&str> = default.lines().collect();
+    let mut lines: Vec<&str> = gfa.lines().collect();
+    expected.sort_unstable();
+    lines.sort_unstable();
+    assert_eq!(lines, expected, "Different lines with a different order");
+
+    // Segments are sorted by name, and each link comes after the segment it starts from.
+    let lines: Vec<&str> = gfa.lines().collect();
+    let names: Vec<&str> = lines.iter().filter(|line| line.starts_with('S')).map(|line| line.split('\t').nth(1).unwrap()).collect();
+    let mut sorted = names.clone();
+    sorted.sort_unstable();
+    assert_eq!(names, sorted, "Segments are not sorted by name");
+    let mut current: Option<&str> = None;
+    for line in lines.iter() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[0] {
+            "S" => current = Some(fields[1]),
+            "L" => assert_eq!(Some(fields[1]), current, "Link {} is not after its segment", line),
+            _ => {},
+        }
+    }
+}
+
+#[test]
+fn write_without_metadata() {
+    let mut gbz = load_gbz("example.gbz");
+    gbz.remove_metadata();
+    let writer = GfaWriter::new(&gbz);
+    let mut output: Vec<u8> = Vec::new();
+    assert!(writer.write(&mut output).is_err(), "Wrote paths without metadata");
+}
+
+//-----------------------------------------------------------------------------
@@ -4,18 +4,20 @@
 //! This enables representing bidirected sequence graphs compatible with a subset of the [GFA format](https://github.com/GFA-spec/GFA-spec/blob/master/GFA1.md).
 //! Unlike in the [C++ implementation](https://github.com/jltsiren/gbwtgraph), the actual graph interface is provided by the [`crate::GBZ`] structure.
 //!
-//! At the moment, this implementation only supports graphs built with other tools.
+//! New graphs can be built with [`GraphBuilder`].
 
 use crate::headers::{Header, GraphPayload};
-use crate::support::{StringArray, StringIter};
+use crate::support::{SharedMap, StringArray, StringIter};
 
 use simple_sds::ops::{BitVec, Select, PredSucc};
 use simple_sds::serialize::Serialize;
-use simple_sds::sparse_vector::{SparseVector, OneIter};
+use simple_sds::sparse_vector::{SparseVector, SparseBuilder, OneIter};
 
-use std::io::{Error, ErrorKind};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
 use std::iter::FusedIterator;
 use std::ops::Range;
+use std::sync::Arc;
 use std::io;
 
 #[cfg(test)]
@@ -89,6 +91,23 @@ pub struct Graph {
 
 //-----------------------------------------------------------------------------
 
+// Construction.
+impl Graph {
+    // Creates a graph from the given parts.
+    // The segments and the mapping are empty if there is no node-to-segment translation.
+    // The caller is responsible for the consistency of the parts.
+    pub(crate) fn from_parts(nodes: usize, sequences: StringArray, segments: StringArray, mapping: SparseVector) -> Self {
+        let mut header = Header::<GraphPayload>::new();
+        header.payload_mut().nodes = nodes;
+        if !segments.is_empty() {
+            header.set(GraphPayload::FLAG_TRANSLATION);
+        }
+        Graph {
+            header, sequences, segments, mapping,
+        }
+    }
+}
+
 /// Sequences for nodes.
 impl Graph {
     /// Returns the number of nodes in the original graph.
@@ -280,6 +299,39 @@ impl Graph {
 
 //-----------------------------------------------------------------------------
 
+/// Memory mapping.
+impl Graph {
+    /// Loads the graph from a reader over the bytes of a memory mapped file.
+    ///
+    /// The sequences and the segment names are borrowed from the mapping; see [`StringArray::load_mapped`].
+    /// The reader must be positioned at the start of a serialized graph, and it will be positioned after the graph.
+    pub fn load_mapped(reader: &mut io::Cursor<&[u8]>, map: &Arc<SharedMap>) -> io::Result<Self> {
+        Self::load_with(reader, |reader| StringArray::load_mapped(reader, map))
+    }
+
+    /// Returns `true` if the sequences and the segment names are borrowed from a memory mapping.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        self.sequences.is_mapped()
+    }
+
+    // Loads the graph using the given function for loading the string arrays.
+    fn load_with<T: io::Read, F: FnMut(&mut T) -> io::Result<StringArray>>(reader: &mut T, mut load_strings: F) -> io::Result<Self> {
+        let header = Header::<GraphPayload>::load(reader)?;
+        header.validate()?;
+
+        let sequences = load_strings(reader)?;
+
+        let segments = load_strings(reader)?;
+        let mapping = SparseVector::load(reader)?;
+        check_parts(&header, &sequences, &segments, &mapping)?;
+
+        Ok(Graph {
+            header, sequences, segments, mapping,
+        })
+    }
+}
+
 impl Serialize for Graph {
     fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
         self.header.serialize(writer)
@@ -293,42 +345,187 @@ impl Serialize for Graph {
     }
 
     fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
-        let header = Header::<GraphPayload>::load(reader)?;
-        if let Err(msg) = header.validate() {
-            return Err(Error::new(ErrorKind::InvalidData, msg));
+        Self::load_with(reader, StringArray::load)
+    }
+
+    fn size_in_elements(&self) -> usize {
+        self.header.size_in_elements() + self.sequences.size_in_elements() + self.segments.size_in_elements() + self.mapping.size_in_elements()
+    }
+}
+
+// Checks that the node-to-segment translation is consistent with the header and the sequences.
+fn check_parts(header: &Header<GraphPayload>, sequences: &StringArray, segments: &StringArray, mapping: &SparseVector) -> Result<(), crate::Error> {
+    if header.is_set(GraphPayload::FLAG_TRANSLATION) == segments.is_empty() {
+        return Err(crate::Error::InvalidData(String::from("Graph: Translation flag does not match the presence of segment names")));
+    }
+    if header.is_set(GraphPayload::FLAG_TRANSLATION) {
+        // If there are no gaps in the node id space, `mapping.len() == header.payload().nodes + 1`.
+        // Unused nodes create gaps.
+        if mapping.len() <= header.payload().nodes {
+            return Err(crate::Error::InvalidData(String::from("Graph: Node-to-segment mapping does not match the number of nodes")));
+        }
+        if mapping.len() != sequences.len() + 1 {
+            return Err(crate::Error::InvalidData(String::from("Graph: Node-to-segment mapping does not match the number of sequences")));
         }
+        if mapping.count_ones() != segments.len() {
+            return Err(crate::Error::InvalidData(String::from("Graph: Node-to-segment mapping does not match the number of segments")));
+        }
+        if mapping.one_iter().next().map(|(_, node_id)| node_id) != Some(1) {
+            return Err(crate::Error::InvalidData(String::from("Graph: The first segment does not start at node 1")));
+        }
+    } else if !segments.is_empty() {
+        return Err(crate::Error::InvalidData(String::from("Graph: Translation flag does not match the presence of node-to-segment mapping")));
+    }
+    Ok(())
+}
 
-        let sequences = StringArray::load(reader)?;
+//-----------------------------------------------------------------------------
 
-        let segments = StringArray::load(reader)?;
-        if header.is_set(GraphPayload::FLAG_TRANSLATION) == segments.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidData, "Graph: Translation flag does not match the presence of segment names"));
+/// A builder for [`Graph`].
+///
+/// The builder collects node sequences by node identifier and an optional node-to-segment translation.
+/// Node identifiers must be positive, as node `0` corresponds to the endmarker in the GBWT index.
+/// Without a translation, sequence `0` will correspond to the smallest node identifier, and unused identifiers between the smallest and the largest node get empty sequences.
+///
+/// If segments are added, the graph will contain a node-to-segment translation.
+/// The node ranges of the segments must then partition the range of node identifiers starting from `1`.
+/// Segments are ordered by their node ranges, and nodes may be missing from segments that are not used in the graph.
+/// The graph is built using [`TryFrom`], which performs the same consistency checks as loading a serialized graph.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::Graph;
+/// use gbwt::graph::GraphBuilder;
+/// use std::convert::TryFrom;
+///
+/// let mut builder = GraphBuilder::new();
+/// builder.add_node(1, "GAT").unwrap();
+/// builder.add_node(2, "TA").unwrap();
+/// builder.add_node(3, "CA").unwrap();
+/// builder.add_segment("first", 1..3).unwrap();
+/// builder.add_segment("second", 3..4).unwrap();
+/// let graph = Graph::try_from(builder).unwrap();
+///
+/// assert!(graph.has_translation());
+/// assert_eq!(graph.nodes(), 3);
+/// assert_eq!(graph.sequences(), 3);
+/// assert_eq!(graph.sequence(1), "TA".as_bytes());
+/// assert_eq!(graph.segment_name(0), "first".as_bytes());
+/// assert_eq!(graph.segment_sequence(0), "GATTA".as_bytes());
+/// assert_eq!(graph.node_to_segment(3).name, "second".as_bytes());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GraphBuilder {
+    nodes: BTreeMap<usize, String>,
+    segments: Vec<(String, Range<usize>)>,
+}
+
+impl GraphBuilder {
+    /// Creates a new empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of nodes added so far.
+    #[inline]
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the number of segments added so far.
+    #[inline]
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns `true` if no nodes have been added.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Adds a node with the given identifier and sequence.
+    ///
+    /// Returns an error if the identifier is `0` or if the node already exists.
+    pub fn add_node(&mut self, node_id: usize, sequence: &str) -> Result<(), crate::Error> {
+        if node_id == 0 {
+            return Err(crate::Error::InvalidData(String::from("GraphBuilder: Node identifier 0 is not valid")));
         }
+        if self.nodes.contains_key(&node_id) {
+            return Err(crate::Error::InvalidData(String::from("GraphBuilder: Duplicate node identifier")));
+        }
+        self.nodes.insert(node_id, sequence.to_string());
+        Ok(())
+    }
 
-        let mapping = SparseVector::load(reader)?;
-        if header.is_set(GraphPayload::FLAG_TRANSLATION) {
-            // If there are no gaps in the node id space, `mapping.len() == header.payload().nodes + 1`.
-            // Unused nodes create gaps.
-            if mapping.len() <= header.payload().nodes {
-                return Err(Error::new(ErrorKind::InvalidData, "Graph: Node-to-segment mapping does not match the number of nodes"));
-            }
-            if mapping.len() != sequences.len() + 1 {
-                return Err(Error::new(ErrorKind::InvalidData, "Graph: Node-to-segment mapping does not match the number of sequences"));
-            }
-            if mapping.count_ones() != segments.len() {
-                return Err(Error::new(ErrorKind::InvalidData, "Graph: Node-to-segment mapping does not match the number of segments"));
-            }
-        } else if !segments.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidData, "Graph: Translation flag does not match the presence of node-to-segment mapping"));
+    /// Adds a segment with the given name corresponding to the given range of node identifiers.
+    ///
+    /// The name may be empty if the segment is not used in the graph.
+    /// Returns an error if the range is empty or contains node `0`.
+    /// Overlapping ranges and duplicate names are detected when the graph is built.
+    pub fn add_segment(&mut self, name: &str, nodes: Range<usize>) -> Result<(), crate::Error> {
+        if nodes.is_empty() || nodes.start == 0 {
+            return Err(crate::Error::InvalidData(String::from("GraphBuilder: Invalid node range for a segment")));
         }
+        self.segments.push((name.to_string(), nodes));
+        Ok(())
+    }
 
-        Ok(Graph {
-            header, sequences, segments, mapping,
-        })
+    // Returns the sequences for nodes `first..last + 1`, with empty sequences for missing nodes.
+    fn sequences(&self, first: usize, last: usize) -> Vec<&str> {
+        let mut result: Vec<&str> = vec![""; last + 1 - first];
+        for (node_id, sequence) in self.nodes.iter() {
+            result[node_id - first] = sequence;
+        }
+        result
     }
+}
 
-    fn size_in_elements(&self) -> usize {
-        self.header.size_in_elements() + self.sequences.size_in_elements() + self.segments.size_in_elements() + self.mapping.size_in_elements()
+impl TryFrom<GraphBuilder> for Graph {
+    type Error = crate::Error;
+
+    fn try_from(source: GraphBuilder) -> Result<Self, Self::Error> {
+        let (sequences, segments, mapping) = if source.segments.is_empty() {
+            let sequences = match (source.nodes.keys().next(), source.nodes.keys().next_back()) {
+                (Some(first), Some(last)) => source.sequences(*first, *last),
+                _ => Vec::new(),
+            };
+            let mapping = SparseVector::try_from(SparseBuilder::new(0, 0).unwrap()).unwrap();
+            (sequences, Vec::new(), mapping)
+        } else {
+            let mut segments: Vec<&(String, Range<usize>)> = source.segments.iter().collect();
+            segments.sort_unstable_by_key(|(_, nodes)| nodes.start);
+            let mut names: HashSet<&str> = HashSet::with_capacity(segments.len());
+            let mut next = 1;
+            for (name, nodes) in segments.iter() {
+                if nodes.start != next {
+                    return Err(crate::Error::InvalidData(String::from("GraphBuilder: Segment node ranges do not partition the node identifiers")));
+                }
+                if !name.is_empty() && !names.insert(name.as_str()) {
+                    return Err(crate::Error::InvalidData(String::from("GraphBuilder: Duplicate segment name")));
+                }
+                next = nodes.end;
+            }
+            if let Some(last) = source.nodes.keys().next_back() {
+                if *last >= next {
+                    return Err(crate::Error::InvalidData(String::from("GraphBuilder: Node is not covered by any segment")));
+                }
+            }
+
+            let sequences = source.sequences(1, next - 1);
+            let mut builder = SparseBuilder::new(next, segments.len()).map_err(|_| crate::Error::InvalidData(String::from("GraphBuilder: Cannot build the node-to-segment mapping")))?;
+            for (_, nodes) in segments.iter() {
+                unsafe { builder.set_unchecked(nodes.start); }
+            }
+            let mapping = SparseVector::try_from(builder).map_err(|_| crate::Error::InvalidData(String::from("GraphBuilder: Cannot build the node-to-segment mapping")))?;
+            let names: Vec<&str> = segments.iter().map(|(name, _)| name.as_str()).collect();
+            (sequences, names, mapping)
+        };
+
+        let graph = Graph::from_parts(source.nodes(), StringArray::from(sequences), StringArray::from(segments), mapping);
+        check_parts(&graph.header, &graph.sequences, &graph.segments, &graph.mapping)?;
+        Ok(graph)
     }
 }
 
@@ -121,3 +121,94 @@ fn translation() {
 }
 
 //-----------------------------------------------------------------------------
+
+fn check_builder(graph: &Graph, truth: &Graph, name: &str) {
+    assert_eq!(graph.nodes(), truth.nodes(), "[{}]: Invalid number of nodes", name);
+    assert_eq!(graph.sequences(), truth.sequences(), "[{}]: Invalid number of sequences", name);
+    assert!(graph.iter().eq(truth.iter()), "[{}]: Invalid sequences", name);
+    assert_eq!(graph.has_translation(), truth.has_translation(), "[{}]: Invalid translation flag", name);
+    assert_eq!(graph.segments(), truth.segments(), "[{}]: Invalid number of segments", name);
+    assert!(graph.segment_iter().eq(truth.segment_iter()), "[{}]: Invalid segments", name);
+}
+
+#[test]
+fn builder() {
+    let filename = support::get_test_data("example.gg");
+    let truth: Graph = serialize::load_from(&filename).unwrap();
+    let mut builder = GraphBuilder::new();
+    for (i, sequence) in truth.iter().enumerate() {
+        if !sequence.is_empty() {
+            builder.add_node(i + 11, std::str::from_utf8(sequence).unwrap()).unwrap();
+        }
+    }
+    assert_eq!(builder.nodes(), truth.nodes(), "Invalid number of nodes in the builder");
+    let graph = Graph::try_from(builder).unwrap();
+    check_builder(&graph, &truth, "no translation");
+    serialize::test(&graph, "graph-builder", None, true);
+}
+
+#[test]
+fn builder_trans() {
+    let filename = support::get_test_data("translation.gg");
+    let truth: Graph = serialize::load_from(&filename).unwrap();
+    let mut builder = GraphBuilder::new();
+    for (i, sequence) in truth.iter().enumerate() {
+        if !sequence.is_empty() {
+            builder.add_node(i + 1, std::str::from_utf8(sequence).unwrap()).unwrap();
+        }
+    }
+    // Add the segments in reverse order, as the builder sorts them by node ranges.
+    for segment in truth.segment_iter().collect::<Vec<Segment>>().into_iter().rev() {
+        builder.add_segment(std::str::from_utf8(segment.name).unwrap(), segment.nodes.clone()).unwrap();
+    }
+    assert_eq!(builder.segments(), truth.segments(), "Invalid number of segments in the builder");
+    let graph = Graph::try_from(builder).unwrap();
+    check_builder(&graph, &truth, "translation");
+    serialize::test(&graph, "graph-builder-translation", None, true);
+}
+
+#[test]
+fn empty_builder() {
+    let builder = GraphBuilder::new();
+    assert!(builder.is_empty(), "The builder should be empty");
+    let graph = Graph::try_from(builder).unwrap();
+    assert!(graph.is_empty(), "The graph should be empty");
+    assert_eq!(graph.nodes(), 0, "Invalid number of nodes");
+    assert!(!graph.has_translation(), "The graph should not contain a translation");
+}
+
+#[test]
+fn invalid_builder() {
+    let mut builder = GraphBuilder::new();
+    assert!(builder.add_node(0, "GA").is_err(), "Added node 0");
+    builder.add_node(1, "GA").unwrap();
+    assert!(builder.add_node(1, "T").is_err(), "Added a duplicate node");
+    assert!(builder.add_segment("s1", 2..2).is_err(), "Added a segment with an empty range");
+    assert!(builder.add_segment("s0", 0..1).is_err(), "Added a segment containing node 0");
+
+    // Gap between segments.
+    let mut gap = builder.clone();
+    gap.add_segment("s1", 1..2).unwrap();
+    gap.add_segment("s2", 3..4).unwrap();
+    assert!(Graph::try_from(gap).is_err(), "Built a graph with a gap between segments");
+
+    // Overlapping segments.
+    let mut overlap = builder.clone();
+    overlap.add_segment("s1", 1..3).unwrap();
+    overlap.add_segment("s2", 2..4).unwrap();
+    assert!(Graph::try_from(overlap).is_err(), "Built a graph with overlapping segments");
+
+    // Duplicate names.
+    let mut duplicate = builder.clone();
+    duplicate.add_segment("s1", 1..2).unwrap();
+    duplicate.add_segment("s1", 2..3).unwrap();
+    assert!(Graph::try_from(duplicate).is_err(), "Built a graph with duplicate segment names");
+
+    // Node outside the segments.
+    let mut outside = builder;
+    outside.add_node(3, "T").unwrap();
+    outside.add_segment("s1", 1..3).unwrap();
+    assert!(Graph::try_from(outside).is_err(), "Built a graph with a node outside the segments");
+}
+
+//-----------------------------------------------------------------------------
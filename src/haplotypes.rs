@@ -0,0 +1,726 @@
+//! Haplotype-aware algorithms over the paths stored in a GBWT index.
+//!
+//! [`GBWT::haplotype_match`] finds the best-scoring mosaic of haplotypes for a query walk using the Li-Stephens model.
+//! Sets of haplotypes with the same score are represented as ranges of GBWT positions instead of being enumerated.
+//!
+//! [`GBWT::set_maximal_matches`] finds the long shared haplotype blocks between a stored sequence and the other sequences in the style of the PBWT.
+//!
+//! # Examples
+//!
+//! ```
+//! use gbwt::{GBZ, Orientation};
+//! use gbwt::haplotypes::Scoring;
+//! use gbwt::support;
+//! use simple_sds::serialize;
+//!
+//! let filename = support::get_test_data("example.gbz");
+//! let gbz: GBZ = serialize::load_from(&filename).unwrap();
+//!
+//! // The walk differs from paths 0 and 3 by a single node.
+//! let walk = vec![
+//!     (11, Orientation::Forward),
+//!     (12, Orientation::Forward),
+//!     (14, Orientation::Forward),
+//!     (16, Orientation::Forward),
+//!     (17, Orientation::Forward),
+//! ];
+//! let result = gbz.haplotype_match(&walk, &Scoring::default()).unwrap();
+//! assert_eq!(result.recombinations(), 0);
+//! assert_eq!(result.mismatches.len(), 1);
+//! assert_eq!(result.score, 4 - 4);
+//!
+//! // With expensive mismatches, the walk switches from path 0 to path 3.
+//! let result = gbz.haplotype_match(&walk, &Scoring::new(1, 10, 6)).unwrap();
+//! assert_eq!(result.recombinations(), 1);
+//! assert!(result.mismatches.is_empty());
+//! assert_eq!(result.score, 5 - 6);
+//! ```
+
+use crate::{GBWT, GBZ, Orientation, Pos, SearchState, BidirectionalState};
+use crate::ENDMARKER;
+use crate::bwt::BWT;
+use crate::support;
+
+use simple_sds::int_vector::IntVector;
+use simple_sds::ops::Access;
+use simple_sds::bits;
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::cmp;
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// Scoring parameters for haplotype matching.
+///
+/// Each query node copied from a haplotype with the same node gets `match_score`.
+/// Each query node copied from a haplotype with a different node, or not copied from any haplotype, costs `mismatch_penalty`.
+/// Each switch to a new set of haplotypes after the first one costs `recombination_penalty`.
+/// The penalties are subtracted from the score.
+/// They are normally non-negative, and a negative recombination penalty disables pruning in [`GBWT::haplotype_match`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scoring {
+    /// Score for each matching node.
+    pub match_score: isize,
+    /// Penalty for each mismatching node.
+    pub mismatch_penalty: isize,
+    /// Penalty for each switch between haplotypes.
+    pub recombination_penalty: isize,
+}
+
+impl Scoring {
+    /// Creates new scoring parameters.
+    pub fn new(match_score: isize, mismatch_penalty: isize, recombination_penalty: isize) -> Self {
+        Scoring {
+            match_score, mismatch_penalty, recombination_penalty,
+        }
+    }
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Scoring::new(1, 4, 6)
+    }
+}
+
+/// A segment of a haplotype mosaic copied from a set of haplotypes.
+///
+/// The haplotypes copy a path of the same length as the segment, which differs from the query at the mismatches within the segment.
+/// In a bidirectional index, `state` is the bidirectional search state for all occurrences of the path.
+/// The haplotypes selected by the mosaic are a subrange of the positions in the forward state.
+/// When the mosaic switches to the segment, it only selects the positions of the first node that do not have a better score.
+/// The selected positions are therefore not necessarily contiguous in the reverse state, and the subrange is only stored for the forward state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HaplotypeSegment {
+    /// Range of query offsets.
+    pub range: Range<usize>,
+    /// Bidirectional search state for all occurrences of the copied path, or [`None`] if the index is not bidirectional.
+    pub state: Option<BidirectionalState>,
+    /// Search state for the positions of the selected haplotypes at the last node of the segment.
+    ///
+    /// If `state` is present, this is a subrange of `state.forward`.
+    pub selected: SearchState,
+}
+
+/// The best-scoring haplotype mosaic for a query.
+///
+/// The query is partitioned into segments copied from sets of haplotypes and unmatched nodes.
+/// A segment may contain mismatches, where the haplotypes visit a different node than the query.
+/// See [`HaplotypeSegment`] for the representation of the segments.
+/// [`GBWT::locate`] can be used for listing the selected haplotypes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HaplotypeMatch {
+    /// Total score of the mosaic.
+    pub score: isize,
+    /// Segments copied from the haplotypes in query order.
+    pub segments: Vec<HaplotypeSegment>,
+    /// Query offsets with a mismatch, either within a segment or outside the segments, in sorted order.
+    pub mismatches: Vec<usize>,
+}
+
+impl HaplotypeMatch {
+    /// Returns the query offsets where the mosaic switches to a new set of haplotypes.
+    ///
+    /// These are the starting offsets of all segments except the first one.
+    pub fn switch_points(&self) -> Vec<usize> {
+        self.segments.iter().skip(1).map(|segment| segment.range.start).collect()
+    }
+
+    /// Returns the number of switches between haplotypes.
+    pub fn recombinations(&self) -> usize {
+        self.segments.len().saturating_sub(1)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+// The state a solution comes from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Source {
+    // All nodes so far are mismatches before the first segment.
+    Start,
+    // The last node is a mismatch after a segment.
+    Gap,
+    // The last node is copied from the haplotypes in the range with this index.
+    Haplotypes(usize),
+}
+
+// How a range of haplotypes came to copy the current node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Transition {
+    // The haplotypes copied the previous node from the range with this index.
+    Stay(usize),
+    // The haplotypes start a new segment after the given state.
+    Switch(Source),
+}
+
+// A range of haplotype positions copying the current node, with the best score for them.
+#[derive(Clone, Debug)]
+struct Copying {
+    node: usize,
+    range: Range<usize>,
+    score: isize,
+    transition: Transition,
+}
+
+// States after copying a query node.
+#[derive(Clone, Debug)]
+struct Step {
+    // The best score for a mismatch after a segment and the previous state.
+    gap: Option<(isize, Source)>,
+    // Disjoint ranges of haplotype positions.
+    haplotypes: Vec<Copying>,
+}
+
+impl Step {
+    // Returns the best score among the solutions with at least one segment and the corresponding state.
+    fn best_after_segment(&self) -> Option<(isize, Source)> {
+        let mut result = self.gap.map(|(score, _)| (score, Source::Gap));
+        for (i, copying) in self.haplotypes.iter().enumerate() {
+            match result {
+                Some((score, _)) if score >= copying.score => {},
+                _ => result = Some((copying.score, Source::Haplotypes(i))),
+            }
+        }
+        result
+    }
+}
+
+/// Haplotype matching.
+impl GBWT {
+    /// Returns the best-scoring mosaic of haplotypes for the query.
+    ///
+    /// The query is a sequence of GBWT node identifiers.
+    /// The algorithm follows the Li-Stephens recurrence over query offsets.
+    /// A haplotype copying a query node can stay on the same haplotype for the next node, which is either a match or a mismatch.
+    /// Alternatively, the mosaic can switch to any haplotype matching the next node for the recombination penalty, or leave the next node unmatched for the mismatch penalty.
+    ///
+    /// Instead of individual haplotypes, the algorithm stores disjoint ranges of GBWT positions with the same score.
+    /// Staying on the haplotypes follows all ranges in a record using a single scan, and a switch covers all positions of the matching node that do not have a better score.
+    /// If the recombination penalty is non-negative, ranges scoring below the best score minus the recombination penalty are discarded, as switching to the same haplotypes later is never worse.
+    /// Ties are broken in favor of staying on the same haplotypes.
+    /// In a bidirectional index, the bidirectional search state of each segment is found by extending a search over the copied path.
+    pub fn haplotype_match(&self, query: &[usize], scoring: &Scoring) -> HaplotypeMatch {
+        let bwt: &BWT = self.as_ref();
+        // steps[i] contains the states after processing query[..i + 1].
+        let mut steps: Vec<Step> = Vec::with_capacity(query.len());
+        for (offset, &node) in query.iter().enumerate() {
+            let start_score = -scoring.mismatch_penalty * (offset as isize);
+            let after_segment = steps.last().and_then(|step| step.best_after_segment());
+            let mut next = Step {
+                gap: after_segment.map(|(score, source)| (score - scoring.mismatch_penalty, source)),
+                haplotypes: Vec::new(),
+            };
+
+            // Switching to a new segment is free before the first segment.
+            let mut switch = (start_score, Source::Start);
+            if let Some((score, source)) = after_segment {
+                if score - scoring.recombination_penalty > switch.0 {
+                    switch = (score - scoring.recombination_penalty, source);
+                }
+            }
+            let switch_score = switch.0 + scoring.match_score;
+
+            // Stay on the same haplotypes.
+            let mut stays: Vec<Copying> = Vec::new();
+            if let Some(prev) = steps.last() {
+                let mut order: Vec<usize> = (0..prev.haplotypes.len()).collect();
+                order.sort_unstable_by_key(|i| (prev.haplotypes[*i].node, prev.haplotypes[*i].range.start));
+                let mut start = 0;
+                while start < order.len() {
+                    let prev_node = prev.haplotypes[order[start]].node;
+                    let limit = start + order[start..].iter().take_while(|i| prev.haplotypes[**i].node == prev_node).count();
+                    if let Some(record) = bwt.record(self.node_to_record(prev_node)) {
+                        let ranges: Vec<Range<usize>> = order[start..limit].iter().map(|i| prev.haplotypes[*i].range.clone()).collect();
+                        for (k, successor, range) in record.follow_ranges(&ranges) {
+                            if successor == ENDMARKER {
+                                continue;
+                            }
+                            let prev_index = order[start + k];
+                            let delta = if successor == node { scoring.match_score } else { -scoring.mismatch_penalty };
+                            stays.push(Copying {
+                                node: successor,
+                                range,
+                                score: prev.haplotypes[prev_index].score + delta,
+                                transition: Transition::Stay(prev_index),
+                            });
+                        }
+                    }
+                    start = limit;
+                }
+            }
+
+            // Merge the pieces of the same range and switch to the remaining positions of the node.
+            stays.sort_unstable_by_key(|copying| (copying.node, copying.range.start));
+            let mut covered = 0;
+            let total = self.find(node).map(|state| state.range.end).unwrap_or(0);
+            for copying in stays {
+                if copying.node == node {
+                    if copying.score < switch_score {
+                        continue;
+                    }
+                    if covered < copying.range.start {
+                        next.haplotypes.push(Copying {
+                            node,
+                            range: covered..copying.range.start,
+                            score: switch_score,
+                            transition: Transition::Switch(switch.1),
+                        });
+                    }
+                    covered = copying.range.end;
+                }
+                match next.haplotypes.last_mut() {
+                    Some(last) if last.node == copying.node && last.transition == copying.transition && last.range.end == copying.range.start => {
+                        last.range.end = copying.range.end;
+                    },
+                    _ => next.haplotypes.push(copying),
+                }
+            }
+            if covered < total {
+                next.haplotypes.push(Copying {
+                    node,
+                    range: covered..total,
+                    score: switch_score,
+                    transition: Transition::Switch(switch.1),
+                });
+            }
+
+            // Discard the ranges that cannot be better than switching to the same haplotypes later.
+            if scoring.recombination_penalty >= 0 {
+                let start_after = start_score - scoring.mismatch_penalty;
+                let best = next.best_after_segment().map_or(start_after, |(score, _)| cmp::max(score, start_after));
+                let threshold = best - scoring.recombination_penalty;
+                next.haplotypes.retain(|copying| copying.score >= threshold);
+            }
+            steps.push(next);
+        }
+
+        // Find the best final state.
+        let mut result = HaplotypeMatch {
+            score: -scoring.mismatch_penalty * (query.len() as isize),
+            segments: Vec::new(),
+            mismatches: Vec::new(),
+        };
+        let mut source = Source::Start;
+        if let Some((score, best)) = steps.last().and_then(|step| step.best_after_segment()) {
+            if score > result.score {
+                result.score = score;
+                source = best;
+            }
+        }
+
+        // Trace back the solution.
+        // The segment under construction is stored as the end offset, the selected positions, and the copied path in reverse order.
+        let mut offset = query.len();
+        let mut segment: Option<(usize, SearchState, Vec<usize>)> = None;
+        while offset > 0 {
+            let step = &steps[offset - 1];
+            match source {
+                Source::Start => {
+                    result.mismatches.extend((0..offset).rev());
+                    break;
+                },
+                Source::Gap => {
+                    result.mismatches.push(offset - 1);
+                    source = step.gap.map(|(_, prev)| prev).unwrap_or(Source::Start);
+                },
+                Source::Haplotypes(i) => {
+                    let copying = &step.haplotypes[i];
+                    let (_, _, path) = segment.get_or_insert_with(|| (offset, SearchState {
+                        node: copying.node,
+                        range: copying.range.clone(),
+                    }, Vec::new()));
+                    path.push(copying.node);
+                    if copying.node != query[offset - 1] {
+                        result.mismatches.push(offset - 1);
+                    }
+                    match copying.transition {
+                        Transition::Stay(prev) => source = Source::Haplotypes(prev),
+                        Transition::Switch(prev) => {
+                            if let Some((end, selected, path)) = segment.take() {
+                                result.segments.push(HaplotypeSegment {
+                                    range: offset - 1..end,
+                                    state: self.bd_path_state(path.iter().rev()),
+                                    selected,
+                                });
+                            }
+                            source = prev;
+                        },
+                    }
+                },
+            }
+            offset -= 1;
+        }
+        result.segments.reverse();
+        result.mismatches.reverse();
+
+        result
+    }
+
+    // Returns the bidirectional search state for the path, or `None` if the index is not bidirectional or the path does not occur.
+    fn bd_path_state<'a, I: Iterator<Item = &'a usize>>(&self, mut path: I) -> Option<BidirectionalState> {
+        if !self.is_bidirectional() {
+            return None;
+        }
+        let mut state = self.bd_find(*path.next()?)?;
+        for node in path {
+            state = self.extend_forward(&state, *node)?;
+        }
+        Some(state)
+    }
+}
+
+/// Haplotype matching.
+impl GBZ {
+    /// Returns the best-scoring mosaic of haplotypes for the walk, or [`None`] if the walk contains nodes that do not exist in the graph.
+    ///
+    /// The walk is given as a sequence of oriented nodes in the original graph.
+    /// See [`GBWT::haplotype_match`] for details.
+    pub fn haplotype_match(&self, walk: &[(usize, Orientation)], scoring: &Scoring) -> Option<HaplotypeMatch> {
+        let mut query: Vec<usize> = Vec::with_capacity(walk.len());
+        for (id, orientation) in walk.iter() {
+            if !self.has_node(*id) {
+                return None;
+            }
+            query.push(support::encode_node(*id, *orientation));
+        }
+        let index: &GBWT = self.as_ref();
+        Some(index.haplotype_match(&query, scoring))
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Divergence arrays for the records of a [`GBWT`] index.
+///
+/// In the PBWT, the divergence array stores the starting site of the longest match between each haplotype and the preceding haplotype in the sorted order.
+/// Sites are not well-defined in a graph, so this structure stores the lengths of the matches instead.
+/// The positions in each record are sorted by the reversed sequence prefixes ending at them.
+/// For each offset `i > 0`, the array for the record stores the length of the longest common suffix of the prefixes ending at offsets `i - 1` and `i`.
+/// The length is not stored if the prefixes are identical.
+/// The arrays are concatenated into a single integer vector using as many bits per value as the longest match length requires.
+///
+/// The arrays are built by following ranges of positions sharing a context of increasing length.
+/// A range is followed further only if it is a proper subrange of the range for a shorter context, which bounds the number of ranges by the total length of the sequences.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{GBWT, Orientation};
+/// use gbwt::haplotypes::DivergenceArrays;
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.gbwt");
+/// let index: GBWT = serialize::load_from(&filename).unwrap();
+/// let divergence = DivergenceArrays::new(&index);
+///
+/// // Paths 0 and 2 are identical, while path 3 reaches node 17 from a different node.
+/// let record_id = index.node_to_record(support::encode_node(17, Orientation::Forward));
+/// assert_eq!(divergence.match_len(record_id, 1), None);
+/// assert_eq!(divergence.match_len(record_id, 2), Some(1));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DivergenceArrays {
+    // Match lengths for all records, with `0` for identical prefixes and offset 0.
+    lengths: IntVector,
+    // Starting offset of each record in `lengths`, with the total length at the end.
+    starts: Vec<usize>,
+}
+
+impl DivergenceArrays {
+    const IDENTICAL: usize = usize::MAX;
+
+    /// Builds the divergence arrays for the index.
+    pub fn new(index: &GBWT) -> Self {
+        let bwt: &BWT = index.as_ref();
+        let mut lengths: Vec<Vec<usize>> = (0..bwt.len()).map(|record_id| {
+            let len = if record_id == ENDMARKER { 0 } else { bwt.record(record_id).map_or(0, |record| record.len()) };
+            vec![Self::IDENTICAL; len]
+        }).collect();
+
+        // Ranges of positions sharing a context of length `len`, as (record id, range).
+        let mut intervals: Vec<(usize, Range<usize>)> = lengths.iter().enumerate()
+            .filter(|(_, array)| !array.is_empty())
+            .map(|(record_id, array)| (record_id, 0..array.len()))
+            .collect();
+        let mut len = 1;
+        while !intervals.is_empty() {
+            // Extend the contexts by one node.
+            intervals.sort_unstable_by_key(|(record_id, range)| (*record_id, range.start));
+            let mut pieces: Vec<(usize, Range<usize>, usize)> = Vec::new();
+            let mut start = 0;
+            while start < intervals.len() {
+                let record_id = intervals[start].0;
+                let limit = start + intervals[start..].iter().take_while(|(id, _)| *id == record_id).count();
+                if let Some(record) = bwt.record(record_id) {
+                    let ranges: Vec<Range<usize>> = intervals[start..limit].iter().map(|(_, range)| range.clone()).collect();
+                    for (k, successor, range) in record.follow_ranges(&ranges) {
+                        if successor != ENDMARKER {
+                            pieces.push((index.node_to_record(successor), range, start + k));
+                        }
+                    }
+                }
+                start = limit;
+            }
+
+            // The image of a range in a successor node is contiguous.
+            pieces.sort_unstable_by_key(|(record_id, range, _)| (*record_id, range.start));
+            let mut next: Vec<(usize, Range<usize>)> = Vec::new();
+            let mut prev_source = usize::MAX;
+            for (record_id, range, source) in pieces {
+                match next.last_mut() {
+                    Some((id, prev)) if *id == record_id && source == prev_source && prev.end == range.start => prev.end = range.end,
+                    _ => next.push((record_id, range)),
+                }
+                prev_source = source;
+            }
+
+            // The prefixes on both sides of a new boundary share a context of length `len` but not `len + 1`.
+            for (record_id, range) in next.iter() {
+                let array = &mut lengths[*record_id];
+                for boundary in [range.start, range.end] {
+                    if boundary > 0 && boundary < array.len() && array[boundary] == Self::IDENTICAL {
+                        array[boundary] = len;
+                    }
+                }
+            }
+            next.retain(|(record_id, range)| {
+                let array = &lengths[*record_id];
+                [range.start, range.end].iter().any(|boundary| *boundary > 0 && *boundary < array.len() && array[*boundary] == len)
+            });
+            intervals = next;
+            len += 1;
+        }
+
+        // Pack the arrays. The longest match length is `len - 1`, as no boundary got value `len`.
+        let mut starts: Vec<usize> = Vec::with_capacity(lengths.len() + 1);
+        let mut total = 0;
+        for array in lengths.iter() {
+            starts.push(total);
+            total += array.len();
+        }
+        starts.push(total);
+        let mut packed = IntVector::with_capacity(total, bits::bit_len((len - 1) as u64)).unwrap();
+        for array in lengths.iter() {
+            packed.extend(array.iter().map(|value| if *value == Self::IDENTICAL { 0 } else { *value }));
+        }
+
+        DivergenceArrays {
+            lengths: packed,
+            starts,
+        }
+    }
+
+    /// Returns the number of records.
+    #[inline]
+    pub fn records(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    // Returns the length of the array for the record, or `0` if there is no such record.
+    fn record_len(&self, record_id: usize) -> usize {
+        if record_id < self.records() { self.starts[record_id + 1] - self.starts[record_id] } else { 0 }
+    }
+
+    /// Returns the length of the longest common suffix of the prefixes ending at offsets `offset - 1` and `offset` of the record.
+    ///
+    /// Returns [`None`] if the prefixes are identical or if there is no such pair of positions.
+    pub fn match_len(&self, record_id: usize, offset: usize) -> Option<usize> {
+        if offset == 0 || offset >= self.record_len(record_id) {
+            return None;
+        }
+        let value = self.lengths.get(self.starts[record_id] + offset) as usize;
+        if value == 0 { None } else { Some(value) }
+    }
+
+    // Returns the boundary value for the given offset, with identical prefixes matching everything.
+    fn boundary(&self, record_id: usize, offset: usize) -> usize {
+        self.match_len(record_id, offset).unwrap_or(Self::IDENTICAL)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// A set-maximal match between a stored sequence and the other sequences.
+///
+/// The match covers the nodes of the sequence in the given interval of offsets.
+/// No other sequence contains a longer substring of the sequence that also covers the interval.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetMaximalMatch {
+    /// Offsets of the matching nodes in the sequence.
+    pub interval: Range<usize>,
+    /// Search state for all occurrences of the matching nodes, including those in the sequence itself.
+    pub state: SearchState,
+    /// Identifiers of the other sequences containing the matching nodes in sorted order.
+    pub partners: Vec<usize>,
+}
+
+/// Set-maximal matches.
+impl GBWT {
+    /// Returns the set-maximal matches between sequence `id` and the other sequences.
+    ///
+    /// Returns [`None`] if there is no such sequence or if the index does not contain document array samples.
+    /// The matches are sorted by interval.
+    /// In a bidirectional index, the sequence for the other orientation of the same path does not count as another sequence.
+    /// This builds the divergence arrays for the index.
+    /// Use [`GBWT::set_maximal_matches_with`] to reuse the same arrays for multiple sequences.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gbwt::{GBWT, Orientation};
+    /// use gbwt::support;
+    /// use simple_sds::serialize;
+    ///
+    /// let filename = support::get_test_data("example.gbwt");
+    /// let index: GBWT = serialize::load_from(&filename).unwrap();
+    ///
+    /// // Paths 0 and 2 are identical.
+    /// let matches = index.set_maximal_matches(support::encode_path(0, Orientation::Forward)).unwrap();
+    /// assert_eq!(matches.len(), 1);
+    /// assert_eq!(matches[0].interval, 0..5);
+    /// assert_eq!(matches[0].partners, vec![support::encode_path(2, Orientation::Forward)]);
+    /// ```
+    pub fn set_maximal_matches(&self, id: usize) -> Option<Vec<SetMaximalMatch>> {
+        if id >= self.sequences() || !self.has_da_samples() {
+            return None;
+        }
+        self.set_maximal_matches_with(id, &DivergenceArrays::new(self))
+    }
+
+    /// Returns the set-maximal matches between sequence `id` and the other sequences using the given divergence arrays.
+    ///
+    /// Returns [`None`] if there is no such sequence or if the index does not contain document array samples.
+    ///
+    /// The algorithm sweeps over the sequence in the style of the PBWT.
+    /// At each position, the nearest positions of the other sequences in the record order have the longest matches ending there, and the divergence arrays give the lengths of the matches.
+    /// A match is reported if it cannot be extended to the next position.
+    /// The match interval is then found by expanding the range around the position of the sequence as long as the divergence values are at least the length of the match.
+    /// The other sequences are only listed for the matches that are reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `id`: Sequence identifier.
+    /// * `divergence`: Divergence arrays built for this index.
+    ///
+    /// # Panics
+    ///
+    /// May panic if the divergence arrays were built for a different index.
+    pub fn set_maximal_matches_with(&self, id: usize, divergence: &DivergenceArrays) -> Option<Vec<SetMaximalMatch>> {
+        if id >= self.sequences() || !self.has_da_samples() {
+            return None;
+        }
+        let excluded: Vec<usize> = if self.is_bidirectional() { vec![id, support::flip_path(id)] } else { vec![id] };
+
+        // Offsets of the positions of the excluded sequences in each record, in sorted order.
+        let mut own: HashMap<usize, Vec<usize>> = HashMap::new();
+        for sequence in excluded.iter() {
+            for pos in self.sequence_positions(*sequence) {
+                own.entry(pos.node).or_default().push(pos.offset);
+            }
+        }
+        for offsets in own.values_mut() {
+            offsets.sort_unstable();
+        }
+        let is_own = |node: usize, offset: usize| -> bool {
+            own.get(&node).is_some_and(|offsets| offsets.binary_search(&offset).is_ok())
+        };
+
+        // The length of the longest match ending at each offset of the sequence.
+        let path = self.sequence_positions(id);
+        let mut lengths: Vec<usize> = Vec::with_capacity(path.len());
+        for (i, pos) in path.iter().enumerate() {
+            let record_id = self.node_to_record(pos.node);
+            let len = divergence.record_len(record_id);
+            let mut best = 0;
+            // Nearest position of another sequence before the position.
+            let mut matched = DivergenceArrays::IDENTICAL;
+            let mut offset = pos.offset;
+            while offset > 0 {
+                matched = cmp::min(matched, divergence.boundary(record_id, offset));
+                offset -= 1;
+                if !is_own(pos.node, offset) {
+                    best = matched;
+                    break;
+                }
+            }
+            // Nearest position of another sequence after the position.
+            matched = DivergenceArrays::IDENTICAL;
+            offset = pos.offset + 1;
+            while offset < len {
+                matched = cmp::min(matched, divergence.boundary(record_id, offset));
+                if !is_own(pos.node, offset) {
+                    best = cmp::max(best, matched);
+                    break;
+                }
+                offset += 1;
+            }
+            lengths.push(cmp::min(best, i + 1));
+        }
+
+        let mut result: Vec<SetMaximalMatch> = Vec::new();
+        for (i, pos) in path.iter().enumerate() {
+            let len = lengths[i];
+            if len == 0 || (i + 1 < path.len() && lengths[i + 1] > len) {
+                continue;
+            }
+            // The occurrences of the match are the positions around this one with long enough matches.
+            let record_id = self.node_to_record(pos.node);
+            let mut start = pos.offset;
+            while start > 0 && divergence.boundary(record_id, start) >= len {
+                start -= 1;
+            }
+            let mut end = pos.offset + 1;
+            while end < divergence.record_len(record_id) && divergence.boundary(record_id, end) >= len {
+                end += 1;
+            }
+            let state = SearchState {
+                node: pos.node,
+                range: start..end,
+            };
+            let partners: Vec<usize> = self.distinct_sequences(&state).into_iter()
+                .map(|(sequence, _)| sequence)
+                .filter(|sequence| !excluded.contains(sequence))
+                .collect();
+            result.push(SetMaximalMatch {
+                interval: i + 1 - len..i + 1,
+                state,
+                partners,
+            });
+        }
+
+        Some(result)
+    }
+
+    /// Returns the set-maximal matches for all sequences.
+    ///
+    /// The matches for sequence `id` are at position `id` of the result.
+    /// The divergence arrays are built only once.
+    /// See [`GBWT::set_maximal_matches_with`] for details.
+    ///
+    /// Returns [`None`] if the index does not contain document array samples.
+    pub fn all_set_maximal_matches(&self) -> Option<Vec<Vec<SetMaximalMatch>>> {
+        if !self.has_da_samples() {
+            return None;
+        }
+        let divergence = DivergenceArrays::new(self);
+        Some((0..self.sequences()).map(|id| self.set_maximal_matches_with(id, &divergence).unwrap_or_default()).collect())
+    }
+
+    // Returns the positions of sequence `id` in order.
+    fn sequence_positions(&self, id: usize) -> Vec<Pos> {
+        let mut result: Vec<Pos> = Vec::new();
+        let mut pos = self.start(id);
+        while let Some(p) = pos {
+            result.push(p);
+            pos = self.forward(p);
+        }
+        result
+    }
+}
+
+//-----------------------------------------------------------------------------
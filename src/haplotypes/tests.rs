@@ -0,0 +1,342 @@
+use super::*;
+
+use crate::gbwt::GBWTBuilder;
+use crate::test_support;
+
+use simple_sds::serialize;
+
+use std::convert::TryFrom;
+
+//-----------------------------------------------------------------------------
+
+fn load_index() -> GBWT {
+    test_support::load_index("example.gbwt")
+}
+
+fn forward(id: usize) -> usize {
+    support::encode_node(id, Orientation::Forward)
+}
+
+fn sequences(index: &GBWT) -> Vec<Vec<usize>> {
+    (0..index.sequences()).map(|id| index.sequence(id).unwrap().collect()).collect()
+}
+
+fn count_occurrences(sequences: &[Vec<usize>], pattern: &[usize]) -> usize {
+    sequences.iter().map(|sequence| {
+        (0..sequence.len()).filter(|i| sequence[*i..].starts_with(pattern)).count()
+    }).sum()
+}
+
+// Brute force best score using the Li-Stephens recurrence over individual haplotype positions.
+fn best_score(sequences: &[Vec<usize>], query: &[usize], scoring: &Scoring) -> isize {
+    // Scores for mismatching the entire prefix, for a mismatch after a segment, and for copying each position.
+    let mut start: isize = 0;
+    let mut gap: Option<isize> = None;
+    let mut copying: Vec<Vec<Option<isize>>> = sequences.iter().map(|sequence| vec![None; sequence.len()]).collect();
+    for node in query.iter() {
+        let after_segment = copying.iter().flatten().flatten().copied().chain(gap).max();
+        let switch = after_segment.map_or(start, |score| cmp::max(start, score - scoring.recombination_penalty));
+        let next: Vec<Vec<Option<isize>>> = sequences.iter().enumerate().map(|(h, sequence)| {
+            (0..sequence.len()).map(|p| {
+                let is_match = sequence[p] == *node;
+                let delta = if is_match { scoring.match_score } else { -scoring.mismatch_penalty };
+                let stay = if p > 0 { copying[h][p - 1].map(|score| score + delta) } else { None };
+                let switched = if is_match { Some(switch + scoring.match_score) } else { None };
+                stay.into_iter().chain(switched).max()
+            }).collect()
+        }).collect();
+        gap = after_segment.map(|score| score - scoring.mismatch_penalty);
+        start -= scoring.mismatch_penalty;
+        copying = next;
+    }
+    copying.iter().flatten().flatten().copied().chain(gap).fold(start, cmp::max)
+}
+
+// Returns the query offsets where the haplotype differs from the query.
+fn differences(haplotype: &[usize], query: &[usize], offset: usize) -> Vec<usize> {
+    (0..query.len()).filter(|i| haplotype[*i] != query[*i]).map(|i| offset + i).collect()
+}
+
+fn check_match(index: &GBWT, query: &[usize], scoring: &Scoring) -> HaplotypeMatch {
+    let sequences = sequences(index);
+    let result = index.haplotype_match(query, scoring);
+    assert_eq!(result.score, best_score(&sequences, query, scoring), "Invalid score for {:?} with {:?}", query, scoring);
+
+    // The segments are disjoint, and the offsets outside them are mismatches.
+    let mut covered = vec![false; query.len()];
+    let mut prev_end = 0;
+    for segment in result.segments.iter() {
+        let (range, state) = (&segment.range, &segment.selected);
+        assert!(!range.is_empty() && range.start >= prev_end, "Invalid segment {:?} for {:?}", range, query);
+        prev_end = range.end;
+        for offset in range.clone() {
+            covered[offset] = true;
+        }
+
+        // The segment starts with a match, and the haplotypes copy it with mismatches at the reported offsets.
+        let mismatches: Vec<usize> = result.mismatches.iter().copied().filter(|offset| range.contains(offset)).collect();
+        assert!(!mismatches.contains(&range.start), "Segment {:?} of {:?} starts with a mismatch", range, query);
+        assert!(!state.is_empty(), "Empty state for segment {:?} of {:?}", range, query);
+        for id in index.locate(state) {
+            let sequence = &sequences[id];
+            let found = (range.len() - 1..sequence.len()).any(|end| {
+                sequence[end] == state.node && differences(&sequence[end + 1 - range.len()..end + 1], &query[range.clone()], range.start) == mismatches
+            });
+            assert!(found, "Sequence {} does not copy segment {:?} of {:?}", id, range, query);
+        }
+
+        // The selected positions are a subrange of all occurrences of the copied path.
+        if let Some(bd_state) = segment.state.as_ref() {
+            assert_eq!(bd_state.forward.node, state.node, "Invalid forward node for segment {:?} of {:?}", range, query);
+            assert_eq!(bd_state.reverse.node, support::flip_node(query[range.start]), "Invalid reverse node for segment {:?} of {:?}", range, query);
+            assert_eq!(bd_state.forward.len(), bd_state.reverse.len(), "Forward and reverse states have different lengths for segment {:?} of {:?}", range, query);
+            assert!(bd_state.forward.range.start <= state.range.start && state.range.end <= bd_state.forward.range.end, "The selected positions are not within the bidirectional state for segment {:?} of {:?}", range, query);
+        } else {
+            assert!(!index.is_bidirectional(), "No bidirectional state for segment {:?} of {:?}", range, query);
+        }
+    }
+    for (offset, is_covered) in covered.iter().enumerate() {
+        if !is_covered {
+            assert!(result.mismatches.contains(&offset), "Offset {} of {:?} is neither copied nor a mismatch", offset, query);
+        }
+    }
+    assert!(result.mismatches.windows(2).all(|pair| pair[0] < pair[1]), "The mismatches are not sorted for {:?}", query);
+
+    // The solution adds up to the score.
+    let matches = (query.len() - result.mismatches.len()) as isize;
+    let score = scoring.match_score * matches
+        - scoring.mismatch_penalty * (result.mismatches.len() as isize)
+        - scoring.recombination_penalty * (result.recombinations() as isize);
+    assert_eq!(score, result.score, "The solution does not add up to the score for {:?}", query);
+
+    let switch_points: Vec<usize> = result.segments.iter().skip(1).map(|segment| segment.range.start).collect();
+    assert_eq!(result.switch_points(), switch_points, "Invalid switch points for {:?}", query);
+
+    result
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn exact_haplotype() {
+    let index = load_index();
+    let query: Vec<usize> = vec![forward(11), forward(12), forward(14), forward(15), forward(17)];
+    let result = check_match(&index, &query, &Scoring::default());
+    assert_eq!(result.score, 5, "Invalid score for an exact match");
+    assert_eq!(result.segments.len(), 1, "Invalid number of segments for an exact match");
+    assert_eq!(result.segments[0].selected.len(), 2, "Invalid number of haplotypes for an exact match");
+    assert_eq!(result.segments[0].state.as_ref().map(|state| state.forward.len()), Some(2), "Invalid bidirectional state for an exact match");
+}
+
+#[test]
+fn recombination() {
+    let index = load_index();
+    let query: Vec<usize> = vec![forward(11), forward(12), forward(14), forward(16), forward(17)];
+
+    // Staying on the same haplotype with a mismatch is better than a recombination.
+    let result = check_match(&index, &query, &Scoring::default());
+    assert_eq!(result.recombinations(), 0, "Invalid number of recombinations");
+    assert_eq!(result.mismatches.len(), 1, "Invalid number of mismatches");
+    assert_eq!(result.segments.len(), 1, "The mismatch is not within the segment");
+
+    // With an expensive mismatch, a recombination is better.
+    let result = check_match(&index, &query, &Scoring::new(1, 10, 6));
+    assert_eq!(result.recombinations(), 1, "Invalid number of recombinations with an expensive mismatch");
+    assert!(result.mismatches.is_empty(), "Found mismatches with an expensive mismatch");
+    assert_eq!(result.switch_points().len(), 1, "Invalid number of switch points with an expensive mismatch");
+}
+
+#[test]
+fn mismatches() {
+    let index = load_index();
+    let query: Vec<usize> = vec![forward(11), forward(12), forward(99), forward(14), forward(15), forward(17)];
+    let result = check_match(&index, &query, &Scoring::default());
+    assert_eq!(result.mismatches, vec![2], "Invalid mismatches");
+
+    let query: Vec<usize> = vec![forward(99), forward(98)];
+    let result = check_match(&index, &query, &Scoring::default());
+    assert!(result.segments.is_empty(), "Found segments without matches");
+    assert_eq!(result.mismatches, vec![0, 1], "Invalid mismatches without matches");
+
+    let result = check_match(&index, &[], &Scoring::default());
+    assert_eq!(result.score, 0, "Invalid score for an empty query");
+}
+
+#[test]
+fn mixed_queries() {
+    let index = load_index();
+    let reverse = |id| support::encode_node(id, Orientation::Reverse);
+    let queries: Vec<Vec<usize>> = vec![
+        vec![forward(11), forward(13), forward(14), forward(15), forward(17), forward(21), forward(22)],
+        vec![reverse(17), reverse(16), reverse(14), reverse(12), reverse(11)],
+        vec![forward(21), forward(22), forward(24), reverse(23), reverse(21), forward(21), forward(22), forward(24), forward(25)],
+        vec![forward(11), forward(99), forward(14), forward(16), forward(98), forward(21), forward(23), forward(25)],
+    ];
+    let scorings = [
+        Scoring::default(), Scoring::new(2, 1, 3), Scoring::new(1, 10, 1),
+        Scoring::new(1, 1, 0), Scoring::new(1, 2, -1), Scoring::new(0, 0, 0),
+    ];
+    for scoring in scorings.iter() {
+        for query in queries.iter() {
+            let _ = check_match(&index, query, scoring);
+        }
+    }
+}
+
+#[test]
+fn unidirectional() {
+    let index = load_index();
+    let mut builder = GBWTBuilder::new(false);
+    for path in 0..index.sequences() / 2 {
+        let sequence: Vec<usize> = index.sequence(support::encode_path(path, Orientation::Forward)).unwrap().collect();
+        builder.insert(&sequence).unwrap();
+    }
+    let index = GBWT::try_from(builder).unwrap();
+    let query: Vec<usize> = vec![forward(11), forward(12), forward(14), forward(16), forward(17), forward(22), forward(24)];
+    for scoring in [Scoring::default(), Scoring::new(1, 10, 6)] {
+        let _ = check_match(&index, &query, &scoring);
+    }
+}
+
+#[test]
+fn gbz_walk() {
+    let filename = support::get_test_data("example.gbz");
+    let gbz: GBZ = serialize::load_from(&filename).unwrap();
+    let walk = vec![(11, Orientation::Forward), (12, Orientation::Forward), (14, Orientation::Forward)];
+    let result = gbz.haplotype_match(&walk, &Scoring::default()).unwrap();
+    assert_eq!(result.score, 3, "Invalid score for a GBZ walk");
+    assert!(gbz.haplotype_match(&[(99, Orientation::Forward)], &Scoring::default()).is_none(), "Matched a walk with an invalid node");
+}
+
+//-----------------------------------------------------------------------------
+
+// Returns the prefix ending at each position as a reversed sequence of nodes.
+fn true_prefixes(index: &GBWT) -> HashMap<Pos, Vec<usize>> {
+    let mut result: HashMap<Pos, Vec<usize>> = HashMap::new();
+    for id in 0..index.sequences() {
+        let mut prefix: Vec<usize> = Vec::new();
+        let mut pos = index.start(id);
+        while let Some(p) = pos {
+            prefix.push(p.node);
+            result.insert(p, prefix.iter().rev().copied().collect());
+            pos = index.forward(p);
+        }
+    }
+    result
+}
+
+fn check_divergence(index: &GBWT, name: &str) {
+    let divergence = DivergenceArrays::new(index);
+    let bwt: &BWT = index.as_ref();
+    assert_eq!(divergence.records(), bwt.len(), "[{}]: Invalid number of records", name);
+
+    let prefixes = true_prefixes(index);
+    for record_id in 0..bwt.len() {
+        let len = if record_id == ENDMARKER { 0 } else { bwt.record(record_id).map_or(0, |record| record.len()) };
+        for offset in 1..len {
+            let node = index.record_to_node(record_id);
+            let prev = &prefixes[&Pos::new(node, offset - 1)];
+            let curr = &prefixes[&Pos::new(node, offset)];
+            let truth = if prev == curr { None } else { Some(prev.iter().zip(curr.iter()).take_while(|(a, b)| a == b).count()) };
+            assert_eq!(divergence.match_len(record_id, offset), truth, "[{}]: Invalid match length at offset {} of record {}", name, offset, record_id);
+        }
+        assert!(divergence.match_len(record_id, 0).is_none(), "[{}]: Found a match length at offset 0 of record {}", name, record_id);
+        assert!(divergence.match_len(record_id, len).is_none(), "[{}]: Found a match length past the end of record {}", name, record_id);
+    }
+}
+
+#[test]
+fn divergence_arrays() {
+    let index = load_index();
+    check_divergence(&index, "Bidirectional");
+
+    let filename = support::get_test_data("with-empty.gbwt");
+    let index: GBWT = serialize::load_from(&filename).unwrap();
+    check_divergence(&index, "With empty");
+
+    // Repeated nodes and identical paths.
+    let paths = vec![
+        vec![forward(1), forward(2), forward(1), forward(2), forward(3)],
+        vec![forward(1), forward(2), forward(3)],
+        vec![forward(2), forward(1), forward(2), forward(1), forward(2), forward(3)],
+        vec![forward(1), forward(2), forward(3)],
+    ];
+    let mut builder = GBWTBuilder::new(false);
+    for path in paths.iter() {
+        builder.insert(path).unwrap();
+    }
+    let index = GBWT::try_from(builder).unwrap();
+    check_divergence(&index, "Cycles");
+    check_set_maximal_matches(&index, "Cycles");
+}
+
+//-----------------------------------------------------------------------------
+
+fn contains(sequence: &[usize], pattern: &[usize]) -> bool {
+    (0..sequence.len()).any(|i| sequence[i..].starts_with(pattern))
+}
+
+// Brute force set-maximal matches as (interval, partners).
+fn true_set_maximal_matches(index: &GBWT, id: usize) -> Vec<(Range<usize>, Vec<usize>)> {
+    let sequences = sequences(index);
+    let excluded: Vec<usize> = if index.is_bidirectional() { vec![id, support::flip_path(id)] } else { vec![id] };
+    let others: Vec<usize> = (0..sequences.len()).filter(|sequence| !excluded.contains(sequence)).collect();
+    let path = &sequences[id];
+
+    let mut result = Vec::new();
+    let mut covered = 0;
+    for start in 0..path.len() {
+        let mut end = start;
+        while end < path.len() && others.iter().any(|other| contains(&sequences[*other], &path[start..end + 1])) {
+            end += 1;
+        }
+        if end > start && end > covered {
+            covered = end;
+            let partners: Vec<usize> = others.iter().copied().filter(|other| contains(&sequences[*other], &path[start..end])).collect();
+            result.push((start..end, partners));
+        }
+    }
+    result
+}
+
+fn check_set_maximal_matches(index: &GBWT, name: &str) {
+    let all = index.all_set_maximal_matches().unwrap();
+    assert_eq!(all.len(), index.sequences(), "[{}]: Invalid number of sequences in all matches", name);
+    for id in 0..index.sequences() {
+        let matches = index.set_maximal_matches(id).unwrap();
+        assert_eq!(all[id], matches, "[{}]: Sequence {} has different matches in all matches", name, id);
+        let truth = true_set_maximal_matches(index, id);
+        assert_eq!(matches.len(), truth.len(), "[{}]: Invalid number of matches for sequence {}", name, id);
+        for (i, (found, (interval, partners))) in matches.iter().zip(truth.iter()).enumerate() {
+            assert_eq!(found.interval, *interval, "[{}]: Invalid interval for match {} of sequence {}", name, i, id);
+            assert_eq!(found.partners, *partners, "[{}]: Invalid partners for match {} of sequence {}", name, i, id);
+            let expected_node = index.sequence_from(id, interval.end - 1).and_then(|mut iter| iter.next());
+            assert_eq!(Some(found.state.node), expected_node, "[{}]: Invalid state for match {} of sequence {}", name, i, id);
+        }
+    }
+    assert!(index.set_maximal_matches(index.sequences()).is_none(), "[{}]: Found matches for an invalid sequence", name);
+}
+
+#[test]
+fn set_maximal_matches() {
+    let index = load_index();
+    check_set_maximal_matches(&index, "Bidirectional");
+
+    let filename = support::get_test_data("with-empty.gbwt");
+    let index: GBWT = serialize::load_from(&filename).unwrap();
+    check_set_maximal_matches(&index, "With empty");
+}
+
+#[test]
+fn set_maximal_matches_unidirectional() {
+    let index = load_index();
+    let mut builder = GBWTBuilder::new(false);
+    for path in 0..index.sequences() / 2 {
+        let sequence: Vec<usize> = index.sequence(support::encode_path(path, Orientation::Forward)).unwrap().collect();
+        builder.insert(&sequence).unwrap();
+    }
+    let index = GBWT::try_from(builder).unwrap();
+    check_set_maximal_matches(&index, "Unidirectional");
+}
+
+//-----------------------------------------------------------------------------
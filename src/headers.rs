@@ -1,5 +1,7 @@
 //! File format headers.
 
+use crate::Error;
+
 use simple_sds::serialize::Serializable;
 
 //-----------------------------------------------------------------------------
@@ -12,6 +14,7 @@ use simple_sds::serialize::Serializable;
 /// # Examples
 ///
 /// ```
+/// use gbwt::Error;
 /// use gbwt::headers::{Header, Payload};
 /// use simple_sds::serialize::Serialize;
 ///
@@ -37,7 +40,7 @@ use simple_sds::serialize::Serializable;
 ///         0x1
 ///     }
 ///
-///     fn validate(_: &Header<Self>) -> Result<(), String> {
+///     fn validate(_: &Header<Self>) -> Result<(), Error> {
 ///         Ok(())
 ///     }
 /// }
@@ -95,21 +98,21 @@ impl<T: Payload> Header<T> {
         self.flags &= !flag;
     }
 
-    /// Validates the header and returns an error message if the header is invalid.
-    pub fn validate(&self) -> Result<(), String> {
+    /// Validates the header and returns an error if the header is invalid.
+    ///
+    /// An invalid tag, an unsupported version, or invalid flags for the version result in [`Error::InvalidHeader`] with the corresponding reason.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |reason| Error::InvalidHeader { kind: T::NAME, version: self.version, reason };
         if self.tag != T::TAG {
-            return Err(format!("{}: Invalid tag {:X}", T::NAME, self.tag));
+            return Err(invalid("Invalid tag"));
+        }
+        if self.version < T::MIN_VERSION || self.version > T::VERSION {
+            return Err(invalid("Unsupported version"));
         }
-        for v in T::MIN_VERSION..T::VERSION + 1 {
-            if self.version == v {
-                if (self.flags & T::mask(v)) == self.flags {
-                    return T::validate(self);
-                } else {
-                    return Err(format!("{}: Invalid flags {:X} for version {}", T::NAME, self.flags, self.version));
-                }
-            }
+        if (self.flags & T::mask(self.version)) != self.flags {
+            return Err(invalid("Invalid flags"));
         }
-        Err(format!("{}: Invalid version {} (expected {} to {})", T::NAME, self.version, T::MIN_VERSION, T::VERSION))
+        T::validate(self)
     }
 
     /// Returns a reference to the payload.
@@ -167,8 +170,8 @@ pub trait Payload: Copy + Eq + Default {
     /// Returns the binary mask corresponding to valid flags in the specified version.
     fn mask(version: u32) -> u64;
 
-    /// Performs type-specific validation and returns an error message if the header is invalid.
-    fn validate(header: &Header<Self>) -> Result<(), String>;
+    /// Performs type-specific validation and returns an error if the header is invalid.
+    fn validate(header: &Header<Self>) -> Result<(), Error>;
 }
 
 //-----------------------------------------------------------------------------
@@ -214,9 +217,13 @@ impl Payload for GBWTPayload {
         Self::FLAG_BIDIRECTIONAL | Self::FLAG_METADATA | Self::FLAG_SIMPLE_SDS
     }
 
-    fn validate(header: &Header<Self>) -> Result<(), String> {
+    fn validate(header: &Header<Self>) -> Result<(), Error> {
+        // SDSL format is not supported.
         if !header.is_set(Self::FLAG_SIMPLE_SDS) {
-            return Err(format!("{}: SDSL format is not supported", Self::NAME));
+            return Err(Error::InvalidHeader { kind: Self::NAME, version: header.version(), reason: "SDSL format is not supported" });
+        }
+        if header.payload().offset > header.payload().alphabet_size {
+            return Err(Error::InvalidData(format!("{}: Alphabet offset exceeds alphabet size", Self::NAME)));
         }
         Ok(())
     }
@@ -262,7 +269,7 @@ impl Payload for MetadataPayload {
         Self::FLAG_PATH_NAMES | Self::FLAG_SAMPLE_NAMES | Self::FLAG_CONTIG_NAMES
     }
 
-    fn validate(_: &Header<Self>) -> Result<(), String> {
+    fn validate(_: &Header<Self>) -> Result<(), Error> {
         Ok(())
     }
 }
@@ -298,9 +305,10 @@ impl Payload for GraphPayload {
         Self::FLAG_TRANSLATION | Self::FLAG_SIMPLE_SDS
     }
 
-    fn validate(header: &Header<Self>) -> Result<(), String> {
+    fn validate(header: &Header<Self>) -> Result<(), Error> {
+        // SDSL format is not supported.
         if !header.is_set(Self::FLAG_SIMPLE_SDS) {
-            return Err(format!("{}: SDSL format is not supported", Self::NAME));
+            return Err(Error::InvalidHeader { kind: Self::NAME, version: header.version(), reason: "SDSL format is not supported" });
         }
         Ok(())
     }
@@ -327,7 +335,47 @@ impl Payload for GBZPayload {
         0
     }
 
-    fn validate(_: &Header<Self>) -> Result<(), String> {
+    fn validate(_: &Header<Self>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Payload for the reference positions header.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct ReferencePositionsPayload {
+    /// Number of reference paths.
+    pub paths: usize,
+
+    /// Number of sampled positions.
+    pub samples: usize,
+
+    /// Sample interval in nodes.
+    pub sample_interval: usize,
+
+    /// Number of (node, window) entries in the node index.
+    pub node_entries: usize,
+}
+
+impl Payload for ReferencePositionsPayload {
+    const NAME: &'static str = "ReferencePositionsHeader";
+    const TAG: u32 = 0x534F5052;
+    const VERSION: u32 = 1;
+    const MIN_VERSION: u32 = 1;
+    const DEFAULT_FLAGS: u64 = 0;
+
+    fn update(&mut self) {}
+
+    fn mask(_: u32) -> u64 {
+        0
+    }
+
+    fn validate(header: &Header<Self>) -> Result<(), Error> {
+        if header.payload().sample_interval == 0 {
+            return Err(Error::InvalidData(format!("{}: Sample interval must be positive", Self::NAME)));
+        }
         Ok(())
     }
 }
@@ -398,6 +446,17 @@ mod tests {
         }
         serialize::test(&header, "gbz-header", Some(2), true);
     }
+
+    #[test]
+    fn reference_positions_header() {
+        let mut header = Header::<ReferencePositionsPayload>::new();
+        assert!(header.validate().is_err(), "Default: Zero sample interval is valid");
+        header.payload_mut().sample_interval = 1;
+        if let Err(msg) = header.validate() {
+            panic!("{}", msg);
+        }
+        serialize::test(&header, "reference-positions-header", Some(6), true);
+    }
 }
 
 //-----------------------------------------------------------------------------
@@ -31,10 +31,18 @@
 //! * GBWT / GBZ files written by this library can be identified by `source` tag value `jltsiren/gbwt-rs`.
 
 pub mod bwt;
+pub mod cached;
+#[cfg(unix)]
+pub mod disk;
+pub mod error;
 pub mod gbwt;
 pub mod gbz;
+pub mod gfa;
 pub mod graph;
+pub mod haplotypes;
 pub mod headers;
+pub mod reference;
+pub mod subgraph;
 pub mod support;
 
 // Shared internal code for the binaries.
@@ -42,10 +50,15 @@ pub mod support;
 #[doc(hidden)]
 pub mod internal;
 
+#[cfg(test)]
+mod test_support;
+
 //-----------------------------------------------------------------------------
 
 pub use crate::bwt::Pos;
-pub use crate::gbwt::{GBWT, SearchState, BidirectionalState, Metadata, PathName};
+pub use crate::cached::CachedGBWT;
+pub use crate::error::Error;
+pub use crate::gbwt::{GBWT, SearchState, BidirectionalState, Metadata, PathName, PathSense};
 pub use crate::gbz::GBZ;
 pub use crate::graph::{Graph, Segment};
 pub use crate::support::Orientation;
@@ -64,4 +77,12 @@ pub const SOURCE_VALUE: &str = "jltsiren/gbwt-rs";
 /// Sample name for named / reference paths.
 pub const REF_SAMPLE: &str = "_gbwt_ref";
 
+/// Key of the GBWT tag listing the names of reference samples separated by [`REFERENCE_SAMPLES_SEPARATOR`].
+pub const REFERENCE_SAMPLES_KEY: &str = "reference_samples";
+
+/// Separator between sample names in the [`REFERENCE_SAMPLES_KEY`] tag.
+///
+/// This is the same separator as in the C++ implementation.
+pub const REFERENCE_SAMPLES_SEPARATOR: char = '#';
+
 //-----------------------------------------------------------------------------
@@ -0,0 +1,430 @@
+//! Reference positions: Coordinate lookups on reference paths in a GBZ graph.
+//!
+//! [`ReferencePositions`] translates between sequence offsets on selected reference paths and positions in the graph.
+//! The structure is built from a [`GBZ`] graph and it can be serialized to a separate file.
+//! Queries require the graph the structure was built from.
+
+use crate::{GBWT, GBZ, Orientation, Pos};
+use crate::headers::{Header, ReferencePositionsPayload};
+use crate::support;
+
+use simple_sds::int_vector::IntVector;
+use simple_sds::ops::{Vector, Access};
+use simple_sds::serialize::Serialize;
+use simple_sds::bits;
+
+use std::io::{Error, ErrorKind};
+use std::ops::Range;
+use std::io;
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// Sampled sequence offsets along reference paths in a [`GBZ`] graph.
+///
+/// For each selected path, the structure samples the first node, every `sample_interval`-th node, and the last node in the forward orientation.
+/// Each sample stores the GBWT position of the node and the sequence offset of the start of the node.
+/// The nodes between a sample and the next `sample_interval`-th node form the window of the sample.
+/// The structure also indexes the windows each node occurs in.
+/// Queries start from a sample and follow the path using the GBWT, which requires the same graph that was used for building the structure.
+///
+/// * [`ReferencePositions::position_to_node`] finds the node covering a sequence offset on a reference path.
+/// * [`ReferencePositions::node_to_positions`] finds the reference path offsets where a node starts.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::{GBZ, Orientation, PathSense};
+/// use gbwt::reference::ReferencePositions;
+/// use gbwt::support;
+/// use simple_sds::serialize;
+///
+/// let filename = support::get_test_data("example.gbz");
+/// let gbz: GBZ = serialize::load_from(&filename).unwrap();
+/// let paths = gbz.metadata().unwrap().paths_with_sense(PathSense::Generic);
+/// let positions = ReferencePositions::new(&gbz, &paths, 2).unwrap();
+///
+/// // Path 0 visits nodes 11, 12, 14, 15, and 17 in forward orientation.
+/// let (node, orientation, offset) = positions.position_to_node(&gbz, 0, 0).unwrap();
+/// assert_eq!((node, orientation, offset), (11, Orientation::Forward, 0));
+/// let node_len = gbz.sequence_len(11).unwrap();
+/// assert_eq!(positions.position_to_node(&gbz, 0, node_len).unwrap().0, 12);
+/// assert_eq!(positions.node_to_positions(&gbz, 12), vec![(0, node_len)]);
+///
+/// // Node 13 is not on the reference paths.
+/// assert!(positions.node_to_positions(&gbz, 13).is_empty());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReferencePositions {
+    header: Header<ReferencePositionsPayload>,
+    // Path identifiers in sorted order.
+    paths: IntVector,
+    // Sequence length of each path.
+    lengths: IntVector,
+    // Samples for path `i` are in the range `sample_starts[i]..sample_starts[i + 1]`.
+    sample_starts: IntVector,
+    // Sequence offset of each sample.
+    sample_offsets: IntVector,
+    // GBWT node of each sample.
+    sample_nodes: IntVector,
+    // Offset in the GBWT record of each sample.
+    sample_record_offsets: IntVector,
+    // Node identifiers in sorted order, with one entry for each window the node occurs in.
+    node_ids: IntVector,
+    // Sample identifier starting the window for each entry in `node_ids`.
+    node_windows: IntVector,
+}
+
+//-----------------------------------------------------------------------------
+
+/// Construction.
+impl ReferencePositions {
+    /// Default sample interval in nodes.
+    pub const SAMPLE_INTERVAL: usize = 64;
+
+    /// Builds reference positions for the given paths in the graph.
+    ///
+    /// Path identifiers refer to paths in the original graph.
+    /// Returns [`crate::Error::InvalidData`] if the sample interval is zero and [`crate::Error::PathNotFound`] if a path does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `gbz`: A GBZ graph.
+    /// * `paths`: Identifiers of the reference paths. Duplicates are ignored.
+    /// * `sample_interval`: Sample every `sample_interval`-th node on each path.
+    pub fn new(gbz: &GBZ, paths: &[usize], sample_interval: usize) -> Result<Self, crate::Error> {
+        if sample_interval == 0 {
+            return Err(crate::Error::InvalidData(String::from("ReferencePositions: Sample interval must be positive")));
+        }
+        let mut paths = paths.to_vec();
+        paths.sort_unstable();
+        paths.dedup();
+        if let Some(id) = paths.last().filter(|id| **id >= gbz.paths()) {
+            return Err(crate::Error::PathNotFound(*id));
+        }
+
+        let index: &GBWT = gbz.as_ref();
+        let mut lengths: Vec<usize> = Vec::with_capacity(paths.len());
+        let mut sample_starts: Vec<usize> = Vec::with_capacity(paths.len() + 1);
+        let mut samples: Vec<(usize, Pos)> = Vec::new();
+        let mut node_entries: Vec<(usize, usize)> = Vec::new();
+        for path_id in paths.iter() {
+            sample_starts.push(samples.len());
+            let mut offset = 0;
+            let mut nodes = 0;
+            let mut window = 0;
+            let mut pos = index.start(support::encode_path(*path_id, Orientation::Forward));
+            while let Some(p) = pos {
+                let next = index.forward(p);
+                if nodes % sample_interval == 0 {
+                    window = samples.len();
+                }
+                if nodes % sample_interval == 0 || next.is_none() {
+                    samples.push((offset, p));
+                }
+                node_entries.push((support::node_id(p.node), window));
+                offset += gbz.sequence_len(support::node_id(p.node)).unwrap_or(0);
+                nodes += 1;
+                pos = next;
+            }
+            lengths.push(offset);
+        }
+        sample_starts.push(samples.len());
+        node_entries.sort_unstable();
+        node_entries.dedup();
+
+        let mut header = Header::<ReferencePositionsPayload>::new();
+        header.payload_mut().paths = paths.len();
+        header.payload_mut().samples = samples.len();
+        header.payload_mut().sample_interval = sample_interval;
+        header.payload_mut().node_entries = node_entries.len();
+
+        let sample_offsets: Vec<usize> = samples.iter().map(|(offset, _)| *offset).collect();
+        let sample_nodes: Vec<usize> = samples.iter().map(|(_, pos)| pos.node).collect();
+        let sample_record_offsets: Vec<usize> = samples.iter().map(|(_, pos)| pos.offset).collect();
+        let node_ids: Vec<usize> = node_entries.iter().map(|(node_id, _)| *node_id).collect();
+        let node_windows: Vec<usize> = node_entries.iter().map(|(_, window)| *window).collect();
+        let result = ReferencePositions {
+            header,
+            paths: Self::int_vector(&paths),
+            lengths: Self::int_vector(&lengths),
+            sample_starts: Self::int_vector(&sample_starts),
+            sample_offsets: Self::int_vector(&sample_offsets),
+            sample_nodes: Self::int_vector(&sample_nodes),
+            sample_record_offsets: Self::int_vector(&sample_record_offsets),
+            node_ids: Self::int_vector(&node_ids),
+            node_windows: Self::int_vector(&node_windows),
+        };
+
+        Ok(result)
+    }
+
+    // Builds a minimal-width integer vector with the given values.
+    fn int_vector(values: &[usize]) -> IntVector {
+        let max_value = values.iter().copied().max().unwrap_or(0);
+        let mut result = IntVector::with_capacity(values.len(), bits::bit_len(max_value as u64)).unwrap();
+        result.extend(values.iter().copied());
+        result
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Statistics.
+impl ReferencePositions {
+    /// Returns the number of reference paths.
+    pub fn paths(&self) -> usize {
+        self.header.payload().paths
+    }
+
+    /// Returns the number of sampled positions.
+    pub fn samples(&self) -> usize {
+        self.header.payload().samples
+    }
+
+    /// Returns the sample interval in nodes.
+    pub fn sample_interval(&self) -> usize {
+        self.header.payload().sample_interval
+    }
+
+    /// Returns an iterator over the identifiers of the reference paths in sorted order.
+    pub fn path_iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.paths()).map(move |rank| self.paths.get(rank) as usize)
+    }
+
+    /// Returns `true` if the path is one of the reference paths.
+    pub fn has_path(&self, path_id: usize) -> bool {
+        self.path_rank(path_id).is_some()
+    }
+
+    /// Returns the sequence length of the reference path, or [`None`] if there is no such reference path.
+    pub fn path_len(&self, path_id: usize) -> Option<usize> {
+        let rank = self.path_rank(path_id)?;
+        Some(self.lengths.get(rank) as usize)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Queries.
+impl ReferencePositions {
+    /// Returns the node covering the given sequence offset on a reference path as (node identifier, orientation, offset in node).
+    ///
+    /// Returns [`None`] if there is no such reference path or if the offset is past the end of the path.
+    /// The graph must be the one used for building the structure.
+    pub fn position_to_node(&self, gbz: &GBZ, path_id: usize, offset: usize) -> Option<(usize, Orientation, usize)> {
+        let rank = self.path_rank(path_id)?;
+        if offset >= self.lengths.get(rank) as usize {
+            return None;
+        }
+
+        // Find the last sample at or before the offset.
+        let range = self.sample_range(rank);
+        let mut low = range.start;
+        let mut high = range.end;
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.sample_offsets.get(mid) as usize <= offset {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        let index: &GBWT = gbz.as_ref();
+        let mut pos = self.sample_pos(low);
+        let mut node_start = self.sample_offsets.get(low) as usize;
+        loop {
+            let (node_id, orientation) = support::decode_node(pos.node);
+            let node_len = gbz.sequence_len(node_id)?;
+            if offset < node_start + node_len {
+                return Some((node_id, orientation, offset - node_start));
+            }
+            node_start += node_len;
+            pos = index.forward(pos)?;
+        }
+    }
+
+    /// Returns the reference path offsets where the given node starts as (path identifier, sequence offset) pairs.
+    ///
+    /// Both orientations of the node are considered.
+    /// The pairs are sorted, and the result is empty if there is no such node or if no reference path visits it.
+    /// The graph must be the one used for building the structure.
+    ///
+    /// The query scans the windows of the reference paths the node occurs in.
+    /// Its cost depends on the number of such windows and the sample interval but not on the number of other paths visiting the node.
+    pub fn node_to_positions(&self, gbz: &GBZ, node_id: usize) -> Vec<(usize, usize)> {
+        let mut result: Vec<(usize, usize)> = Vec::new();
+        let index: &GBWT = gbz.as_ref();
+        for entry in self.node_range(node_id) {
+            let sample = self.node_windows.get(entry) as usize;
+            let path_id = self.paths.get(self.sample_rank(sample)) as usize;
+            let mut pos = Some(self.sample_pos(sample));
+            let mut offset = self.sample_offsets.get(sample) as usize;
+            for _ in 0..self.sample_interval() {
+                let p = match pos {
+                    Some(p) => p,
+                    None => break,
+                };
+                let id = support::node_id(p.node);
+                if id == node_id {
+                    result.push((path_id, offset));
+                }
+                offset += gbz.sequence_len(id).unwrap_or(0);
+                pos = index.forward(p);
+            }
+        }
+
+        result.sort_unstable();
+        result
+    }
+
+    // Returns the rank of the path among the reference paths.
+    fn path_rank(&self, path_id: usize) -> Option<usize> {
+        let mut low = 0;
+        let mut high = self.paths();
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let value = self.paths.get(mid) as usize;
+            if value == path_id {
+                return Some(mid);
+            } else if value < path_id {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        None
+    }
+
+    // Returns the rank of the path the sample belongs to.
+    fn sample_rank(&self, sample: usize) -> usize {
+        // Find the last path with `sample_starts[rank] <= sample`.
+        let mut low = 0;
+        let mut high = self.paths();
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.sample_starts.get(mid) as usize <= sample {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    // Returns the range of entries in `node_ids` for the given node.
+    fn node_range(&self, node_id: usize) -> Range<usize> {
+        let lower_bound = |value: usize| -> usize {
+            let mut low = 0;
+            let mut high = self.node_ids.len();
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if (self.node_ids.get(mid) as usize) < value {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            low
+        };
+        lower_bound(node_id)..lower_bound(node_id + 1)
+    }
+
+    // Returns the range of sample identifiers for the path with the given rank.
+    fn sample_range(&self, rank: usize) -> Range<usize> {
+        (self.sample_starts.get(rank) as usize)..(self.sample_starts.get(rank + 1) as usize)
+    }
+
+    // Returns the GBWT position of the sample.
+    fn sample_pos(&self, sample: usize) -> Pos {
+        Pos::new(self.sample_nodes.get(sample) as usize, self.sample_record_offsets.get(sample) as usize)
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+impl Serialize for ReferencePositions {
+    fn serialize_header<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.header.serialize(writer)
+    }
+
+    fn serialize_body<T: io::Write>(&self, writer: &mut T) -> io::Result<()> {
+        self.paths.serialize(writer)?;
+        self.lengths.serialize(writer)?;
+        self.sample_starts.serialize(writer)?;
+        self.sample_offsets.serialize(writer)?;
+        self.sample_nodes.serialize(writer)?;
+        self.sample_record_offsets.serialize(writer)?;
+        self.node_ids.serialize(writer)?;
+        self.node_windows.serialize(writer)?;
+        Ok(())
+    }
+
+    fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
+        let header = Header::<ReferencePositionsPayload>::load(reader)?;
+        header.validate()?;
+
+        let paths = IntVector::load(reader)?;
+        let lengths = IntVector::load(reader)?;
+        if paths.len() != header.payload().paths || lengths.len() != header.payload().paths {
+            return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Path count mismatch"));
+        }
+        for rank in 1..paths.len() {
+            if paths.get(rank - 1) >= paths.get(rank) {
+                return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Path identifiers are not sorted"));
+            }
+        }
+
+        let sample_starts = IntVector::load(reader)?;
+        if sample_starts.len() != header.payload().paths + 1 {
+            return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Invalid number of sample ranges"));
+        }
+        for rank in 0..paths.len() {
+            if sample_starts.get(rank) > sample_starts.get(rank + 1) {
+                return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Sample ranges are not sorted"));
+            }
+        }
+        let samples = header.payload().samples;
+        if sample_starts.get(0) != 0 || sample_starts.get(paths.len()) as usize != samples {
+            return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Sample ranges do not match the number of samples"));
+        }
+
+        let sample_offsets = IntVector::load(reader)?;
+        let sample_nodes = IntVector::load(reader)?;
+        let sample_record_offsets = IntVector::load(reader)?;
+        if sample_offsets.len() != samples || sample_nodes.len() != samples || sample_record_offsets.len() != samples {
+            return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Sample count mismatch"));
+        }
+
+        let node_ids = IntVector::load(reader)?;
+        let node_windows = IntVector::load(reader)?;
+        let entries = header.payload().node_entries;
+        if node_ids.len() != entries || node_windows.len() != entries {
+            return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Node entry count mismatch"));
+        }
+        for entry in 1..entries {
+            if node_ids.get(entry - 1) > node_ids.get(entry) {
+                return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Node identifiers are not sorted"));
+            }
+        }
+        if (0..entries).any(|entry| node_windows.get(entry) as usize >= samples) {
+            return Err(Error::new(ErrorKind::InvalidData, "ReferencePositions: Invalid window sample"));
+        }
+
+        Ok(ReferencePositions {
+            header, paths, lengths, sample_starts, sample_offsets, sample_nodes, sample_record_offsets, node_ids, node_windows,
+        })
+    }
+
+    fn size_in_elements(&self) -> usize {
+        self.header.size_in_elements() +
+            self.paths.size_in_elements() + self.lengths.size_in_elements() + self.sample_starts.size_in_elements() +
+            self.sample_offsets.size_in_elements() + self.sample_nodes.size_in_elements() + self.sample_record_offsets.size_in_elements() +
+            self.node_ids.size_in_elements() + self.node_windows.size_in_elements()
+    }
+}
+
+//-----------------------------------------------------------------------------
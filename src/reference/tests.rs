@@ -0,0 +1,86 @@
+use super::*;
+
+use crate::PathSense;
+use crate::test_support::{load_gbz, path_nodes};
+
+use simple_sds::serialize;
+
+//-----------------------------------------------------------------------------
+
+fn check_positions(gbz: &GBZ, paths: &[usize], sample_interval: usize, name: &str) {
+    let positions = ReferencePositions::new(gbz, paths, sample_interval).unwrap();
+    assert_eq!(positions.paths(), paths.len(), "[{}]: Invalid number of paths", name);
+    assert_eq!(positions.sample_interval(), sample_interval, "[{}]: Invalid sample interval", name);
+    let path_ids: Vec<usize> = positions.path_iter().collect();
+    assert_eq!(path_ids, paths, "[{}]: Invalid path identifiers", name);
+
+    // Offsets to nodes.
+    let mut truth: Vec<(usize, usize, usize)> = Vec::new();
+    for path_id in paths.iter() {
+        let nodes = path_nodes(gbz, *path_id);
+        let mut path_len = 0;
+        for (node_id, orientation, start) in nodes.iter() {
+            let node_len = gbz.sequence_len(*node_id).unwrap();
+            for offset in *start..*start + node_len {
+                assert_eq!(positions.position_to_node(gbz, *path_id, offset), Some((*node_id, *orientation, offset - start)), "[{}]: Invalid node for path {} offset {}", name, path_id, offset);
+            }
+            truth.push((*node_id, *path_id, *start));
+            path_len = start + node_len;
+        }
+        assert!(positions.has_path(*path_id), "[{}]: Path {} not found", name, path_id);
+        assert_eq!(positions.path_len(*path_id), Some(path_len), "[{}]: Invalid length for path {}", name, path_id);
+        assert_eq!(positions.position_to_node(gbz, *path_id, path_len), None, "[{}]: Found a node past the end of path {}", name, path_id);
+    }
+
+    // Nodes to offsets.
+    assert!(positions.node_ids.len() <= truth.len(), "[{}]: Too many node entries", name);
+    for node_id in gbz.node_iter() {
+        let expected: Vec<(usize, usize)> = truth.iter().filter(|(id, _, _)| *id == node_id).map(|(_, path, offset)| (*path, *offset)).collect();
+        assert_eq!(positions.node_to_positions(gbz, node_id), expected, "[{}]: Invalid positions for node {}", name, node_id);
+    }
+    assert!(positions.node_to_positions(gbz, gbz.max_node() + 1).is_empty(), "[{}]: Found positions for a nonexistent node", name);
+
+    // Other paths.
+    for path_id in 0..gbz.paths() + 1 {
+        if !paths.contains(&path_id) {
+            assert!(!positions.has_path(path_id), "[{}]: Found non-reference path {}", name, path_id);
+            assert_eq!(positions.position_to_node(gbz, path_id, 0), None, "[{}]: Found a node on non-reference path {}", name, path_id);
+        }
+    }
+
+    serialize::test(&positions, name, None, true);
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn reference_paths() {
+    let gbz = load_gbz("example.gbz");
+    let paths = gbz.metadata().unwrap().paths_with_sense(PathSense::Generic);
+    for sample_interval in [1, 2, ReferencePositions::SAMPLE_INTERVAL] {
+        check_positions(&gbz, &paths, sample_interval, &format!("reference-positions-{}", sample_interval));
+    }
+}
+
+#[test]
+fn all_paths() {
+    let gbz = load_gbz("example.gbz");
+    let paths: Vec<usize> = (0..gbz.paths()).collect();
+    check_positions(&gbz, &paths, 3, "all-paths");
+
+    let gbz = load_gbz("translation.gbz");
+    let paths: Vec<usize> = (0..gbz.paths()).collect();
+    check_positions(&gbz, &paths, 2, "translation");
+}
+
+#[test]
+fn invalid_construction() {
+    let gbz = load_gbz("example.gbz");
+    assert!(ReferencePositions::new(&gbz, &[0], 0).is_err(), "Built reference positions with a zero sample interval");
+    assert!(ReferencePositions::new(&gbz, &[gbz.paths()], 1).is_err(), "Built reference positions for a nonexistent path");
+
+    let positions = ReferencePositions::new(&gbz, &[1, 0, 1], 1).unwrap();
+    assert_eq!(positions.paths(), 2, "Duplicate paths were not removed");
+}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,377 @@
+//! Subgraphs: Self-contained parts of a GBZ graph around a reference interval.
+//!
+//! [`GBZ::subgraph`] extracts the nodes within a context distance of an interval on a reference path, the edges between them, and the fragments of the haplotypes passing through them.
+//! The resulting [`Subgraph`] does not depend on the original graph, and it can be written in the GFA format using [`Subgraph::write_gfa`].
+//!
+//! # Examples
+//!
+//! ```
+//! use gbwt::GBZ;
+//! use gbwt::support;
+//! use simple_sds::serialize;
+//!
+//! let filename = support::get_test_data("example.gbz");
+//! let gbz: GBZ = serialize::load_from(&filename).unwrap();
+//!
+//! // Path 0 visits nodes 11, 12, 14, 15, and 17.
+//! let start = gbz.sequence_len(11).unwrap() + gbz.sequence_len(12).unwrap();
+//! let end = start + gbz.sequence_len(14).unwrap();
+//! let subgraph = gbz.subgraph(0, start..end, 1).unwrap();
+//!
+//! // Node 14 and its neighbors.
+//! assert_eq!(subgraph.node_iter().collect::<Vec<usize>>(), vec![12, 13, 14, 15, 16]);
+//! let expected = start - gbz.sequence_len(12).unwrap()..end + gbz.sequence_len(15).unwrap();
+//! assert_eq!(subgraph.reference().interval, Some(expected));
+//!
+//! let mut gfa: Vec<u8> = Vec::new();
+//! subgraph.write_gfa(&mut gfa).unwrap();
+//! ```
+
+use crate::{Error, GBWT, GBZ, Orientation, PathSense, Pos, ENDMARKER};
+use crate::gfa;
+use crate::support;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::ops::Range;
+use std::io;
+
+#[cfg(test)]
+mod tests;
+
+//-----------------------------------------------------------------------------
+
+/// A fragment of a path in a [`Subgraph`].
+///
+/// The fragment is a maximal part of the path in forward orientation that stays within the subgraph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubgraphPath {
+    /// Path identifier in the original graph.
+    pub path_id: usize,
+    /// Sample name.
+    pub sample: String,
+    /// Phase / haplotype identifier.
+    pub phase: usize,
+    /// Contig name.
+    pub contig: String,
+    /// Sense of the path.
+    pub sense: PathSense,
+    /// Sequence offsets covered by the fragment, if known.
+    ///
+    /// The offsets are relative to the start of the contig, with the starting offset of the path taken into account.
+    pub interval: Option<Range<usize>>,
+    /// Oriented nodes visited by the fragment.
+    pub path: Vec<(usize, Orientation)>,
+}
+
+/// A self-contained subgraph extracted from a [`GBZ`] graph.
+///
+/// The subgraph contains the selected nodes, the edges between them, a fragment of the reference path, and the fragments of the other paths passing through the nodes.
+/// Node identifiers are the same as in the original graph.
+/// The sequence offsets of the haplotype fragments are not known, as determining them would require following each path from its start.
+///
+/// See [`GBZ::subgraph`] for an example.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subgraph {
+    nodes: BTreeMap<usize, Vec<u8>>,
+    edges: BTreeSet<((usize, Orientation), (usize, Orientation))>,
+    reference: SubgraphPath,
+    haplotypes: Vec<SubgraphPath>,
+}
+
+//-----------------------------------------------------------------------------
+
+/// Subgraph extraction.
+impl GBZ {
+    /// Extracts the subgraph around an interval of the given reference path.
+    ///
+    /// The subgraph contains all nodes overlapping the interval and all nodes within `context` bp of them in either direction.
+    /// The distance to a node is the total length of the nodes between it and the nodes overlapping the interval.
+    /// A node is in the subgraph if the distance is less than `context`, which means that `context == 0` selects only the nodes overlapping the interval.
+    ///
+    /// The reference fragment is the maximal part of the reference path that contains the interval and stays within the subgraph.
+    /// The haplotype fragments are found by determining where the paths enter the subgraph using [`GBZ::search_state`] and [`GBZ::follow_backward`], and then following the paths until they leave the subgraph.
+    /// The reference fragment is not listed as a haplotype, but other fragments of the reference path are.
+    ///
+    /// Returns [`Error::PathNotFound`] if there is no such path.
+    /// Returns [`Error::InvalidData`] if there is no metadata with path names, if the GBWT index does not contain document array samples, or if the interval is empty or out of bounds.
+    ///
+    /// # Arguments
+    ///
+    /// * `path_id`: Path identifier in the original graph.
+    /// * `interval`: Sequence offsets on the path in forward orientation.
+    /// * `context`: Context length in bp.
+    pub fn subgraph(&self, path_id: usize, interval: Range<usize>, context: usize) -> Result<Subgraph, Error> {
+        let metadata = self.metadata().ok_or_else(|| Error::InvalidData(String::from("GBZ: Subgraph extraction requires metadata")))?;
+        if !metadata.has_path_names() {
+            return Err(Error::InvalidData(String::from("GBZ: Subgraph extraction requires path names")));
+        }
+        let index: &GBWT = self.as_ref();
+        if !index.has_da_samples() {
+            return Err(Error::InvalidData(String::from("GBZ: Subgraph extraction requires document array samples")));
+        }
+        if interval.is_empty() {
+            return Err(Error::InvalidData(String::from("GBZ: Empty reference interval")));
+        }
+
+        // Reference path as (node, orientation, starting offset).
+        let mut reference: Vec<(usize, Orientation, usize)> = Vec::new();
+        let mut path_len = 0;
+        for (node_id, orientation) in self.path(path_id, Orientation::Forward).ok_or(Error::PathNotFound(path_id))? {
+            reference.push((node_id, orientation, path_len));
+            path_len += self.sequence_len(node_id).ok_or(Error::NodeNotFound(node_id))?;
+        }
+        if interval.end > path_len {
+            return Err(Error::InvalidData(String::from("GBZ: Reference interval is out of bounds")));
+        }
+        let first = reference.partition_point(|(_, _, offset)| *offset <= interval.start) - 1;
+        let last = reference.partition_point(|(_, _, offset)| *offset < interval.end) - 1;
+
+        // Distance-limited traversal from the nodes overlapping the interval.
+        let mut distances: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut active: BTreeSet<(usize, usize)> = BTreeSet::new();
+        for (node_id, _, _) in reference[first..=last].iter() {
+            if !distances.contains_key(node_id) {
+                distances.insert(*node_id, 0);
+                active.insert((0, *node_id));
+            }
+        }
+        let start_nodes: BTreeSet<usize> = distances.keys().copied().collect();
+        while let Some((distance, node_id)) = active.pop_first() {
+            let next_distance = if start_nodes.contains(&node_id) { 0 } else { distance + self.sequence_len(node_id).ok_or(Error::NodeNotFound(node_id))? };
+            if next_distance >= context {
+                continue;
+            }
+            let successors = self.successors(node_id, Orientation::Forward).ok_or(Error::NodeNotFound(node_id))?;
+            let predecessors = self.predecessors(node_id, Orientation::Forward).ok_or(Error::NodeNotFound(node_id))?;
+            for (next_id, _) in successors.chain(predecessors) {
+                let update = match distances.get(&next_id) {
+                    Some(old) => next_distance < *old,
+                    None => true,
+                };
+                if update {
+                    if let Some(old) = distances.insert(next_id, next_distance) {
+                        active.remove(&(old, next_id));
+                    }
+                    active.insert((next_distance, next_id));
+                }
+            }
+        }
+
+        let mut nodes: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for node_id in distances.keys() {
+            let sequence = self.sequence(*node_id).ok_or(Error::NodeNotFound(*node_id))?;
+            nodes.insert(*node_id, sequence.to_vec());
+        }
+
+        // Edges between the nodes in canonical orientation.
+        let mut edges: BTreeSet<((usize, Orientation), (usize, Orientation))> = BTreeSet::new();
+        for node_id in nodes.keys() {
+            for (next_id, next_o) in self.successors(*node_id, Orientation::Forward).ok_or(Error::NodeNotFound(*node_id))? {
+                if nodes.contains_key(&next_id) && next_id >= *node_id {
+                    edges.insert(((*node_id, Orientation::Forward), (next_id, next_o)));
+                }
+            }
+            for (next_id, next_o) in self.successors(*node_id, Orientation::Reverse).ok_or(Error::NodeNotFound(*node_id))? {
+                if nodes.contains_key(&next_id) && (next_id > *node_id || (next_id == *node_id && next_o == Orientation::Forward)) {
+                    edges.insert(((*node_id, Orientation::Reverse), (next_id, next_o)));
+                }
+            }
+        }
+
+        // Extend the reference fragment as long as it stays within the subgraph.
+        let (mut first, mut last) = (first, last);
+        while first > 0 && nodes.contains_key(&reference[first - 1].0) {
+            first -= 1;
+        }
+        while last + 1 < reference.len() && nodes.contains_key(&reference[last + 1].0) {
+            last += 1;
+        }
+        let (last_id, _, last_offset) = reference[last];
+        let last_len = self.sequence_len(last_id).ok_or(Error::NodeNotFound(last_id))?;
+        let reference_path = self.subgraph_path(
+            path_id,
+            Some(reference[first].2..last_offset + last_len),
+            reference[first..=last].iter().map(|(node_id, orientation, _)| (*node_id, *orientation)).collect()
+        )?;
+        // The reference fragment enters the subgraph at this position.
+        let reference_start = index.position_at(support::encode_path(path_id, Orientation::Forward), first);
+
+        // Haplotype fragments.
+        let mut haplotypes: Vec<SubgraphPath> = Vec::new();
+        for node_id in nodes.keys() {
+            for orientation in [Orientation::Forward, Orientation::Reverse] {
+                for pos in self.fragment_starts(&nodes, *node_id, orientation) {
+                    let sequence_id = index.locate_pos(pos).ok_or_else(|| Error::InvalidData(String::from("GBZ: Could not locate a path in the subgraph")))?;
+                    let (fragment_path_id, fragment_orientation) = support::decode_path(sequence_id);
+                    if fragment_orientation != Orientation::Forward || Some(pos) == reference_start {
+                        continue;
+                    }
+                    let mut path: Vec<(usize, Orientation)> = Vec::new();
+                    let mut curr = Some(pos);
+                    while let Some(p) = curr {
+                        if p.node == ENDMARKER || !nodes.contains_key(&support::node_id(p.node)) {
+                            break;
+                        }
+                        path.push(support::decode_node(p.node));
+                        curr = index.forward(p);
+                    }
+                    haplotypes.push(self.subgraph_path(fragment_path_id, None, path)?);
+                }
+            }
+        }
+        haplotypes.sort_unstable_by(|a, b| (a.path_id, &a.path).cmp(&(b.path_id, &b.path)));
+
+        Ok(Subgraph {
+            nodes, edges,
+            reference: reference_path,
+            haplotypes,
+        })
+    }
+
+    // Returns the positions where the paths enter the subgraph at the given node.
+    fn fragment_starts(&self, nodes: &BTreeMap<usize, Vec<u8>>, node_id: usize, orientation: Orientation) -> Vec<Pos> {
+        let state = match self.search_state(node_id, orientation) {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+
+        // Occurrences with a predecessor in the subgraph form subranges of the range.
+        let mut internal: Vec<Range<usize>> = Vec::new();
+        let predecessors = match self.follow_backward(&state) {
+            Some(iter) => iter,
+            None => return Vec::new(),
+        };
+        for pred in predecessors {
+            let (pred_id, _) = pred.from();
+            if nodes.contains_key(&pred_id) {
+                internal.push(pred.forward.range.clone());
+            }
+        }
+        internal.sort_unstable_by_key(|range| range.start);
+
+        let mut result: Vec<Pos> = Vec::new();
+        let mut offset = state.forward.range.start;
+        for range in internal.iter() {
+            result.extend((offset..range.start).map(|i| Pos::new(state.forward.node, i)));
+            offset = range.end;
+        }
+        result.extend((offset..state.forward.range.end).map(|i| Pos::new(state.forward.node, i)));
+        result
+    }
+
+    // Builds a subgraph path using the metadata.
+    fn subgraph_path(&self, path_id: usize, interval: Option<Range<usize>>, path: Vec<(usize, Orientation)>) -> Result<SubgraphPath, Error> {
+        let metadata = self.metadata().ok_or_else(|| Error::InvalidData(String::from("GBZ: Subgraph extraction requires metadata")))?;
+        let path_name = metadata.path(path_id).ok_or(Error::PathNotFound(path_id))?;
+        let sense = metadata.path_sense(path_id).ok_or(Error::PathNotFound(path_id))?;
+        let interval = interval.map(|range| range.start + path_name.fragment()..range.end + path_name.fragment());
+        Ok(SubgraphPath {
+            path_id,
+            sample: metadata.sample_name(path_name.sample()),
+            phase: path_name.phase(),
+            contig: metadata.contig_name(path_name.contig()),
+            sense,
+            interval,
+            path,
+        })
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// Nodes and edges.
+impl Subgraph {
+    /// Returns the number of nodes in the subgraph.
+    #[inline]
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the subgraph contains a node with identifier `node_id`.
+    #[inline]
+    pub fn has_node(&self, node_id: usize) -> bool {
+        self.nodes.contains_key(&node_id)
+    }
+
+    /// Returns the sequence for the node, or [`None`] if there is no such node.
+    pub fn sequence(&self, node_id: usize) -> Option<&[u8]> {
+        self.nodes.get(&node_id).map(|sequence| sequence.as_slice())
+    }
+
+    /// Returns an iterator over the node identifiers in sorted order.
+    pub fn node_iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.keys().copied()
+    }
+
+    /// Returns the number of edges in the subgraph.
+    #[inline]
+    pub fn edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Returns an iterator over the edges in canonical orientation.
+    ///
+    /// An edge from the forward orientation of a node is canonical if the other node has the same or a greater identifier.
+    /// An edge from the reverse orientation is canonical if the other node has a greater identifier or if the edge is a self-loop to the forward orientation.
+    pub fn edge_iter(&self) -> impl Iterator<Item = ((usize, Orientation), (usize, Orientation))> + '_ {
+        self.edges.iter().copied()
+    }
+}
+
+/// Paths.
+impl Subgraph {
+    /// Returns the reference fragment.
+    #[inline]
+    pub fn reference(&self) -> &SubgraphPath {
+        &self.reference
+    }
+
+    /// Returns the haplotype fragments sorted by path identifier and node sequence.
+    #[inline]
+    pub fn haplotypes(&self) -> &[SubgraphPath] {
+        &self.haplotypes
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// GFA output.
+impl Subgraph {
+    /// Writes the subgraph in the GFA format.
+    ///
+    /// Nodes are written as S-lines and edges as L-lines, using the same line formats as [`GfaWriter`](crate::gfa::GfaWriter).
+    /// Fragments of generic paths are written as P-lines named after the contig, with the sequence offsets as `contig[start-end]` when they are known.
+    /// Other fragments are written as W-lines, with `*` as the sequence offsets when they are not known.
+    pub fn write_gfa<T: Write>(&self, output: &mut T) -> io::Result<()> {
+        gfa::write_header(output)?;
+        for (node_id, sequence) in self.nodes.iter() {
+            gfa::write_segment(node_id.to_string().as_bytes(), sequence, output)?;
+        }
+        for ((from, from_o), (to, to_o)) in self.edges.iter() {
+            gfa::write_link((from.to_string().as_bytes(), *from_o), (to.to_string().as_bytes(), *to_o), output)?;
+        }
+
+        write_path(&self.reference, output)?;
+        for haplotype in self.haplotypes.iter() {
+            write_path(haplotype, output)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_path<T: Write>(path: &SubgraphPath, output: &mut T) -> io::Result<()> {
+    let steps = path.path.iter().map(|(node_id, orientation)| (node_id.to_string(), *orientation));
+    if path.sense == PathSense::Generic {
+        let name = match &path.interval {
+            Some(interval) => format!("{}[{}-{}]", path.contig, interval.start, interval.end),
+            None => path.contig.clone(),
+        };
+        gfa::write_p_line(name.as_bytes(), steps, output)
+    } else {
+        gfa::write_w_line(&path.sample, path.phase, &path.contig, path.interval.clone(), steps, output)
+    }
+}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,200 @@
+use super::*;
+
+use crate::REF_SAMPLE;
+use crate::test_support::{load_gbz, path_nodes};
+
+use std::collections::HashMap;
+
+//-----------------------------------------------------------------------------
+
+// Returns the nodes in the subgraph by relaxing the distances until they no longer change.
+fn true_nodes(gbz: &GBZ, path_id: usize, interval: &Range<usize>, context: usize) -> BTreeSet<usize> {
+    let mut distances: HashMap<usize, usize> = HashMap::new();
+    for (node_id, _, offset) in path_nodes(gbz, path_id) {
+        let end = offset + gbz.sequence_len(node_id).unwrap();
+        if offset < interval.end && end > interval.start {
+            distances.insert(node_id, 0);
+        }
+    }
+    let starts: BTreeSet<usize> = distances.keys().copied().collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let current: Vec<(usize, usize)> = distances.iter().map(|(id, d)| (*id, *d)).collect();
+        for (node_id, distance) in current {
+            let next = if starts.contains(&node_id) { 0 } else { distance + gbz.sequence_len(node_id).unwrap() };
+            if next >= context {
+                continue;
+            }
+            let mut neighbors: Vec<usize> = Vec::new();
+            for orientation in [Orientation::Forward, Orientation::Reverse] {
+                neighbors.extend(gbz.successors(node_id, orientation).unwrap().map(|(id, _)| id));
+            }
+            for neighbor in neighbors {
+                if distances.get(&neighbor).map(|d| next < *d).unwrap_or(true) {
+                    distances.insert(neighbor, next);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    distances.keys().copied().collect()
+}
+
+// Returns (path id, path) for the maximal fragments of the paths within the nodes, excluding the reference fragment starting at node `reference_first` of the path.
+fn true_fragments(gbz: &GBZ, path_id: usize, reference_first: usize, nodes: &BTreeSet<usize>) -> Vec<(usize, Vec<(usize, Orientation)>)> {
+    let mut result: Vec<(usize, Vec<(usize, Orientation)>)> = Vec::new();
+    for other in 0..gbz.paths() {
+        let path: Vec<(usize, Orientation)> = gbz.path(other, Orientation::Forward).unwrap().collect();
+        let mut start = 0;
+        while start < path.len() {
+            if !nodes.contains(&path[start].0) {
+                start += 1;
+                continue;
+            }
+            let mut end = start;
+            while end < path.len() && nodes.contains(&path[end].0) {
+                end += 1;
+            }
+            if other != path_id || start != reference_first {
+                result.push((other, path[start..end].to_vec()));
+            }
+            start = end;
+        }
+    }
+    result.sort_unstable();
+    result
+}
+
+fn check_subgraph(gbz: &GBZ, path_id: usize, interval: Range<usize>, context: usize) {
+    let name = format!("path {}, interval {:?}, context {}", path_id, interval, context);
+    let subgraph = gbz.subgraph(path_id, interval.clone(), context).unwrap();
+
+    // Nodes.
+    let nodes = true_nodes(gbz, path_id, &interval, context);
+    assert_eq!(subgraph.nodes(), nodes.len(), "[{}]: Invalid number of nodes", name);
+    assert_eq!(subgraph.node_iter().collect::<BTreeSet<usize>>(), nodes, "[{}]: Invalid nodes", name);
+    for node_id in nodes.iter() {
+        assert!(subgraph.has_node(*node_id), "[{}]: Node {} not found", name, node_id);
+        assert_eq!(subgraph.sequence(*node_id), gbz.sequence(*node_id), "[{}]: Invalid sequence for node {}", name, node_id);
+    }
+
+    // Edges.
+    for ((from, from_o), (to, to_o)) in subgraph.edge_iter() {
+        assert!(nodes.contains(&from) && nodes.contains(&to), "[{}]: Edge ({}, {}) outside the subgraph", name, from, to);
+        assert!(gbz.successors(from, from_o).unwrap().any(|x| x == (to, to_o)), "[{}]: Edge ({}, {}) not in the graph", name, from, to);
+    }
+    let mut edge_count = 0;
+    for node_id in nodes.iter() {
+        for orientation in [Orientation::Forward, Orientation::Reverse] {
+            edge_count += gbz.successors(*node_id, orientation).unwrap().filter(|(id, _)| nodes.contains(id)).count();
+        }
+    }
+    let self_loops = subgraph.edge_iter().filter(|((from, from_o), (to, to_o))| from == to && from_o != to_o).count();
+    assert_eq!(2 * subgraph.edges() - self_loops, edge_count, "[{}]: Invalid number of edges", name);
+
+    // Reference fragment.
+    let reference = subgraph.reference();
+    assert_eq!(reference.path_id, path_id, "[{}]: Invalid reference path", name);
+    let interval_range = reference.interval.clone().unwrap();
+    assert!(interval_range.start <= interval.start && interval_range.end >= interval.end, "[{}]: Reference fragment does not cover the interval", name);
+    let ref_nodes = path_nodes(gbz, path_id);
+    let first = ref_nodes.iter().position(|(_, _, offset)| *offset == interval_range.start).unwrap();
+    let expected: Vec<(usize, Orientation)> = ref_nodes[first..first + reference.path.len()].iter().map(|(id, o, _)| (*id, *o)).collect();
+    assert_eq!(reference.path, expected, "[{}]: Invalid reference fragment", name);
+    assert!(reference.path.iter().all(|(id, _)| nodes.contains(id)), "[{}]: Reference fragment leaves the subgraph", name);
+
+    // Haplotype fragments.
+    let fragments: Vec<(usize, Vec<(usize, Orientation)>)> = subgraph.haplotypes().iter().map(|h| (h.path_id, h.path.clone())).collect();
+    assert_eq!(fragments, true_fragments(gbz, path_id, first, &nodes), "[{}]: Invalid haplotype fragments", name);
+    for haplotype in subgraph.haplotypes() {
+        assert!(haplotype.interval.is_none(), "[{}]: Haplotype fragment has an interval", name);
+    }
+
+    // GFA output.
+    let mut gfa: Vec<u8> = Vec::new();
+    subgraph.write_gfa(&mut gfa).unwrap();
+    let gfa = String::from_utf8(gfa).unwrap();
+    let count = |prefix: char| gfa.lines().filter(|line| line.starts_with(prefix)).count();
+    assert_eq!(count('S'), subgraph.nodes(), "[{}]: Invalid number of S-lines", name);
+    assert_eq!(count('L'), subgraph.edges(), "[{}]: Invalid number of L-lines", name);
+    let generic = subgraph.haplotypes().iter().chain(std::iter::once(reference)).filter(|path| path.sense == PathSense::Generic).count();
+    assert_eq!(count('P'), generic, "[{}]: Invalid number of P-lines", name);
+    assert_eq!(count('W'), 1 + subgraph.haplotypes().len() - generic, "[{}]: Invalid number of W-lines", name);
+    assert!(!gfa.lines().any(|line| line.starts_with('W') && line.contains(REF_SAMPLE)), "[{}]: Generic path written as a W-line", name);
+}
+
+//-----------------------------------------------------------------------------
+
+#[test]
+fn subgraphs() {
+    let gbz = load_gbz("example.gbz");
+    for path_id in 0..gbz.paths() {
+        let nodes = path_nodes(&gbz, path_id);
+        let (last_id, _, last_offset) = nodes.last().unwrap();
+        let path_len = last_offset + gbz.sequence_len(*last_id).unwrap();
+        for start in 0..path_len {
+            for context in [0, 1, 2, 5, 100] {
+                check_subgraph(&gbz, path_id, start..start + 1, context);
+                check_subgraph(&gbz, path_id, start..path_len, context);
+            }
+        }
+    }
+}
+
+#[test]
+fn reference_interval() {
+    let gbz = load_gbz("example.gbz");
+    let nodes = path_nodes(&gbz, 0);
+    let (node_id, _, offset) = nodes[2];
+    let subgraph = gbz.subgraph(0, offset..offset + 1, 0).unwrap();
+    assert_eq!(subgraph.node_iter().collect::<Vec<usize>>(), vec![node_id], "Invalid nodes with no context");
+    assert_eq!(subgraph.reference().interval, Some(offset..offset + gbz.sequence_len(node_id).unwrap()), "Invalid reference interval");
+    assert_eq!(subgraph.reference().sense, PathSense::Generic, "Invalid reference path sense");
+
+    let mut gfa: Vec<u8> = Vec::new();
+    subgraph.write_gfa(&mut gfa).unwrap();
+    let gfa = String::from_utf8(gfa).unwrap();
+    let p_line = format!("P\tA[{}-{}]\t{}+\t*", offset, offset + gbz.sequence_len(node_id).unwrap(), node_id);
+    assert!(gfa.lines().any(|line| line == p_line), "Reference path not found in the GFA output");
+}
+
+#[test]
+fn reference_revisits() {
+    // The reference path visits the cycle twice.
+    let gfa = "S\t1\tA\nS\t2\tC\nS\t3\tG\nS\t4\tT\n\
+        L\t1\t+\t2\t+\t0M\nL\t2\t+\t3\t+\t0M\nL\t3\t+\t1\t+\t0M\nL\t3\t+\t4\t+\t0M\n\
+        P\tref\t1+,2+,3+,1+,2+,3+,4+\t*\n";
+    let gbz = crate::gfa::read_gfa(gfa.as_bytes(), 1024).unwrap();
+    let path_len = 7;
+    for start in 0..path_len {
+        for context in [0, 1, 2, 5] {
+            check_subgraph(&gbz, 0, start..start + 1, context);
+        }
+    }
+
+    // The other visit to the node is a haplotype fragment.
+    let subgraph = gbz.subgraph(0, 0..1, 0).unwrap();
+    assert_eq!(subgraph.reference().path, vec![(1, Orientation::Forward)], "Invalid reference fragment");
+    assert_eq!(subgraph.haplotypes().len(), 1, "Invalid number of haplotype fragments");
+    assert_eq!(subgraph.haplotypes()[0].path, vec![(1, Orientation::Forward)], "Invalid haplotype fragment");
+}
+
+#[test]
+fn invalid_subgraphs() {
+    let gbz = load_gbz("example.gbz");
+    let nodes = path_nodes(&gbz, 0);
+    let (last_id, _, last_offset) = nodes.last().unwrap();
+    let path_len = last_offset + gbz.sequence_len(*last_id).unwrap();
+    assert!(matches!(gbz.subgraph(gbz.paths(), 0..1, 10), Err(Error::PathNotFound(id)) if id == gbz.paths()), "Extracted a subgraph for a nonexistent path");
+    assert!(gbz.subgraph(0, 1..1, 10).is_err(), "Extracted a subgraph for an empty interval");
+    assert!(gbz.subgraph(0, 0..path_len + 1, 10).is_err(), "Extracted a subgraph for an interval past the end");
+
+    let mut gbz = gbz;
+    gbz.remove_metadata();
+    assert!(gbz.subgraph(0, 0..1, 10).is_err(), "Extracted a subgraph without metadata");
+}
+
+//-----------------------------------------------------------------------------
@@ -1,22 +1,25 @@
 //! Support structures for GBWT and GBZ.
 
-use simple_sds::int_vector::IntVector;
+use simple_sds::int_vector::{IntVector, IntVectorMapper};
 use simple_sds::ops::{Vector, Access, Push, BitVec, Select};
-use simple_sds::serialize::Serialize;
+use simple_sds::serialize::{MappingMode, MemoryMap, MemoryMapped, Serialize};
 use simple_sds::sparse_vector::SparseVector;
 use simple_sds::bits;
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
 use std::collections::btree_map::Iter as TagIter;
 use std::collections::hash_map::Entry;
+use std::hash::Hash;
 use std::convert::TryFrom;
 use std::io::{Error, ErrorKind};
 use std::iter::FusedIterator;
-use std::ops::Range;
-use std::path::PathBuf;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
 use std::str::Utf8Error;
-use std::{cmp, io, mem};
+use std::sync::{Arc, OnceLock};
+use std::{cmp, fmt, io, mem};
 
 #[cfg(test)]
 mod tests;
@@ -221,6 +224,7 @@ pub fn get_test_data(filename: &'static str) -> PathBuf {
 /// The strings are concatenated and stored in a single byte vector.
 /// This reduces the space overhead for the strings and the time overhead for serializing and loading them.
 /// The serialization format further compresses the starting positions and compacts the alphabet in an attempt to use fewer than 8 bits per byte.
+/// When loaded with [`StringArray::load_mapped`], the packed strings are borrowed from a [`SharedMap`] and decompressed into memory on first access.
 ///
 /// `StringArray` can be built from a [`Vec`] or a slice of any type that can be converted to a string slice.
 /// Construction from an iterator is not feasible, as `StringArray` needs to know the total length of the strings in advance.
@@ -242,7 +246,7 @@ pub fn get_test_data(filename: &'static str) -> PathBuf {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct StringArray {
     index: IntVector,
-    strings: Vec<u8>,
+    strings: StringData,
 }
 
 impl StringArray {
@@ -328,20 +332,65 @@ impl StringArray {
         }
     }
 
+    /// Returns `true` if the packed strings are borrowed from a memory mapping.
+    ///
+    /// The strings remain packed in the mapping until they are accessed for the first time.
+    /// Then all of them are decompressed into memory.
+    /// [`StringArray::len`], [`StringArray::str_len`], and [`StringArray::range_len`] do not access the strings.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.strings, StringData::Mapped(_))
+    }
+
     // Builds an empty string array with capacity for `n` strings of total length `total_len`.
     fn with_capacity(n: usize, total_len: usize) -> StringArray {
         let mut index = IntVector::with_capacity(n + 1, bits::bit_len(total_len as u64)).unwrap();
         index.push(0);
         let strings: Vec<u8> = Vec::with_capacity(total_len);
         StringArray {
-            index, strings,
+            index,
+            strings: StringData::Owned(strings),
         }
     }
 
     // Appends a new string to the array, assuming that there is space for it.
     fn append(&mut self, string: &str) {
-        self.strings.extend(string.bytes());
-        self.index.push(self.strings.len() as u64);
+        if let StringData::Owned(strings) = &mut self.strings {
+            strings.extend(string.bytes());
+            self.index.push(strings.len() as u64);
+        }
+    }
+
+    // Returns the `i`th string without decompressing the entire array if the strings are still packed in a mapping.
+    fn decode(&self, i: usize) -> Cow<'_, [u8]> {
+        let start = self.index.get(i) as usize;
+        let limit = self.index.get(i + 1) as usize;
+        match &self.strings {
+            StringData::Mapped(mapped) if mapped.decoded.get().is_none() => Cow::Owned(mapped.decode(start..limit)),
+            _ => Cow::Borrowed(&self.strings[start..limit]),
+        }
+    }
+
+    // Builds the array from the compressed index without the past-the-end sentinel and the strings.
+    fn from_parts(sv: SparseVector, strings: StringData) -> io::Result<Self> {
+        let mut index = IntVector::with_capacity(sv.count_ones() + 1, bits::bit_len(strings.len() as u64)).unwrap();
+        let mut prev = 0;
+        for (_, offset) in sv.one_iter() {
+            if offset < prev || offset > strings.len() {
+                return Err(Error::new(ErrorKind::InvalidData, "StringArray: Invalid string offsets"));
+            }
+            index.push(offset as u64);
+            prev = offset;
+        }
+        index.push(strings.len() as u64);
+
+        // Sanity checks.
+        if index.get(0) != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "StringArray: First string does not start at offset 0"));
+        }
+        Ok(StringArray {
+            index, strings,
+        })
     }
 
     // Returns (bytes to packed, packed to bytes, packed character width).
@@ -371,6 +420,39 @@ impl StringArray {
     }
 }
 
+/// Memory mapping.
+impl StringArray {
+    /// Loads the string array from a reader over the bytes of a memory mapped file.
+    ///
+    /// The index is loaded into memory as usual, but the packed strings are borrowed from the mapping.
+    /// They are decompressed into memory on first access; see [`StringArray::is_mapped`].
+    /// The reader must be positioned at the start of a serialized string array, and it will be positioned after the array.
+    pub fn load_mapped(reader: &mut io::Cursor<&[u8]>, map: &Arc<SharedMap>) -> io::Result<Self> {
+        let sv = SparseVector::load(reader)?;
+        let alphabet = Vec::<u8>::load(reader)?;
+
+        // The packed strings are a serialized `IntVector`, which starts at a word boundary.
+        let start = reader.position() as usize;
+        if start % mem::size_of::<u64>() != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "StringArray: Packed strings are not aligned"));
+        }
+        let packed = IntVectorMapper::new(&map.map, start / mem::size_of::<u64>())?;
+        reader.set_position(((packed.map_offset() + packed.map_len()) * mem::size_of::<u64>()) as u64);
+        if (0..packed.len()).any(|i| packed.get(i) as usize >= alphabet.len()) {
+            return Err(Error::new(ErrorKind::InvalidData, "StringArray: Packed character not in the alphabet"));
+        }
+
+        let strings = StringData::Mapped(MappedStrings {
+            map: Arc::clone(map),
+            offset: packed.map_offset(),
+            len: packed.len(),
+            alphabet,
+            decoded: OnceLock::new(),
+        });
+        Self::from_parts(sv, strings)
+    }
+}
+
 impl Serialize for StringArray {
     fn serialize_header<T: io::Write>(&self, _: &mut T) -> io::Result<()> {
         Ok(())
@@ -403,20 +485,11 @@ impl Serialize for StringArray {
 
         // Load and decompress the strings.
         let packed = IntVector::load(reader)?;
-        let strings: Vec<u8> = packed.into_iter().map(|x| alphabet[x as usize]).collect();
+        let strings: Option<Vec<u8>> = packed.into_iter().map(|x| alphabet.get(x as usize).copied()).collect();
+        let strings = strings.ok_or_else(|| Error::new(ErrorKind::InvalidData, "StringArray: Packed character not in the alphabet"))?;
 
         // Decompress the index.
-        let mut index = IntVector::with_capacity(sv.count_ones() + 1, bits::bit_len(strings.len() as u64)).unwrap();
-        index.extend(sv.one_iter().map(|(_, x)| x));
-        index.push(strings.len() as u64);
-
-        // Sanity checks.
-        if index.get(0) != 0 {
-            return Err(Error::new(ErrorKind::InvalidData, "StringArray: First string does not start at offset 0"));
-        }
-        Ok(StringArray {
-            index, strings,
-        })
+        Self::from_parts(sv, StringData::Owned(strings))
     }
 
     fn size_in_elements(&self) -> usize {
@@ -445,6 +518,73 @@ impl<T: AsRef<str>> From<Vec<T>> for StringArray {
 
 //-----------------------------------------------------------------------------
 
+// Concatenated strings, either in memory or packed in a shared memory mapping.
+#[derive(Clone)]
+enum StringData {
+    Owned(Vec<u8>),
+    Mapped(MappedStrings),
+}
+
+impl StringData {
+    // Returns the total length of the strings without decompressing them.
+    fn len(&self) -> usize {
+        match self {
+            StringData::Owned(data) => data.len(),
+            StringData::Mapped(mapped) => mapped.len,
+        }
+    }
+}
+
+impl Deref for StringData {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            StringData::Owned(data) => data,
+            StringData::Mapped(mapped) => mapped.decoded.get_or_init(|| mapped.decode(0..mapped.len)),
+        }
+    }
+}
+
+impl PartialEq for StringData {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for StringData {}
+
+impl fmt::Debug for StringData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringData::Owned(data) => f.debug_tuple("Owned").field(&data.len()).finish(),
+            StringData::Mapped(mapped) => f.debug_tuple("Mapped").field(&mapped.offset).field(&mapped.len).finish(),
+        }
+    }
+}
+
+// Strings packed as a serialized `IntVector` in a memory mapping, with the decompressed strings initialized on first access.
+#[derive(Clone)]
+struct MappedStrings {
+    map: Arc<SharedMap>,
+    // Offset of the serialized `IntVector` in `u64` words.
+    offset: usize,
+    len: usize,
+    alphabet: Vec<u8>,
+    decoded: OnceLock<Vec<u8>>,
+}
+
+impl MappedStrings {
+    // Decompresses the given range of bytes.
+    // The packed characters were checked against the alphabet when the strings were loaded.
+    fn decode(&self, range: Range<usize>) -> Vec<u8> {
+        let packed = IntVectorMapper::new(&self.map.map, self.offset).unwrap();
+        range.map(|i| self.alphabet[packed.get(i) as usize]).collect()
+    }
+}
+
+//-----------------------------------------------------------------------------
+
 /// A read-only iterator over [`StringArray`].
 ///
 /// The type of `Item` is `&[`[`u8`]`]`.
@@ -554,7 +694,7 @@ impl Dictionary {
         while low < high {
             let mid = low + (high - low) / 2;
             let id = self.sorted_ids.get(mid) as usize;
-            match string.as_ref().cmp(self.bytes(id)) {
+            match string.as_ref().cmp(&*self.strings.decode(id)) {
                 Ordering::Less => high = mid,
                 Ordering::Equal => return Some(id),
                 Ordering::Greater => low = mid + 1,
@@ -589,6 +729,49 @@ impl Dictionary {
     pub fn string(&self, i: usize) -> Result<String, Utf8Error> {
         self.strings.string(i)
     }
+
+    /// Returns `true` if the packed strings are borrowed from a memory mapping.
+    ///
+    /// See [`StringArray::is_mapped`].
+    /// [`Dictionary::id`] does not decompress the strings.
+    #[inline]
+    pub fn is_mapped(&self) -> bool {
+        self.strings.is_mapped()
+    }
+
+    /// Loads the dictionary from a reader over the bytes of a memory mapped file.
+    ///
+    /// The strings are borrowed from the mapping; see [`StringArray::load_mapped`].
+    pub fn load_mapped(reader: &mut io::Cursor<&[u8]>, map: &Arc<SharedMap>) -> io::Result<Self> {
+        Self::load_with(reader, |reader| StringArray::load_mapped(reader, map))
+    }
+
+    // Loads the dictionary using the given function for loading the strings.
+    fn load_with<T: io::Read, F: FnOnce(&mut T) -> io::Result<StringArray>>(reader: &mut T, load_strings: F) -> io::Result<Self> {
+        let strings = load_strings(reader)?;
+        let sorted_ids = IntVector::load(reader)?;
+
+        // The ids must be a permutation that sorts distinct strings.
+        if sorted_ids.len() != strings.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "Dictionary: Sorted ids do not match the strings"));
+        }
+        let mut prev: Option<Cow<[u8]>> = None;
+        for id in sorted_ids.iter() {
+            let id = id as usize;
+            if id >= strings.len() {
+                return Err(Error::new(ErrorKind::InvalidData, "Dictionary: Invalid string id"));
+            }
+            let curr = strings.decode(id);
+            if prev.as_ref().map_or(false, |prev| *prev >= curr) {
+                return Err(Error::new(ErrorKind::InvalidData, "Dictionary: Strings are not sorted or not distinct"));
+            }
+            prev = Some(curr);
+        }
+
+        Ok(Dictionary {
+            strings, sorted_ids,
+        })
+    }
 }
 
 impl Serialize for Dictionary {
@@ -603,11 +786,7 @@ impl Serialize for Dictionary {
     }
 
     fn load<T: io::Read>(reader: &mut T) -> io::Result<Self> {
-        let strings = StringArray::load(reader)?;
-        let sorted_ids = IntVector::load(reader)?;
-        Ok(Dictionary {
-            strings, sorted_ids,
-        })
+        Self::load_with(reader, StringArray::load)
     }
 
     fn size_in_elements(&self) -> usize {
@@ -728,6 +907,12 @@ impl Tags {
         let _ = self.tags.insert(key, value.to_string());
     }
 
+    /// Removes the tag with the given key and returns its value, or [`None`] if no such tag exists.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let key = key.to_lowercase();
+        self.tags.remove(&key)
+    }
+
     /// Returns an iterator that visits all tags in sorted order by keys.
     ///
     /// The type of `Item` is `(&`[`String`]`, &`[`String`]`)`.
@@ -921,7 +1106,16 @@ impl<'a> Iterator for ByteCodeIter<'a> {
         while self.offset < self.bytes.len() {
             let value = unsafe { *self.bytes.get_unchecked(self.offset) };
             self.offset += 1;
-            result += ((value & ByteCode::MASK) as usize) << offset;
+            // Malformed encodings must not panic. A value that does not fit in `usize` ends the iteration.
+            let part = (value & ByteCode::MASK) as usize;
+            let shifted = part.checked_shl(offset as u32).filter(|shifted| shifted >> offset == part);
+            match shifted.and_then(|shifted| result.checked_add(shifted)) {
+                Some(value) => result = value,
+                None => {
+                    self.offset = self.bytes.len();
+                    return None;
+                },
+            }
             offset += ByteCode::SHIFT;
             if value & ByteCode::FLAG == 0 {
                 return Some(result);
@@ -1179,7 +1373,7 @@ impl<'a> Iterator for RLEIter<'a> {
         let mut run = Run::default();
         if self.sigma >= RLE::THRESHOLD {
             if let Some(value) = self.source.next() { run.value = value; } else { return None; }
-            if let Some(len) = self.source.next() { run.len = len + 1; } else { return None; }
+            if let Some(len) = self.source.next() { run.len = len.saturating_add(1); } else { return None; }
         } else {
             if let Some(byte) = self.source.byte() {
                 run.value = (byte as usize) % self.sigma;
@@ -1188,7 +1382,7 @@ impl<'a> Iterator for RLEIter<'a> {
                 return None;
             }
             if run.len == self.threshold {
-                if let Some(len) = self.source.next() { run.len += len; } else { return None; }
+                if let Some(len) = self.source.next() { run.len = run.len.saturating_add(len); } else { return None; }
             }
         }
         Some(run)
@@ -1342,3 +1536,224 @@ impl DisjointSets {
 }
 
 //-----------------------------------------------------------------------------
+
+/// A least recently used cache with constant-time operations.
+///
+/// The entries are stored in a vector and linked into a list in the order of use.
+/// A hash map from keys to vector offsets makes lookups constant-time.
+/// When the cache is full, inserting a new entry evicts the least recently used entry.
+/// Capacity `0` disables the cache.
+///
+/// # Examples
+///
+/// ```
+/// use gbwt::support::LruCache;
+///
+/// let mut cache: LruCache<usize, &str> = LruCache::new(2);
+/// cache.insert(1, "one");
+/// cache.insert(2, "two");
+/// assert_eq!(cache.get(&1), Some(&"one"));
+///
+/// // Key 2 is now the least recently used.
+/// cache.insert(3, "three");
+/// assert_eq!(cache.len(), 2);
+/// assert!(cache.get(&2).is_none());
+/// assert_eq!(cache.remove(&1), Some("one"));
+/// assert_eq!(cache.get(&3), Some(&"three"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    index: HashMap<K, usize>,
+    entries: Vec<LruEntry<K, V>>,
+    // Most recently used entry.
+    head: usize,
+    // Least recently used entry.
+    tail: usize,
+}
+
+#[derive(Clone, Debug)]
+struct LruEntry<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+impl<K: Copy + Eq + Hash, V> LruCache<K, V> {
+    // Null link.
+    const NONE: usize = usize::MAX;
+
+    /// Returns an empty cache with the given capacity.
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            index: HashMap::new(),
+            entries: Vec::new(),
+            head: Self::NONE,
+            tail: Self::NONE,
+        }
+    }
+
+    /// Returns the maximum number of entries in the cache.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries in the cache.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the key is in the cache, without marking it as used.
+    #[inline]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Returns the value for the given key and marks it as the most recently used, or [`None`] if the key is not in the cache.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let offset = *self.index.get(key)?;
+        self.unlink(offset);
+        self.push_front(offset);
+        Some(&self.entries[offset].value)
+    }
+
+    /// Inserts the value as the most recently used entry and returns the evicted value, if any.
+    ///
+    /// If the key is already in the cache, the old value is replaced and returned.
+    /// Otherwise the least recently used entry is evicted if the cache is full.
+    /// If the capacity is `0`, the value is returned immediately.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if self.capacity == 0 {
+            return Some(value);
+        }
+        if let Some(&offset) = self.index.get(&key) {
+            self.unlink(offset);
+            self.push_front(offset);
+            return Some(mem::replace(&mut self.entries[offset].value, value));
+        }
+
+        let evicted = if self.len() >= self.capacity {
+            let key = self.entries[self.tail].key;
+            self.remove(&key)
+        } else {
+            None
+        };
+        let offset = self.entries.len();
+        self.entries.push(LruEntry {
+            key, value,
+            prev: Self::NONE,
+            next: Self::NONE,
+        });
+        self.index.insert(key, offset);
+        self.push_front(offset);
+        evicted
+    }
+
+    /// Removes the entry with the given key and returns the value, or [`None`] if the key is not in the cache.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let offset = self.index.remove(key)?;
+        self.unlink(offset);
+
+        // Move the last entry to the vacated offset.
+        let last = self.entries.len() - 1;
+        if offset != last {
+            let (prev, next) = (self.entries[last].prev, self.entries[last].next);
+            self.relink(prev, next, offset);
+            self.index.insert(self.entries[last].key, offset);
+        }
+        let entry = self.entries.swap_remove(offset);
+        Some(entry.value)
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.entries.clear();
+        self.head = Self::NONE;
+        self.tail = Self::NONE;
+    }
+
+    // Removes the entry at the given offset from the list.
+    fn unlink(&mut self, offset: usize) {
+        let (prev, next) = (self.entries[offset].prev, self.entries[offset].next);
+        if prev == Self::NONE { self.head = next; } else { self.entries[prev].next = next; }
+        if next == Self::NONE { self.tail = prev; } else { self.entries[next].prev = prev; }
+    }
+
+    // Makes the neighbors of an entry that is about to move point to the given offset.
+    fn relink(&mut self, prev: usize, next: usize, offset: usize) {
+        if prev == Self::NONE { self.head = offset; } else { self.entries[prev].next = offset; }
+        if next == Self::NONE { self.tail = offset; } else { self.entries[next].prev = offset; }
+    }
+
+    // Inserts the entry at the given offset to the front of the list.
+    fn push_front(&mut self, offset: usize) {
+        self.entries[offset].prev = Self::NONE;
+        self.entries[offset].next = self.head;
+        if self.head == Self::NONE { self.tail = offset; } else { self.entries[self.head].prev = offset; }
+        self.head = offset;
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+/// A read-only memory mapped file that can be shared between structures and threads.
+///
+/// This is a wrapper over [`MemoryMap`] that exposes the file as a byte slice.
+/// Structures loaded with functions such as [`crate::GBZ::open_mmap`] hold the mapping through an [`Arc`], and the file remains mapped as long as any of them exist.
+/// Because the mapping is read-only, the operating system can share the pages between processes mapping the same file.
+///
+/// The BWT of a GBWT index is borrowed from the mapping.
+/// [`StringArray`] and [`Dictionary`] borrow their packed strings and decompress them into memory on first access, as their interfaces return the strings as byte slices.
+/// The other parts of the structures are copied into memory when they are loaded.
+pub struct SharedMap {
+    map: MemoryMap,
+}
+
+// The mapping is read-only and never modified after creation.
+unsafe impl Send for SharedMap {}
+unsafe impl Sync for SharedMap {}
+
+impl SharedMap {
+    /// Opens a read-only memory mapping of the given file.
+    pub fn open<P: AsRef<Path>>(filename: P) -> io::Result<Arc<Self>> {
+        let map = MemoryMap::new(filename, MappingMode::ReadOnly)?;
+        Ok(Arc::new(SharedMap { map }))
+    }
+
+    /// Returns the length of the file in bytes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.as_ref().len() * mem::size_of::<u64>()
+    }
+
+    /// Returns `true` if the file is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the contents of the file as a byte slice.
+    pub fn bytes(&self) -> &[u8] {
+        let words: &[u64] = self.map.as_ref();
+        unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, self.len()) }
+    }
+}
+
+impl fmt::Debug for SharedMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedMap").field("len", &self.len()).finish()
+    }
+}
+
+//-----------------------------------------------------------------------------
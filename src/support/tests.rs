@@ -146,6 +146,73 @@ fn array_with_empty_strings() {
     let _ = serialize::test(&array, "string-array-with-empty", None, true);
 }
 
+// Writes the buffer to a temporary file and maps it.
+fn map_buffer(buffer: &[u8], name: &str) -> Arc<SharedMap> {
+    let filename = serialize::temp_file_name(name);
+    std::fs::write(&filename, buffer).unwrap();
+    let map = SharedMap::open(&filename).unwrap();
+    std::fs::remove_file(&filename).unwrap();
+    map
+}
+
+// Returns `true` if the strings are packed in a mapping and have not been decompressed.
+fn is_packed(array: &StringArray) -> bool {
+    matches!(&array.strings, StringData::Mapped(mapped) if mapped.decoded.get().is_none())
+}
+
+#[test]
+fn mapped_string_array() {
+    for truth in [vec![], vec!["first", "second", "third", "fourth"], vec!["first", "second", "", "fourth", ""]] {
+        let array = StringArray::from(truth.as_slice());
+        let mut buffer: Vec<u8> = Vec::new();
+        array.serialize(&mut buffer).unwrap();
+        let map = map_buffer(&buffer, "mapped-string-array");
+
+        let mut reader = io::Cursor::new(map.bytes());
+        let mapped = StringArray::load_mapped(&mut reader, &map).unwrap();
+        assert_eq!(reader.position() as usize, buffer.len(), "Invalid reader position after a mapped string array");
+        assert!(mapped.is_mapped(), "The string array is not memory mapped");
+        assert!(!array.is_mapped(), "The original string array is memory mapped");
+        for i in 0..mapped.len() {
+            assert_eq!(mapped.str_len(i), truth[i].len(), "Incorrect length for mapped string {}", i);
+        }
+        assert!(is_packed(&mapped), "String lengths decompressed the strings");
+        check_array(&mapped, &truth);
+        assert!(!is_packed(&mapped), "Access did not decompress the strings");
+        assert_eq!(mapped, array, "Mapped string array differs from the original");
+    }
+}
+
+// Serializes a string array from its parts.
+fn string_array_parts(offsets: &[usize], alphabet: &[u8], packed: &[usize]) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let index = SparseVector::try_from_iter(offsets.iter().copied()).unwrap();
+    index.serialize(&mut buffer).unwrap();
+    alphabet.to_vec().serialize(&mut buffer).unwrap();
+    let mut strings = IntVector::new(8).unwrap();
+    strings.extend(packed.iter().copied());
+    strings.serialize(&mut buffer).unwrap();
+    buffer
+}
+
+#[test]
+fn malformed_string_array() {
+    let buffer = string_array_parts(&[0, 1], b"ab", &[0, 1]);
+    let array = StringArray::load(&mut buffer.as_slice()).unwrap();
+    check_array(&array, &["a", "b"]);
+
+    let buffer = string_array_parts(&[0, 1], b"ab", &[0, 2]);
+    assert!(StringArray::load(&mut buffer.as_slice()).is_err(), "Loaded a string array with a character not in the alphabet");
+    let buffer = string_array_parts(&[0, 3], b"ab", &[0, 1]);
+    assert!(StringArray::load(&mut buffer.as_slice()).is_err(), "Loaded a string array with an offset past the end");
+    let buffer = string_array_parts(&[1], b"ab", &[0, 1]);
+    assert!(StringArray::load(&mut buffer.as_slice()).is_err(), "Loaded a string array with a non-zero first offset");
+
+    let buffer = string_array_parts(&[0, 1], b"ab", &[0, 2]);
+    let map = map_buffer(&buffer, "malformed-string-array");
+    assert!(StringArray::load_mapped(&mut io::Cursor::new(map.bytes()), &map).is_err(), "Mapped a string array with a character not in the alphabet");
+}
+
 //-----------------------------------------------------------------------------
 
 fn check_dict(dict: &Dictionary, truth: &[&str], missing: &[&str]) {
@@ -194,6 +261,58 @@ fn dict_from_duplicates() {
     assert!(result.is_err(), "Did not get an error from a source with duplicate strings");
 }
 
+// Serializes a dictionary with the given sorted ids.
+fn dict_parts(strings: &[&str], sorted_ids: &[usize]) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::new();
+    StringArray::from(strings).serialize(&mut buffer).unwrap();
+    let mut ids = IntVector::new(8).unwrap();
+    ids.extend(sorted_ids.iter().copied());
+    ids.serialize(&mut buffer).unwrap();
+    buffer
+}
+
+#[test]
+fn malformed_dict() {
+    let truth = vec!["c", "a", "b"];
+    let buffer = dict_parts(&truth, &[1, 2, 0]);
+    let dict = Dictionary::load(&mut buffer.as_slice()).unwrap();
+    check_dict(&dict, &truth, &["d"]);
+
+    let buffer = dict_parts(&truth, &[1, 2]);
+    assert!(Dictionary::load(&mut buffer.as_slice()).is_err(), "Loaded a dictionary with too few sorted ids");
+    let buffer = dict_parts(&truth, &[1, 2, 3]);
+    assert!(Dictionary::load(&mut buffer.as_slice()).is_err(), "Loaded a dictionary with an invalid string id");
+    let buffer = dict_parts(&truth, &[0, 1, 2]);
+    assert!(Dictionary::load(&mut buffer.as_slice()).is_err(), "Loaded a dictionary with unsorted ids");
+    let buffer = dict_parts(&truth, &[1, 1, 2]);
+    assert!(Dictionary::load(&mut buffer.as_slice()).is_err(), "Loaded a dictionary with duplicate ids");
+    let buffer = dict_parts(&["a", "b", "a"], &[0, 2, 1]);
+    assert!(Dictionary::load(&mut buffer.as_slice()).is_err(), "Loaded a dictionary with duplicate strings");
+    let map = map_buffer(&buffer, "malformed-dict");
+    assert!(Dictionary::load_mapped(&mut io::Cursor::new(map.bytes()), &map).is_err(), "Mapped a dictionary with duplicate strings");
+}
+
+#[test]
+fn mapped_dict() {
+    let truth = vec!["first", "second", "third", "fourth"];
+    let missing = vec!["this", "should", "not", "exist"];
+    let dict = Dictionary::try_from(truth.as_slice()).unwrap();
+    let mut buffer: Vec<u8> = Vec::new();
+    dict.serialize(&mut buffer).unwrap();
+    let map = map_buffer(&buffer, "mapped-dict");
+
+    let mut reader = io::Cursor::new(map.bytes());
+    let mapped = Dictionary::load_mapped(&mut reader, &map).unwrap();
+    assert_eq!(reader.position() as usize, buffer.len(), "Invalid reader position after a mapped dictionary");
+    assert!(mapped.is_mapped(), "The dictionary is not memory mapped");
+    for (i, string) in truth.iter().enumerate() {
+        assert_eq!(mapped.id(string), Some(i), "Invalid id for mapped string {}: {}", i, string);
+    }
+    assert!(is_packed(mapped.as_ref()), "Lookups decompressed the strings");
+    check_dict(&mapped, &truth, &missing);
+    assert_eq!(mapped, dict, "Mapped dictionary differs from the original");
+}
+
 //-----------------------------------------------------------------------------
 
 fn check_tags(tags: &Tags, truth: &BTreeMap<&str, &str>, missing: &[&str]) {
@@ -323,6 +442,27 @@ fn random_byte_code() {
     assert_eq!(iter.offset(), encoder.len(), "Iterator did not consume all bytes");
 }
 
+#[test]
+fn malformed_byte_code() {
+    // A value that does not fit in `usize`.
+    let mut bytes = vec![0xFF; 20];
+    bytes.push(0x01);
+    let mut iter = ByteCodeIter::new(&bytes);
+    assert_eq!(iter.next(), None, "Decoded an overlong value");
+    assert_eq!(iter.offset(), bytes.len(), "Iterator did not consume all bytes");
+    assert_eq!(iter.next(), None, "Iterator continued after an overlong value");
+
+    // The largest value that fits in `usize`.
+    let mut encoder = ByteCode::new();
+    encoder.write(usize::MAX);
+    let mut iter = ByteCodeIter::new(encoder.as_ref());
+    assert_eq!(iter.next(), Some(usize::MAX), "Could not decode the largest value");
+
+    // A value that ends early.
+    let mut iter = ByteCodeIter::new(&bytes[..10]);
+    assert_eq!(iter.next(), None, "Decoded a truncated value");
+}
+
 //-----------------------------------------------------------------------------
 
 // Generate `n` random runs from an alphabet of size `sigma`.
@@ -408,6 +548,23 @@ fn run_length_thresholds() {
     test_threshold(254, "sigma == 254");
 }
 
+#[test]
+fn overlong_runs() {
+    // Large alphabet: the length is encoded as `len - 1`.
+    let mut encoder = ByteCode::new();
+    encoder.write(0);
+    encoder.write(usize::MAX);
+    let mut iter = RLEIter::with_sigma(encoder.as_ref(), 300);
+    assert_eq!(iter.next(), Some(Run::new(0, usize::MAX)), "Invalid run length with a large alphabet");
+
+    // Small alphabet: the length continues after a run of length `threshold`.
+    let mut encoder = ByteCode::new();
+    encoder.write_byte(0xFF);
+    encoder.write(usize::MAX);
+    let mut iter = RLEIter::with_sigma(encoder.as_ref(), 2);
+    assert_eq!(iter.next(), Some(Run::new(1, usize::MAX)), "Invalid run length with a small alphabet");
+}
+
 #[test]
 fn gbwt_record() {
     // Original data for the record.
@@ -579,3 +736,66 @@ fn filtered_disjoint_sets() {
 }
 
 //-----------------------------------------------------------------------------
+
+// Compares the cache to a vector of keys in the order of use, with the most recently used key last.
+fn check_lru(cache: &LruCache<usize, usize>, truth: &[usize], name: &str) {
+    assert_eq!(cache.len(), truth.len(), "{}: Invalid number of entries", name);
+    assert_eq!(cache.is_empty(), truth.is_empty(), "{}: Invalid emptiness", name);
+    assert!(cache.len() <= cache.capacity(), "{}: The cache exceeds its capacity", name);
+    for key in truth.iter() {
+        assert!(cache.contains_key(key), "{}: Missing key {}", name, key);
+    }
+}
+
+fn test_lru(capacity: usize, universe: usize, operations: usize) {
+    let mut cache: LruCache<usize, usize> = LruCache::new(capacity);
+    let mut truth: Vec<usize> = Vec::new();
+    let mut rng = rand::thread_rng();
+    for i in 0..operations {
+        let key = rng.gen_range(0..universe);
+        let name = format!("Capacity {}, operation {}", capacity, i);
+        match rng.gen_range(0..3) {
+            0 => {
+                let expected = truth.iter().position(|k| *k == key).map(|offset| truth.remove(offset));
+                assert_eq!(cache.get(&key).copied(), expected.map(|k| 2 * k), "{}: Invalid value for key {}", name, key);
+                if expected.is_some() {
+                    truth.push(key);
+                }
+            },
+            1 => {
+                let evicted = if let Some(offset) = truth.iter().position(|k| *k == key) {
+                    truth.remove(offset);
+                    Some(2 * key)
+                } else if capacity > 0 && truth.len() >= capacity {
+                    Some(2 * truth.remove(0))
+                } else if capacity == 0 {
+                    Some(2 * key)
+                } else {
+                    None
+                };
+                if capacity > 0 {
+                    truth.push(key);
+                }
+                assert_eq!(cache.insert(key, 2 * key), evicted, "{}: Invalid evicted value for key {}", name, key);
+            },
+            _ => {
+                let expected = truth.iter().position(|k| *k == key).map(|offset| truth.remove(offset));
+                assert_eq!(cache.remove(&key), expected.map(|k| 2 * k), "{}: Invalid removed value for key {}", name, key);
+            },
+        }
+        check_lru(&cache, &truth, &name);
+    }
+
+    cache.clear();
+    check_lru(&cache, &[], "Cleared");
+}
+
+#[test]
+fn lru_cache() {
+    test_lru(0, 10, 100);
+    test_lru(1, 10, 200);
+    test_lru(7, 20, 2000);
+    test_lru(32, 40, 2000);
+}
+
+//-----------------------------------------------------------------------------
@@ -0,0 +1,78 @@
+// Shared helper functions for the unit tests.
+
+use crate::{GBWT, GBZ, Orientation, Pos};
+use crate::gbwt::RecordSource;
+use crate::support;
+
+use simple_sds::serialize;
+
+//-----------------------------------------------------------------------------
+
+pub(crate) fn load_index(test_file: &'static str) -> GBWT {
+    let filename = support::get_test_data(test_file);
+    serialize::load_from(&filename).unwrap()
+}
+
+pub(crate) fn load_gbz(test_file: &'static str) -> GBZ {
+    let filename = support::get_test_data(test_file);
+    serialize::load_from(&filename).unwrap()
+}
+
+// Returns all valid positions in the index.
+pub(crate) fn positions(index: &GBWT) -> Vec<Pos> {
+    let mut result = Vec::new();
+    for i in 0..index.sequences() {
+        let mut pos = index.start(i);
+        while let Some(p) = pos {
+            result.push(p);
+            pos = index.forward(p);
+        }
+    }
+    result
+}
+
+// Returns the oriented nodes of the path with their starting offsets.
+pub(crate) fn path_nodes(gbz: &GBZ, path_id: usize) -> Vec<(usize, Orientation, usize)> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+    for (node_id, orientation) in gbz.path(path_id, Orientation::Forward).unwrap() {
+        result.push((node_id, orientation, offset));
+        offset += gbz.sequence_len(node_id).unwrap();
+    }
+    result
+}
+
+//-----------------------------------------------------------------------------
+
+// Checks that the source follows all sequences in the same way as the index.
+pub(crate) fn check_navigation<S: RecordSource>(index: &GBWT, source: &S) {
+    for pos in positions(index) {
+        assert_eq!(RecordSource::forward(source, pos), index.forward(pos), "Invalid forward({:?})", pos);
+        if index.is_bidirectional() {
+            assert_eq!(RecordSource::backward(source, pos), index.backward(pos), "Invalid backward({:?})", pos);
+        }
+    }
+}
+
+// Checks that the source returns the same search states as the index for all nodes and their extensions.
+pub(crate) fn check_search<S: RecordSource>(index: &GBWT, source: &S) {
+    for node in 0..index.alphabet_size() + 1 {
+        assert_eq!(RecordSource::find(source, node), index.find(node), "Invalid find({})", node);
+        if let Some(state) = index.find(node) {
+            for next in 0..index.alphabet_size() + 1 {
+                assert_eq!(RecordSource::extend(source, &state, next), index.extend(&state, next), "Invalid extend({:?}, {})", state, next);
+            }
+        }
+        if index.is_bidirectional() {
+            assert_eq!(RecordSource::bd_find(source, node), index.bd_find(node), "Invalid bd_find({})", node);
+            if let Some(state) = index.bd_find(node) {
+                for next in 0..index.alphabet_size() + 1 {
+                    assert_eq!(RecordSource::extend_forward(source, &state, next), index.extend_forward(&state, next), "Invalid extend_forward({:?}, {})", state, next);
+                    assert_eq!(RecordSource::extend_backward(source, &state, next), index.extend_backward(&state, next), "Invalid extend_backward({:?}, {})", state, next);
+                }
+            }
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------